@@ -0,0 +1,194 @@
+use std::io::{ Result, Write, sink };
+use criterion::{ criterion_group, criterion_main, Criterion, Throughput, black_box };
+use ltools::attrspec::AttrSpec;
+use ltools::bulk::{ BulkParser, parse_entries };
+use ltools::csv::CsvEntryWriter;
+use ltools::json::JsonEntryWriter;
+use ltools::output::OutputBuffer;
+use ltools::tsv::TsvEntryWriter;
+use ltools::entry::{ Entry, OwnedEntry, WriteEntry };
+use ltools::filter::Filter;
+use ltools::lexer::{ Lexer, Token, WriteToken };
+use ltools::loc::{ LocWriteWrapper, WriteLocWrapper };
+use ltools::unfold::Unfolder;
+
+const ENTRY_COUNT: usize = 2000;
+
+fn sample_ldif() -> Vec<u8> {
+    let mut ldif: Vec<u8> = Vec::new();
+    for i in 0..ENTRY_COUNT {
+        write!(ldif, "dn: uid=user{i},ou=people,dc=example,dc=com\n\
+            objectClass: top\n\
+            objectClass: person\n\
+            objectClass: inetOrgPerson\n\
+            uid: user{i}\n\
+            cn: User Number {i}\n\
+            sn: Number {i}\n\
+            mail: user{i}@example.com\n\
+            description: A somewhat longer description that is folded over more than\n  one line, as LDIF writers tend to do for user {i}\n\
+            userPassword:: e1NTSEF9c29tZXRoaW5nc29tZXRoaW5nc29tZXRoaW5n\n\
+            \n").unwrap();
+    }
+    ldif
+}
+
+fn large_value_ldif() -> Vec<u8> {
+    let mut ldif: Vec<u8> = Vec::new();
+    let photo = "QUJD".repeat(16 * 1024);
+    let description = "a long description ".repeat(1024);
+    for i in 0..20 {
+        write!(ldif, "dn: uid=user{i},ou=people,dc=example,dc=com\n\
+            # a comment that is {description}\n\
+            description: {description}\n\
+            jpegPhoto:: {photo}\n\
+            \n").unwrap();
+    }
+    ldif
+}
+
+struct TokenCounter(usize);
+
+impl WriteToken for TokenCounter {
+    fn write_token(&mut self, token: Token) -> Result<()> {
+        black_box(token);
+        self.0 += 1;
+        Ok(())
+    }
+}
+
+struct EntryCounter(usize);
+
+impl WriteEntry for EntryCounter {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        black_box(entry);
+        self.0 += 1;
+        Ok(())
+    }
+}
+
+fn unfold(ldif: &[u8]) -> Vec<u8> {
+    let mut unfolded: Vec<u8> = Vec::new();
+    let mut wrapper = WriteLocWrapper::new(Unfolder::new(LocWriteWrapper::new(&mut unfolded)));
+    wrapper.write_all(ldif).unwrap();
+    wrapper.flush().unwrap();
+    unfolded
+}
+
+fn bench_lex(c: &mut Criterion, ldif: &[u8]) {
+    // the lexer expects its input to be unfolded already
+    let ldif = &unfold(ldif)[..];
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Bytes(ldif.len() as u64));
+    group.bench_function("lexer", |b| b.iter(|| {
+        let mut wrapper = WriteLocWrapper::new(Lexer::new(TokenCounter(0)));
+        wrapper.write_all(ldif).unwrap();
+        wrapper.flush().unwrap();
+    }));
+    group.finish();
+
+    // values of tens of kilobytes, such as certificates and photos, are where the lexer spends
+    // its time in some directories
+    let ldif = &unfold(&large_value_ldif())[..];
+    let mut group = c.benchmark_group("lex_large_values");
+    group.throughput(Throughput::Bytes(ldif.len() as u64));
+    group.bench_function("lexer", |b| b.iter(|| {
+        let mut wrapper = WriteLocWrapper::new(Lexer::new(TokenCounter(0)));
+        wrapper.write_all(ldif).unwrap();
+        wrapper.flush().unwrap();
+    }));
+    group.finish();
+}
+
+fn bench_unfold(c: &mut Criterion, ldif: &[u8]) {
+    let mut group = c.benchmark_group("unfold");
+    group.throughput(Throughput::Bytes(ldif.len() as u64));
+    group.bench_function("unfolder", |b| b.iter(|| {
+        let mut wrapper = WriteLocWrapper::new(Unfolder::new(LocWriteWrapper::new(sink())));
+        wrapper.write_all(ldif).unwrap();
+        wrapper.flush().unwrap();
+    }));
+    group.finish();
+}
+
+fn bench_entries(c: &mut Criterion, ldif: &[u8]) {
+    let mut group = c.benchmark_group("entries");
+    group.throughput(Throughput::Bytes(ldif.len() as u64));
+    group.bench_function("parse_entries", |b| b.iter(|| {
+        let mut counter = EntryCounter(0);
+        parse_entries(ldif, &mut counter).unwrap();
+        assert_eq!(counter.0, ENTRY_COUNT);
+    }));
+    let mut parser = BulkParser::new();
+    group.bench_function("bulk_parser", |b| b.iter(|| {
+        let mut counter = EntryCounter(0);
+        parser.write_entries(&mut &ldif[..], &mut counter).unwrap();
+        assert_eq!(counter.0, ENTRY_COUNT);
+    }));
+    group.finish();
+}
+
+fn bench_filter(c: &mut Criterion, ldif: &[u8]) {
+    let mut entries: Vec<OwnedEntry> = Vec::new();
+    parse_entries(ldif, &mut entries).unwrap();
+    let filters = [
+        ("equality", "(uid=user1000)"),
+        ("presence", "(mail=*)"),
+        ("substring", "(description=*folded*user 1*)"),
+        ("and", "(&(objectClass=person)(|(sn=Number 1*)(cn=*9)))"),
+    ];
+    let mut group = c.benchmark_group("filter");
+    group.throughput(Throughput::Elements(entries.len() as u64));
+    for (name, filter) in filters {
+        let filter = Filter::parse(filter).unwrap();
+        group.bench_function(name, |b| b.iter(|| {
+            entries.iter().filter(|entry| filter.is_match(entry)).count()
+        }));
+    }
+    group.finish();
+}
+
+// Writes the values of every entry to /dev/null, so that each write to the destination is a system
+// call, as it is when writing to a pipe or a file.
+#[cfg(unix)]
+fn bench_extract(c: &mut Criterion, ldif: &[u8]) {
+    let mut entries: Vec<OwnedEntry> = Vec::new();
+    parse_entries(ldif, &mut entries).unwrap();
+    let attrspecs = || ["dn", "uid", "cn", "mail", "description"].map(|spec| AttrSpec::parse(spec).unwrap()).to_vec();
+    let devnull = || std::fs::OpenOptions::new().write(true).open("/dev/null").unwrap();
+    let mut group = c.benchmark_group("extract");
+    group.throughput(Throughput::Elements(entries.len() as u64 * 5));
+    group.bench_function("tsv_unbuffered", |b| b.iter(|| {
+        let mut writer = TsvEntryWriter::new(attrspecs(), devnull());
+        entries.iter().try_for_each(|entry| writer.write_entry(entry)).unwrap();
+    }));
+    group.bench_function("tsv", |b| b.iter(|| {
+        let mut writer = TsvEntryWriter::new(attrspecs(), OutputBuffer::new(devnull()));
+        entries.iter().try_for_each(|entry| writer.write_entry(entry)).unwrap();
+    }));
+    group.bench_function("csv", |b| b.iter(|| {
+        let mut writer = CsvEntryWriter::new(attrspecs(), OutputBuffer::new(devnull()));
+        entries.iter().try_for_each(|entry| writer.write_entry(entry)).unwrap();
+    }));
+    group.bench_function("json", |b| b.iter(|| {
+        let mut writer = JsonEntryWriter::new(attrspecs(), OutputBuffer::new(devnull()));
+        entries.iter().try_for_each(|entry| writer.write_entry(entry)).unwrap();
+    }));
+    group.bench_function("ldif", |b| b.iter(|| {
+        let mut dest = OutputBuffer::new(devnull());
+        entries.iter().try_for_each(|entry| ltools::entry::write_entry_normally(&mut dest, entry)).unwrap();
+    }));
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    let ldif = sample_ldif();
+    bench_lex(c, &ldif);
+    bench_unfold(c, &ldif);
+    bench_entries(c, &ldif);
+    bench_filter(c, &ldif);
+    #[cfg(unix)]
+    bench_extract(c, &ldif);
+}
+
+criterion_group!(pipeline, benches);
+criterion_main!(pipeline);
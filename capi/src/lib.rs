@@ -0,0 +1,4 @@
+//! The shared library of the C API in `ltools::capi`, which `cargo build` writes to
+//! `target/debug/libltools.so` (or `.dylib` or `.dll`).
+
+pub use ltools::capi::*;
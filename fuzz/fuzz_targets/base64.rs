@@ -0,0 +1,23 @@
+// Checks that arbitrary data survives being base64 encoded and decoded again, and that decoding
+// arbitrary input doesn't panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ltools::base64::{DecodeWriter, EncodeWriter};
+use std::io::Write;
+
+fuzz_target!(|data: &[u8]| {
+    let mut encoded: Vec<u8> = Vec::new();
+    let mut encoder = EncodeWriter::new(&mut encoded);
+    encoder.write_all(data).unwrap();
+    encoder.flush().unwrap();
+
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut decoder = DecodeWriter::new(&mut decoded);
+    decoder.write_all(&encoded).unwrap();
+    decoder.flush().unwrap();
+    assert_eq!(decoded, data);
+
+    let mut decoder = DecodeWriter::new(std::io::sink());
+    let _ = decoder.write_all(data).and_then(|_| decoder.flush());
+});
@@ -0,0 +1,12 @@
+// Parses arbitrary text as a search filter and matches an entry against the filters that parse.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ltools::entry::Entry;
+use ltools::filter::Filter;
+
+fuzz_target!(|filter: &str| {
+    if let Ok(filter) = Filter::parse(filter) {
+        filter.is_match(&Entry::from([("cn", b"foo".as_slice()), ("member", b"cn=bar".as_slice())]));
+    }
+});
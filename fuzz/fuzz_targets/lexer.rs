@@ -0,0 +1,37 @@
+// Feeds arbitrary input through the whole parsing pipeline, in chunks of a size given by the first
+// byte, and checks that the outcome is the same as when it is parsed in one go.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ltools::bulk::BulkParser;
+use ltools::lexer::{Token, WriteToken};
+
+#[derive(Default)]
+struct Tokens(Vec<(String, String)>);
+
+impl WriteToken for &mut Tokens {
+    fn write_token(&mut self, token: Token) -> std::io::Result<()> {
+        self.0.push((format!("{:?}", token.kind), token.segment.to_string()));
+        Ok(())
+    }
+}
+
+fn lex(input: &[u8], buffer_size: usize) -> (Tokens, bool) {
+    let mut tokens = Tokens::default();
+    let ok = BulkParser::with_capacity(buffer_size)
+        .write_tokens(&mut &input[..], &mut tokens)
+        .is_ok();
+    (tokens, ok)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&buffer_size, input)) = data.split_first() else {
+        return;
+    };
+    let (whole, whole_ok) = lex(input, input.len());
+    let (split, split_ok) = lex(input, buffer_size as usize);
+    assert_eq!(whole_ok, split_ok);
+    if whole_ok {
+        assert_eq!(whole.0, split.0);
+    }
+});
@@ -0,0 +1,26 @@
+// Unfolds arbitrary input in chunks of a size given by the first byte and checks that the output
+// is the same as when it is unfolded in one go.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ltools::loc::{LocWriteWrapper, WriteLocWrapper};
+use ltools::unfold::Unfolder;
+use std::io::Write;
+
+fn unfold(input: &[u8], chunk_size: usize) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut unfolder = WriteLocWrapper::new(Unfolder::new(LocWriteWrapper::new(&mut output)));
+    for chunk in input.chunks(chunk_size.max(1)) {
+        unfolder.write_all(chunk).unwrap();
+    }
+    unfolder.flush().unwrap();
+    drop(unfolder);
+    output
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, input)) = data.split_first() else {
+        return;
+    };
+    assert_eq!(unfold(input, input.len()), unfold(input, chunk_size as usize));
+});
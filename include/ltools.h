@@ -0,0 +1,42 @@
+/* C declarations for the ltools LDIF parser, in the libltools shared library built by capi/. See src/capi.rs. */
+#ifndef LTOOLS_H
+#define LTOOLS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ltools_parser ltools_parser;
+
+/* Called for every value of an entry, the DN first. Returning nonzero stops parsing. */
+typedef int (*ltools_attribute_callback)(void *userdata, const char *name, const uint8_t *value, size_t value_len);
+/* Called after the last value of an entry. Returning nonzero stops parsing. */
+typedef int (*ltools_entry_callback)(void *userdata);
+
+ltools_parser *ltools_parse_begin(ltools_attribute_callback on_attribute, ltools_entry_callback on_entry, void *userdata);
+int ltools_feed(ltools_parser *parser, const uint8_t *buf, size_t len);
+int ltools_parse_end(ltools_parser *parser);
+const char *ltools_error(const ltools_parser *parser);
+void ltools_free(ltools_parser *parser);
+
+typedef struct ltools_filter ltools_filter;
+typedef struct ltools_entry ltools_entry;
+
+/* Returns NULL if the filter is invalid. */
+ltools_filter *ltools_filter_parse(const char *text);
+/* Returns 1 if the entry matches the filter and 0 otherwise. */
+int ltools_filter_match(const ltools_filter *filter, const ltools_entry *entry);
+void ltools_filter_free(ltools_filter *filter);
+
+ltools_entry *ltools_entry_new(void);
+void ltools_entry_add_value(ltools_entry *entry, const char *name, const uint8_t *value, size_t value_len);
+void ltools_entry_free(ltools_entry *entry);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,4 @@
+//! The `ltools` Python module, which is defined in `ltools::python`. Build and install it with
+//! `maturin develop --release` in this directory, or a wheel with `maturin build --release`.
+
+pub use ltools::python::*;
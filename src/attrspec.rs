@@ -2,19 +2,48 @@ use std::borrow::Cow;
 use nom::Err;
 use nom::sequence::terminated;
 use nom::combinator::eof;
-use crate::entry::EntryValue;
+use crate::entry::{is_pseudo_attribute, Entry, EntryValue};
 use crate::base64::EncodeWriter;
+use crate::xml;
+use std::collections::HashSet;
 use std::ops::Deref;
 use std::io::Write;
+#[cfg(feature = "passwd")]
+use crate::passwd::{hash_password, PasswordScheme};
+#[cfg(feature = "encrypt")]
+use crate::crypt::ValueKey;
 
+#[derive(Clone)]
 pub struct AttrSpec {
     pub attribute: String, // in original case
     pub attribute_lowercase: String,
     pub value_filters: Vec<ValueFilter>,
+    pub exclude: bool, // for exclusions such as !userPassword
 }
 
 impl AttrSpec {
+    /// Parses an attribute specification such as `mail.uniq` or `manager:-none`. In the default
+    /// after `:-`, a backslash followed by two hex digits stands for that byte, as `lescape`
+    /// writes it, so that `:-\00` gives a NUL byte. Other backslashes are taken as they are.
+    ///
+    /// The value filters are applied from left to right, and the default takes the rest of the
+    /// specification, so that `cn:-none.hex` has the default `none.hex` and `cn.hex:-none` is
+    /// the hex-encoded values or `none`. An attribute whose name collides with this syntax,
+    /// such as one named `cn.hex`, is written in double quotes, as in `"cn.hex".base64`. In
+    /// quotes, a backslash followed by two hex digits stands for that byte, and followed by
+    /// another character, for that character, so that `\"` and `\\` stand for `"` and `\`.
+    ///
+    /// The keys of `.encrypt` and `.decrypt` filters are loaded once the specification has been
+    /// parsed, and an error is returned if one can't be.
     pub fn parse(input: &str) -> std::io::Result<AttrSpec> {
+        let mut attrspec = AttrSpec::parse_syntax(input)?;
+        attrspec.load_keys()?;
+        Ok(attrspec)
+    }
+
+    /// Parses an attribute specification like `parse`, but without loading the keys of
+    /// `.encrypt` and `.decrypt` filters, for when only the syntax or the attribute matters.
+    pub fn parse_syntax(input: &str) -> std::io::Result<AttrSpec> {
         let iresult = terminated(parser::attr_spec, eof)(input)
             .map(|(_, spec)| spec);
         match iresult {
@@ -24,8 +53,7 @@ impl AttrSpec {
                     Err::Failure(e) => e.input,
                     Err::Incomplete(_) => unreachable!("unreachable"),
                 };
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                Err(std::io::Error::other(
                     format!("Failed to parse '{}' starting at '{}'", input, parser_location),
                 ))
             },
@@ -33,30 +61,166 @@ impl AttrSpec {
         }
     }
 
-    pub fn filter_values<'a, 'b>(&'a self, values: impl Iterator<Item = &'b [u8]>) -> Cow<Vec<EntryValue<'b>>> {
-        let values: Vec<EntryValue<'b>> = values.map(|value: &[u8]| Cow::Owned(Vec::from(value))).collect();
-        let mut values: Cow<Vec<EntryValue<'b>>> = Cow::Owned(values);
+    /// Loads the keys of the `.encrypt` and `.decrypt` filters from the environment.
+    pub fn load_keys(&mut self) -> std::io::Result<()> {
+        #[cfg(feature = "encrypt")]
+        for filter in self.value_filters.iter_mut() {
+            if let ValueFilter::Encrypt(key) | ValueFilter::Decrypt(key) = filter {
+                key.load()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses an attribute specification whose default value may contain any byte, such as NUL
+    /// or bytes that aren't UTF-8, without them having to be escaped.
+    pub fn parse_bytes(input: &[u8]) -> std::io::Result<AttrSpec> {
+        let mut escaped = String::with_capacity(input.len());
+        for c in input.iter().copied() {
+            if !c.is_ascii() || c == b'\0' {
+                escaped.push_str(&format!("\\{c:02x}"));
+            } else {
+                escaped.push(c as char);
+            }
+        }
+        AttrSpec::parse(&escaped)
+    }
+
+    /// Tells whether this is a wildcard such as `*` or `dirxml-*`, which stands for every
+    /// attribute with the given prefix that isn't given by another attribute specification.
+    pub fn is_wildcard(&self) -> bool {
+        self.attribute.ends_with('*')
+    }
+
+    /// Tells whether the attribute, or the attribute pattern in the case of wildcards, matches
+    /// the given lowercase attribute type name.
+    pub fn matches(&self, attribute_lowercase: &str) -> bool {
+        match self.attribute_lowercase.strip_suffix('*') {
+            Some(prefix) => attribute_lowercase.starts_with(prefix),
+            None => self.attribute_lowercase == attribute_lowercase,
+        }
+    }
+
+    /// Applies the value filters to the given values. Values are borrowed unless a filter
+    /// transforms them. Fails if a filter does, such as `.decrypt` on a value that has been
+    /// tampered with.
+    pub fn filter_values<'a, 'b>(&'a self, values: impl Iterator<Item = &'b [u8]>) -> std::io::Result<Cow<'a, [EntryValue<'b>]>> {
+        self.apply_filters(values.map(Cow::Borrowed).collect())
+    }
+
+    /// Applies the value filters to the values of the attribute in the entry, including
+    /// pseudo-attributes such as `@line`.
+    pub fn entry_values<'a, 'b>(&'a self, entry: &'b Entry) -> std::io::Result<Cow<'a, [EntryValue<'b>]>> {
+        if self.is_pseudo() {
+            let values = entry.pseudo_value(&self.attribute_lowercase).map(Cow::Owned);
+            return self.apply_filters(values.into_iter().collect());
+        }
+        self.filter_values(entry.get(&self.attribute_lowercase))
+    }
+
+    fn apply_filters<'a, 'b>(&'a self, values: Vec<EntryValue<'b>>) -> std::io::Result<Cow<'a, [EntryValue<'b>]>> {
+        let mut values: Cow<[EntryValue<'b>]> = Cow::Owned(values);
         for filter in self.value_filters.iter() {
-            values = filter.filter_values(values);
+            values = filter.filter_values(values)?;
         }
-        values
+        Ok(values)
+    }
+
+    /// Tells whether this is a pseudo-attribute such as `@line`, which isn't read from the input.
+    pub fn is_pseudo(&self) -> bool {
+        is_pseudo_attribute(&self.attribute)
     }
 }
 
+/// Replaces wildcards with a specification for each of the given attributes that matches the
+/// wildcard and isn't named by another specification or excluded. The expanded specifications keep
+/// the value filters of the wildcard, and exclusions are left out.
+pub fn expand_wildcards<S: AsRef<str>>(attrspecs: &[AttrSpec], attributes: &[S]) -> Vec<AttrSpec> {
+    let is_named = |attribute_lowercase: &str| attrspecs.iter()
+        .any(|attrspec| !attrspec.exclude && !attrspec.is_wildcard() && attrspec.attribute_lowercase == attribute_lowercase);
+    let is_excluded = |attribute_lowercase: &str| attrspecs.iter()
+        .any(|attrspec| attrspec.exclude && attrspec.matches(attribute_lowercase));
+    let mut expanded: Vec<AttrSpec> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new(); // attributes expanded from earlier wildcards
+    for attrspec in attrspecs.iter() {
+        if attrspec.exclude {
+            continue;
+        }
+        if !attrspec.is_wildcard() {
+            expanded.push(attrspec.clone());
+            continue;
+        }
+        for attribute in attributes.iter() {
+            let attribute = attribute.as_ref();
+            let attribute_lowercase = attribute.to_ascii_lowercase();
+            if !attrspec.matches(&attribute_lowercase)
+                || is_named(&attribute_lowercase)
+                || is_excluded(&attribute_lowercase)
+                || !seen.insert(attribute_lowercase.clone())
+            {
+                continue;
+            }
+            expanded.push(AttrSpec{
+                attribute: attribute.to_string(),
+                attribute_lowercase,
+                value_filters: attrspec.value_filters.clone(),
+                exclude: false,
+            });
+        }
+    }
+    expanded
+}
+
+#[derive(Clone)]
 pub enum ValueFilter {
     NullCoalesce(Vec<EntryValue<'static>>), // static because values are never borrowed
     Base64,
     Hex,
+    Uniq, // drops repeated values, keeping the first
+    XmlPretty,
+    XmlStrip,
+    #[cfg(feature = "passwd")]
+    HashPassword(PasswordScheme), // such as .ssha, for preparing userPassword values
+    #[cfg(feature = "encrypt")]
+    Encrypt(FilterKey),
+    #[cfg(feature = "encrypt")]
+    Decrypt(FilterKey), // leaves values that aren't encrypted with the key as they are
+}
+
+/// The key of an `.encrypt` or `.decrypt` filter. Parsing only reads the key ID, and
+/// `AttrSpec::load_keys` loads the key itself.
+#[cfg(feature = "encrypt")]
+#[derive(Clone)]
+pub enum FilterKey {
+    Id(String),
+    Loaded(Box<ValueKey>),
+}
+
+#[cfg(feature = "encrypt")]
+impl FilterKey {
+    fn load(&mut self) -> std::io::Result<()> {
+        if let FilterKey::Id(id) = self {
+            *self = FilterKey::Loaded(Box::new(ValueKey::load(id)?));
+        }
+        Ok(())
+    }
+
+    fn loaded(&self) -> std::io::Result<&ValueKey> {
+        match self {
+            FilterKey::Loaded(key) => Ok(key.as_ref()),
+            FilterKey::Id(id) => Err(std::io::Error::other(format!("key '{}' hasn't been loaded", id))),
+        }
+    }
 }
 
 impl ValueFilter {
-    pub fn filter_values<'a, 'b, 'c>(&'a self, values: Cow<'b, Vec<EntryValue<'c>>>) -> Cow<'b, Vec<EntryValue<'c>>>
+    pub fn filter_values<'a, 'b, 'c>(&'a self, values: Cow<'b, [EntryValue<'c>]>) -> std::io::Result<Cow<'b, [EntryValue<'c>]>>
         where 'a: 'b
     {
-        match self {
+        let filtered = match self {
             ValueFilter::NullCoalesce(default_values) => {
                 if values.is_empty() {
-                    Cow::Borrowed(default_values)
+                    Cow::Borrowed(&default_values[..])
                 } else {
                     values
                 }
@@ -66,10 +230,10 @@ impl ValueFilter {
                     values.deref().iter().map(|value| {
                         let mut buf: Vec<u8> = Vec::new();
                         let mut base64encoder = EncodeWriter::new(&mut buf);
-                        base64encoder.write_all(&value.deref()[..]).unwrap();
+                        base64encoder.write_all(value).unwrap();
                         base64encoder.flush().unwrap();
                         Cow::Owned(buf)
-                    }).collect()
+                    }).collect::<Vec<EntryValue>>()
                 )
             },
             ValueFilter::Hex => {
@@ -80,10 +244,60 @@ impl ValueFilter {
                             _ = write!(&mut buf, "{:02x}", byte);
                         }
                         Cow::Owned(buf)
-                    }).collect()
+                    }).collect::<Vec<EntryValue>>()
                 )
             },
-        }
+            ValueFilter::Uniq => {
+                let mut seen: HashSet<&[u8]> = HashSet::new();
+                let unique: Vec<EntryValue<'c>> = values.iter()
+                    .filter(|value| seen.insert(value))
+                    .cloned()
+                    .collect();
+                Cow::Owned(unique)
+            },
+            ValueFilter::XmlPretty | ValueFilter::XmlStrip => {
+                let reformat = if matches!(self, ValueFilter::XmlPretty) { xml::pretty } else { xml::strip };
+                Cow::Owned(
+                    values.deref().iter().map(|value| {
+                        // values that aren't UTF-8 or don't look like XML are left as they are
+                        match std::str::from_utf8(value).ok().and_then(reformat) {
+                            Some(reformatted) => Cow::Owned(reformatted.into_bytes()),
+                            None => value.clone(),
+                        }
+                    }).collect::<Vec<EntryValue>>()
+                )
+            },
+            #[cfg(feature = "passwd")]
+            ValueFilter::HashPassword(scheme) => {
+                Cow::Owned(
+                    values.deref().iter()
+                        .map(|value| hash_password(value, *scheme).map(Cow::Owned))
+                        .collect::<std::io::Result<Vec<EntryValue>>>()?
+                )
+            },
+            #[cfg(feature = "encrypt")]
+            ValueFilter::Encrypt(key) => {
+                let key = key.loaded()?;
+                Cow::Owned(
+                    values.deref().iter()
+                        .map(|value| key.encrypt(value).map(Cow::Owned))
+                        .collect::<std::io::Result<Vec<EntryValue>>>()?
+                )
+            },
+            #[cfg(feature = "encrypt")]
+            ValueFilter::Decrypt(key) => {
+                let key = key.loaded()?;
+                Cow::Owned(
+                    values.deref().iter()
+                        .map(|value| Ok(match key.decrypt(value)? {
+                            Some(decrypted) => Cow::Owned(decrypted),
+                            None => value.clone(),
+                        }))
+                        .collect::<std::io::Result<Vec<EntryValue>>>()?
+                )
+            },
+        };
+        Ok(filtered)
     }
 }
 
@@ -91,31 +305,89 @@ mod parser {
     use super::*;
     use nom::{
         IResult,
-        combinator::map,
+        combinator::{ map, map_res, opt, verify },
         multi::{ fold_many0, many0 },
         branch::alt,
-        sequence::{ pair, preceded },
+        sequence::{ delimited, pair, preceded },
         bytes::complete::{ tag, take_while },
         character::complete::{
+            anychar,
             satisfy,
             digit1,
             char,
+            none_of,
         },
     };
 
+    #[cfg(feature = "encrypt")]
+    use nom::bytes::complete::take_while1;
+
     pub(super) fn attr_spec(input: &str) -> IResult<&str, AttrSpec> {
+        alt((exclusion, inclusion))(input)
+    }
+
+    fn inclusion(input: &str) -> IResult<&str, AttrSpec> {
         map(
             pair(attribute, many0(value_filter)),
             |(attribute, value_filters)| AttrSpec{
                 attribute_lowercase: attribute.to_ascii_lowercase(),
                 attribute,
-                value_filters
+                value_filters,
+                exclude: false,
+            },
+        )(input)
+    }
+
+    fn exclusion(input: &str) -> IResult<&str, AttrSpec> {
+        map(
+            preceded(char('!'), attribute),
+            |attribute| AttrSpec{
+                attribute_lowercase: attribute.to_ascii_lowercase(),
+                attribute,
+                value_filters: Vec::new(),
+                exclude: true,
             },
         )(input)
     }
 
     fn attribute(input: &str) -> IResult<&str, String> {
-        alt((attribute_name, attribute_oid))(input)
+        alt((quoted_attribute, pseudo_attribute, attribute_pattern, attribute_oid, wildcard))(input)
+    }
+
+    // Any name in double quotes, such as "cn.hex", except for those that would be taken as a
+    // pseudo-attribute or a wildcard.
+    fn quoted_attribute(input: &str) -> IResult<&str, String> {
+        let quoted_byte = alt((
+            map(
+                preceded(char('\\'), pair(satisfy(|c| c.is_ascii_hexdigit()), satisfy(|c| c.is_ascii_hexdigit()))),
+                |(high, low)| vec![(high.to_digit(16).unwrap_or(0) * 16 + low.to_digit(16).unwrap_or(0)) as u8],
+            ),
+            map(alt((preceded(char('\\'), anychar), none_of("\"\\"))), |c| c.to_string().into_bytes()),
+        ));
+        verify(
+            map_res(
+                delimited(char('"'), fold_many0(quoted_byte, Vec::new, |mut name, bytes| { name.extend(bytes); name }), char('"')),
+                String::from_utf8,
+            ),
+            |name: &str| !name.is_empty() && !name.starts_with('@') && !name.ends_with('*'),
+        )(input)
+    }
+
+    // such as @line
+    fn pseudo_attribute(input: &str) -> IResult<&str, String> {
+        map(preceded(char('@'), attribute_name), |name| String::from("@") + &name)(input)
+    }
+
+    fn wildcard(input: &str) -> IResult<&str, String> {
+        map(tag("*"), String::from)(input)
+    }
+
+    // attribute type name optionally followed by a wildcard, such as dirxml-*
+    fn attribute_pattern(input: &str) -> IResult<&str, String> {
+        map(
+            pair(attribute_name, opt(wildcard)),
+            |(name, wildcard)| name + wildcard.as_deref().unwrap_or(""),
+        )(input)
     }
 
     fn attribute_name(input: &str) -> IResult<&str, String> {
@@ -140,16 +412,37 @@ mod parser {
     }
 
     fn value_filter(input: &str) -> IResult<&str, ValueFilter> {
-        alt((null_coalesce, base64, hex))(input)
+        alt((null_coalesce, base64, hex, uniq, xml_pretty, xml_strip, hash_password, crypt))(input)
     }
 
     fn null_coalesce(input: &str) -> IResult<&str, ValueFilter> {
         map(
             preceded(tag(":-"), take_while(|_| true)),
-            |value: &str| ValueFilter::NullCoalesce(vec![Cow::Owned(value.into())]),
+            |value: &str| ValueFilter::NullCoalesce(vec![Cow::Owned(unescape_default(value))]),
         )(input)
     }
 
+    // Replaces each backslash followed by two hex digits with the byte they stand for.
+    fn unescape_default(value: &str) -> Vec<u8> {
+        let bytes = value.as_bytes();
+        let mut unescaped: Vec<u8> = Vec::with_capacity(bytes.len());
+        let mut i: usize = 0;
+        while i < bytes.len() {
+            match bytes[i..] {
+                [b'\\', high, low, ..] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                    let digit = |c: u8| (c as char).to_digit(16).unwrap_or(0) as u8;
+                    unescaped.push(digit(high) * 16 + digit(low));
+                    i += 3;
+                },
+                _ => {
+                    unescaped.push(bytes[i]);
+                    i += 1;
+                },
+            }
+        }
+        unescaped
+    }
+
     fn base64(input: &str) -> IResult<&str, ValueFilter> {
         map(tag(".base64"), |_| ValueFilter::Base64)(input)
     }
@@ -158,6 +451,53 @@ mod parser {
         map(tag(".hex"), |_| ValueFilter::Hex)(input)
     }
 
+    fn uniq(input: &str) -> IResult<&str, ValueFilter> {
+        map(tag(".uniq"), |_| ValueFilter::Uniq)(input)
+    }
+
+    fn xml_pretty(input: &str) -> IResult<&str, ValueFilter> {
+        map(tag(".xmlpretty"), |_| ValueFilter::XmlPretty)(input)
+    }
+
+    fn xml_strip(input: &str) -> IResult<&str, ValueFilter> {
+        map(tag(".xmlstrip"), |_| ValueFilter::XmlStrip)(input)
+    }
+
+    #[cfg(feature = "passwd")]
+    fn hash_password(input: &str) -> IResult<&str, ValueFilter> {
+        alt((
+            map(tag(".ssha"), |_| ValueFilter::HashPassword(PasswordScheme::Ssha)),
+            map(tag(".argon2"), |_| ValueFilter::HashPassword(PasswordScheme::Argon2)),
+        ))(input)
+    }
+
+    #[cfg(not(feature = "passwd"))]
+    fn hash_password(input: &str) -> IResult<&str, ValueFilter> {
+        Err(Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)))
+    }
+
+    #[cfg(feature = "encrypt")]
+    fn crypt(input: &str) -> IResult<&str, ValueFilter> {
+        alt((
+            map(key_id(".encrypt("), |id| ValueFilter::Encrypt(FilterKey::Id(id.to_string()))),
+            map(key_id(".decrypt("), |id| ValueFilter::Decrypt(FilterKey::Id(id.to_string()))),
+        ))(input)
+    }
+
+    #[cfg(feature = "encrypt")]
+    fn key_id<'a>(prefix: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+        delimited(
+            tag(prefix),
+            take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            char(')'),
+        )
+    }
+
+    #[cfg(not(feature = "encrypt"))]
+    fn crypt(input: &str) -> IResult<&str, ValueFilter> {
+        Err(Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)))
+    }
+
 }
 
 #[cfg(test)]
@@ -169,4 +509,122 @@ mod test {
         let result = AttrSpec::parse("#");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_expand_wildcards() -> std::io::Result<()> {
+        let attrspecs = [AttrSpec::parse("DN")?, AttrSpec::parse("*.hex")?];
+        let attributes = [String::from("dn"), String::from("objectClass"), String::from("cn")];
+        let expanded = expand_wildcards(&attrspecs, &attributes);
+        let names: Vec<&str> = expanded.iter().map(|attrspec| attrspec.attribute.as_str()).collect();
+        assert_eq!(names, ["DN", "objectClass", "cn"]);
+        assert!(expanded[0].value_filters.is_empty());
+        assert!(matches!(expanded[1].value_filters[..], [ValueFilter::Hex]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_patterns() -> std::io::Result<()> {
+        let attrspecs = [AttrSpec::parse("DirXML-*")?, AttrSpec::parse("!dirxml-Associations")?, AttrSpec::parse("*")?, AttrSpec::parse("!userPassword")?];
+        assert!(attrspecs[0].is_wildcard() && attrspecs[0].matches("dirxml-state"));
+        assert!(attrspecs[1].exclude && !attrspecs[1].is_wildcard());
+        let attributes = ["dn", "DirXML-State", "DirXML-Associations", "userPassword", "cn"];
+        let expanded = expand_wildcards(&attrspecs, &attributes);
+        let names: Vec<&str> = expanded.iter().map(|attrspec| attrspec.attribute.as_str()).collect();
+        assert_eq!(names, ["DirXML-State", "dn", "cn"]);
+        assert!(AttrSpec::parse("!cn.hex").is_err());
+        assert!(AttrSpec::parse("*cn").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_bytes() -> std::io::Result<()> {
+        let no_values: [&[u8]; 0] = [];
+        let defaults = |attrspec: AttrSpec| attrspec.filter_values(no_values.into_iter()).unwrap().into_owned();
+        assert_eq!(defaults(AttrSpec::parse("cn:-a\\00b\\c3\\a6")?), [Cow::Borrowed("a\0bæ".as_bytes())]);
+        assert_eq!(defaults(AttrSpec::parse("path:-C:\\dir\\x")?), [Cow::Borrowed(b"C:\\dir\\x".as_slice())]);
+        assert_eq!(defaults(AttrSpec::parse_bytes(b"cn:-\0\xff\\5c")?), [Cow::Borrowed(b"\0\xff\\".as_slice())]);
+        assert!(AttrSpec::parse_bytes(b"c\xffn").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pseudo_attribute() -> std::io::Result<()> {
+        let attrspec = AttrSpec::parse("@Line:-0")?;
+        assert!(attrspec.is_pseudo());
+        let mut entry = Entry::from([("dn", b"cn=foo".as_slice())]);
+        assert_eq!(attrspec.entry_values(&entry)?.to_vec(), [b"0".as_slice()]);
+        entry.set_provenance(Some(crate::entry::Provenance{ file: None, line: 7, ordinal: 1 }));
+        assert_eq!(attrspec.entry_values(&entry)?.to_vec(), [b"7".as_slice()]);
+        assert!(AttrSpec::parse("@").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_attribute() -> std::io::Result<()> {
+        let attrspec = AttrSpec::parse("\"Weird.hex\".hex:-none")?;
+        assert_eq!(attrspec.attribute, "Weird.hex");
+        assert_eq!(attrspec.attribute_lowercase, "weird.hex");
+        let entry = Entry::from([("dn", b"cn=foo".as_slice()), ("weird.hex", b"a")]);
+        assert_eq!(attrspec.entry_values(&entry)?.to_vec(), [b"61".as_slice()]);
+        assert_eq!(AttrSpec::parse("\"a\\\"b\\\\c\\2e\"")?.attribute, "a\"b\\c.");
+        assert_eq!(AttrSpec::parse_bytes("\"bjørn\"".as_bytes())?.attribute, "bjørn");
+        assert!(AttrSpec::parse("!\"cn.hex\"")?.exclude);
+        assert_eq!(AttrSpec::parse("cn:-\"none\".hex")?.value_filters.len(), 1);
+        assert!(AttrSpec::parse("\"\"").is_err());
+        assert!(AttrSpec::parse("\"@line\"").is_err());
+        assert!(AttrSpec::parse("\"cn*\"").is_err());
+        assert!(AttrSpec::parse("\"cn").is_err());
+        assert!(AttrSpec::parse("\"\\ff\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_uniq() -> std::io::Result<()> {
+        let attrspec = AttrSpec::parse("mail.uniq")?;
+        let values: [&[u8]; 3] = [b"a@example.com", b"b@example.com", b"a@example.com"];
+        let filtered = attrspec.filter_values(values.into_iter())?;
+        assert_eq!(filtered.as_ref(), [b"a@example.com".as_slice(), b"b@example.com"].map(Cow::Borrowed));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml() -> std::io::Result<()> {
+        let attrspec = AttrSpec::parse("DirXML-ConfigValues.xmlstrip")?;
+        let values: [&[u8]; 2] = [b"<a>\n  <b>x</b>\n</a>", b"\xff<a>"];
+        let filtered = attrspec.filter_values(values.into_iter())?;
+        assert_eq!(filtered.as_ref(), [b"<a><b>x</b></a>".as_slice(), b"\xff<a>"].map(Cow::Borrowed));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "passwd")]
+    fn test_hash_password() -> std::io::Result<()> {
+        let attrspec = AttrSpec::parse("userPassword.ssha")?;
+        let values: [&[u8]; 1] = [b"secret"];
+        let filtered = attrspec.filter_values(values.into_iter())?;
+        assert_eq!(crate::passwd::verify_password(&filtered[0], b"secret"), Some(true));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "encrypt")]
+    fn test_crypt() -> std::io::Result<()> {
+        std::env::set_var("LTOOLS_KEY_ATTRSPEC_TEST", "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=");
+        let encrypt = AttrSpec::parse("employeeNumber.encrypt(attrspec-test)")?;
+        let decrypt = AttrSpec::parse("employeeNumber.decrypt(attrspec-test)")?;
+        let values: [&[u8]; 1] = [b"1234"];
+        let encrypted = encrypt.filter_values(values.into_iter())?;
+        assert!(encrypted[0].starts_with(b"{AESGCM:attrspec-test}"));
+        let values: [&[u8]; 2] = [&encrypted[0], b"5678"];
+        let decrypted = decrypt.filter_values(values.into_iter())?;
+        assert_eq!(decrypted.as_ref(), [b"1234".as_slice(), b"5678"].map(Cow::Borrowed));
+        let values: [&[u8]; 1] = [b"{AESGCM:attrspec-test}AAAAAAAAAAAAAAAAAAAAAAAA"];
+        assert!(decrypt.filter_values(values.into_iter()).is_err());
+        let result = AttrSpec::parse("employeeNumber.decrypt(attrspec-missing)");
+        assert!(result.is_err_and(|err| err.to_string().contains("LTOOLS_KEY_ATTRSPEC_MISSING")));
+        let mut attrspec = AttrSpec::parse_syntax("employeeNumber.decrypt(attrspec-missing)")?;
+        assert!(attrspec.filter_values(values.into_iter()).is_err());
+        assert!(attrspec.load_keys().is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,270 @@
+use crate::base64::DecodeState;
+use crate::changerecord::{ModRdnChangeRecord, ModifyChangeRecord, ModifyChangeRecordOp, ModifyChangeRecordOpType, WriteChangeRecord};
+use crate::entry::{Entry, OwnedEntry};
+use std::io::{Error, ErrorKind, Result};
+
+/// The formats of directory audit logs that can be turned into change records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditFormat {
+    /// The entries of the OpenLDAP accesslog overlay, with reqType, reqDN and reqMod.
+    AccessLog,
+    /// The entries of the retro changelog of 389 Directory Server and its relatives, with
+    /// changeType, targetDn and changes.
+    RetroChangelog,
+}
+
+impl AuditFormat {
+    /// Tells which format an entry is in, if any.
+    pub fn detect(entry: &Entry) -> Option<AuditFormat> {
+        if entry.get_one("reqtype").is_some() && entry.get_one("reqdn").is_some() {
+            Some(AuditFormat::AccessLog)
+        } else if entry.get_one("changetype").is_some() && entry.get_one("targetdn").is_some() {
+            Some(AuditFormat::RetroChangelog)
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes the change that an audit log entry records as a change record. Returns false without
+/// writing anything if the entry is not in a known audit log format, or records an operation
+/// that doesn't change anything, such as a search, or that failed.
+pub fn write_change<W: WriteChangeRecord>(entry: &Entry, dest: &mut W) -> Result<bool> {
+    match AuditFormat::detect(entry) {
+        Some(AuditFormat::AccessLog) => write_access_log_change(entry, dest),
+        Some(AuditFormat::RetroChangelog) => write_retro_changelog_change(entry, dest),
+        None => Ok(false),
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn get_str<'a>(entry: &'a Entry, attr: &str) -> Result<std::borrow::Cow<'a, str>> {
+    entry.get_one_str(attr).ok_or_else(|| invalid(format!("audit log entry lacks {}", attr)))
+}
+
+fn is_true(value: Option<&[u8]>) -> bool {
+    matches!(value, Some(value) if value.eq_ignore_ascii_case(b"true") || value == b"1")
+}
+
+fn write_access_log_change<W: WriteChangeRecord>(entry: &Entry, dest: &mut W) -> Result<bool> {
+    if entry.get_one("reqresult").is_some_and(|result| result != b"0") {
+        return Ok(false);
+    }
+    let dn = get_str(entry, "reqdn")?;
+    let mods: Vec<(&str, u8, Option<&[u8]>)> = entry.get("reqmod")
+        .map(parse_req_mod)
+        .collect::<Result<_>>()?;
+    match get_str(entry, "reqtype")?.to_ascii_lowercase().as_str() {
+        "add" => {
+            let mut added = OwnedEntry::default();
+            added.add_value("dn", dn.as_bytes());
+            for (attr, _, value) in mods.iter() {
+                if let Some(value) = value {
+                    added.add_value(attr, value);
+                }
+            }
+            dest.write_add(&added)?;
+        },
+        "delete" => dest.write_delete(&dn)?,
+        "modify" => {
+            let mut modify = ModifyChangeRecord{
+                dn: dn.into_owned(),
+                ops: Vec::new(),
+            };
+            for (attr, op, value) in mods {
+                let typ = match op {
+                    b'+' => ModifyChangeRecordOpType::Add,
+                    b'-' => ModifyChangeRecordOpType::Delete,
+                    b'=' => ModifyChangeRecordOpType::Replace,
+                    _ => return Err(invalid(format!("unsupported modification {}:{} of {}", attr, op as char, modify.dn))),
+                };
+                // consecutive values of the same modification make up a single operation
+                let same_op = modify.ops.last_mut()
+                    .filter(|last| last.attr == attr && std::mem::discriminant(&last.typ) == std::mem::discriminant(&typ));
+                match same_op {
+                    Some(last) => last.values.extend(value),
+                    None => modify.ops.push(ModifyChangeRecordOp{
+                        typ,
+                        attr: attr.to_string(),
+                        values: value.into_iter().collect(),
+                    }),
+                }
+            }
+            dest.write_modify(&modify)?;
+        },
+        "modrdn" => {
+            let new_rdn = get_str(entry, "reqnewrdn")?;
+            let new_superior = entry.get_one_str("reqnewsuperior");
+            dest.write_modrdn(&ModRdnChangeRecord{
+                dn: &dn,
+                new_rdn: &new_rdn,
+                delete_old_rdn: is_true(entry.get_one("reqdeleteoldrdn")),
+                new_superior: new_superior.as_deref(),
+            })?;
+        },
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// Parses a reqMod value such as `mail:+ alice@example.com` into the attribute, the operation and
+// the value, which is left out when the whole attribute is deleted or replaced, as in `mail:-`.
+fn parse_req_mod(req_mod: &[u8]) -> Result<(&str, u8, Option<&[u8]>)> {
+    let malformed = || invalid(format!("malformed reqMod value {}", String::from_utf8_lossy(req_mod)));
+    let colon = req_mod.iter().position(|c| *c == b':').ok_or_else(malformed)?;
+    let attr = std::str::from_utf8(&req_mod[..colon]).map_err(|_| malformed())?;
+    let op = *req_mod.get(colon + 1).ok_or_else(malformed)?;
+    let value = match &req_mod[colon + 2..] {
+        [] => None,
+        [b' ', value @ ..] => Some(value),
+        _ => return Err(malformed()),
+    };
+    Ok((attr, op, value))
+}
+
+fn write_retro_changelog_change<W: WriteChangeRecord>(entry: &Entry, dest: &mut W) -> Result<bool> {
+    let dn = get_str(entry, "targetdn")?;
+    let changes = parse_changes(entry.get_one("changes").unwrap_or_default())?;
+    match get_str(entry, "changetype")?.to_ascii_lowercase().as_str() {
+        "add" => {
+            let mut added = OwnedEntry::default();
+            added.add_value("dn", dn.as_bytes());
+            for (attr, value) in changes.iter() {
+                added.add_value(attr, value);
+            }
+            dest.write_add(&added)?;
+        },
+        "delete" => dest.write_delete(&dn)?,
+        "modify" => {
+            let mut modify = ModifyChangeRecord{
+                dn: dn.into_owned(),
+                ops: Vec::new(),
+            };
+            let mut in_op = false;
+            for (attr, value) in changes.iter() {
+                if attr == "-" {
+                    in_op = false;
+                } else if in_op {
+                    if let Some(op) = modify.ops.last_mut() {
+                        op.values.push(value);
+                    }
+                } else {
+                    let typ = match attr.to_ascii_lowercase().as_str() {
+                        "add" => ModifyChangeRecordOpType::Add,
+                        "delete" => ModifyChangeRecordOpType::Delete,
+                        "replace" => ModifyChangeRecordOpType::Replace,
+                        _ => return Err(invalid(format!("unexpected {} in the changes of {}", attr, modify.dn))),
+                    };
+                    modify.ops.push(ModifyChangeRecordOp{
+                        typ,
+                        attr: String::from_utf8_lossy(value).into_owned(),
+                        values: Vec::new(),
+                    });
+                    in_op = true;
+                }
+            }
+            dest.write_modify(&modify)?;
+        },
+        "modrdn" => {
+            let new_rdn = get_str(entry, "newrdn")?;
+            let new_superior = entry.get_one_str("newsuperior");
+            dest.write_modrdn(&ModRdnChangeRecord{
+                dn: &dn,
+                new_rdn: &new_rdn,
+                delete_old_rdn: is_true(entry.get_one("deleteoldrdn")),
+                new_superior: new_superior.as_deref(),
+            })?;
+        },
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// Parses the LDIF fragment of a changes value into attributes and values, with the "-" lines
+// that end the operations of a modify given as "-" attributes without a value.
+fn parse_changes(changes: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let text = String::from_utf8_lossy(changes);
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n').map(|line| line.trim_end_matches(['\r', '\0'])) {
+        match (line.strip_prefix(' '), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ if line.is_empty() => {},
+            _ => lines.push(line.to_string()),
+        }
+    }
+    let mut parsed: Vec<(String, Vec<u8>)> = Vec::new();
+    for line in lines {
+        if line == "-" {
+            parsed.push((line, Vec::new()));
+            continue;
+        }
+        let Some((attr, value)) = line.split_once(':') else {
+            return Err(invalid(format!("malformed line in changes: {}", line)));
+        };
+        let value = match value.strip_prefix(':') {
+            Some(encoded) => {
+                let mut decoded: Vec<u8> = Vec::new();
+                let mut state = DecodeState::default();
+                state.decode(encoded.trim().as_bytes(), &mut decoded)
+                    .and_then(|_| state.finish())
+                    .map_err(|_| invalid(format!("invalid base64 in changes: {}", line)))?;
+                decoded
+            },
+            None => value.strip_prefix(' ').unwrap_or(value).as_bytes().to_vec(),
+        };
+        parsed.push((attr.to_string(), value));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bulk::parse_entries;
+
+    fn convert(ldif: &str) -> Result<String> {
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        parse_entries(ldif.as_bytes(), &mut entries)?;
+        let mut output: Vec<u8> = Vec::new();
+        for entry in entries.iter() {
+            write_change(entry, &mut output)?;
+        }
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[test]
+    fn test_access_log() -> Result<()> {
+        assert_eq!(convert(concat!(
+            "dn: reqStart=20240101000000.000001Z,cn=accesslog\nobjectClass: auditModify\n",
+            "reqType: modify\nreqDN: uid=alice,dc=example\nreqResult: 0\n",
+            "reqMod: mail:+ a@example.com\nreqMod: mail:+ b@example.com\nreqMod: title:-\nreqMod: sn:= Smith\n\n",
+            "dn: reqStart=20240101000000.000002Z,cn=accesslog\nreqType: delete\nreqDN: uid=bob,dc=example\nreqResult: 32\n\n",
+            "dn: reqStart=20240101000000.000003Z,cn=accesslog\nreqType: modrdn\nreqDN: uid=carol,dc=example\n",
+            "reqNewRDN: uid=caroline\nreqDeleteOldRDN: TRUE\n\n",
+            "dn: reqStart=20240101000000.000004Z,cn=accesslog\nreqType: search\nreqDN: dc=example\n",
+        ))?, concat!(
+            "dn: uid=alice,dc=example\nchangetype: modify\n",
+            "add: mail\nmail: a@example.com\nmail: b@example.com\n-\n",
+            "delete: title\n-\nreplace: sn\nsn: Smith\n-\n\n",
+            "dn: uid=carol,dc=example\nchangetype: modrdn\nnewrdn: uid=caroline\ndeleteoldrdn: 1\n\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_retro_changelog() -> Result<()> {
+        assert_eq!(convert(concat!(
+            "dn: changenumber=1,cn=changelog\nchangeNumber: 1\ntargetDn: uid=alice,dc=example\nchangeType: add\n",
+            "changes:: b2JqZWN0Q2xhc3M6IHBlcnNvbgpjbjogQWxpY2UKc246OiBVMjFwZEdnPQo=\n\n",
+            "dn: changenumber=2,cn=changelog\nchangeNumber: 2\ntargetDn: uid=alice,dc=example\nchangeType: modify\n",
+            "changes:: cmVwbGFjZTogc24Kc246IEpvbmVzCi0KYWRkOiBtYWlsCm1haWw6IGFAZXhhbXBsZS5jb20KLQoA\n",
+        ))?, concat!(
+            "dn: uid=alice,dc=example\nchangetype: add\nobjectClass: person\ncn: Alice\nsn: Smith\n\n",
+            "dn: uid=alice,dc=example\nchangetype: modify\nreplace: sn\nsn: Jones\n-\nadd: mail\nmail: a@example.com\n-\n\n",
+        ));
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
-use std::io::{ Write, Result, Error, ErrorKind };
-use std::default::Default;
+use alloc::format;
+use crate::sink::{ Sink, Result, invalid_data, other };
+#[cfg(feature = "std")]
+use std::io::Write;
 
 #[derive(Clone, Copy)]
 enum State {
@@ -11,6 +13,8 @@ enum State {
     P1, // expecting one more padding
 }
 
+/// The state of a base64 decoder between buffers. It decodes into any `Sink`, and
+/// `DecodeWriter` adapts it to `std::io::Write`.
 #[derive(Clone, Copy)]
 pub struct DecodeState {
     state: State,
@@ -23,41 +27,6 @@ impl Default for DecodeState {
     }
 }
 
-pub struct DecodeWriter<W: Write> {
-    inner: W,
-    state: State,
-    octet: u8,
-}
-
-impl<W: Write> DecodeWriter<W> {
-    pub fn new(inner: W) -> DecodeWriter<W> {
-        DecodeWriter{ inner, state: State::B0, octet: 0u8 }
-    }
-
-    pub fn new_with_state(inner: W, state: DecodeState) -> DecodeWriter<W> {
-        DecodeWriter{
-            inner,
-            state: state.state,
-            octet: state.octet,
-        }
-    }
-
-    pub fn get_ptr(&self) -> &W {
-        &self.inner
-    }
-
-    pub fn get_mut(&mut self) -> &mut W {
-        &mut self.inner
-    }
-
-    pub fn get_state(&self) -> DecodeState {
-        DecodeState {
-            state: self.state,
-            octet: self.octet,
-        }
-    }
-}
-
 fn decode_value_of(digit: u8) -> Option<u8> {
     match digit {
         b'A'..=b'Z' => Some(digit - b'A'),
@@ -69,8 +38,8 @@ fn decode_value_of(digit: u8) -> Option<u8> {
     }
 }
 
-impl<W: Write> Write for DecodeWriter<W> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+impl DecodeState {
+    pub fn decode<S: Sink>(&mut self, buf: &[u8], dest: &mut S) -> Result<()> {
         for c in buf.iter().copied() {
             self.state = match (self.state, c, decode_value_of(c)) {
                 (State::B0, _, Some(value)) => {
@@ -79,40 +48,86 @@ impl<W: Write> Write for DecodeWriter<W> {
                 },
                 (State::B6, _, Some(value)) => {
                     self.octet |= value >> 4;
-                    self.inner.write_all(&[self.octet])?;
+                    dest.put(&[self.octet])?;
                     self.octet = value << 4;
                     State::B4
                 },
                 (State::B4, _, Some(value)) => {
                     self.octet |= value >> 2;
-                    self.inner.write_all(&[self.octet])?;
+                    dest.put(&[self.octet])?;
                     self.octet = value << 6;
                     State::B2
                 },
                 (State::B4, b'=', _) => State::P1,
                 (State::B2, _, Some(value)) => {
                     self.octet |= value;
-                    self.inner.write_all(&[self.octet])?;
+                    dest.put(&[self.octet])?;
                     State::B0
                 },
                 (State::B2 | State::P1, b'=', _) => State::P0,
-                (_, _, _) => return Err(Error::new(ErrorKind::InvalidData, format!("unexpected character 0x{:02X}", c))),
+                (_, _, _) => return Err(invalid_data(format!("unexpected character 0x{:02X}", c))),
             }
         }
-        Ok(buf.len())
+        Ok(())
     }
 
-    fn flush(&mut self) -> Result<()> {
+    /// Checks that the decoded value did not end in the middle of a group of characters.
+    pub fn finish(&self) -> Result<()> {
         match self.state {
             // valid final states
             State::P0 | State::B0 => Ok(()),
             // other states
-            _ => Err(Error::new(ErrorKind::Other, "base64 decoder flushed on invalid end state")),
+            _ => Err(other("base64 decoder flushed on invalid end state")),
         }
     }
 }
 
-enum EncodeState {
+#[cfg(feature = "std")]
+pub struct DecodeWriter<W: Write> {
+    inner: W,
+    state: DecodeState,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> DecodeWriter<W> {
+    pub fn new(inner: W) -> DecodeWriter<W> {
+        DecodeWriter{ inner, state: DecodeState::default() }
+    }
+
+    pub fn new_with_state(inner: W, state: DecodeState) -> DecodeWriter<W> {
+        DecodeWriter{
+            inner,
+            state,
+        }
+    }
+
+    pub fn get_ptr(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn get_state(&self) -> DecodeState {
+        self.state
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for DecodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.state.decode(buf, &mut self.inner)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.state.finish()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum EncodeBits {
     B0, // 0 bits filled
     B2, // 2 bits filled
     B4, // 4 bits filled
@@ -129,79 +144,107 @@ fn encode_value_of(u6: u8) -> u8 {
     }
 }
 
-pub struct EncodeWriter<W: Write> {
-    inner: W,
-    state: EncodeState,
+/// The state of a base64 encoder between buffers. It encodes into any `Sink`, and
+/// `EncodeWriter` adapts it to `std::io::Write`.
+#[derive(Clone, Copy)]
+pub struct EncodeState {
+    bits: EncodeBits,
     u6: u8,
 }
 
-impl<W: Write> EncodeWriter<W> {
-    pub fn new(inner: W) -> EncodeWriter<W> {
-        EncodeWriter{
-            inner,
-            state: EncodeState::B0,
-            u6: 0,
-        }
+impl Default for EncodeState {
+    fn default() -> Self {
+        EncodeState{ bits: EncodeBits::B0, u6: 0 }
     }
+}
 
-    fn emit(&mut self) -> Result<()> {
-        self.inner.write_all(&[encode_value_of(self.u6)])?;
-        Ok(())
+impl EncodeState {
+    fn emit<S: Sink>(&self, dest: &mut S) -> Result<()> {
+        dest.put(&[encode_value_of(self.u6)])
     }
-}
 
-impl<W: Write> Write for EncodeWriter<W> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+    pub fn encode<S: Sink>(&mut self, buf: &[u8], dest: &mut S) -> Result<()> {
         for c in buf.iter().copied() {
-            self.state = match self.state {
-                EncodeState::B0 => {
+            self.bits = match self.bits {
+                EncodeBits::B0 => {
                     self.u6 = c >> 2;
-                    self.emit()?;
+                    self.emit(dest)?;
                     self.u6 = (c << 4) & 0x3F;
-                    EncodeState::B2
+                    EncodeBits::B2
                 },
-                EncodeState::B2 => {
+                EncodeBits::B2 => {
                     self.u6 |= c >> 4;
-                    self.emit()?;
+                    self.emit(dest)?;
                     self.u6 = (c << 2) & 0x3F;
-                    EncodeState::B4
+                    EncodeBits::B4
                 }
-                EncodeState::B4 => {
+                EncodeBits::B4 => {
                     self.u6 |= c >> 6;
-                    self.emit()?;
+                    self.emit(dest)?;
                     self.u6 = c & 0x3F;
-                    self.emit()?;
-                    EncodeState::B0
+                    self.emit(dest)?;
+                    EncodeBits::B0
                 }
             }
         }
-        Ok(buf.len())
+        Ok(())
     }
 
-    fn flush(&mut self) -> Result<()> {
-        match self.state {
-            EncodeState::B0 => {},
-            EncodeState::B2 => {
-                self.emit()?;
-                self.inner.write_all(b"==")?;
+    /// Writes the remaining bits and the padding, and resets the state for a new value.
+    pub fn finish<S: Sink>(&mut self, dest: &mut S) -> Result<()> {
+        match self.bits {
+            EncodeBits::B0 => {},
+            EncodeBits::B2 => {
+                self.emit(dest)?;
+                dest.put(b"==")?;
             },
-            EncodeState::B4 => {
-                self.emit()?;
-                self.inner.write_all(b"=")?;
+            EncodeBits::B4 => {
+                self.emit(dest)?;
+                dest.put(b"=")?;
             },
         }
+        *self = EncodeState::default();
         Ok(())
     }
 }
 
+#[cfg(feature = "std")]
+pub struct EncodeWriter<W: Write> {
+    inner: W,
+    state: EncodeState,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> EncodeWriter<W> {
+    pub fn new(inner: W) -> EncodeWriter<W> {
+        EncodeWriter{
+            inner,
+            state: EncodeState::default(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.state.encode(buf, &mut self.inner)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.state.finish(&mut self.inner)
+    }
+}
+
 #[cfg(test)]
 mod test {
     pub use super::*;
+    use std::io::ErrorKind;
 
     #[test]
     fn test1() -> Result<()> {
         let mut decoder = DecodeWriter::new(Vec::new());
-        decoder.write(b"SGVsbG8gd29ybGQ=")?;
+        decoder.write_all(b"SGVsbG8gd29ybGQ=")?;
         decoder.flush()?;
         assert_eq!(std::str::from_utf8(decoder.get_ptr()), Ok("Hello world"));
         Ok(())
@@ -210,7 +253,7 @@ mod test {
     #[test]
     fn test2() -> Result<()> {
         let mut decoder = DecodeWriter::new(Vec::new());
-        decoder.write(b"SGVsbG8gd29ybGQh")?;
+        decoder.write_all(b"SGVsbG8gd29ybGQh")?;
         decoder.flush()?;
         assert_eq!(std::str::from_utf8(decoder.get_ptr()), Ok("Hello world!"));
         Ok(())
@@ -219,7 +262,7 @@ mod test {
     #[test]
     fn test3() -> Result<()> {
         let mut decoder = DecodeWriter::new(Vec::new());
-        decoder.write(b"SGVsbG93b3JsZA==")?;
+        decoder.write_all(b"SGVsbG93b3JsZA==")?;
         decoder.flush()?;
         assert_eq!(std::str::from_utf8(decoder.get_ptr()), Ok("Helloworld"));
         Ok(())
@@ -229,7 +272,7 @@ mod test {
     fn test_invalid_data() {
         let mut buf = Vec::new();
         let mut decoder = DecodeWriter::new(&mut buf);
-        let result = decoder.write(b"\r");
+        let result = decoder.write_all(b"\r");
         if let Err(error) = result {
             assert_eq!(error.kind(), ErrorKind::InvalidData);
             assert_eq!(error.to_string(), "unexpected character 0x0D");
@@ -242,7 +285,7 @@ mod test {
     fn test_encode_a() -> Result<()> {
         let mut buf: Vec<u8> = Vec::new();
         let mut encoder = EncodeWriter::new(&mut buf);
-        encoder.write(b"abcd")?;
+        encoder.write_all(b"abcd")?;
         encoder.flush()?;
         assert_eq!(String::from_utf8_lossy(&buf[..]), "YWJjZA==");
         Ok(())
@@ -252,10 +295,27 @@ mod test {
     fn test_encode_b() -> Result<()> {
         let mut buf: Vec<u8> = Vec::new();
         let mut encoder = EncodeWriter::new(&mut buf);
-        encoder.write(b"12345678")?;
+        encoder.write_all(b"12345678")?;
         encoder.flush()?;
         assert_eq!(String::from_utf8_lossy(&buf[..]), "MTIzNDU2Nzg=");
         Ok(())
     }
-}
 
+    #[test]
+    fn test_states() -> Result<()> {
+        let mut encoded: Vec<u8> = Vec::new();
+        let mut encoder = EncodeState::default();
+        encoder.encode(b"Hello ", &mut encoded)?;
+        encoder.encode(b"world", &mut encoded)?;
+        encoder.finish(&mut encoded)?;
+        assert_eq!(encoded, b"SGVsbG8gd29ybGQ=");
+        let mut decoded: Vec<u8> = Vec::new();
+        let mut decoder = DecodeState::default();
+        decoder.decode(&encoded[..5], &mut decoded)?;
+        assert!(decoder.finish().is_err());
+        decoder.decode(&encoded[5..], &mut decoded)?;
+        decoder.finish()?;
+        assert_eq!(decoded, b"Hello world");
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+use clap::{arg, command, ArgGroup};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use ltools::bulk::BulkParser;
+use ltools::dn::{avas, escape_value, rdns, unescape_value};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use ltools::schema::DN_ATTRIBUTES;
+use std::collections::HashSet;
+use std::io::{stdin, stdout, BufWriter, Stdout, Write};
+
+const PASSWORD_ATTRIBUTES: &[&str] = &[
+    "userpassword",
+    "authpassword",
+    "unicodepwd",
+    "sambantpassword",
+    "sambalmpassword",
+];
+
+struct LAnonymize {
+    key: Vec<u8>,
+    pseudonymize: HashSet<String>,
+    mask: HashSet<String>,
+    drop: HashSet<String>,
+    dn_attributes: HashSet<String>,
+    writer: LdifEntryWriter<BufWriter<Stdout>>,
+}
+
+fn parse_arguments() -> Result<LAnonymize, &'static str> {
+    let matches = with_defaults(command!("lanonymize")
+        .disable_colored_help(true)
+        .arg(arg!(-k --key <KEY> "Secret key for the pseudonyms. The same key gives the same pseudonyms.")
+            .required(false))
+        .arg(arg!(--"key-file" <FILE> "Read the secret key from FILE.")
+            .required(false))
+        .group(ArgGroup::new("secret")
+            .args(&["key", "key-file"])
+            .required(true))
+        .arg(arg!(-p --pseudonymize <ATTRIBUTE> "Replace values of ATTRIBUTE with pseudonyms. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(-m --mask <ATTRIBUTE> "Mask values of ATTRIBUTE, keeping the domain of email addresses and the format of telephone numbers. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(-d --drop <ATTRIBUTE> "Remove ATTRIBUTE from the entries, in addition to password attributes. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"dn-attribute" <ATTRIBUTE> "Treat values of ATTRIBUTE as DNs, in addition to member, manager and similar attributes. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let key: Vec<u8> = match (matches.get_one::<String>("key"), matches.get_one::<String>("key-file")) {
+        (Some(key), _) => key.as_bytes().to_vec(),
+        (None, Some(filepath)) => match std::fs::read(filepath) {
+            Ok(mut key) => {
+                while key.last().map(u8::is_ascii_whitespace).unwrap_or(false) {
+                    key.pop();
+                }
+                key
+            },
+            Err(_) => return Err("failed to read key file"),
+        },
+        (None, None) => return Err("missing secret key"),
+    };
+    if key.is_empty() {
+        return Err("the secret key is empty");
+    }
+
+    let attributes = |name: &str| -> HashSet<String> {
+        matches.get_many::<String>(name)
+            .into_iter()
+            .flatten()
+            .map(|attr| attr.to_ascii_lowercase())
+            .collect()
+    };
+    let mut drop = attributes("drop");
+    drop.extend(PASSWORD_ATTRIBUTES.iter().map(|attr| attr.to_string()));
+    let mut dn_attributes = attributes("dn-attribute");
+    dn_attributes.extend(DN_ATTRIBUTES.iter().map(|attr| attr.to_string()));
+    dn_attributes.insert(String::from("dn"));
+
+    let mut writer = LdifEntryWriter::new(BufWriter::new(stdout()));
+    writer.set_sort_attributes(false);
+
+    Ok(LAnonymize{
+        key,
+        pseudonymize: attributes("pseudonymize"),
+        mask: attributes("mask"),
+        drop,
+        dn_attributes,
+        writer,
+    })
+}
+
+impl LAnonymize {
+    fn digest(&self, value: &[u8]) -> [u8; 32] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        // LDAP usually compares values case-insensitively, so differently cased references to the
+        // same value get the same pseudonym
+        mac.update(&value.to_ascii_lowercase());
+        mac.finalize().into_bytes().into()
+    }
+
+    fn pseudonym(&self, value: &[u8]) -> Vec<u8> {
+        self.digest(value)[..8].iter()
+            .flat_map(|b| format!("{:02x}", b).into_bytes())
+            .collect()
+    }
+
+    fn mask(&self, value: &[u8]) -> Vec<u8> {
+        if let Some(at) = value.iter().rposition(|c| *c == b'@') {
+            let mut masked = self.pseudonym(&value[..at]);
+            masked.extend_from_slice(&value[at..]);
+            return masked;
+        }
+        if value.iter().any(u8::is_ascii_digit) {
+            let digest = self.digest(value);
+            let mut digits = digest.iter().cycle().map(|b| b'0' + b % 10);
+            return value.iter()
+                .map(|c| if c.is_ascii_digit() { digits.next().unwrap_or(*c) } else { *c })
+                .collect();
+        }
+        self.pseudonym(value)
+    }
+
+    fn anonymize_value(&self, attr: &str, value: &[u8]) -> Option<Vec<u8>> {
+        if self.pseudonymize.contains(attr) {
+            Some(self.pseudonym(value))
+        } else if self.mask.contains(attr) {
+            Some(self.mask(value))
+        } else {
+            None
+        }
+    }
+
+    // Anonymizes the RDN values of a DN the same way as the corresponding attribute values, so
+    // that references between entries still match.
+    fn anonymize_dn(&self, dn: &str) -> String {
+        rdns(dn)
+            .map(|rdn| avas(rdn)
+                .map(|(attr, value)| {
+                    match self.anonymize_value(&attr.to_ascii_lowercase(), &unescape_value(value)) {
+                        Some(anonymized) => format!("{}={}", attr, escape_value(&anonymized)),
+                        None => format!("{}={}", attr, value),
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("+"))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+impl WriteEntry for LAnonymize {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let mut anonymized = OwnedEntry::default();
+        for attr in entry.attributes() {
+            if self.drop.contains(attr.lowercase) {
+                continue;
+            }
+            for value in entry.get(attr.lowercase) {
+                let dn = if self.dn_attributes.contains(attr.lowercase) { std::str::from_utf8(value).ok() } else { None };
+                match dn {
+                    Some(dn) => anonymized.add_value(attr.name, self.anonymize_dn(dn).as_bytes()),
+                    None => match self.anonymize_value(attr.lowercase, value) {
+                        Some(value) => anonymized.add_value(attr.name, &value),
+                        None => anonymized.add_value(attr.name, value),
+                    },
+                }
+            }
+        }
+        self.writer.write_entry(&anonymized)
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut lanonymize = parse_arguments()?;
+    BulkParser::new().write_entries(&mut stdin().lock(), &mut lanonymize)?;
+    lanonymize.writer.get_mut().flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lanonymize", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lanonymize() -> LAnonymize {
+        LAnonymize{
+            key: b"secret".to_vec(),
+            pseudonymize: HashSet::from([String::from("cn"), String::from("uid")]),
+            mask: HashSet::from([String::from("mail"), String::from("telephonenumber")]),
+            drop: HashSet::new(),
+            dn_attributes: HashSet::from([String::from("dn")]),
+            writer: LdifEntryWriter::new(BufWriter::new(stdout())),
+        }
+    }
+
+    #[test]
+    fn test_a() {
+        let lanonymize = lanonymize();
+        let cn = lanonymize.pseudonym(b"Smith, John");
+        assert_eq!(cn.len(), 16);
+        assert_eq!(cn, lanonymize.pseudonym(b"smith, john"));
+        assert_ne!(cn, lanonymize.pseudonym(b"Smith, Jane"));
+        let dn = lanonymize.anonymize_dn("cn=Smith\\, John, ou=people,dc=example");
+        assert_eq!(dn, format!("cn={},ou=people,dc=example", String::from_utf8_lossy(&cn)));
+    }
+
+    #[test]
+    fn test_mask() {
+        let lanonymize = lanonymize();
+        let mail = lanonymize.mask(b"john.smith@example.com");
+        assert!(mail.ends_with(b"@example.com"));
+        assert_ne!(mail, b"john.smith@example.com");
+        let telephone = lanonymize.mask(b"+47 22 33 44 55");
+        assert_eq!(telephone.len(), 15);
+        assert!(telephone.starts_with(b"+"));
+        assert_eq!(telephone.iter().filter(|c| **c == b' ').count(), 4);
+    }
+}
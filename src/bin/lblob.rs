@@ -0,0 +1,198 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::entry::{Entry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+
+const SMALLEST_BUCKET: u64 = 64; // values shorter than this are counted together
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+    directory: String,
+    min_size: usize,
+    attributes: Option<HashSet<String>>, // lowercase, None means all but the DN
+    histogram: bool,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lblob")
+        .disable_colored_help(true)
+        .about("Writes the input entries with large values moved out to files of their own, replacing them with '<' file URL references, so that entries with certificates, photos and the like can be opened in an editor.")
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input)."))
+        .arg(arg!(-d --directory <DIRECTORY> "Write the extracted values to files in DIRECTORY, which is created if needed. Each file is named after the DN, the attribute and the index of the value.")
+            .required(false)
+            .default_value("blobs"))
+        .arg(arg!(-s --"min-size" <BYTES> "Extract values of at least BYTES bytes.")
+            .required(false)
+            .default_value("1024"))
+        .arg(arg!(-a --attribute <ATTRIBUTE> "Only extract values of ATTRIBUTE. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--histogram "Instead of extracting values, write the number of values of each attribute by size range, as tab-separated attribute, smallest size, largest size, count and total bytes.")
+            .action(clap::ArgAction::SetTrue)
+            .conflicts_with_all(&["directory", "min-size"]))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let min_size = match matches.get_one::<String>("min-size").map(|size| size.parse::<usize>()) {
+        Some(Ok(size)) => size,
+        Some(Err(_)) => return Err("failed to parse minimum size"),
+        None => 1024,
+    };
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        directory: matches.get_one::<String>("directory").unwrap().clone(),
+        min_size,
+        attributes: matches.get_many::<String>("attribute")
+            .map(|attrs| attrs.map(|attr| attr.to_lowercase()).collect()),
+        histogram: matches.get_flag("histogram"),
+    })
+}
+
+struct BlobWriter<W: Write> {
+    inner: LdifEntryWriter<W>,
+    directory: PathBuf, // absolute
+    min_size: usize,
+    attributes: Option<HashSet<String>>,
+    file_names: HashSet<String>, // written so far, for telling apart DNs that sanitize alike
+}
+
+impl<W: Write> BlobWriter<W> {
+    fn extract(&mut self, dn: &str, attr: &str, index: usize, value: &[u8]) -> std::io::Result<String> {
+        let base = format!("{}.{}.{}", sanitize(dn), attr, index);
+        let mut file_name = base.clone();
+        let mut suffix = 1;
+        while !self.file_names.insert(file_name.clone()) {
+            suffix += 1;
+            file_name = format!("{}~{}", base, suffix);
+        }
+        let path = self.directory.join(&file_name);
+        std::fs::write(&path, value)?;
+        Ok(format!("file://{}", path.display()))
+    }
+}
+
+impl<W: Write> WriteEntry for BlobWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let dn = entry.get_one_str("dn").unwrap_or_default().into_owned();
+        let mut urls: Vec<Option<String>> = Vec::new();
+        for attr in entry.attributes() {
+            let extracted = attr.lowercase != "dn"
+                && self.attributes.as_ref().is_none_or(|attributes| attributes.contains(attr.lowercase));
+            for (index, value) in entry.get(attr.lowercase).enumerate() {
+                urls.push(if extracted && value.len() >= self.min_size {
+                    Some(self.extract(&dn, attr.lowercase, index, value)?)
+                } else {
+                    None
+                });
+            }
+        }
+        // the writer visits the values in the same order when attribute sorting is off
+        let mut urls = urls.into_iter();
+        self.inner.write_entry_with_urls(entry, |_, _, _| Ok(urls.next().flatten()))
+    }
+}
+
+// Replaces characters that are unsafe in file names and file URLs with underscores.
+fn sanitize(dn: &str) -> String {
+    let sanitized: String = dn.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '=' | ',' | '-' | '.') { c } else { '_' })
+        .collect();
+    if sanitized.is_empty() { String::from("_") } else { sanitized }
+}
+
+#[derive(Default)]
+struct Histogram {
+    attributes: Option<HashSet<String>>,
+    buckets: BTreeMap<(String, u64), (usize, u64)>, // count and total size by attribute and smallest size
+}
+
+impl WriteEntry for Histogram {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        for attr in entry.attributes() {
+            if self.attributes.as_ref().is_some_and(|attributes| !attributes.contains(attr.lowercase)) {
+                continue;
+            }
+            for value in entry.get(attr.lowercase) {
+                let size = value.len() as u64;
+                let (count, total) = self.buckets.entry((attr.lowercase.to_string(), bucket(size))).or_default();
+                *count += 1;
+                *total += size;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns the smallest size of the power-of-two range that the size falls in.
+fn bucket(size: u64) -> u64 {
+    if size < SMALLEST_BUCKET {
+        0
+    } else {
+        1 << size.ilog2()
+    }
+}
+
+fn write_histogram<W: Write>(histogram: &Histogram, mut dest: W) -> std::io::Result<()> {
+    for ((attr, smallest), (count, total)) in histogram.buckets.iter() {
+        let largest = if *smallest == 0 { SMALLEST_BUCKET - 1 } else { smallest * 2 - 1 };
+        writeln!(dest, "{}\t{}\t{}\t{}\t{}", attr, smallest, largest, count, total)?;
+    }
+    Ok(())
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    let mut dest = BufWriter::new(stdout());
+    if params.histogram {
+        let mut histogram = Histogram{
+            attributes: params.attributes,
+            ..Histogram::default()
+        };
+        for input in inputs.iter() {
+            open_input(input)
+                .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut histogram))
+                .map_err(|err| FileError::new(input, err))?;
+        }
+        write_histogram(&histogram, &mut dest)?;
+        dest.flush()?;
+        return Ok(());
+    }
+    std::fs::create_dir_all(&params.directory)
+        .map_err(|err| FileError::new(&params.directory, err))?;
+    let mut inner = LdifEntryWriter::new(&mut dest);
+    inner.set_sort_attributes(false);
+    let mut writer = BlobWriter{
+        inner,
+        directory: std::fs::canonicalize(&params.directory)?,
+        min_size: params.min_size,
+        attributes: params.attributes,
+        file_names: HashSet::new(),
+    };
+    for input in inputs.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut writer))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    dest.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lblob", &*err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,152 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::dn::normalize;
+use ltools::entry::{Entry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use std::collections::HashSet;
+use std::io::{stdout, BufWriter, Write};
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+    unique: bool,
+    version_line: bool,
+    fold_width: Option<usize>,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lcat")
+        .disable_colored_help(true)
+        .about("Concatenates LDIF files into a single stream of entries separated by blank lines, leaving out their version lines.")
+        .arg(arg!([FILES] ... "LDIF files to concatenate instead of standard input ('-' means standard input)."))
+        .arg(arg!(-u --unique "Only write the first entry with each DN, comparing DNs regardless of case and spacing.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"version-line" "Start the output with a single 'version: 1' line.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(-w --width <COLUMNS> "Fold lines longer than COLUMNS. 0 disables folding.")
+            .required(false)
+            .default_value("76"))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let fold_width = match matches.get_one::<String>("width").map(|width| width.parse::<usize>()) {
+        Some(Ok(0)) => None,
+        Some(Ok(width)) => Some(width),
+        Some(Err(_)) => return Err("failed to parse line width"),
+        None => Some(76),
+    };
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        unique: matches.get_flag("unique"),
+        version_line: matches.get_flag("version-line"),
+        fold_width,
+    })
+}
+
+// Passes on entries whose DN has not been seen before, if unique is set.
+struct EntryFilter<W: WriteEntry> {
+    inner: W,
+    unique: bool,
+    seen: HashSet<String>,
+    duplicates: usize,
+}
+
+impl<W: WriteEntry> WriteEntry for &mut EntryFilter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        if self.unique {
+            if let Some(dn) = entry.get_one_str("dn") {
+                if !self.seen.insert(normalize(&dn)) {
+                    self.duplicates += 1;
+                    return Ok(());
+                }
+            }
+        }
+        self.inner.write_entry(entry)
+    }
+}
+
+// Writes the entries of the inputs to dest, and returns the number of entries left out as
+// duplicates.
+fn concatenate<W: Write>(params: &Parameters, mut dest: W) -> std::io::Result<usize> {
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    if params.version_line {
+        dest.write_all(b"version: 1\n")?;
+    }
+    let mut writer = LdifEntryWriter::new(dest);
+    writer.set_fold_width(params.fold_width)
+        .set_sort_attributes(false);
+    let mut filter = EntryFilter{
+        inner: writer,
+        unique: params.unique,
+        seen: HashSet::new(),
+        duplicates: 0,
+    };
+    for input in inputs.iter() {
+        // each input is parsed separately, so that an entry at the end of one input that isn't
+        // followed by a blank line is not merged with the first entry of the next
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut filter))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    filter.inner.get_mut().flush()?;
+    Ok(filter.duplicates)
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let duplicates = concatenate(&params, BufWriter::new(stdout()))?;
+    if duplicates != 0 {
+        let message = format!("left out {} entries with DNs that appeared earlier", duplicates);
+        report("lcat", &Diagnostic::new(Severity::Warning, "duplicate-dn", message));
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lcat", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_concatenate() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("ltools-lcat-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let a = dir.join("a.ldif");
+        let b = dir.join("b.ldif");
+        // the first file doesn't end with a blank line, or even a newline, and the entries of the
+        // output are separated by a single blank line however many there are in the input
+        std::fs::write(&a, b"version: 1\ndn: cn=a,dc=example\ncn: a")?;
+        std::fs::write(&b, b"version: 1\n\n\ndn: cn=b,dc=example\ncn: b\n\n\n\ndn: CN=A, dc=example\ncn: again\n")?;
+        let mut params = Parameters{
+            inputs: vec![a.to_string_lossy().into_owned(), b.to_string_lossy().into_owned()],
+            unique: false,
+            version_line: false,
+            fold_width: Some(76),
+        };
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(concatenate(&params, &mut output)?, 0);
+        assert_eq!(String::from_utf8_lossy(&output), "dn: cn=a,dc=example\ncn: a\n\ndn: cn=b,dc=example\ncn: b\n\ndn: CN=A, dc=example\ncn: again\n");
+
+        params.unique = true;
+        params.version_line = true;
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(concatenate(&params, &mut output)?, 1);
+        assert_eq!(String::from_utf8_lossy(&output), "version: 1\ndn: cn=a,dc=example\ncn: a\n\ndn: cn=b,dc=example\ncn: b\n");
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
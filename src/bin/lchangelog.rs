@@ -0,0 +1,71 @@
+use clap::{arg, command};
+use ltools::auditlog::write_change;
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::entry::{Entry, WriteEntry};
+use std::io::{stdout, BufWriter, Write};
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lchangelog")
+        .disable_colored_help(true)
+        .about("Converts audit log entries, from the OpenLDAP accesslog overlay or a retro changelog, into LDIF changerecords that can be replayed or analyzed, in the order they appear. Searches, binds and failed operations are left out.")
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input)."))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+    })
+}
+
+struct ChangeWriter<W: Write> {
+    dest: W,
+    skipped: usize,
+}
+
+impl<W: Write> WriteEntry for ChangeWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        if !write_change(entry, &mut self.dest)? {
+            self.skipped += 1;
+        }
+        Ok(())
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    let mut writer = ChangeWriter{
+        dest: BufWriter::new(stdout()),
+        skipped: 0,
+    };
+    for input in inputs.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut writer))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    writer.dest.flush()?;
+    if writer.skipped != 0 {
+        let message = format!("left out {} entries that don't record a successful change", writer.skipped);
+        report("lchangelog", &Diagnostic::new(Severity::Warning, "not-a-change", message));
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lchangelog", &*err);
+        std::process::exit(1);
+    }
+}
@@ -1,36 +1,58 @@
-use clap::{arg, command, ArgAction};
+use clap::{arg, command, Command, ArgAction};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::bulk::BulkParser;
+use ltools::compress::{create_output, decompress, open_input, Compression};
 use ltools::crstrip::CrStripper;
-use ltools::entry::{Entry, EntryTokenWriter, OwnedEntry, WriteEntry, write_attrval};
-use ltools::lexer::Lexer;
+use ltools::diff::{Diff, SortedComparison};
+use ltools::dn::{normalize, rdns};
+use ltools::changerecord::{LdifChangeRecordWriter, ModRdnChangeRecord, ModifyChangeRecord, ModifyChangeRecordOpType, ModifyOptions, WriteChangeRecord};
+use ltools::entry::{Entry, EntryTokenWriter, OwnedEntry, WriteEntry};
+use ltools::filter::Filter;
+use ltools::inplace::InPlaceFile;
+use ltools::ldif::Base64Policy;
+use ltools::lexer::{Lexer, WriteToken};
 use ltools::loc::WriteLocWrapper;
+use ltools::normalize::Normalizers;
+use ltools::operational::{operational_args, OperationalAttributes, OperationalStripper};
+use ltools::rename::{AttributeMap, AttributeRenameTokenWriter};
+use ltools::progress::{total_file_size, Progress, ProgressReader, ProgressTokenWriter};
+use ltools::schema::attribute_syntaxes_from_schema;
 use ltools::unfold::Unfolder;
 use std::borrow::Cow;
 use std::cmp::{Ord, Ordering};
-use std::collections::BTreeMap;
-use std::io::{copy, Read, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{copy, BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
 use std::ops::Deref;
-use std::iter::Peekable;
 
 struct Parameters {
     old: String,
     new: String,
     invert: bool,
     force: bool,
+    modify_options: ModifyOptions,
     attrs: Vec<String>,       // should be lowercase
     defer_attrs: Vec<String>, // should be lowercase
+    operational: Option<OperationalAttributes>, // to leave out of the comparison
+    filter: Option<Filter>, // entries to compare
+    progress: Option<Progress>,
+    output: String, // - means standard output
+    compression: Option<Compression>, // None means according to the output file extension
+    fingerprint_cache: Option<String>, // file with the fingerprints of the old entries
+    attr_report: bool,
+    html: Option<String>, // file to write an HTML report of the changes to
+    base64_policy: Option<Base64Policy>, // None keeps the default of base64-encoding values with spaces
+    preserve_encoding: bool,
+    map_file: Option<String>,
+    map: Option<AttributeMap>, // to rename attributes of both inputs with, read from map_file
+    normalize: Vec<String>, // normalizers as ATTRIBUTE=NORMALIZER,...
+    schema: Option<String>, // file with a subschema entry to normalize values by syntax with
 }
 
-fn parse_arguments() -> Result<Parameters, &'static str> {
-    let mut params = Parameters {
-        old: "-".into(),
-        new: "-".into(),
-        attrs: Vec::new(),
-        invert: false,
-        force: false,
-        defer_attrs: Vec::new(),
-    };
-
-    let matches = command!("lcompare")
+// The command without the defaults from the configuration file, which the tests use.
+fn command() -> Command<'static> {
+    command!("lcompare")
         .disable_colored_help(true)
         .arg(arg!(<OLD> "The LDIF entry records from which the changerecords transition"))
         .arg(arg!(<NEW> "The LDIF entry records to which the changerecords transition"))
@@ -41,58 +63,179 @@ fn parse_arguments() -> Result<Parameters, &'static str> {
         )
         .arg(arg!(invert: -v --invert "In modify and add changerecords, compare based on every attribute except for those in ATTRIBUTES").action(ArgAction::SetTrue))
         .arg(arg!(force: -f --force "Allow lcompare to output delete changerecords").action(ArgAction::SetTrue))
-        .get_matches();
+        .arg(arg!(--filter <FILTER> "Only compare entries that match the LDAP filter FILTER in either the old or the new input")
+            .required(false))
+        .arg(arg!(--"strict-filter" "Parse FILTER strictly as RFC 4515 has it, in parentheses and without whitespace between its parts. By default, a filter such as cn=foo needn't be in parentheses, and there may be whitespace between parentheses and operators, as in '( & (a=1) (b=2) )'.").action(ArgAction::SetTrue))
+        .arg(arg!(--"replace-all" "Modify attributes with differences by replacing all of their values rather than adding and deleting the values that differ").action(ArgAction::SetTrue))
+        .arg(arg!(--ordered <ATTRIBUTE> "Compare the values of ATTRIBUTE as an ordered list rather than as a set, so that values that are only reordered are replaced, such as for olcAccess. Can be given multiple times.")
+            .required(false)
+            .action(ArgAction::Append))
+        .arg(arg!(--normalize <SPEC> "Normalize the values of an attribute before comparing them, with SPEC of the form ATTRIBUTE=NORMALIZER,... and the normalizers dn, which normalizes DNs, case, which folds case, space, which trims and collapses whitespace, and telephone, which removes spaces and hyphens. The changerecords have the new values as they are. Can be given multiple times.")
+            .required(false)
+            .action(ArgAction::Append))
+        .arg(arg!(--schema <FILE> "Normalize values by the syntaxes of their attributes according to the subschema entry in FILE, such as one exported from cn=schema: DNs as by the dn normalizer, directory and printable strings as by case and space, and telephone numbers as by telephone.")
+            .required(false))
+        .args(operational_args("Leave operational attributes such as modifyTimestamp and entryUUID out of the comparison."))
+        .arg(arg!(--"attr-report" "Instead of changerecords, write a tab-separated table of how many values of each attribute the changerecords would add and remove, and in how many entries. The dn row counts the entries added, deleted and changed.")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("replace-all"))
+        .arg(arg!(--html <FILE> "Also write a self-contained HTML report of the changerecords to FILE, with tables of the changes by attribute and a section for each entry in which added values are green and removed values red.")
+            .required(false))
+        .arg(arg!(-o --output <FILE> "Write the changerecords to FILE instead of standard output. FILE is compressed if it ends with .gz or .zst.")
+            .required(false))
+        .arg(arg!(--compress <METHOD> "Compress the output with METHOD, which is gzip, zstd or none, regardless of the output file extension.")
+            .required(false))
+        .arg(arg!(--"fingerprint-cache" <FILE> "Skip comparing the attributes of entries whose fingerprint is the same as the one stored for their DN in FILE, and store the fingerprints of the new entries in FILE afterwards. FILE should come from the run where OLD was the new input.")
+            .required(false))
+        .arg(arg!(--"utf8-values" "Write values that are valid UTF-8 as they are instead of base64-encoding them, and only base64-encode values where LDIF requires it otherwise.")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--map <FILE> "Rename attributes of both inputs according to FILE, which has lines such as mail=rfc822Mailbox, such as for comparing exports from directory servers that name attributes differently. ATTRIBUTES and the changerecords use the new names.")
+            .required(false))
+        .arg(arg!(--"preserve-encoding" "Base64-encode the values that were base64-encoded in either input, such as ones with trailing spaces, and otherwise only base64-encode values where LDIF requires it.")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--progress "Report the bytes read, the entries processed and the rate to standard error, as a progress bar if it is a terminal.")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+}
+
+fn parse_arguments() -> Result<Parameters, Box<dyn std::error::Error>> {
+    parse_arguments_from(with_defaults(command()), std::env::args_os())
+}
+
+fn parse_arguments_from<I, T>(cmd: Command, args: I) -> Result<Parameters, Box<dyn std::error::Error>>
+    where I: IntoIterator<Item = T>, T: Into<std::ffi::OsString> + Clone
+{
+    let mut params = Parameters {
+        old: "-".into(),
+        new: "-".into(),
+        attrs: Vec::new(),
+        invert: false,
+        force: false,
+        modify_options: ModifyOptions::default(),
+        defer_attrs: Vec::new(),
+        operational: None,
+        filter: None,
+        progress: None,
+        output: "-".into(),
+        compression: None,
+        fingerprint_cache: None,
+        attr_report: false,
+        html: None,
+        base64_policy: None,
+        preserve_encoding: false,
+        map_file: None,
+        map: None,
+        normalize: Vec::new(),
+        schema: None,
+    };
+
+    let matches = cmd.get_matches_from(args);
+
+    set_json_output(matches.get_flag("diagnostics-json"));
 
     if let Some(old) = matches.get_one::<String>("OLD") {
         params.old = old.clone();
     } else {
         // shouldn't happen when the argument is required
-        return Err("missing LDIF input parameter");
+        return Err("missing LDIF input parameter".into());
     }
 
     if let Some(new) = matches.get_one::<String>("NEW") {
         params.new = new.clone();
     } else {
         // shouldn't happen when the argument is required
-        return Err("missing LDIF input parameter");
+        return Err("missing LDIF input parameter".into());
     }
 
     params.attrs = matches.get_many::<String>("ATTRIBUTES")
         .map(|attrs| attrs.map(|attr| attr.to_lowercase()).collect())
-        .unwrap_or_else(Vec::new);
+        .unwrap_or_default();
     params.invert = matches.get_flag("invert") != params.attrs.is_empty();
 
     params.defer_attrs = matches.get_many::<String>("defer")
         .map(|attrs| attrs.map(|attr| attr.to_lowercase()).collect())
-        .unwrap_or_else(Vec::new);
+        .unwrap_or_default();
     if params.invert {
         params.attrs.extend(params.defer_attrs.iter().cloned());
     }
 
     params.force = matches.get_flag("force");
+    params.modify_options.replace_all = matches.get_flag("replace-all");
+    params.modify_options.ordered = matches.get_many::<String>("ordered")
+        .map(|attrs| attrs.map(|attr| attr.to_lowercase()).collect())
+        .unwrap_or_default();
+    params.attr_report = matches.get_flag("attr-report");
+    params.html = matches.get_one::<String>("html").cloned();
+
+    if let Some(filter) = matches.get_one::<String>("filter") {
+        params.filter = Some(match matches.get_flag("strict-filter") {
+            true => Filter::parse_strict(filter)?,
+            false => Filter::parse(filter)?,
+        });
+    }
+
+    params.operational = OperationalAttributes::from_matches(&matches);
+
+    if let Some(output) = matches.get_one::<String>("output") {
+        params.output = output.clone();
+    }
+    if let Some(method) = matches.get_one::<String>("compress") {
+        params.compression = Some(Compression::parse(method)?);
+    }
+
+    params.fingerprint_cache = matches.get_one::<String>("fingerprint-cache").cloned();
+
+    if matches.get_flag("utf8-values") {
+        let mut policy = Base64Policy::default();
+        policy.set_allow_utf8(true);
+        params.base64_policy = Some(policy);
+    }
+    params.preserve_encoding = matches.get_flag("preserve-encoding");
+    params.map_file = matches.get_one::<String>("map").cloned();
+    params.normalize = matches.get_many::<String>("normalize").into_iter().flatten().cloned().collect();
+    params.schema = matches.get_one::<String>("schema").cloned();
+
+    if matches.get_flag("progress") {
+        let files: Vec<&String> = [&params.old, &params.new].into_iter().filter(|input| *input != "-").collect();
+        params.progress = Some(Progress::new("lcompare", total_file_size(&files)));
+    }
 
     Ok(params)
 }
 
+/// A normalized DN, ordered so that ancestors come before their descendants, for writing adds
+/// parent-first and deletes child-first.
 #[derive(PartialEq, Eq)]
-struct DnKey(String);
+struct DnKey {
+    dn: String,
+    path: Vec<String>, // RDNs from the root down
+}
+
+impl DnKey {
+    fn new(dn: &str) -> DnKey {
+        let dn = normalize(&dn.to_lowercase());
+        let mut path: Vec<String> = rdns(&dn).map(String::from).collect();
+        path.reverse();
+        DnKey{
+            dn,
+            path,
+        }
+    }
+}
 
 impl Deref for DnKey {
     type Target = str;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.dn
     }
 }
 
 impl Ord for DnKey {
     fn cmp(&self, other: &Self) -> Ordering {
-        // we want shorter ancestor DNs to be ordered before longer descendant DNs
-        let cmp = self.0.len().cmp(&other.0.len());
-        match cmp {
-            Ordering::Equal => self.0.cmp(&other.0),
-            _ => cmp,
-        }
+        // comparing the RDNs from the root down orders a DN right before its descendants
+        self.path.cmp(&other.path)
     }
 }
 
@@ -120,252 +263,322 @@ impl WriteEntry for EntryBTreeMap {
                 return Ok(());
             }
         };
-        self.0.insert(DnKey(dn.to_lowercase()), entry.into());
+        self.0.insert(DnKey::new(&dn), entry.into());
         Ok(())
     }
 }
 
-fn read_entries<R: Read>(mut input: R) -> std::io::Result<EntryBTreeMap> {
+fn read_entries<R: Read>(input: R, params: &Parameters) -> std::io::Result<EntryBTreeMap> {
     let mut entries = EntryBTreeMap::new();
-    let token_writer = EntryTokenWriter::new(&mut entries);
+    let progress = params.progress.as_ref();
+    match params.operational {
+        Some(ref operational) => {
+            let stripper = OperationalStripper::new(operational.clone(), &mut entries);
+            let token_writer = ProgressTokenWriter::new(EntryTokenWriter::new(stripper), progress);
+            parse_input(input, AttributeRenameTokenWriter::new(params.map.as_ref(), token_writer))?;
+        },
+        None => {
+            let token_writer = ProgressTokenWriter::new(EntryTokenWriter::new(&mut entries), progress);
+            parse_input(input, AttributeRenameTokenWriter::new(params.map.as_ref(), token_writer))?;
+        },
+    }
+    Ok(entries)
+}
+
+fn parse_input<R: Read, TR: WriteToken>(mut input: R, token_writer: TR) -> std::io::Result<()> {
     let lexer = Lexer::new(token_writer);
     let unfolder = Unfolder::new(lexer);
     let crstripper = CrStripper::new(unfolder);
     let mut wrapper = WriteLocWrapper::new(crstripper);
     copy(&mut input, &mut wrapper)?;
     wrapper.flush()?;
-    Ok(entries)
+    Ok(())
 }
 
-fn write_add<W: Write>(
-    w: &mut W,
-    entry: &Entry<'_, '_>,
-    attrs: &[String],
-    invert: bool,
-) -> std::io::Result<()> {
-    let dn: Cow<str> = match entry.get_one_str("dn") {
-        Some(dn) => dn,
-        None => return Ok(()),
-    };
-    let mut w = w;
-    write_attrval(&mut w, "dn", dn.as_bytes())?;
-    writeln!(w, "changetype: add")?;
-    for attr in entry
-        .attributes()
-        .filter(|attr| invert != attrs.iter().any(|arg_attr| attr.lowercase == arg_attr))
+// Leaves out the attributes that are not compared, except for the DN.
+fn select_attributes(entry: &Entry, attrs: &[String], invert: bool) -> OwnedEntry {
+    let mut selected = OwnedEntry::default();
+    for attr in entry.attributes()
+        .filter(|attr| attr.lowercase == "dn" || invert != attrs.iter().any(|arg_attr| attr.lowercase == arg_attr))
     {
-        if attr.lowercase == "dn" {
-            continue;
-        }
-        for value in entry.get(attr.name) {
-            write_attrval(&mut w, attr.name, value)?;
+        for value in entry.get(attr.lowercase) {
+            selected.add_value(attr.name, value);
         }
     }
-    writeln!(w)?;
-    Ok(())
+    selected
 }
 
-fn write_delete<W: Write>(w: &mut W, dn: &str) -> std::io::Result<()> {
-    let mut w = w;
-    write_attrval(&mut w, "dn", dn.as_bytes())?;
-    writeln!(w, "changetype: delete")?;
-    writeln!(w)?;
-    Ok(())
+// Reads the fingerprints of entries, written by write_fingerprints, by normalized DN. A missing
+// file has no fingerprints.
+fn read_fingerprints(path: &str) -> std::io::Result<HashMap<String, u64>> {
+    let mut fingerprints: HashMap<String, u64> = HashMap::new();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(fingerprints),
+        Err(err) => return Err(err),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let fingerprint = line.rsplit_once('\t')
+            .and_then(|(dn, hash)| Some((DnKey::new(dn).dn, u64::from_str_radix(hash, 16).ok()?)));
+        match fingerprint {
+            Some((dn, hash)) => fingerprints.insert(dn, hash),
+            None => return Err(std::io::Error::new(ErrorKind::InvalidData, "malformed fingerprint cache")),
+        };
+    }
+    Ok(fingerprints)
 }
 
-enum ModifyChangeRecordOpType {
-    Add,
-    Delete,
-    Replace,
+// Replaces the file with the DN and fingerprint of each entry, in the same form as lfingerprint.
+fn write_fingerprints(path: &str, entries: &EntryBTreeMap) -> std::io::Result<()> {
+    // InPlaceFile needs an existing file to take the permissions of
+    File::options().create(true).append(true).open(path)?;
+    let mut file = InPlaceFile::create(path)?;
+    for (dn, entry) in entries.0.iter() {
+        writeln!(file, "{}\t{:016x}", &**dn, entry.fingerprint())?;
+    }
+    file.commit()
 }
 
-struct ModifyChangeRecordOp<'a> {
-    typ: ModifyChangeRecordOpType,
-    attr: String,
-    values: Vec<&'a [u8]>,
+#[derive(Default)]
+struct AttributeCounts {
+    added: usize,   // values
+    removed: usize, // values
+    entries: usize, // changerecords that change the attribute
 }
 
-struct ModifyChangeRecord<'a> {
-    dn: String,
-    ops: Vec<ModifyChangeRecordOp<'a>>,
+// Counts what the changerecords written to it change, by attribute.
+#[derive(Default)]
+struct AttributeReport {
+    attributes: BTreeMap<String, AttributeCounts>, // by lowercase name
+    entries: AttributeCounts, // the added, deleted and changed entries
 }
 
-impl<'z> ModifyChangeRecord<'z> {
-    fn new<'a, 'b, 'c, 'd>(
-        old: Option<&'z Entry<'a, 'b>>,
-        new: &'z Entry<'c, 'd>,
-        attrs: &[String],
-        invert: bool,
-    ) -> Option<ModifyChangeRecord<'z>>
-    where
-        'b: 'z,
-        'd: 'z,
-    {
-        let dn: Cow<str> = match new.get_one_str("dn") {
-            Some(dn) => dn,
-            None => return None,
-        };
-        let mut modify = ModifyChangeRecord {
-            dn: dn.into_owned(),
-            ops: Vec::new(),
-        };
+impl AttributeReport {
+    fn write<W: Write>(&self, dest: &mut W) -> std::io::Result<()> {
+        writeln!(dest, "attribute\tadded\tremoved\tentries")?;
+        let rows = std::iter::once(("dn", &self.entries))
+            .chain(self.attributes.iter().map(|(attr, counts)| (attr.as_str(), counts)));
+        for (attr, counts) in rows {
+            writeln!(dest, "{}\t{}\t{}\t{}", attr, counts.added, counts.removed, counts.entries)?;
+        }
+        Ok(())
+    }
+}
 
-        let mut old_attrs: Vec<&str> = match old {
-            Some(old) => old
-                .attributes()
-                .map(|attr| attr.lowercase)
-                .filter(|lattr| lattr != &"dn")
-                .filter(|lattr| invert != attrs.iter().any(|arg_attr| arg_attr == *lattr))
-                .collect(),
-            None => Vec::new(),
-        };
-        let mut new_attrs: Vec<&str> = new
-            .attributes()
-            .map(|attr| attr.lowercase)
-            .filter(|lattr| lattr != &"dn")
-            .filter(|lattr| invert != attrs.iter().any(|arg_attr| arg_attr == *lattr))
-            .collect();
+impl WriteChangeRecord for AttributeReport {
+    fn write_add(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.entries.added += 1;
+        self.entries.entries += 1;
+        for attr in entry.attributes().filter(|attr| attr.lowercase != "dn") {
+            let counts = self.attributes.entry(attr.lowercase.to_string()).or_default();
+            counts.added += entry.get(attr.lowercase).count();
+            counts.entries += 1;
+        }
+        Ok(())
+    }
 
-        old_attrs.sort();
-        new_attrs.sort();
-        let comparison = SortedComparison{
-            old_iter: old_attrs.iter().peekable(),
-            new_iter: new_attrs.iter().peekable(),
-            compare_items: Ord::cmp,
-        };
-        for op in comparison {
-            match op {
-                Diff::Add(new_attr) => {
-                    let op = ModifyChangeRecordOp {
-                        typ: ModifyChangeRecordOpType::Add,
-                        attr: new_attr.to_string(),
-                        values: new.get(new_attr).collect(),
-                    };
-                    if !op.values.is_empty() {
-                        modify.ops.push(op);
-                    }
-                },
-                Diff::Delete(old_attr) => {
-                    if let Some(old) = old {
-                        let op = ModifyChangeRecordOp {
-                            typ: ModifyChangeRecordOpType::Delete,
-                            attr: old_attr.to_string(),
-                            values: old.get(old_attr).collect(),
-                        };
-                        if !op.values.is_empty() {
-                            modify.ops.push(op);
-                        }
-                    }
-                },
-                Diff::Modify(old_attr, new_attr) => {
-                    let del_values: Vec<&[u8]> = {
-                        if let Some(old) = old {
-                            old.get(old_attr)
-                                .filter(|old_value: &&[u8]| {
-                                    !new.get(new_attr)
-                                        .any(|new_value: &[u8]| new_value == *old_value)
-                                })
-                                .collect()
-                        } else {
-                            Vec::new()
-                        }
-                    };
-                    let add_values: Vec<&[u8]> = new
-                        .get(new_attr)
-                        .filter(|new_value: &&[u8]| {
-                            if let Some(old) = old {
-                                !old.get(old_attr)
-                                    .any(|old_value: &[u8]| old_value == *new_value)
-                            } else {
-                                true
-                            }
-                        })
-                        .collect();
-                    if add_values.len() == 1 && del_values.len() == 1 && new.get(new_attr).count() == 1 {
-                        // at least on eDirectory, replace works better on single-valued attributes
-                        let op = ModifyChangeRecordOp {
-                            typ: ModifyChangeRecordOpType::Replace,
-                            attr: new_attr.to_string(),
-                            values: add_values,
-                        };
-                        modify.ops.push(op);
-                    } else {
-                        if !del_values.is_empty() {
-                            let op = ModifyChangeRecordOp {
-                                typ: ModifyChangeRecordOpType::Delete,
-                                attr: old_attr.to_string(),
-                                values: del_values,
-                            };
-                            modify.ops.push(op);
-                        }
-                        if !add_values.is_empty() {
-                            let op = ModifyChangeRecordOp {
-                                typ: ModifyChangeRecordOpType::Add,
-                                attr: new_attr.to_string(),
-                                values: add_values,
-                            };
-                            modify.ops.push(op);
-                        }
-                    }
+    fn write_delete(&mut self, _dn: &str) -> std::io::Result<()> {
+        self.entries.removed += 1;
+        self.entries.entries += 1;
+        Ok(())
+    }
+
+    fn write_modify(&mut self, modify: &ModifyChangeRecord) -> std::io::Result<()> {
+        self.entries.entries += 1;
+        let mut changed: Vec<&str> = Vec::new();
+        for op in modify.ops.iter() {
+            let counts = self.attributes.entry(op.attr.clone()).or_default();
+            match op.typ {
+                ModifyChangeRecordOpType::Add => counts.added += op.values.len(),
+                ModifyChangeRecordOpType::Delete => counts.removed += op.values.len(),
+                ModifyChangeRecordOpType::Replace => {
+                    // without --replace-all, replace is only used for a single value replacing another
+                    counts.added += op.values.len();
+                    counts.removed += op.values.len();
                 },
             }
+            if !changed.contains(&op.attr.as_str()) {
+                changed.push(&op.attr);
+                counts.entries += 1;
+            }
         }
-        if modify.ops.is_empty() {
-            None
-        } else {
-            Some(modify)
+        Ok(())
+    }
+
+    fn write_modrdn(&mut self, _modrdn: &ModRdnChangeRecord) -> std::io::Result<()> {
+        self.entries.entries += 1;
+        Ok(())
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
         }
     }
+    escaped
 }
 
-fn write_modify<W: Write>(w: &mut W, modify: &ModifyChangeRecord) -> std::io::Result<()> {
-    let mut w = w;
-    write_attrval(&mut w, "dn", modify.dn.as_bytes())?;
-    writeln!(w, "changetype: modify")?;
-    for op in modify.ops.iter() {
-        match op.typ {
-            ModifyChangeRecordOpType::Add => {
-                writeln!(w, "add: {}", op.attr)?;
-            }
-            ModifyChangeRecordOpType::Delete => {
-                writeln!(w, "delete: {}", op.attr)?;
-            }
-            ModifyChangeRecordOpType::Replace => {
-                writeln!(w, "replace: {}", op.attr)?;
+const HTML_STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin-bottom:1em}\
+th,td{border:1px solid #ccc;padding:.2em .6em;text-align:left;vertical-align:top}\
+td.value{font-family:monospace;white-space:pre-wrap;word-break:break-all}\
+tr.added td.value{background:#e6ffec}\
+tr.removed td.value{background:#ffebe9;text-decoration:line-through}\
+tr.replaced td.value{background:#fff8c5}\
+h2{font-size:1.1em;font-family:monospace;margin-top:1.5em}\
+h2 .kind{font-family:sans-serif;font-weight:normal;color:#666}";
+
+// Gathers the changerecords written to it into an HTML report, with the tables of an
+// AttributeReport followed by a section for each changerecord.
+#[derive(Default)]
+struct HtmlReport {
+    counts: AttributeReport,
+    sections: String,
+}
+
+impl HtmlReport {
+    fn write<W: Write>(&self, title: &str, dest: &mut W) -> std::io::Result<()> {
+        let title = escape_html(title);
+        writeln!(dest, "<!DOCTYPE html>")?;
+        writeln!(dest, "<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body>", title, HTML_STYLE)?;
+        writeln!(dest, "<h1>{}</h1>", title)?;
+        let entries = &self.counts.entries;
+        writeln!(dest, "<table><tr><th>entries added</th><th>deleted</th><th>changed</th></tr>")?;
+        writeln!(dest, "<tr><td>{}</td><td>{}</td><td>{}</td></tr></table>", entries.added, entries.removed, entries.entries - entries.added - entries.removed)?;
+        writeln!(dest, "<table><tr><th>attribute</th><th>values added</th><th>values removed</th><th>entries</th></tr>")?;
+        for (attr, counts) in self.counts.attributes.iter() {
+            writeln!(dest, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>", escape_html(attr), counts.added, counts.removed, counts.entries)?;
+        }
+        writeln!(dest, "</table>")?;
+        dest.write_all(self.sections.as_bytes())?;
+        writeln!(dest, "</body></html>")
+    }
+
+    fn push_section(&mut self, dn: &str, kind: &str, rows: &[(&str, &str, Cow<str>)]) {
+        self.sections.push_str(&format!("<section><h2>{} <span class=\"kind\">{}</span></h2>\n", escape_html(dn), kind));
+        if !rows.is_empty() {
+            self.sections.push_str("<table>\n");
+            for (class, attr, value) in rows.iter() {
+                self.sections.push_str(&format!("<tr class=\"{}\"><td>{}</td><td class=\"value\">{}</td></tr>\n", class, escape_html(attr), escape_html(value)));
             }
+            self.sections.push_str("</table>\n");
         }
-        for value in op.values.iter() {
-            write_attrval(&mut w, &op.attr, value)?;
+        self.sections.push_str("</section>\n");
+    }
+}
+
+impl WriteChangeRecord for HtmlReport {
+    fn write_add(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.counts.write_add(entry)?;
+        let rows: Vec<(&str, &str, Cow<str>)> = entry.attributes()
+            .filter(|attr| attr.lowercase != "dn")
+            .flat_map(|attr| entry.get(attr.lowercase).map(move |value| ("added", attr.name, String::from_utf8_lossy(value))))
+            .collect();
+        self.push_section(&entry.get_one_str("dn").unwrap_or_default(), "added", &rows);
+        Ok(())
+    }
+
+    fn write_delete(&mut self, dn: &str) -> std::io::Result<()> {
+        self.counts.write_delete(dn)?;
+        self.push_section(dn, "deleted", &[]);
+        Ok(())
+    }
+
+    fn write_modify(&mut self, modify: &ModifyChangeRecord) -> std::io::Result<()> {
+        self.counts.write_modify(modify)?;
+        let rows: Vec<(&str, &str, Cow<str>)> = modify.ops.iter()
+            .flat_map(|op| {
+                let class = match op.typ {
+                    ModifyChangeRecordOpType::Add => "added",
+                    ModifyChangeRecordOpType::Delete => "removed",
+                    ModifyChangeRecordOpType::Replace => "replaced",
+                };
+                op.values.iter().map(move |value| (class, op.attr.as_str(), String::from_utf8_lossy(value)))
+            })
+            .collect();
+        self.push_section(&modify.dn, "changed", &rows);
+        Ok(())
+    }
+
+    fn write_modrdn(&mut self, modrdn: &ModRdnChangeRecord) -> std::io::Result<()> {
+        self.counts.write_modrdn(modrdn)?;
+        let mut rows: Vec<(&str, &str, Cow<str>)> = vec![("added", "newrdn", Cow::Borrowed(modrdn.new_rdn))];
+        rows.extend(modrdn.new_superior.map(|superior| ("added", "newsuperior", Cow::Borrowed(superior))));
+        self.push_section(modrdn.dn, "renamed", &rows);
+        Ok(())
+    }
+}
+
+// Writes changerecords to the first destination, and to the second if there is one.
+struct Both<'a, A: WriteChangeRecord, B: WriteChangeRecord>(&'a mut A, Option<&'a mut B>);
+
+impl<A: WriteChangeRecord, B: WriteChangeRecord> WriteChangeRecord for Both<'_, A, B> {
+    fn write_add(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.0.write_add(entry)?;
+        match self.1 {
+            Some(ref mut dest) => dest.write_add(entry),
+            None => Ok(()),
+        }
+    }
+
+    fn write_delete(&mut self, dn: &str) -> std::io::Result<()> {
+        self.0.write_delete(dn)?;
+        match self.1 {
+            Some(ref mut dest) => dest.write_delete(dn),
+            None => Ok(()),
+        }
+    }
+
+    fn write_modify(&mut self, modify: &ModifyChangeRecord) -> std::io::Result<()> {
+        self.0.write_modify(modify)?;
+        match self.1 {
+            Some(ref mut dest) => dest.write_modify(modify),
+            None => Ok(()),
+        }
+    }
+
+    fn write_modrdn(&mut self, modrdn: &ModRdnChangeRecord) -> std::io::Result<()> {
+        self.0.write_modrdn(modrdn)?;
+        match self.1 {
+            Some(ref mut dest) => dest.write_modrdn(modrdn),
+            None => Ok(()),
         }
-        writeln!(w, "-")?;
     }
-    writeln!(w)?;
-    Ok(())
 }
 
-fn compare_entries(
+fn compare_entries<W: WriteChangeRecord>(
     old_entries: &EntryBTreeMap,
     new_entries: &EntryBTreeMap,
     params: &Parameters,
+    fingerprints: &HashMap<String, u64>, // of the old entries
+    dest: &mut W,
 ) -> std::io::Result<()> {
-    let comparison = SortedComparison{
-        old_iter: old_entries.0.iter().peekable(),
-        new_iter: new_entries.0.iter().peekable(),
-        compare_items: |(old_dn, _), (new_dn, _)| old_dn.cmp(new_dn),
-    };
+    let comparison = SortedComparison::new(&old_entries.0, &new_entries.0, |(old_dn, _), (new_dn, _)| old_dn.cmp(new_dn));
     let mut deferred_deletes: Vec<Cow<str>> = Vec::new();
     let mut deferred_modifies: Vec<ModifyChangeRecord> = Vec::new();
+    let is_selected = |entry: &OwnedEntry| params.filter.as_ref()
+        .map(|filter| filter.is_match(entry))
+        .unwrap_or(true);
+    let is_unchanged = |dn: &DnKey, entry: &OwnedEntry| fingerprints.get(&dn.dn)
+        .is_some_and(|fingerprint| *fingerprint == entry.fingerprint());
     for op in comparison {
         match op {
+            Diff::Add((_, new_entry)) if !is_selected(new_entry) => (),
+            Diff::Delete((_, old_entry)) if !is_selected(old_entry) => (),
+            Diff::Modify((_, old_entry), (_, new_entry)) if !is_selected(old_entry) && !is_selected(new_entry) => (),
+            Diff::Modify(_, (dn, new_entry)) if is_unchanged(dn, new_entry) => (),
             Diff::Add((_, new_entry)) => {
-                write_add(
-                    &mut std::io::stdout(),
-                    new_entry,
-                    &params.attrs,
-                    params.invert,
-                )?;
+                dest.write_add(&select_attributes(new_entry, &params.attrs, params.invert))?;
                 if let Some(defer) =
-                    ModifyChangeRecord::new(None, new_entry, &params.defer_attrs, false)
+                    ModifyChangeRecord::with_options(None, new_entry, &params.defer_attrs, false, &params.modify_options)
                 {
                     deferred_modifies.push(defer)
                 }
@@ -375,24 +588,27 @@ fn compare_entries(
                     if params.force {
                         deferred_deletes.push(dn);
                     } else {
-                        eprintln!("lcompare: no --force option given, not writing delete changerecord for {}", dn);
+                        let message = format!("no --force option given, not writing delete changerecord for {}", dn);
+                        report("lcompare", &Diagnostic::new(Severity::Warning, "delete-without-force", message).in_entry(Some(&dn)));
                     }
                 }
             },
             Diff::Modify((_, old_entry), (_, new_entry)) => {
-                if let Some(change) = ModifyChangeRecord::new(
+                if let Some(change) = ModifyChangeRecord::with_options(
                     Some(old_entry),
                     new_entry,
                     &params.attrs,
                     params.invert,
+                    &params.modify_options,
                 ) {
-                    write_modify(&mut std::io::stdout(), &change)?;
+                    dest.write_modify(&change)?;
                 }
-                if let Some(defer) = ModifyChangeRecord::new(
+                if let Some(defer) = ModifyChangeRecord::with_options(
                     Some(old_entry),
                     new_entry,
                     &params.defer_attrs,
                     false,
+                    &params.modify_options,
                 ) {
                     deferred_modifies.push(defer)
                 }
@@ -400,89 +616,84 @@ fn compare_entries(
         }
     }
     for modify in deferred_modifies.iter() {
-        write_modify(&mut std::io::stdout(), modify)?;
+        dest.write_modify(modify)?;
     }
     for delete in deferred_deletes.iter().rev() {
-        write_delete(&mut std::io::stdout(), delete)?;
+        dest.write_delete(delete)?;
     }
     Ok(())
 }
 
-struct SortedComparison<T, O, N, F>
-    where T: Copy,
-          O: Iterator<Item = T>,
-          N: Iterator<Item = T>,
-          F: Fn(&T, &T) -> Ordering,
-{
-    old_iter: Peekable<O>,
-    new_iter: Peekable<N>,
-    compare_items: F,
-}
-
-enum Diff<T> {
-    Add(T),
-    Delete(T),
-    Modify(T, T),
-}
-
-impl<T, O, N, F> Iterator for SortedComparison<T, O, N, F>
-where T: Copy,
-      O: Iterator<Item = T>,
-      N: Iterator<Item = T>,
-      F: Fn(&T, &T) -> Ordering,
-{
-    type Item = Diff<T>;
-
-    fn next(&mut self) -> Option<Diff<T>> {
-        match (self.old_iter.peek(), self.new_iter.peek()) {
-            (Some(old), Some(new)) => {
-                match (self.compare_items)(old, new) {
-                    Ordering::Less => {
-                        let op = Diff::Delete(*old);
-                        self.old_iter.next();
-                        Some(op)
-                    },
-                    Ordering::Greater => {
-                        let op = Diff::Add(*new);
-                        self.new_iter.next();
-                        Some(op)
-                    },
-                    Ordering::Equal => {
-                        let op = Diff::Modify(*old, *new);
-                        self.old_iter.next();
-                        self.new_iter.next();
-                        Some(op)
-                    },
-                }
-            },
-            (Some(old), None) => {
-                let op = Diff::Delete(*old);
-                self.old_iter.next();
-                Some(op)
-            },
-            (None, Some(new)) => {
-                let op = Diff::Add(*new);
-                self.new_iter.next();
-                Some(op)
-            },
-            (None, None) => None,
-        }
-    }
-}
-
 fn do_io<Old: Read, New: Read>(
     old: &mut Old,
     new: &mut New,
     params: &Parameters,
 ) -> std::io::Result<()> {
-    let old_entries = read_entries(old)?;
-    let new_entries = read_entries(new)?;
-    compare_entries(&old_entries, &new_entries, params)?;
+    let (old_entries, new_entries) = match params.progress {
+        Some(ref progress) => {
+            let old_entries = read_entries(decompress(ProgressReader::new(old, progress))?, params)?;
+            let new_entries = read_entries(decompress(ProgressReader::new(new, progress))?, params)?;
+            progress.finish();
+            (old_entries, new_entries)
+        },
+        None => (read_entries(decompress(old)?, params)?, read_entries(decompress(new)?, params)?),
+    };
+    let mut dest = create_output(&params.output, params.compression)
+        .map_err(|err| FileError::new(&params.output, err))?;
+    let fingerprints = match params.fingerprint_cache {
+        Some(ref path) => read_fingerprints(path).map_err(|err| FileError::new(path, err))?,
+        None => HashMap::new(),
+    };
+    let mut html = params.html.as_ref().map(|_| HtmlReport::default());
+    if params.attr_report {
+        let mut report = AttributeReport::default();
+        compare_entries(&old_entries, &new_entries, params, &fingerprints, &mut Both(&mut report, html.as_mut()))?;
+        report.write(&mut dest)?;
+    } else {
+        let mut writer = LdifChangeRecordWriter::new(&mut dest);
+        if params.preserve_encoding {
+            let mut policy = params.base64_policy.clone().unwrap_or_default();
+            for entry in old_entries.0.values().chain(new_entries.0.values()) {
+                policy.add_encoded_values(entry);
+            }
+            writer.set_base64_policy(policy);
+        } else if let Some(ref policy) = params.base64_policy {
+            writer.set_base64_policy(policy.clone());
+        }
+        compare_entries(&old_entries, &new_entries, params, &fingerprints, &mut Both(&mut writer, html.as_mut()))?;
+    }
+    dest.finish()?;
+    if let (Some(path), Some(html)) = (&params.html, &html) {
+        let title = format!("Changes from {} to {}", params.old, params.new);
+        File::create(path)
+            .map(BufWriter::new)
+            .and_then(|mut file| html.write(&title, &mut file).and_then(|_| file.flush()))
+            .map_err(|err| FileError::new(path, err))?;
+    }
+    if let Some(ref path) = params.fingerprint_cache {
+        write_fingerprints(path, &new_entries).map_err(|err| FileError::new(path, err))?;
+    }
     Ok(())
 }
 
 fn get_result() -> Result<(), Box<dyn std::error::Error>> {
-    let params = parse_arguments()?;
+    let mut params = parse_arguments()?;
+    if let Some(ref path) = params.map_file {
+        params.map = Some(AttributeMap::load(path).map_err(|err| FileError::new(path, err))?);
+    }
+    if let Some(ref path) = params.schema {
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        open_input(path)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut entries))
+            .map_err(|err| FileError::new(path, err))?;
+        let entry = entries.iter()
+            .find(|entry| entry.get_one("attributetypes").is_some())
+            .ok_or("the schema file has no attributeTypes")?;
+        params.modify_options.normalizers = Normalizers::from_syntaxes(&attribute_syntaxes_from_schema(entry));
+    }
+    for spec in params.normalize.iter() {
+        params.modify_options.normalizers.parse_and_add(spec)?;
+    }
     match (&params.old[..], &params.new[..]) {
         ("-", "-") => return Err("both inputs can't be standard input".into()),
         ("-", new) => {
@@ -508,7 +719,7 @@ fn get_result() -> Result<(), Box<dyn std::error::Error>> {
 fn main() {
     let result = get_result();
     if let Err(err) = result {
-        eprintln!("lcompare: {}", err);
+        report_error("lcompare", &*err);
         std::process::exit(1);
     }
 }
@@ -518,22 +729,135 @@ mod test {
     use super::*;
 
     #[test]
-    fn test() {
-        let old = vec![1,3,5];
-        let new = vec![2,3,4];
-        let comparison = SortedComparison{
-            old_iter: old.iter().peekable(),
-            new_iter: new.iter().peekable(),
-            compare_items: |x, y| x.cmp(y),
-        };
-
-        for op in comparison {
-            match op {
-                Diff::Add(x) => println!("add {}", x),
-                Diff::Delete(x) => println!("del {}", x),
-                Diff::Modify(x, y) => println!("mod {} {}", x, y),
+    fn test_dn_order() {
+        let mut keys: Vec<DnKey> = [
+            "cn=foo,ou=people,dc=example",
+            "ou=a\\,b,dc=example",
+            "dc=example",
+            "cn=x,ou=a\\,b,dc=example",
+            "ou=people, dc=example",
+            "cn=bar,ou=p,dc=example",
+            "ou=p,dc=example",
+        ].into_iter().map(DnKey::new).collect();
+        keys.sort();
+        let position = |dn: &str| keys.iter().position(|key| key.dn == dn).unwrap();
+        for key in keys.iter() {
+            let mut ancestor = key.dn.as_str();
+            while let Some(parent) = ltools::dn::parent(ancestor) {
+                assert!(position(parent) < position(&key.dn), "{} before {}", parent, key.dn);
+                ancestor = parent;
             }
         }
     }
-}
 
+    // Compares old with new with the options, and returns the changerecords.
+    fn compare(old: &str, new: &str, options: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+        let output = std::env::temp_dir().join(format!("ltools-lcompare-{}-{:?}.ldif", std::process::id(), std::thread::current().id()));
+        let output = output.to_string_lossy();
+        let mut args = vec!["lcompare", "old.ldif", "new.ldif", "-o", &output];
+        args.extend_from_slice(options);
+        let params = parse_arguments_from(command(), args)?;
+        do_io(&mut old.as_bytes(), &mut new.as_bytes(), &params)?;
+        let changes = std::fs::read_to_string(&*output)?;
+        std::fs::remove_file(&*output)?;
+        Ok(changes)
+    }
+
+    fn compare_with_cache(old: &str, new: &str, cache: &str) -> Result<String, Box<dyn std::error::Error>> {
+        compare(old, new, &["--fingerprint-cache", cache])
+    }
+
+    #[test]
+    fn test_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let old = "dn: cn=a,dc=example\nobjectClass: person\nsn: 1\n\n\
+                   dn: cn=b,dc=example\nobjectClass: group\nsn: 1\n\n\
+                   dn: cn=c,dc=example\nobjectClass: group\nsn: 1\n\n\
+                   dn: cn=d,dc=example\nobjectClass: person\n\n";
+        let new = "dn: cn=a,dc=example\nobjectClass: person\nsn: 2\n\n\
+                   dn: cn=b,dc=example\nobjectClass: group\nsn: 2\n\n\
+                   dn: cn=c,dc=example\nobjectClass: person\nsn: 1\n\n\
+                   dn: cn=e,dc=example\nobjectClass: group\n\n\
+                   dn: cn=f,dc=example\nobjectClass: person\n\n";
+        let dns = |changes: String| -> Vec<String> {
+            changes.lines().filter_map(|line| line.strip_prefix("dn: ")).map(String::from).collect()
+        };
+        // an entry is compared if its old or its new version matches, and only matching entries
+        // are added or deleted
+        let changes = compare(old, new, &["--force", "--filter", "(objectClass=person)"])?;
+        assert_eq!(dns(changes), ["cn=a,dc=example", "cn=c,dc=example", "cn=f,dc=example", "cn=d,dc=example"]);
+        let changes = compare(old, new, &["--force", "--filter", "(&(objectClass=group)(sn=2))"])?;
+        assert_eq!(dns(changes), ["cn=b,dc=example"]);
+        assert!(compare(old, new, &["--filter", "(objectClass=person"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let cache = std::env::temp_dir().join(format!("ltools-lcompare-cache-{}", std::process::id())).to_string_lossy().into_owned();
+        let first = "dn: cn=a,dc=example\nsn: 1\n\ndn: cn=b,dc=example\nsn: 1\n";
+        let second = "dn: cn=a,dc=example\nsn: 2\n\ndn: cn=b,dc=example\nsn: 1\n";
+        let third = "dn: cn=a,dc=example\nsn: 2\n\ndn: cn=b,dc=example\nsn: 3\n";
+
+        // without a cache file, every entry is compared, and the cache is written afterwards
+        assert!(compare_with_cache(first, second, &cache)?.contains("dn: cn=a,dc=example\nchangetype: modify\nreplace: sn\nsn: 2\n"));
+        let fingerprints = read_fingerprints(&cache)?;
+        assert_eq!(fingerprints.len(), 2);
+        assert_eq!(fingerprints.get("cn=a,dc=example"), Some(&OwnedEntry::from([("dn", b"cn=a,dc=example".as_slice()), ("sn", b"2")]).fingerprint()));
+
+        // a new entry with the fingerprint in the cache is taken to be unchanged and isn't
+        // compared, even though the old input given here differs
+        assert_eq!(compare_with_cache(first, second, &cache)?, "");
+
+        // an entry that changed since the cache was written is compared, however stale the cache
+        let changes = compare_with_cache(second, third, &cache)?;
+        assert!(changes.contains("dn: cn=b,dc=example\nchangetype: modify\nreplace: sn\nsn: 3\n"));
+        assert!(!changes.contains("cn=a"));
+        assert_eq!(read_fingerprints(&cache)?.get("cn=b,dc=example"), Some(&OwnedEntry::from([("dn", b"cn=b,dc=example".as_slice()), ("sn", b"3")]).fingerprint()));
+
+        std::fs::write(&cache, "cn=a,dc=example\n")?;
+        assert!(compare_with_cache(second, third, &cache).is_err());
+        std::fs::remove_file(&cache)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_attr_report() -> std::io::Result<()> {
+        let old = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("mail", b"a@example.com"), ("sn", b"Foo")]);
+        let new = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("mail", b"b@example.com"), ("mail", b"c@example.com"), ("sn", b"Bar")]);
+        let added = OwnedEntry::from([("dn", b"cn=bar".as_slice()), ("sn", b"Bar")]);
+        let mut report = AttributeReport::default();
+        report.write_modify(&ModifyChangeRecord::new(Some(&old), &new, &[], true, false).unwrap())?;
+        report.write_add(&added)?;
+        report.write_delete("cn=baz")?;
+        let mut table: Vec<u8> = Vec::new();
+        report.write(&mut table)?;
+        assert_eq!(String::from_utf8_lossy(&table), concat!(
+            "attribute\tadded\tremoved\tentries\n",
+            "dn\t1\t1\t3\n",
+            "mail\t2\t1\t1\n",
+            "sn\t2\t1\t2\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_html_report() -> std::io::Result<()> {
+        let old = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("mail", b"a@example.com"), ("sn", b"Foo")]);
+        let new = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("mail", b"<b@example.com>"), ("mail", b"c@example.com"), ("sn", b"Foo")]);
+        let mut report = HtmlReport::default();
+        report.write_modify(&ModifyChangeRecord::new(Some(&old), &new, &[], true, false).unwrap())?;
+        report.write_delete("cn=bar")?;
+        let mut html: Vec<u8> = Vec::new();
+        report.write("a & b", &mut html)?;
+        let html = String::from_utf8_lossy(&html);
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<h1>a &amp; b</h1>"));
+        assert!(html.contains("<tr><td>0</td><td>1</td><td>1</td></tr>"));
+        assert!(html.contains("<tr><td>mail</td><td>2</td><td>1</td><td>1</td></tr>"));
+        assert!(html.contains("<tr class=\"removed\"><td>mail</td><td class=\"value\">a@example.com</td></tr>"));
+        assert!(html.contains("<tr class=\"added\"><td>mail</td><td class=\"value\">&lt;b@example.com&gt;</td></tr>"));
+        assert!(html.contains("<h2>cn=bar <span class=\"kind\">deleted</span></h2>"));
+        assert!(html.ends_with("</body></html>\n"));
+        Ok(())
+    }
+}
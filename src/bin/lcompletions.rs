@@ -0,0 +1,128 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::completions::{Shell, VARIABLE};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::entry::OwnedEntry;
+use ltools::schema::AttributeTypeDescription;
+use std::collections::HashSet;
+use std::io::{stdout, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::process::Command;
+
+const TOOLS: &[&str] = &[
+    "lanonymize", "lblob", "lcat", "lchangelog", "lcompare", "lcompletions", "lcount", "ldiff3",
+    "lescape", "lfilter", "lfingerprint", "lfmt", "lgen", "lget", "lgraph", "lgroup", "lpasswd",
+    "lprocess", "lquery", "lrefcheck", "lrename", "lsample", "lsed", "ltemplate", "ltree", "lundo",
+    "luniq-check", "lvalidate", "lvalues", "lwatch",
+];
+
+struct Parameters {
+    shell: Option<String>,
+    attributes: bool,
+    schema: Option<String>,
+    samples: Vec<String>,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lcompletions")
+        .disable_colored_help(true)
+        .about("Writes completions for every tool for the given shell, to be loaded with 'source <(lcompletions bash)' in ~/.bashrc, 'source <(lcompletions zsh)' after compinit in ~/.zshrc or 'lcompletions fish | source' in config.fish. Options and arguments that take attribute names complete them with the output of 'lcompletions --attributes', which lists the attribute types of the schema and the attributes of the sample files, best set in the [lcompletions] section of ltools.toml.")
+        .arg(arg!([SHELL] "The shell, which is bash, zsh or fish.")
+            .required_unless_present("attributes"))
+        .arg(arg!(--attributes "Instead of completions, write the attribute names from the schema and the sample files, one per line.")
+            .action(clap::ArgAction::SetTrue)
+            .conflicts_with("SHELL"))
+        .arg(arg!(--schema <FILE> "List the attribute types of the subschema entry in FILE, such as one exported from cn=schema.")
+            .required(false))
+        .arg(arg!(--sample <FILE> "List the attributes of the entries in FILE. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    Ok(Parameters{
+        shell: match matches.get_one::<String>("SHELL") {
+            Some(shell) if Shell::parse(shell).is_err() => return Err("the shell must be bash, zsh or fish"),
+            shell => shell.cloned(),
+        },
+        attributes: matches.get_flag("attributes"),
+        schema: matches.get_one::<String>("schema").cloned(),
+        samples: matches.get_many::<String>("sample")
+            .map(|samples| samples.cloned().collect())
+            .unwrap_or_default(),
+    })
+}
+
+fn read_entries(path: &str) -> Result<Vec<OwnedEntry>, FileError> {
+    let mut entries: Vec<OwnedEntry> = Vec::new();
+    open_input(path)
+        .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut entries))
+        .map_err(|err| FileError::new(path, err))?;
+    Ok(entries)
+}
+
+// The attribute names as first spelled, sorted regardless of case.
+fn attribute_names(params: &Parameters) -> Result<Vec<String>, FileError> {
+    let mut names: Vec<String> = Vec::new();
+    if let Some(ref schema) = params.schema {
+        for entry in read_entries(schema)?.iter() {
+            let descriptions = entry.get_str("attributetypes")
+                .filter_map(|description| AttributeTypeDescription::parse(&description));
+            names.extend(descriptions.flat_map(|description| description.names));
+        }
+    }
+    for sample in params.samples.iter() {
+        for entry in read_entries(sample)?.iter() {
+            names.extend(entry.attributes().map(|attr| attr.name.to_string()));
+        }
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    names.retain(|name| seen.insert(name.to_ascii_lowercase()));
+    names.sort_by_key(|name| name.to_ascii_lowercase());
+    Ok(names)
+}
+
+// Each tool writes its own completions, as only it knows its options. The tools are looked for
+// next to this one, and those that aren't installed are left out.
+fn write_completions(shell: &str, dest: &mut dyn Write) -> std::io::Result<()> {
+    let exe = std::env::current_exe()?;
+    let dir = exe.parent().unwrap_or(Path::new("."));
+    for tool in TOOLS {
+        let output = match Command::new(dir.join(tool)).env(VARIABLE, shell).output() {
+            Ok(output) => output,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!("{}: {}", tool, String::from_utf8_lossy(&output.stderr).trim())));
+        }
+        dest.write_all(&output.stdout)?;
+    }
+    Ok(())
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut dest = BufWriter::new(stdout());
+    if params.attributes {
+        for name in attribute_names(&params)? {
+            writeln!(dest, "{}", name)?;
+        }
+    } else if let Some(ref shell) = params.shell {
+        write_completions(shell, &mut dest)?;
+    }
+    dest.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lcompletions", &*err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,154 @@
+use clap::{arg, command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::bulk::BulkParser;
+use ltools::compress::{decompress, open_input};
+use ltools::entry::{Entry, WriteEntry};
+use ltools::filter::Filter;
+use ltools::jobs::{check_inputs, process_inputs};
+use std::io::{stdin, stdout, BufWriter, Read, Write};
+
+struct Parameters {
+    filter: Option<Filter>,
+    inputs: Vec<String>, // empty means standard input
+    jobs: usize,
+}
+
+fn parse_arguments() -> Result<Parameters, Box<dyn std::error::Error>> {
+    let matches = with_defaults(command!("lcount")
+        .disable_colored_help(true)
+        .about("Counts the entries of LDIF files, or of standard input if no files are given. With more than one file, the count of each file is written followed by a tab and the name of the file, and then the total.")
+        .arg(arg!([FILES] ... "LDIF files to count the entries of ('-' means standard input)."))
+        .arg(arg!(-f --filter <FILTER> "Only count entries that match FILTER, such as (objectClass=person).")
+            .required(false))
+        .arg(arg!(--"strict-filter" "Parse the filter strictly as RFC 4515 has it, in parentheses and without whitespace between its parts.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--jobs <N> "Count up to N files at the same time. The counts are still written in the order the files are given.")
+            .required(false)
+            .value_parser(clap::value_parser!(usize)))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let filter = match matches.get_one::<String>("filter") {
+        Some(filter) if matches.get_flag("strict-filter") => Some(Filter::parse_strict(filter)?),
+        Some(filter) => Some(Filter::parse(filter)?),
+        None => None,
+    };
+
+    Ok(Parameters{
+        filter,
+        inputs: matches.get_many::<String>("FILES").into_iter().flatten().cloned().collect(),
+        jobs: matches.get_one::<usize>("jobs").copied().unwrap_or(1),
+    })
+}
+
+struct Counter<'a> {
+    filter: Option<&'a Filter>,
+    count: u64,
+}
+
+impl WriteEntry for Counter<'_> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        if self.filter.is_none_or(|filter| filter.is_match(entry)) {
+            self.count += 1;
+        }
+        Ok(())
+    }
+}
+
+fn count_input(filter: Option<&Filter>, input: &mut dyn Read) -> std::io::Result<u64> {
+    let mut counter = Counter{ filter, count: 0 };
+    // records without a DN, such as version: 1, aren't entries
+    BulkParser::new()
+        .set_ignore_entries_without_dn(true)
+        .write_entries(&mut decompress(input)?, &mut counter)?;
+    Ok(counter.count)
+}
+
+fn count_file(filter: Option<&Filter>, path: &str) -> std::io::Result<u64> {
+    open_input(path)
+        .and_then(|mut input| count_input(filter, &mut input))
+        .map_err(|err| FileError::new(path, err).into())
+}
+
+fn write_counts<W: Write>(params: &Parameters, dest: &mut W) -> std::io::Result<()> {
+    let filter = params.filter.as_ref();
+    if params.inputs.is_empty() {
+        return writeln!(dest, "{}", count_input(filter, &mut stdin())?);
+    }
+    if params.inputs.len() == 1 {
+        return writeln!(dest, "{}", count_file(filter, &params.inputs[0])?);
+    }
+    let mut total: u64 = 0;
+    let mut write_count = |(input, count): (&str, u64)| {
+        total += count;
+        writeln!(dest, "{}\t{}", count, input)
+    };
+    if params.jobs > 1 {
+        process_inputs(&params.inputs, params.jobs, |input| Ok((input.to_string(), count_file(filter, input)?)), |(input, count)| {
+            write_count((&input, count))
+        })?;
+    } else {
+        for input in params.inputs.iter() {
+            write_count((input, count_file(filter, input)?))?;
+        }
+    }
+    writeln!(dest, "{}\ttotal", total)
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    if params.jobs > 1 {
+        check_inputs(&params.inputs)?;
+    }
+    let mut dest = BufWriter::new(stdout());
+    write_counts(&params, &mut dest)?;
+    dest.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lcount", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count() -> std::io::Result<()> {
+        let ldif = b"version: 1\n\ndn: cn=a\nobjectClass: person\n\ndn: cn=b\nobjectClass: group\n\ndn: cn=c\nobjectClass: person\n";
+        assert_eq!(count_input(None, &mut &ldif[..])?, 3);
+        let filter = Filter::parse("(objectClass=person)").unwrap();
+        assert_eq!(count_input(Some(&filter), &mut &ldif[..])?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_jobs() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("ltools-lcount-jobs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let mut inputs: Vec<String> = Vec::new();
+        for i in 0..5 {
+            let path = dir.join(format!("{}.ldif", i));
+            let ldif: String = (0..10 * i).map(|j| format!("dn: uid=u{j},dc=example\nuid: u{j}\n\n")).collect();
+            std::fs::write(&path, ldif)?;
+            inputs.push(path.to_string_lossy().into_owned());
+        }
+        let mut serial: Vec<u8> = Vec::new();
+        write_counts(&Parameters{ filter: None, inputs: inputs.clone(), jobs: 1 }, &mut serial)?;
+        let mut parallel: Vec<u8> = Vec::new();
+        write_counts(&Parameters{ filter: None, inputs: inputs.clone(), jobs: 3 }, &mut parallel)?;
+        assert_eq!(String::from_utf8_lossy(&parallel), String::from_utf8_lossy(&serial));
+        assert!(String::from_utf8_lossy(&serial).ends_with("100\ttotal\n"));
+        assert!(String::from_utf8_lossy(&serial).starts_with(&format!("0\t{}\n10\t{}\n", inputs[0], inputs[1])));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
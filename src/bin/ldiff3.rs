@@ -0,0 +1,283 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::{create_output, open_input};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::dn::normalize;
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use ltools::store::EntryStore;
+use std::collections::HashSet;
+use std::io::{stdout, BufWriter, Write};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+struct Parameters {
+    base: String,
+    ours: String,
+    theirs: String,
+    prefer: Side,
+    report: Option<String>,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("ldiff3")
+        .disable_colored_help(true)
+        .about("Merges the changes that two snapshots, OURS and THEIRS, have made to a common ancestor BASE, and writes the merged LDIF. Attributes that both sides have changed differently are conflicts, which are resolved in favour of one side and reported. The exit status is 1 if there were conflicts.")
+        .arg(arg!(<BASE> "LDIF file with the common ancestor of OURS and THEIRS."))
+        .arg(arg!(<OURS> "LDIF file with the first changed snapshot, whose order of entries the merge follows."))
+        .arg(arg!(<THEIRS> "LDIF file with the second changed snapshot."))
+        .arg(arg!(-p --prefer <SIDE> "Resolve conflicts in favour of SIDE, which is ours (the default) or theirs.")
+            .required(false))
+        .arg(arg!(-r --report <FILE> "Write a record for each conflict to FILE, in LDIF, with the DN, the attribute and its values in BASE, OURS and THEIRS. An entry that one side deleted and the other changed is reported with a deleted line naming the side that deleted it.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let prefer = match matches.get_one::<String>("prefer").map(String::as_str) {
+        None | Some("ours") => Side::Ours,
+        Some("theirs") => Side::Theirs,
+        Some(_) => return Err("the side to prefer must be ours or theirs"),
+    };
+
+    Ok(Parameters{
+        base: matches.get_one::<String>("BASE").unwrap().clone(),
+        ours: matches.get_one::<String>("OURS").unwrap().clone(),
+        theirs: matches.get_one::<String>("THEIRS").unwrap().clone(),
+        prefer,
+        report: matches.get_one::<String>("report").cloned(),
+    })
+}
+
+// An attribute that both sides changed differently, or with no attribute, an entry that one side
+// deleted and the other changed. The values of a side are None if it deleted the entry.
+struct Conflict {
+    dn: String,
+    attribute: Option<String>,
+    base: Vec<Vec<u8>>,
+    ours: Option<Vec<Vec<u8>>>,
+    theirs: Option<Vec<Vec<u8>>>,
+}
+
+impl Conflict {
+    fn to_entry(&self) -> OwnedEntry {
+        let mut entry = OwnedEntry::default();
+        entry.add_value("dn", self.dn.as_bytes());
+        if let Some(ref attribute) = self.attribute {
+            entry.add_value("attribute", attribute.as_bytes());
+        }
+        for (name, values) in [("base", Some(&self.base)), ("ours", self.ours.as_ref()), ("theirs", self.theirs.as_ref())] {
+            for value in values.into_iter().flatten() {
+                entry.add_value(name, value);
+            }
+        }
+        match (&self.ours, &self.theirs) {
+            (None, _) => entry.add_value("deleted", b"ours"),
+            (_, None) => entry.add_value("deleted", b"theirs"),
+            _ => {},
+        }
+        entry
+    }
+}
+
+// The values of an attribute, sorted and without duplicates, for comparing them as a set.
+fn value_set<'a>(entry: Option<&'a Entry>, attr: &str) -> Vec<&'a [u8]> {
+    let mut values: Vec<&[u8]> = entry.into_iter().flat_map(|entry| entry.get(attr)).collect();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+// Whether both sides have the entry with the same values, or neither has it. The values are
+// compared as sets and the DN is left out, since the entries are paired by DN.
+fn is_same(a: Option<&Entry>, b: Option<&Entry>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.diff(b).is_empty(),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// Merges the attributes of an entry that both sides have, and that at least one side added or
+// both changed.
+fn merge_entry(base: Option<&Entry>, ours: &Entry, theirs: &Entry, prefer: Side, conflicts: &mut Vec<Conflict>) -> OwnedEntry {
+    let dn = ours.get_one_str("dn").unwrap_or_default().into_owned();
+    let mut merged = OwnedEntry::default();
+    merged.add_value("dn", dn.as_bytes());
+    let mut seen: HashSet<&str> = HashSet::from(["dn"]);
+    let attributes = ours.attributes()
+        .chain(theirs.attributes())
+        .chain(base.into_iter().flat_map(|base| base.attributes()));
+    for attr in attributes {
+        if !seen.insert(attr.lowercase) {
+            continue;
+        }
+        let base_values = value_set(base, attr.lowercase);
+        let our_values = value_set(Some(ours), attr.lowercase);
+        let their_values = value_set(Some(theirs), attr.lowercase);
+        let values = if our_values == their_values || base_values == their_values {
+            ours.get(attr.lowercase).collect::<Vec<&[u8]>>()
+        } else if base_values == our_values {
+            theirs.get(attr.lowercase).collect()
+        } else {
+            conflicts.push(Conflict{
+                dn: dn.clone(),
+                attribute: Some(attr.name.to_string()),
+                base: base_values.iter().map(|value| value.to_vec()).collect(),
+                ours: Some(our_values.iter().map(|value| value.to_vec()).collect()),
+                theirs: Some(their_values.iter().map(|value| value.to_vec()).collect()),
+            });
+            match prefer {
+                Side::Ours => ours.get(attr.lowercase).collect(),
+                Side::Theirs => theirs.get(attr.lowercase).collect(),
+            }
+        };
+        for value in values {
+            merged.add_value(attr.name, value);
+        }
+    }
+    merged
+}
+
+// Writes the merged entries, in the order of ours followed by the entries that only theirs has,
+// and returns the conflicts.
+fn merge<W: WriteEntry>(base: &EntryStore, ours: &EntryStore, theirs: &EntryStore, prefer: Side, mut dest: W) -> std::io::Result<Vec<Conflict>> {
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let dns = ours.iter()
+        .chain(theirs.iter())
+        .filter_map(|entry| entry.get_one_str("dn"));
+    for dn in dns {
+        if !seen.insert(normalize(&dn)) {
+            continue;
+        }
+        let (b, o, t) = (base.get(&dn), ours.get(&dn), theirs.get(&dn));
+        let conflict = |deleted: Side| Conflict{
+            dn: dn.to_string(),
+            attribute: None,
+            base: Vec::new(),
+            ours: if deleted == Side::Ours { None } else { Some(Vec::new()) },
+            theirs: if deleted == Side::Theirs { None } else { Some(Vec::new()) },
+        };
+        match (o, t) {
+            (Some(o), Some(t)) if is_same(Some(o), Some(t)) || is_same(b, Some(t)) => dest.write_entry(o)?,
+            (Some(_), Some(t)) if is_same(b, o) => dest.write_entry(t)?,
+            (Some(o), Some(t)) => dest.write_entry(&merge_entry(b, o, t, prefer, &mut conflicts))?,
+            (Some(o), None) | (None, Some(o)) if b.is_none() => dest.write_entry(o)?,
+            (Some(o), None) if !is_same(b, Some(o)) => {
+                conflicts.push(conflict(Side::Theirs));
+                if prefer == Side::Ours {
+                    dest.write_entry(o)?;
+                }
+            },
+            (None, Some(t)) if !is_same(b, Some(t)) => {
+                conflicts.push(conflict(Side::Ours));
+                if prefer == Side::Theirs {
+                    dest.write_entry(t)?;
+                }
+            },
+            // deleted by one side and left unchanged by the other
+            _ => {},
+        }
+    }
+    Ok(conflicts)
+}
+
+fn get_result() -> Result<i32, Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut stores: Vec<EntryStore> = Vec::new();
+    for input in [&params.base, &params.ours, &params.theirs] {
+        let mut store = EntryStore::new();
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut store))
+            .map_err(|err| FileError::new(input, err))?;
+        stores.push(store);
+    }
+
+    let mut dest = BufWriter::new(stdout());
+    let mut writer = LdifEntryWriter::new(&mut dest);
+    writer.set_sort_attributes(false);
+    let conflicts = merge(&stores[0], &stores[1], &stores[2], params.prefer, writer)?;
+    dest.flush()?;
+
+    if let Some(ref report_file) = params.report {
+        let mut output = create_output(report_file, None).map_err(|err| FileError::new(report_file, err))?;
+        let mut writer = LdifEntryWriter::new(&mut output);
+        writer.set_sort_attributes(false);
+        for conflict in conflicts.iter() {
+            writer.write_entry(&conflict.to_entry())?;
+        }
+        output.finish()?;
+    }
+    if conflicts.is_empty() {
+        return Ok(0);
+    }
+    let message = format!("resolved {} conflicts in favour of {}", conflicts.len(), if params.prefer == Side::Ours { "ours" } else { "theirs" });
+    report("ldiff3", &Diagnostic::new(Severity::Warning, "merge-conflict", message));
+    Ok(1)
+}
+
+fn main() {
+    match get_result() {
+        Err(err) => {
+            report_error("ldiff3", &*err);
+            std::process::exit(2);
+        },
+        Ok(status) => std::process::exit(status),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ltools::bulk::parse_entries;
+
+    fn store(ldif: &str) -> std::io::Result<EntryStore> {
+        let mut store = EntryStore::new();
+        parse_entries(ldif.as_bytes(), &mut store)?;
+        Ok(store)
+    }
+
+    #[test]
+    fn test_merge() -> std::io::Result<()> {
+        let base = store(concat!(
+            "dn: cn=a\ncn: a\nmail: a@example.com\nsn: A\n\n",
+            "dn: cn=b\ncn: b\n\n",
+            "dn: cn=c\ncn: c\n\n",
+        ))?;
+        let ours = store(concat!(
+            "dn: cn=a\ncn: a\nmail: a@example.com\nsn: Ours\n\n",
+            "dn: cn=c\ncn: c\ntitle: Boss\n\n",
+            "dn: cn=d\ncn: d\n\n",
+        ))?;
+        let theirs = store(concat!(
+            "dn: cn=a\ncn: a\nmail: b@example.com\nsn: Theirs\n\n",
+            "dn: cn=b\ncn: b\n\n",
+            "dn: cn=e\ncn: e\n\n",
+        ))?;
+        let mut merged: Vec<OwnedEntry> = Vec::new();
+        let conflicts = merge(&base, &ours, &theirs, Side::Ours, &mut merged)?;
+        let dns: Vec<String> = merged.iter().map(|entry| entry.get_one_str("dn").unwrap().into_owned()).collect();
+        assert_eq!(dns, ["cn=a", "cn=c", "cn=d", "cn=e"]);
+        assert_eq!(merged[0].get_one_str("mail").as_deref(), Some("b@example.com"));
+        assert_eq!(merged[0].get_one_str("sn").as_deref(), Some("Ours"));
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].attribute.as_deref(), Some("sn"));
+        assert_eq!(conflicts[0].theirs, Some(vec![b"Theirs".to_vec()]));
+        assert_eq!(conflicts[1].dn, "cn=c");
+        assert_eq!(conflicts[1].to_entry().get_one_str("deleted").as_deref(), Some("theirs"));
+
+        let mut merged: Vec<OwnedEntry> = Vec::new();
+        merge(&base, &ours, &theirs, Side::Theirs, &mut merged)?;
+        assert_eq!(merged[0].get_one_str("sn").as_deref(), Some("Theirs"));
+        assert_eq!(merged.len(), 3);
+        Ok(())
+    }
+}
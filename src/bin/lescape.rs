@@ -1,11 +1,21 @@
-use clap::{command, Arg};
+use clap::{arg, command, Arg};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output};
+use ltools::dn;
+use ltools::filter::escape_value;
 use std::io::Write;
 use std::matches;
 
-fn parse_arguments() -> Result<bool, &'static str> {
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Mode {
+    Filter, // RFC 4515 search filter values
+    Dn,     // RFC 4514 distinguished name attribute values
+}
+
+fn parse_arguments() -> Result<(bool, Mode), &'static str> {
     let mut reverse_escaping = false;
 
-    let matches = command!("lescape")
+    let matches = with_defaults(command!("lescape")
         .disable_colored_help(true)
         .arg(
             Arg::new("reverse")
@@ -14,46 +24,80 @@ fn parse_arguments() -> Result<bool, &'static str> {
                 .action(clap::ArgAction::SetTrue)
                 .help("Reverse the escaping."),
         )
-        .get_matches();
+        .arg(
+            Arg::new("mode")
+                .short('m')
+                .long("mode")
+                .value_parser(["filter", "dn"])
+                .default_value("filter")
+                .help("Escape for use in LDAP search filters (RFC 4515) or in DNs (RFC 4514)."),
+        )
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
 
     if matches.get_flag("reverse") {
         reverse_escaping = true;
     }
 
-    Ok(reverse_escaping)
+    let mode = match matches.get_one::<String>("mode").map(String::as_str) {
+        Some("dn") => Mode::Dn,
+        Some("filter") | None => Mode::Filter,
+        Some(_) => return Err("invalid escaping mode"),
+    };
+
+    Ok((reverse_escaping, mode))
 }
 
 fn lescape<W: Write>(mut dest: W, buf: &[u8]) -> std::io::Result<usize> {
-    let mut written = 0;
-    for (i, c) in buf.iter().copied().enumerate() {
-        if !c.is_ascii() || matches!(c, b'\\' | b'*' | b'(' | b')' | b':' | b'\0') {
-            if i > written {
-                dest.write_all(&buf[written..i])?;
-            }
-            write!(dest, "\\{c:02x}")?;
-            written = i + 1;
-        }
-    }
-    if written != buf.len() {
-        dest.write_all(&buf[written..])?;
-    }
+    dest.write_all(escape_value(buf).as_bytes())?;
     Ok(buf.len())
 }
 
+// characters that may be escaped with a backslash in a DN attribute value
+fn is_dn_special(c: u8) -> bool {
+    matches!(c, b'"' | b'+' | b',' | b';' | b'<' | b'>' | b'\\' | b' ' | b'#' | b'=')
+}
+
 struct LEscaper<W: Write> {
     dest: W,
+    mode: Mode,
+    line: Vec<u8>, // used in DN mode, where each line is escaped once it has been read
 }
 
 impl<W: Write> LEscaper<W> {
-    fn new(dest: W) -> LEscaper<W> {
+    fn new(dest: W, mode: Mode) -> LEscaper<W> {
         LEscaper{
             dest,
+            mode,
+            line: Vec::new(),
         }
     }
+
+    fn write_line(&mut self) -> std::io::Result<()> {
+        self.dest.write_all(dn::escape_value(&self.line).as_bytes())?;
+        self.line.clear();
+        Ok(())
+    }
+
+    // Each line is escaped as a separate DN attribute value.
+    fn dn_escape(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut lines = buf.split(|c| *c == b'\n');
+        self.line.extend_from_slice(lines.next().unwrap_or_default());
+        for line in lines {
+            self.write_line()?;
+            self.dest.write_all(b"\n")?;
+            self.line.extend_from_slice(line);
+        }
+        Ok(buf.len())
+    }
 }
 
 struct LUnescaper<W: Write> {
     dest: W,
+    mode: Mode,
     state: LUnescaperState,
 }
 
@@ -65,9 +109,10 @@ enum LUnescaperState {
 }
 
 impl<W: Write> LUnescaper<W> {
-    fn new(dest: W) -> LUnescaper<W> {
+    fn new(dest: W, mode: Mode) -> LUnescaper<W> {
         LUnescaper{
             dest,
+            mode,
             state: LUnescaperState::Normal,
         }
     }
@@ -108,19 +153,24 @@ impl<W: Write> Write for LUnescaper<W> {
                     LUnescaperState::Backslash
                 },
                 (LUnescaperState::Normal, _) => LUnescaperState::Normal,
+                (LUnescaperState::Backslash, c) if self.mode == Mode::Dn && is_dn_special(c) => {
+                    self.dest.write_all(&[c])?;
+                    written = i + 1;
+                    LUnescaperState::Normal
+                },
                 (LUnescaperState::Backslash, d1) => {
                     if d1.is_ascii_hexdigit() {
                         LUnescaperState::FirstDigit(d1)
                     } else {
                         return std::io::Result::Err(
-                            std::io::Error::new(std::io::ErrorKind::Other,
+                            std::io::Error::other(
                                                 format!("invalid hexadecimal digit 0x{d1:02x}")));
                     }
                 },
                 (LUnescaperState::FirstDigit(d1), d2) => {
                     if !d2.is_ascii_hexdigit() {
                         return std::io::Result::Err(
-                            std::io::Error::new(std::io::ErrorKind::Other,
+                            std::io::Error::other(
                                                 format!("invalid hexadecimal digit 0x{d2:02x}")));
                     }
                     let byte = (hexdigit_to_lower_bits(d1) << 4) | hexdigit_to_lower_bits(d2);
@@ -144,22 +194,29 @@ impl<W: Write> Write for LUnescaper<W> {
 
 impl<W: Write> Write for LEscaper<W> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        lescape(&mut self.dest, buf)
+        match self.mode {
+            Mode::Filter => lescape(&mut self.dest, buf),
+            Mode::Dn => self.dn_escape(buf),
+        }
     }
 
+    /// This method is used to indicate end-of-file.
     fn flush(&mut self) -> std::io::Result<()> {
+        self.write_line()?;
         self.dest.flush()
     }
 }
 
 fn get_result() -> Result<(), Box<dyn std::error::Error>> {
-    let reverse_escaping = parse_arguments()?;
+    let (reverse_escaping, mode) = parse_arguments()?;
     if !reverse_escaping {
-        let mut lescaper = LEscaper::new(std::io::stdout());
+        let mut lescaper = LEscaper::new(std::io::stdout(), mode);
         std::io::copy(&mut std::io::stdin(), &mut lescaper)?;
+        lescaper.flush()?;
     } else {
-        let mut lunescaper = LUnescaper::new(std::io::stdout());
+        let mut lunescaper = LUnescaper::new(std::io::stdout(), mode);
         std::io::copy(&mut std::io::stdin(), &mut lunescaper)?;
+        lunescaper.flush()?;
     }
     Ok(())
 }
@@ -167,7 +224,53 @@ fn get_result() -> Result<(), Box<dyn std::error::Error>> {
 fn main() {
     let result = get_result();
     if let Err(err) = result {
-        eprintln!("lescape: {}", err);
+        report_error("lescape", &*err);
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn escape(mode: Mode, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut lescaper = LEscaper::new(&mut output, mode);
+        // in pieces, so that spaces are carried over between writes
+        for chunk in input.chunks(3) {
+            lescaper.write_all(chunk)?;
+        }
+        lescaper.flush()?;
+        Ok(output)
+    }
+
+    fn unescape(mode: Mode, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut lunescaper = LUnescaper::new(&mut output, mode);
+        lunescaper.write_all(input)?;
+        lunescaper.flush()?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_dn_escape() -> std::io::Result<()> {
+        assert_eq!(escape(Mode::Dn, b"Smith, John")?, b"Smith\\, John");
+        assert_eq!(escape(Mode::Dn, b"a+b=c;\"<>\\")?, br#"a\+b=c\;\"\<\>\\"#);
+        assert_eq!(escape(Mode::Dn, b" #1  x  ")?, b"\\ #1  x \\ ");
+        assert_eq!(escape(Mode::Dn, b"#a\n b \nc\0")?, b"\\#a\n\\ b\\ \nc\\00");
+        assert_eq!(escape(Mode::Dn, "å\t\n".as_bytes())?, "å\\09\n".as_bytes());
+        assert_eq!(escape(Mode::Filter, b"a,(b)*")?, b"a,\\28b\\29\\2a");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dn_unescape() -> std::io::Result<()> {
+        assert_eq!(unescape(Mode::Dn, b"Smith\\, John\\2c\\ \\#")?, b"Smith, John, #");
+        assert!(unescape(Mode::Filter, b"Smith\\, John").is_err());
+        assert!(unescape(Mode::Dn, b"\\x").is_err());
+        for value in [b"Smith, John".as_slice(), b" #1 a+b  ", b"\"q\"\n<x>;\\\0"] {
+            assert_eq!(unescape(Mode::Dn, &escape(Mode::Dn, value)?)?, value);
+        }
+        Ok(())
+    }
+}
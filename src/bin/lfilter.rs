@@ -1,102 +1,462 @@
-use clap::{arg, command};
+use clap::{arg, command, Command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::compress::{create_output, decompress, open_input, Compression, CompressWriter};
 use ltools::crstrip::CrStripper;
 use ltools::lexer::Lexer;
 use ltools::loc::WriteLocWrapper;
 use ltools::unfold::Unfolder;
-use ltools::entry::{Entry, OwnedEntry, WriteEntry, EntryTokenWriter, write_entry_normally};
-use ltools::filter::Filter;
+use ltools::entry::{Entry, OwnedEntry, WriteEntry, write_entry_normally, write_entry_with_policy};
+use ltools::changerecord::{ChangeRecord, ChangeRecordTokenWriter, WriteRecord};
+use ltools::filter::{Filter, MatchOptions};
+use ltools::filter::approx::ApproxMatch;
+use ltools::filter::dnglob::DnGlob;
+use ltools::filter::syntax::Syntax;
+use ltools::bulk::BulkParser;
+use ltools::dn::normalize;
+use ltools::jobs::{check_inputs, process_inputs};
+use ltools::ldif::Base64Policy;
+use ltools::operational::{operational_args, OperationalAttributes};
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::{copy, Write, Stdout};
+use std::io::{copy, stdin, Read, Write};
 
-struct LFilter {
+enum ValueCount {
+    Min(String, usize),
+    Max(String, usize),
+}
+
+impl ValueCount {
+    fn parse(condition: &str, make: fn(String, usize) -> ValueCount) -> Result<ValueCount, &'static str> {
+        let (attr, count) = match condition.split_once('=') {
+            Some(split) => split,
+            None => return Err("value count conditions must be given as ATTRIBUTE=COUNT"),
+        };
+        match count.parse::<usize>() {
+            Ok(count) => Ok(make(attr.to_lowercase(), count)),
+            Err(_) => Err("failed to parse value count"),
+        }
+    }
+
+    fn is_match(&self, entry: &Entry) -> bool {
+        match self {
+            ValueCount::Min(attr, min) => entry.get(attr).count() >= *min,
+            ValueCount::Max(attr, max) => entry.get(attr).count() <= *max,
+        }
+    }
+}
+
+// Values of an attribute to match, read from a file with a value on each line.
+struct ValueList {
+    attr: String, // lowercase
+    path: String,
+    values: HashSet<Vec<u8>>, // as returned by key
+}
+
+impl ValueList {
+    fn parse(spec: &str) -> Result<ValueList, &'static str> {
+        match spec.split_once('=') {
+            Some((attr, path)) if !attr.is_empty() && !path.is_empty() => Ok(ValueList{
+                attr: attr.to_ascii_lowercase(),
+                path: path.to_string(),
+                values: HashSet::new(),
+            }),
+            _ => Err("value lists must be given as ATTRIBUTE=FILE"),
+        }
+    }
+
+    // Values are compared regardless of ASCII case as in filters, and DNs after normalizing them.
+    fn key(&self, value: &[u8]) -> Vec<u8> {
+        if self.attr == "dn" {
+            normalize(&String::from_utf8_lossy(value)).into_bytes()
+        } else {
+            value.to_ascii_lowercase()
+        }
+    }
+
+    fn load(&mut self) -> std::io::Result<()> {
+        let content = std::fs::read(&self.path)?;
+        for line in content.split(|c| *c == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if !line.is_empty() {
+                self.values.insert(self.key(line));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_match(&self, entry: &Entry) -> bool {
+        entry.get(&self.attr).any(|value| self.values.contains(&self.key(value)))
+    }
+}
+
+fn write_entry<W: Write>(w: &mut W, entry: &Entry, base64_policy: Option<&Base64Policy>, with_line: bool) -> std::io::Result<()> {
+    if let Some(provenance) = entry.provenance().filter(|_| with_line) {
+        writeln!(w, "# line {}", provenance.line)?;
+    }
+    match base64_policy {
+        Some(policy) => write_entry_with_policy(w, entry, policy),
+        None => write_entry_normally(w, entry),
+    }
+}
+
+// What entries and change records are matched against, which is shared by the workers of --jobs.
+struct Matcher {
     filter: Filter,
-    matched_output: Option<Box<dyn Write>>,
-    unmatched_output: Option<Stdout>,
-    matched_entries: Vec<OwnedEntry>,
+    changetypes: Vec<String>,
+    touches: Vec<String>, // lowercase
+    dn_filters: Vec<DnGlob>, // of which the DN has to match one
+    match_options: MatchOptions,
+    schema: Option<String>, // file with a subschema entry
+    value_counts: Vec<ValueCount>,
+    value_lists: Vec<ValueList>,
+    operational: Option<OperationalAttributes>, // to strip from the output
+    base64_policy: Option<Base64Policy>, // None keeps the default of base64-encoding values with spaces
+    with_line: bool,
+}
+
+struct LFilter<'a, W: Write> {
+    matcher: &'a Matcher,
+    matched_output: Option<W>,
+    unmatched_output: Option<W>,
+    defer_matched: bool,
+    deferred: Vec<u8>, // matched output that is written after the unmatched output
     found_match: bool,
 }
 
-fn parse_arguments() -> Result<LFilter, &'static str> {
+struct Parameters {
+    matcher: Matcher,
+    inputs: Vec<String>, // empty means standard input
+    jobs: usize,
+    matched_output: Option<CompressWriter<Box<dyn Write>>>,
+    unmatched_output: Option<CompressWriter<Box<dyn Write>>>,
+    defer_matched: bool,
+}
 
-    let mut matches = command!("lfilter")
+// The command without the defaults from the configuration file, which the tests use.
+fn command() -> Command<'static> {
+    command!("lfilter")
         .disable_colored_help(true)
         .arg(arg!(<FILTER> "LDAP filter."))
         .arg(arg!([OUTPUT] "Output file for matched entries. Non-matched entries will be written to standard output."))
         .arg(arg!(-q --quiet "Do not output to standard output unless it is specified as an explicit output.")
             .action(clap::ArgAction::SetTrue))
-        .get_matches();
+        .arg(arg!(-i --input <FILE> "Read LDIF from FILE instead of standard input ('-' means standard input). Can be given multiple times, in which case the files are read one after another.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--jobs <N> "Filter up to N input files at the same time. Output is still written in the order the files are given.")
+            .required(false)
+            .value_parser(clap::value_parser!(usize)))
+        .arg(arg!(-u --"unmatched-output" <FILE> "Output file for non-matched entries. Matched entries will be written to OUTPUT, or standard output if OUTPUT is not given.")
+            .required(false))
+        .arg(arg!(--"min-values" <CONDITION> "Only match entries that have at least COUNT values of ATTRIBUTE, given as ATTRIBUTE=COUNT. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"max-values" <CONDITION> "Only match entries that have at most COUNT values of ATTRIBUTE, given as ATTRIBUTE=COUNT. For example, mail=0 matches entries without a mail value. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"in-file" <LIST> "Only match entries with a value of ATTRIBUTE that is a line of FILE, given as ATTRIBUTE=FILE, such as uid=uids.txt. Values are compared regardless of case, and DNs regardless of spaces after commas. This is much faster than a filter such as (|(uid=a)(uid=b)...) for long lists. Can be given multiple times, in which case entries have to match every list.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--changetype <TYPE> "Only match change records of TYPE, which is add, delete, modify or modrdn. Can be given multiple times. Change records are matched against FILTER with their DN, changetype and other lines as attributes.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--touches <ATTRIBUTE> "Only match change records that change ATTRIBUTE: adds with it, modifies with an operation on it and modrdns with it in the new RDN. Can be given multiple times, in which case a record that changes any of them matches.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"dn-filter" <GLOB> "Only match entries whose DN matches GLOB, in which * matches any characters, such as '*,ou=sa,o=system' for the entries below ou=sa,o=system. Case and spaces after commas are ignored. Can be given multiple times, in which case a DN that matches any of them matches.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"strict-filter" "Parse filters strictly as RFC 4515 has them, in parentheses and without whitespace between their parts. By default, a filter such as cn=foo needn't be in parentheses, and there may be whitespace between parentheses and operators, as in '( & (a=1) (b=2) )'.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--approx <METHOD> "Method used for approximate (~=) matching: soundex (default), levenshtein or levenshtein:THRESHOLD, where THRESHOLD is the minimum similarity between 0 and 1.")
+            .required(false))
+        .arg(arg!(--syntax <SYNTAX> "Compare values of an attribute in equality and ordering (>=, <=) filters according to a syntax, given as ATTRIBUTE=SYNTAX where SYNTAX is string (default), integer or telephone, which ignores spaces and hyphens. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--schema <FILE> "Compare values of attributes with integer or telephone number syntax according to the subschema entry in FILE, such as one exported from cn=schema, accordingly.")
+            .required(false))
+        .args(operational_args("Remove operational attributes such as modifyTimestamp and entryUUID."))
+        .arg(arg!(--"utf8-values" "Write values of entries that are valid UTF-8 as they are instead of base64-encoding them, and only base64-encode values where LDIF requires it otherwise.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"with-line" "Write a comment with the line that each entry starts on in the input before it, such as # line 12, for tracing entries back to the input. Filters can also match the line as the pseudo-attribute @line and the number of the entry as @ordinal, as in (@line>=1000).")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--compress <METHOD> "Compress the output with METHOD, which is gzip, zstd or none. By default, output files are compressed according to their extension, .gz or .zst, and standard output is not compressed.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+}
+
+fn parse_arguments() -> Result<Parameters, Box<dyn std::error::Error>> {
+    parse_arguments_from(with_defaults(command()), std::env::args_os())
+}
+
+fn parse_arguments_from<I, T>(cmd: Command, args: I) -> Result<Parameters, Box<dyn std::error::Error>>
+    where I: IntoIterator<Item = T>, T: Into<std::ffi::OsString> + Clone
+{
+    let mut matches = cmd.get_matches_from(args);
+
+    set_json_output(matches.get_flag("diagnostics-json"));
 
     let filter: Filter = match matches.get_one::<String>("FILTER") {
-        None => return Err("missing argument FILTER"),
-        Some(filter) => match Filter::parse(filter) {
-            Ok(filter) => filter,
-            Err(_) => return Err("failed to parse filter"),
-        },
+        None => return Err("missing argument FILTER".into()),
+        Some(filter) if matches.get_flag("strict-filter") => Filter::parse_strict(filter)?,
+        Some(filter) => Filter::parse(filter)?,
     };
 
+    let mut changetypes: Vec<String> = Vec::new();
+    for changetype in matches.get_many::<String>("changetype").into_iter().flatten() {
+        match changetype.to_ascii_lowercase().as_str() {
+            "add" | "delete" | "modify" | "modrdn" | "moddn" => changetypes.push(changetype.to_string()),
+            _ => return Err("changetype must be add, delete, modify or modrdn".into()),
+        }
+    }
+    let touches: Vec<String> = matches.get_many::<String>("touches")
+        .into_iter()
+        .flatten()
+        .map(|attr| attr.to_ascii_lowercase())
+        .collect();
+
+    let dn_filters: Vec<DnGlob> = matches.get_many::<String>("dn-filter")
+        .into_iter()
+        .flatten()
+        .map(|glob| DnGlob::new(glob))
+        .collect();
+
+    let mut match_options = MatchOptions::default();
+    if let Some(approx) = matches.get_one::<String>("approx") {
+        match_options.approx = ApproxMatch::parse(approx)?;
+    }
+    for syntax in matches.get_many::<String>("syntax").into_iter().flatten() {
+        let (attr, syntax) = match syntax.split_once('=') {
+            Some(split) => split,
+            None => return Err("syntaxes must be given as ATTRIBUTE=SYNTAX".into()),
+        };
+        match_options.set_syntax(attr, Syntax::parse(syntax)?);
+    }
+
+    let mut value_counts: Vec<ValueCount> = Vec::new();
+    for condition in matches.get_many::<String>("min-values").into_iter().flatten() {
+        value_counts.push(ValueCount::parse(condition, ValueCount::Min)?);
+    }
+    for condition in matches.get_many::<String>("max-values").into_iter().flatten() {
+        value_counts.push(ValueCount::parse(condition, ValueCount::Max)?);
+    }
+
+    let mut value_lists: Vec<ValueList> = Vec::new();
+    for spec in matches.get_many::<String>("in-file").into_iter().flatten() {
+        value_lists.push(ValueList::parse(spec)?);
+    }
+
+    let operational = OperationalAttributes::from_matches(&matches);
+
+    let base64_policy = matches.get_flag("utf8-values").then(|| {
+        let mut policy = Base64Policy::default();
+        policy.set_allow_utf8(true);
+        policy
+    });
+
     let mut quiet = false;
     if matches.get_flag("quiet") {
         quiet = true;
     }
 
     let matched_output: Option<String> = matches.remove_one::<String>("OUTPUT");
-    let mut unmatched_output = None;
-    let matched_output: Option<Box<dyn Write>> = match matched_output {
-        None => if quiet { None } else { Some(Box::new(std::io::stdout())) },
-        Some(filepath) => {
-            unmatched_output = if quiet { None } else { Some(std::io::stdout()) };
-            match File::create(filepath) {
-                Ok(file) => Some(Box::new(file)),
-                Err(_) => return Err("Failed to open output file"),
-            }
-        }
+    let unmatched_output: Option<String> = matches.remove_one::<String>("unmatched-output");
+    let mut defer_matched = false;
+    let compression = match matches.get_one::<String>("compress") {
+        Some(method) => Some(Compression::parse(method)?),
+        None => None,
+    };
+    let create_output = |filepath: &str| create_output(filepath, compression).map_err(|_| "Failed to open output file");
+    let stdout = || if quiet { Ok(None) } else { create_output("-").map(Some) };
+    let (matched_output, unmatched_output) = match (matched_output, unmatched_output) {
+        (None, None) => (stdout()?, None),
+        (Some(matched_filepath), None) => {
+            defer_matched = !quiet;
+            (Some(create_output(&matched_filepath)?), stdout()?)
+        },
+        (None, Some(unmatched_filepath)) => (stdout()?, Some(create_output(&unmatched_filepath)?)),
+        (Some(matched_filepath), Some(unmatched_filepath)) => {
+            (Some(create_output(&matched_filepath)?), Some(create_output(&unmatched_filepath)?))
+        },
     };
 
-    Ok(LFilter{
+    let matcher = Matcher{
         filter,
+        changetypes,
+        touches,
+        dn_filters,
+        match_options,
+        schema: matches.get_one::<String>("schema").cloned(),
+        value_counts,
+        value_lists,
+        operational,
+        base64_policy,
+        with_line: matches.get_flag("with-line"),
+    };
+    Ok(Parameters{
+        matcher,
+        inputs: matches.get_many::<String>("input").into_iter().flatten().cloned().collect(),
+        jobs: matches.get_one::<usize>("jobs").copied().unwrap_or(1),
         matched_output,
         unmatched_output,
-        matched_entries: Vec::new(),
-        found_match: false,
+        defer_matched,
     })
 }
 
-impl WriteEntry for LFilter {
+impl Matcher {
+    fn is_match(&self, entry: &Entry) -> bool {
+        self.filter.is_match_with(entry, &self.match_options)
+            && self.value_counts.iter().all(|count| count.is_match(entry))
+            && self.value_lists.iter().all(|list| list.is_match(entry))
+            && (self.dn_filters.is_empty() || entry.get_one_str("dn").is_some_and(|dn| self.dn_filters.iter().any(|glob| glob.is_match(&dn))))
+    }
+}
+
+impl<W: Write> WriteEntry for LFilter<'_, W> {
     fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
-        if self.filter.is_match(entry) {
+        let matcher = self.matcher;
+        // the change record conditions leave out entries
+        let dest: Option<&mut dyn Write> = if matcher.changetypes.is_empty() && matcher.touches.is_empty() && matcher.is_match(entry) {
             self.found_match = true;
-            if self.unmatched_output.is_some() {
-                self.matched_entries.push(entry.into()); // defer writing matched entries so that
-                                                         // they don't potentially interleave the
-                                                         // unmatched entries if user passes
-                                                         // something like >(cat) as output file
+            if self.defer_matched {
+                // defer writing matched entries so that they don't potentially interleave the
+                // unmatched entries if user passes something like >(cat) as output file
+                Some(&mut self.deferred)
+            } else {
+                self.matched_output.as_mut().map(|output| output as &mut dyn Write)
+            }
+        } else {
+            self.unmatched_output.as_mut().map(|output| output as &mut dyn Write)
+        };
+        let Some(mut dest) = dest else {
+            return Ok(());
+        };
+        // operational attributes are stripped after matching, so that filters can still use them
+        match matcher.operational {
+            Some(ref operational) => write_entry(&mut dest, &operational.strip(entry), matcher.base64_policy.as_ref(), matcher.with_line),
+            None => write_entry(&mut dest, entry, matcher.base64_policy.as_ref(), matcher.with_line),
+        }
+    }
+}
+
+impl<W: Write> WriteRecord for LFilter<'_, W> {
+    fn write_change_record(&mut self, record: &ChangeRecord) -> std::io::Result<()> {
+        let matcher = self.matcher;
+        let is_match = (matcher.changetypes.is_empty() || matcher.changetypes.iter().any(|changetype| record.is_changetype(changetype)))
+            && (matcher.touches.is_empty() || {
+                let touched = record.touched_attributes();
+                matcher.touches.iter().any(|attr| touched.contains(attr))
+            })
+            && matcher.is_match(&record.as_entry());
+        if is_match {
+            self.found_match = true;
+            if self.defer_matched {
+                record.write_ldif(&mut self.deferred)?;
             } else if let Some(ref mut matched_output) = self.matched_output {
-                write_entry_normally(matched_output, entry)?;
+                record.write_ldif(matched_output)?;
             }
         } else if let Some(ref mut unmatched_output) = self.unmatched_output {
-            write_entry_normally(unmatched_output, entry)?;
+            record.write_ldif(unmatched_output)?;
         }
         Ok(())
     }
 }
 
-fn get_result() -> Result<i32, Box<dyn std::error::Error>> {
-    let mut lfilter = parse_arguments()?;
-    let token_writer = EntryTokenWriter::new(&mut lfilter);
+// Filters the LDIF read from input into the outputs of lfilter.
+fn filter_input<W: Write>(lfilter: &mut LFilter<W>, input: &mut dyn Read) -> std::io::Result<()> {
+    let token_writer = ChangeRecordTokenWriter::new(lfilter);
     let lexer = Lexer::new(token_writer);
     let unfolder = Unfolder::new(lexer);
     let crstripper = CrStripper::new(unfolder);
     let mut wrapper = WriteLocWrapper::new(crstripper);
-    copy(&mut std::io::stdin(), &mut wrapper)?;
-    wrapper.flush()?;
-    if let Some(ref mut unmatched_output) = lfilter.unmatched_output {
-        unmatched_output.flush()?;
+    copy(&mut decompress(input)?, &mut wrapper)?;
+    wrapper.flush()
+}
+
+fn filter_file<W: Write>(lfilter: &mut LFilter<W>, path: &str) -> std::io::Result<()> {
+    open_input(path)
+        .and_then(|mut input| filter_input(lfilter, &mut input))
+        .map_err(|err| FileError::new(path, err).into())
+}
+
+// Filters each input file in a worker thread, and writes the output of each file in the order the
+// files were given. The output of a file is buffered in memory until it can be written.
+fn filter_files_parallel<W: Write>(lfilter: &mut LFilter<W>, inputs: &[String], jobs: usize) -> std::io::Result<()> {
+    let matcher = lfilter.matcher;
+    let has_matched_output = lfilter.matched_output.is_some();
+    let has_unmatched_output = lfilter.unmatched_output.is_some();
+    process_inputs(inputs, jobs, |input| {
+        let mut worker = LFilter{
+            matcher,
+            matched_output: has_matched_output.then(Vec::new),
+            unmatched_output: has_unmatched_output.then(Vec::new),
+            defer_matched: false,
+            deferred: Vec::new(),
+            found_match: false,
+        };
+        filter_file(&mut worker, input)?;
+        Ok(worker)
+    }, |worker| {
+        lfilter.found_match |= worker.found_match;
+        if let (Some(dest), Some(unmatched)) = (lfilter.unmatched_output.as_mut(), worker.unmatched_output) {
+            dest.write_all(&unmatched)?;
+        }
+        match (lfilter.defer_matched, lfilter.matched_output.as_mut(), worker.matched_output) {
+            (true, _, Some(matched)) => lfilter.deferred.extend(matched),
+            (false, Some(dest), Some(matched)) => dest.write_all(&matched)?,
+            _ => {},
+        }
+        Ok(())
+    })
+}
+
+fn get_result() -> Result<i32, Box<dyn std::error::Error>> {
+    run(parse_arguments()?)
+}
+
+// Filters the inputs into the outputs, and returns the exit status, which is 1 if no entry
+// matched.
+fn run(mut params: Parameters) -> Result<i32, Box<dyn std::error::Error>> {
+    if params.jobs > 1 {
+        check_inputs(&params.inputs)?;
     }
-    if let Some(mut matched_output) = lfilter.matched_output {
-        for entry in lfilter.matched_entries.iter() {
-            write_entry_normally(&mut matched_output, entry)?;
+    if let Some(ref schema) = params.matcher.schema {
+        let mut file = File::open(schema).map_err(|err| FileError::new(schema, err))?;
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        BulkParser::new().write_entries(&mut file, &mut entries).map_err(|err| FileError::new(schema, err))?;
+        for entry in entries.iter() {
+            params.matcher.match_options.add_schema_syntaxes(entry);
         }
-        matched_output.flush()?;
+    }
+    for list in params.matcher.value_lists.iter_mut() {
+        let path = list.path.clone();
+        list.load().map_err(|err| FileError::new(&path, err))?;
+    }
+    let mut lfilter = LFilter{
+        matcher: &params.matcher,
+        matched_output: params.matched_output,
+        unmatched_output: params.unmatched_output,
+        defer_matched: params.defer_matched,
+        deferred: Vec::new(),
+        found_match: false,
+    };
+    if params.inputs.is_empty() {
+        filter_input(&mut lfilter, &mut stdin())?;
+    } else if params.jobs > 1 && params.inputs.len() > 1 {
+        filter_files_parallel(&mut lfilter, &params.inputs, params.jobs)?;
+    } else {
+        for input in params.inputs.iter() {
+            filter_file(&mut lfilter, input)?;
+        }
+    }
+    if let Some(unmatched_output) = lfilter.unmatched_output {
+        unmatched_output.finish()?;
+    }
+    if let Some(mut matched_output) = lfilter.matched_output {
+        matched_output.write_all(&lfilter.deferred)?;
+        matched_output.finish()?;
     }
     let status = if lfilter.found_match { 0 } else { 1 };
     Ok(status)
@@ -106,7 +466,7 @@ fn main() {
     let result = get_result();
     match result {
         Err(err) => {
-            eprintln!("lfilter: {}", err);
+            report_error("lfilter", &*err);
             std::process::exit(2); // based on what grep's man page says
         },
         Ok(status) => {
@@ -114,3 +474,182 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matcher(filter: &str) -> Matcher {
+        Matcher{
+            filter: Filter::parse(filter).unwrap(),
+            changetypes: Vec::new(),
+            touches: Vec::new(),
+            dn_filters: Vec::new(),
+            match_options: MatchOptions::default(),
+            schema: None,
+            value_counts: Vec::new(),
+            value_lists: Vec::new(),
+            operational: None,
+            base64_policy: None,
+            with_line: false,
+        }
+    }
+
+    fn lfilter(matcher: &Matcher) -> LFilter<'_, Vec<u8>> {
+        LFilter{
+            matcher,
+            matched_output: Some(Vec::new()),
+            unmatched_output: Some(Vec::new()),
+            defer_matched: false,
+            deferred: Vec::new(),
+            found_match: false,
+        }
+    }
+
+    // Filters the LDIF, and returns the matched and the unmatched output.
+    fn filter(matcher: &Matcher, ldif: &[u8]) -> std::io::Result<(String, String)> {
+        let mut lfilter = lfilter(matcher);
+        filter_input(&mut lfilter, &mut &ldif[..])?;
+        let output = |output: Option<Vec<u8>>| String::from_utf8_lossy(&output.unwrap()).into_owned();
+        Ok((output(lfilter.matched_output), output(lfilter.unmatched_output)))
+    }
+
+    #[test]
+    fn test_unmatched_output() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("ltools-lfilter-unmatched-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(path("in.ldif"), b"dn: cn=a\ncn: a\n\ndn: cn=b\ncn: b\n\ndn: cn=c\ncn: c\n\n")?;
+        let read = |name: &str| -> std::io::Result<String> {
+            let mut content = String::new();
+            open_input(&path(name))?.read_to_string(&mut content)?;
+            Ok(content)
+        };
+
+        let params = parse_arguments_from(command(), ["lfilter", "(cn=b)", &path("matched.ldif"), "-u", &path("unmatched.ldif.gz"), "-i", &path("in.ldif")])?;
+        assert_eq!(run(params)?, 0);
+        assert_eq!(read("matched.ldif")?, "dn: cn=b\ncn: b\n\n");
+        assert_eq!(Compression::detect(&std::fs::read(path("unmatched.ldif.gz"))?), Compression::Gzip);
+        assert_eq!(read("unmatched.ldif.gz")?, "dn: cn=a\ncn: a\n\ndn: cn=c\ncn: c\n\n");
+
+        // with -q, matched entries aren't written anywhere, and without a match the status is 1
+        let params = parse_arguments_from(command(), ["lfilter", "-q", "(cn=x)", "--unmatched-output", &path("unmatched.ldif"), "-i", &path("in.ldif")])?;
+        assert_eq!(run(params)?, 1);
+        assert_eq!(read("unmatched.ldif")?, read("in.ldif")?);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_counts() -> std::io::Result<()> {
+        let ldif = b"dn: cn=none\ncn: none\n\ndn: cn=one\ncn: one\nmail: a\n\ndn: cn=two\ncn: two\nMail: a\nmail: b\n\n";
+        let dns = |min: &[&str], max: &[&str]| -> std::io::Result<Vec<String>> {
+            let mut matcher = matcher("(cn=*)");
+            for condition in min {
+                matcher.value_counts.push(ValueCount::parse(condition, ValueCount::Min).unwrap());
+            }
+            for condition in max {
+                matcher.value_counts.push(ValueCount::parse(condition, ValueCount::Max).unwrap());
+            }
+            let (matched, _) = filter(&matcher, ldif)?;
+            Ok(matched.lines().filter_map(|line| line.strip_prefix("dn: ")).map(String::from).collect())
+        };
+        assert_eq!(dns(&["mail=1"], &[])?, ["cn=one", "cn=two"]);
+        assert_eq!(dns(&["MAIL=2"], &[])?, ["cn=two"]);
+        assert_eq!(dns(&[], &["mail=0"])?, ["cn=none"]);
+        assert_eq!(dns(&[], &["mail=1"])?, ["cn=none", "cn=one"]);
+        assert_eq!(dns(&["mail=1"], &["mail=1"])?, ["cn=one"]);
+        assert!(dns(&["mail=3"], &[])?.is_empty());
+        assert!(ValueCount::parse("mail", ValueCount::Min).is_err());
+        assert!(ValueCount::parse("mail=-1", ValueCount::Max).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_file() -> std::io::Result<()> {
+        let ldif = b"dn: uid=foo,dc=example\nuid: foo\n\ndn: uid=bar,dc=example\nuid: Bar\n\ndn: uid=baz,dc=example\nuid: baz\n\n";
+        let path = std::env::temp_dir().join(format!("ltools-lfilter-in-file-{}.txt", std::process::id()));
+        let list = |content: &[u8], attr: &str| -> std::io::Result<Matcher> {
+            std::fs::write(&path, content)?;
+            let mut matcher = matcher("(uid=*)");
+            let mut list = ValueList::parse(&format!("{}={}", attr, path.display())).unwrap();
+            list.load()?;
+            matcher.value_lists.push(list);
+            Ok(matcher)
+        };
+
+        // values are compared regardless of case, and CRLF line endings and blank lines are
+        // ignored
+        let (matched, unmatched) = filter(&list(b"BAR\r\n\nfoo\r\nqux\n", "uid")?, ldif)?;
+        assert_eq!(matched, "dn: uid=foo,dc=example\nuid: foo\n\ndn: uid=bar,dc=example\nuid: Bar\n\n");
+        assert_eq!(unmatched, "dn: uid=baz,dc=example\nuid: baz\n\n");
+
+        // DNs are compared after normalizing them
+        let (matched, _) = filter(&list(b"UID=baz, dc=example\n", "dn")?, ldif)?;
+        assert_eq!(matched, "dn: uid=baz,dc=example\nuid: baz\n\n");
+
+        let (matched, unmatched) = filter(&list(b"qux\n", "uid")?, ldif)?;
+        assert_eq!((matched.as_str(), unmatched.len()), ("", ldif.len()));
+
+        // an empty list matches nothing
+        let (matched, unmatched) = filter(&list(b"", "uid")?, ldif)?;
+        assert_eq!((matched.as_str(), unmatched.len()), ("", ldif.len()));
+
+        std::fs::remove_file(&path)?;
+        assert!(ValueList::parse("uid").is_err());
+        assert!(ValueList::parse("=uids.txt").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jobs() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("ltools-lfilter-jobs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let mut inputs: Vec<String> = Vec::new();
+        for i in 0..5 {
+            let path = dir.join(format!("{}.ldif", i));
+            let mut ldif = String::new();
+            for j in 0..50 {
+                ldif.push_str(&format!("dn: uid=u{i}-{j},dc=example\nuid: u{i}-{j}\nemployeeNumber: {}\n\n", j % 3));
+            }
+            std::fs::write(&path, ldif)?;
+            inputs.push(path.to_string_lossy().into_owned());
+        }
+        let matcher = matcher("(employeeNumber=1)");
+        let mut serial = lfilter(&matcher);
+        for input in inputs.iter() {
+            filter_file(&mut serial, input)?;
+        }
+        let mut parallel = lfilter(&matcher);
+        filter_files_parallel(&mut parallel, &inputs, 3)?;
+        assert!(parallel.found_match);
+        assert!(!serial.matched_output.as_ref().unwrap().is_empty());
+        assert_eq!(parallel.matched_output, serial.matched_output);
+        assert_eq!(parallel.unmatched_output, serial.unmatched_output);
+
+        inputs.push(String::from("-"));
+        inputs.push(String::from("-"));
+        assert!(filter_files_parallel(&mut lfilter(&matcher), &inputs, 3).is_err());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_operational() -> std::io::Result<()> {
+        let ldif = b"dn: cn=a\ncn: a\nmodifyTimestamp: 20230101000000Z\n\ndn: cn=b\ncn: b\nmodifyTimestamp: 20230101000000Z\n\n";
+        // operational attributes can still be matched on when they are stripped from the output
+        let mut stripping = matcher("(&(cn=a)(modifyTimestamp=*))");
+        stripping.operational = Some(OperationalAttributes::default());
+        let mut stripped = lfilter(&stripping);
+        filter_input(&mut stripped, &mut &ldif[..])?;
+        assert_eq!(String::from_utf8_lossy(stripped.matched_output.as_ref().unwrap()), "dn: cn=a\ncn: a\n\n");
+        assert_eq!(String::from_utf8_lossy(stripped.unmatched_output.as_ref().unwrap()), "dn: cn=b\ncn: b\n\n");
+
+        let keeping = matcher("(cn=a)");
+        let mut kept = lfilter(&keeping);
+        filter_input(&mut kept, &mut &ldif[..])?;
+        assert_eq!(String::from_utf8_lossy(kept.matched_output.as_ref().unwrap()), "dn: cn=a\ncn: a\nmodifyTimestamp: 20230101000000Z\n\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::entry::{Entry, WriteEntry};
+use std::io::{stdout, BufWriter, Write};
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lfingerprint")
+        .disable_colored_help(true)
+        .about("Writes the DN of each entry followed by a tab and a hash of its other attributes and values, for telling which entries have changed between two snapshots.")
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input)."))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+    })
+}
+
+struct FingerprintWriter<W: Write> {
+    dest: W,
+}
+
+impl<W: Write> WriteEntry for FingerprintWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        if let Some(dn) = entry.get_one_str("dn") {
+            writeln!(self.dest, "{}\t{:016x}", dn, entry.fingerprint())?;
+        }
+        Ok(())
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    let mut writer = FingerprintWriter{
+        dest: BufWriter::new(stdout()),
+    };
+    for input in inputs.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut writer))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    writer.dest.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lfingerprint", &*err);
+        std::process::exit(1);
+    }
+}
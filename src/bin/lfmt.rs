@@ -0,0 +1,383 @@
+use clap::{arg, command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::bulk::BulkParser;
+use ltools::dn::{avas, is_ancestor_or_self, normalize, parent, rdn, rdns, unescape_value};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::inplace::InPlaceFile;
+use ltools::ldif::{Base64Policy, LdifEntryWriter, LineEnding};
+use ltools::operational::{operational_args, OperationalAttributes, OperationalStripper};
+use ltools::schema::{Projection, Projector};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{stdin, stdout, BufWriter, Read, Write};
+
+// The object classes of added parents by their RDN attribute type, unless given otherwise.
+const CONTAINER_CLASSES: &[(&str, &str)] = &[
+    ("c", "country"),
+    ("cn", "container"),
+    ("dc", "domain"),
+    ("l", "locality"),
+    ("o", "organization"),
+    ("ou", "organizationalUnit"),
+];
+
+struct Parameters {
+    fold_width: Option<usize>,
+    line_ending: LineEnding,
+    sort_attributes: bool,
+    operational: Option<OperationalAttributes>, // to strip
+    schema: Option<String>, // file with a subschema entry
+    project: Vec<String>, // object classes to trim entries to
+    projection: Option<Projection>, // of project according to schema
+    canonical: bool,
+    add_parents: bool,
+    existing: Vec<String>, // normalized DNs assumed to exist along with their ancestors
+    container_classes: Vec<(String, String)>, // lowercase RDN attribute type and object class
+    base64_policy: Base64Policy,
+    input: Option<String>, // None means standard input
+    in_place: Vec<String>, // files to rewrite instead of reading standard input
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lfmt")
+        .disable_colored_help(true)
+        .about("Rewrites LDIF from standard input, or the files given with --in-place, in a canonical form.")
+        .arg(arg!([FILE] "Read FILE instead of standard input."))
+        .arg(arg!(--canonical "Also sort the entries by DN, with each entry after its parent, and the values of each attribute, and don't fold lines, so that the output only depends on the content of the entries. Suitable as a git textconv command.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"add-parents" "Add an entry before the first entry below each parent that is missing from the input, so that the input can be imported into an empty tree. The entries have the object class for the attribute type of their RDN and the values of the RDN.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"assume-exists" <DN> "Take the entry DN and its ancestors to exist when adding parents, such as the base DN of the directory the input is imported into. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"container-class" <MAPPING> "Give added parents whose RDN has the attribute type ATTRIBUTE the object class CLASS, with MAPPING of the form ATTRIBUTE=CLASS. By default, ou gives organizationalUnit, o organization, cn container, dc domain, l locality and c country. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(-w --width <COLUMNS> "Fold lines longer than COLUMNS. 0 disables folding.")
+            .required(false)
+            .default_value("76"))
+        .arg(arg!(--crlf "Terminate lines with CRLF instead of LF.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"no-sort" "Keep attributes in the order they are first seen instead of sorting them by name.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"utf8-values" "Write values that are valid UTF-8 as they are instead of base64-encoding them. This is not strictly LDIF, and isn't read back by the ltools, but most LDAP tools accept it.")
+            .action(clap::ArgAction::SetTrue))
+        .args(operational_args("Remove operational attributes such as modifyTimestamp and entryUUID."))
+        .arg(arg!(--project <CLASS> "Trim entries to the attributes that the object class CLASS and its superclasses allow according to the schema given with --schema, and leave out other object classes. Can be given multiple times to allow the attributes of several classes.")
+            .required(false)
+            .requires("schema")
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--schema <FILE> "Read object classes and attribute types for --project from the subschema entry in FILE, such as one exported from cn=schema.")
+            .required(false))
+        .arg(arg!(-i --"in-place" <FILE> "Rewrite FILE in place instead of reading standard input and writing standard output. The file is only replaced once it has been rewritten completely. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let fold_width = match matches.get_one::<String>("width").map(|width| width.parse::<usize>()) {
+        Some(Ok(0)) => None,
+        Some(Ok(width)) => Some(width),
+        Some(Err(_)) => return Err("failed to parse line width"),
+        None => Some(76),
+    };
+
+    let operational = OperationalAttributes::from_matches(&matches);
+
+    let line_ending = if matches.get_flag("crlf") { LineEnding::CrLf } else { LineEnding::Lf };
+    let canonical = matches.get_flag("canonical");
+    let mut container_classes: Vec<(String, String)> = Vec::new();
+    for mapping in matches.get_many::<String>("container-class").into_iter().flatten() {
+        let Some((attr, class)) = mapping.split_once('=') else {
+            return Err("container classes must be given as ATTRIBUTE=CLASS");
+        };
+        container_classes.push((attr.trim().to_ascii_lowercase(), class.trim().to_string()));
+    }
+    for (attr, class) in CONTAINER_CLASSES.iter() {
+        if !container_classes.iter().any(|(other, _)| other == attr) {
+            container_classes.push((attr.to_string(), class.to_string()));
+        }
+    }
+    let mut base64_policy = Base64Policy::default();
+    base64_policy.set_allow_utf8(matches.get_flag("utf8-values"));
+
+    Ok(Parameters{
+        fold_width: if canonical { None } else { fold_width },
+        line_ending,
+        sort_attributes: canonical || !matches.get_flag("no-sort"),
+        operational,
+        schema: matches.get_one::<String>("schema").cloned(),
+        project: matches.get_many::<String>("project").into_iter().flatten().cloned().collect(),
+        projection: None,
+        canonical,
+        add_parents: matches.get_flag("add-parents"),
+        existing: matches.get_many::<String>("assume-exists").into_iter().flatten().map(|dn| normalize(dn)).collect(),
+        container_classes,
+        base64_policy,
+        input: matches.get_one::<String>("FILE").cloned(),
+        in_place: matches.get_many::<String>("in-place").into_iter().flatten().cloned().collect(),
+    })
+}
+
+// Collects the entries for writing them sorted by DN, with the values of each attribute sorted.
+#[derive(Default)]
+struct CanonicalSorter {
+    entries: Vec<(Vec<String>, OwnedEntry)>, // by the RDNs of the DN from the root down
+}
+
+impl WriteEntry for CanonicalSorter {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let dn = normalize(&entry.get_one_str("dn").unwrap_or_default());
+        let mut path: Vec<String> = rdns(&dn).map(String::from).collect();
+        path.reverse();
+        let mut sorted = OwnedEntry::default();
+        for attr in entry.attributes() {
+            let mut values: Vec<&[u8]> = entry.get(attr.lowercase).collect();
+            values.sort_unstable();
+            for value in values {
+                sorted.add_value(attr.name, value);
+            }
+        }
+        self.entries.push((path, sorted));
+        Ok(())
+    }
+}
+
+impl CanonicalSorter {
+    fn finish<W: WriteEntry>(mut self, mut dest: W) -> std::io::Result<()> {
+        // comparing the RDNs from the root down puts each entry right before its descendants
+        self.entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (_, entry) in self.entries.iter() {
+            dest.write_entry(entry)?;
+        }
+        Ok(())
+    }
+}
+
+// Collects the entries for writing them with the parents that are missing from the input.
+#[derive(Default)]
+struct ParentAdder {
+    entries: Vec<OwnedEntry>,
+}
+
+impl WriteEntry for ParentAdder {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.entries.push(entry.into());
+        Ok(())
+    }
+}
+
+impl ParentAdder {
+    fn finish<W: WriteEntry>(self, params: &Parameters, mut dest: W) -> std::io::Result<()> {
+        let mut existing: HashSet<String> = self.entries.iter()
+            .filter_map(|entry| entry.get_one_str("dn"))
+            .map(|dn| normalize(&dn))
+            .collect();
+        for entry in self.entries.iter() {
+            let dn = entry.get_one_str("dn").unwrap_or_default();
+            let mut missing: Vec<&str> = Vec::new();
+            let mut ancestor = parent(&dn);
+            while let Some(dn) = ancestor {
+                let normalized = normalize(dn);
+                if existing.contains(&normalized) || params.existing.iter().any(|base| is_ancestor_or_self(&normalized, base)) {
+                    break;
+                }
+                missing.push(dn);
+                ancestor = parent(dn);
+            }
+            for dn in missing.iter().rev() {
+                dest.write_entry(&container(params, dn)?)?;
+                existing.insert(normalize(dn));
+            }
+            dest.write_entry(entry)?;
+        }
+        Ok(())
+    }
+}
+
+// An entry with the DN, the object class for the attribute type of the RDN and the values of the
+// RDN.
+fn container(params: &Parameters, dn: &str) -> std::io::Result<OwnedEntry> {
+    let mut entry = OwnedEntry::default();
+    entry.add_value("dn", dn.as_bytes());
+    let avas: Vec<(&str, &str)> = avas(rdn(dn)).collect();
+    let attr = avas.first().map(|(attr, _)| attr.trim().to_ascii_lowercase()).unwrap_or_default();
+    let class = params.container_classes.iter()
+        .find(|(other, _)| *other == attr)
+        .map(|(_, class)| class)
+        .ok_or_else(|| std::io::Error::other(format!("no object class for the missing parent {}; give one with --container-class {}=CLASS", dn, attr)))?;
+    entry.add_value("objectClass", b"top");
+    entry.add_value("objectClass", class.as_bytes());
+    for (attr, value) in avas.iter() {
+        entry.add_value(attr.trim(), &unescape_value(value.trim()));
+    }
+    Ok(entry)
+}
+
+fn format<R: Read + ?Sized, W: Write>(params: &Parameters, input: &mut R, output: W) -> std::io::Result<()> {
+    let mut writer = LdifEntryWriter::new(BufWriter::new(output));
+    writer.set_fold_width(params.fold_width)
+        .set_line_ending(params.line_ending)
+        .set_sort_attributes(params.sort_attributes)
+        .set_base64_policy(params.base64_policy.clone());
+    match (params.add_parents, params.canonical) {
+        (true, true) => {
+            let mut adder = ParentAdder::default();
+            write_entries(params, input, &mut adder)?;
+            let mut sorter = CanonicalSorter::default();
+            adder.finish(params, &mut sorter)?;
+            sorter.finish(&mut writer)?;
+        },
+        (true, false) => {
+            let mut adder = ParentAdder::default();
+            write_entries(params, input, &mut adder)?;
+            adder.finish(params, &mut writer)?;
+        },
+        (false, true) => {
+            let mut sorter = CanonicalSorter::default();
+            write_entries(params, input, &mut sorter)?;
+            sorter.finish(&mut writer)?;
+        },
+        (false, false) => write_entries(params, input, &mut writer)?,
+    }
+    writer.get_mut().flush()
+}
+
+fn write_entries<R: Read + ?Sized, W: WriteEntry>(params: &Parameters, input: &mut R, dest: W) -> std::io::Result<()> {
+    match params.projection {
+        Some(ref projection) => write_stripped_entries(params, input, Projector::new(projection.clone(), dest)),
+        None => write_stripped_entries(params, input, dest),
+    }
+}
+
+fn write_stripped_entries<R: Read + ?Sized, W: WriteEntry>(params: &Parameters, input: &mut R, dest: W) -> std::io::Result<()> {
+    match params.operational {
+        Some(ref operational) => BulkParser::new()
+            .write_entries(input, OperationalStripper::new(operational.clone(), dest)),
+        None => BulkParser::new().write_entries(input, dest),
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut params = parse_arguments()?;
+    if let Some(ref schema) = params.schema {
+        let mut file = File::open(schema).map_err(|err| FileError::new(schema, err))?;
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        BulkParser::new().write_entries(&mut file, &mut entries).map_err(|err| FileError::new(schema, err))?;
+        if !params.project.is_empty() {
+            let entry = entries.iter()
+                .find(|entry| entry.get_one("objectclasses").is_some())
+                .ok_or("the schema file has no objectClasses")?;
+            let projection = Projection::from_schema(entry, &params.project).map_err(|err| FileError::new(schema, err))?;
+            params.projection = Some(projection);
+        }
+    }
+    match params.input {
+        Some(ref path) if params.in_place.is_empty() => {
+            File::open(path)
+                .and_then(|mut input| format(&params, &mut input, stdout().lock()))
+                .map_err(|err| FileError::new(path, err))?;
+        },
+        None if params.in_place.is_empty() => format(&params, &mut stdin().lock(), stdout().lock())?,
+        _ => {},
+    }
+    for path in params.in_place.iter() {
+        let result = File::open(path).and_then(|mut input| {
+            let mut output = InPlaceFile::create(path)?;
+            format(&params, &mut input, &mut output)?;
+            output.commit()
+        });
+        result.map_err(|err| FileError::new(path, err))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lfmt", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_canonical() -> std::io::Result<()> {
+        let params = Parameters{
+            fold_width: None,
+            line_ending: LineEnding::Lf,
+            sort_attributes: true,
+            operational: None,
+            schema: None,
+            project: Vec::new(),
+            projection: None,
+            canonical: true,
+            add_parents: false,
+            existing: Vec::new(),
+            container_classes: Vec::new(),
+            base64_policy: Base64Policy::default(),
+            input: None,
+            in_place: Vec::new(),
+        };
+        let ldif = concat!(
+            "dn: cn=b,ou=x,dc=example\nobjectClass: top\ncn: b\nobjectClass: person\n\n",
+            "dn: dc=example\ndc: example\n\n",
+            "dn: ou=x, DC=example\nou: x\n\n",
+            "dn: cn=a,ou=x,dc=example\ncn: a\n",
+        );
+        let mut output: Vec<u8> = Vec::new();
+        format(&params, &mut ldif.as_bytes(), &mut output)?;
+        assert_eq!(String::from_utf8_lossy(&output), concat!(
+            "dn: dc=example\ndc: example\n\n",
+            "dn: ou=x, DC=example\nou: x\n\n",
+            "dn: cn=a,ou=x,dc=example\ncn: a\n\n",
+            "dn: cn=b,ou=x,dc=example\ncn: b\nobjectClass: person\nobjectClass: top\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_parents() -> std::io::Result<()> {
+        let params = Parameters{
+            fold_width: None,
+            line_ending: LineEnding::Lf,
+            sort_attributes: false,
+            operational: None,
+            schema: None,
+            project: Vec::new(),
+            projection: None,
+            canonical: false,
+            add_parents: true,
+            existing: vec![String::from("dc=example")],
+            container_classes: vec![(String::from("ou"), String::from("organizationalUnit")), (String::from("l"), String::from("locality"))],
+            base64_policy: Base64Policy::default(),
+            input: None,
+            in_place: Vec::new(),
+        };
+        let ldif = concat!(
+            "dn: cn=a,ou=People,l=Oslo\\, Norway,dc=example\ncn: a\n\n",
+            "dn: cn=b,ou=people,l=Oslo\\, Norway,dc=example\ncn: b\n\n",
+            "dn: ou=groups,dc=example\nou: groups\n\n",
+            "dn: cn=c,ou=groups,dc=example\ncn: c\n",
+        );
+        let mut output: Vec<u8> = Vec::new();
+        format(&params, &mut ldif.as_bytes(), &mut output)?;
+        assert_eq!(String::from_utf8_lossy(&output), concat!(
+            "dn: l=Oslo\\, Norway,dc=example\nobjectClass: top\nobjectClass: locality\nl: Oslo, Norway\n\n",
+            "dn: ou=People,l=Oslo\\, Norway,dc=example\nobjectClass: top\nobjectClass: organizationalUnit\nou: People\n\n",
+            "dn: cn=a,ou=People,l=Oslo\\, Norway,dc=example\ncn: a\n\n",
+            "dn: cn=b,ou=people,l=Oslo\\, Norway,dc=example\ncn: b\n\n",
+            "dn: ou=groups,dc=example\nou: groups\n\n",
+            "dn: cn=c,ou=groups,dc=example\ncn: c\n",
+        ));
+        let ldif = "dn: cn=a,uid=x,dc=example\ncn: a\n";
+        assert!(format(&params, &mut ldif.as_bytes(), &mut Vec::new()).is_err());
+        Ok(())
+    }
+}
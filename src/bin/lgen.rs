@@ -0,0 +1,221 @@
+use clap::{arg, command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output};
+use ltools::dn::{avas, rdns};
+use ltools::entry::{OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use rand::rngs::StdRng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::io::{stdout, BufWriter, Write};
+
+const GIVEN_NAMES: [&str; 32] = [
+    "Alice", "Bob", "Carol", "David", "Emma", "Frank", "Grace", "Henrik", "Ingrid", "James",
+    "Kari", "Lars", "Maria", "Nils", "Olivia", "Per", "Queenie", "Ragnhild", "Sofia", "Thomas",
+    "Una", "Viktor", "Wenche", "Xavier", "Yusuf", "Zoe", "Ahmed", "Bjørn", "Chen", "Dagny",
+    "Elif", "Fatima",
+];
+
+const SURNAMES: [&str; 32] = [
+    "Andersen", "Berg", "Chowdhury", "Dahl", "Eriksen", "Fischer", "Garcia", "Hansen",
+    "Iversen", "Johansen", "Khan", "Larsen", "Moen", "Nguyen", "Olsen", "Pedersen", "Quist",
+    "Rasmussen", "Smith", "Tanaka", "Ullmann", "Vik", "Wang", "Xu", "Yilmaz", "Zimmermann",
+    "O'Brien", "Haugen", "Kowalski", "Lund", "Müller", "Novak",
+];
+
+const DEPARTMENTS: [&str; 8] = [
+    "Engineering", "Finance", "Human Resources", "Legal", "Marketing", "Operations", "Sales", "Support",
+];
+
+struct Parameters {
+    base: String,
+    count: usize,
+    groups: usize,
+    fan_out: usize,
+    seed: Option<u64>,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lgen")
+        .disable_colored_help(true)
+        .about("Writes LDIF with fake but realistic people and groups under a base DN, for load testing parsers and servers without using production data.")
+        .arg(arg!(-n --count <COUNT> "Write COUNT people.")
+            .required(false)
+            .default_value("100"))
+        .arg(arg!(-b --base <DN> "Put the entries in ou=people and ou=groups under DN. The domain of the mail addresses is taken from its dc components.")
+            .required(false)
+            .default_value("dc=example,dc=com"))
+        .arg(arg!(-g --groups <COUNT> "Write COUNT groups. The default is one for every ten people.")
+            .required(false))
+        .arg(arg!(--"fan-out" <MEMBERS> "Give each group MEMBERS members, picked at random among the people.")
+            .required(false)
+            .default_value("10"))
+        .arg(arg!(--seed <SEED> "Seed the random generator with the number SEED, so that the same output is written each time.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let parse_number = |name: &str, message: &'static str| -> Result<Option<usize>, &'static str> {
+        matches.get_one::<String>(name)
+            .map(|value| value.parse::<usize>().map_err(|_| message))
+            .transpose()
+    };
+    let count = parse_number("count", "failed to parse count")?.unwrap_or(100);
+    let seed = matches.get_one::<String>("seed")
+        .map(|seed| seed.parse::<u64>().map_err(|_| "failed to parse seed"))
+        .transpose()?;
+
+    Ok(Parameters{
+        base: matches.get_one::<String>("base").unwrap().clone(),
+        count,
+        groups: parse_number("groups", "failed to parse group count")?.unwrap_or(count / 10),
+        fan_out: parse_number("fan-out", "failed to parse fan-out")?.unwrap_or(10),
+        seed,
+    })
+}
+
+// Returns the domain given by the dc components of the DN, such as example.com.
+fn mail_domain(base: &str) -> String {
+    let components: Vec<&str> = rdns(base)
+        .flat_map(avas)
+        .filter(|(attr, _)| attr.eq_ignore_ascii_case("dc"))
+        .map(|(_, value)| value)
+        .collect();
+    if components.is_empty() {
+        String::from("example.com")
+    } else {
+        components.join(".").to_lowercase()
+    }
+}
+
+// Makes a name usable in uids and mail addresses, such as bjorn for Bjørn.
+fn ascii_name(name: &str) -> String {
+    name.chars()
+        .filter_map(|c| match c {
+            'ø' | 'ö' | 'Ø' | 'Ö' => Some('o'),
+            'ü' | 'Ü' => Some('u'),
+            'å' | 'ä' | 'æ' | 'Å' | 'Ä' | 'Æ' => Some('a'),
+            c if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn generate<R: Rng, W: WriteEntry>(params: &Parameters, rng: &mut R, mut dest: W) -> std::io::Result<()> {
+    let people_dn = format!("ou=people,{}", params.base);
+    let groups_dn = format!("ou=groups,{}", params.base);
+    let domain = mail_domain(&params.base);
+    for (ou, dn) in [("people", &people_dn), ("groups", &groups_dn)] {
+        dest.write_entry(&OwnedEntry::from([
+            ("dn", dn.as_bytes()),
+            ("objectClass", b"top".as_slice()),
+            ("objectClass", b"organizationalUnit".as_slice()),
+            ("ou", ou.as_bytes()),
+        ]))?;
+    }
+
+    let mut uid_counts: HashMap<String, usize> = HashMap::new();
+    let mut person_dns: Vec<String> = Vec::with_capacity(params.count);
+    for index in 0..params.count {
+        let given_name = *GIVEN_NAMES.choose(rng).unwrap();
+        let surname = *SURNAMES.choose(rng).unwrap();
+        let mut uid = format!("{}.{}", ascii_name(given_name), ascii_name(surname));
+        let uid_count = uid_counts.entry(uid.clone()).or_default();
+        *uid_count += 1;
+        if *uid_count > 1 {
+            uid = format!("{}{}", uid, uid_count);
+        }
+        let dn = format!("uid={},{}", uid, people_dn);
+        let mut person = OwnedEntry::default();
+        person.add_value("dn", dn.as_bytes());
+        for objectclass in ["top", "person", "organizationalPerson", "inetOrgPerson"] {
+            person.add_value("objectClass", objectclass.as_bytes());
+        }
+        person.add_value("uid", uid.as_bytes());
+        person.add_value("cn", format!("{} {}", given_name, surname).as_bytes());
+        person.add_value("givenName", given_name.as_bytes());
+        person.add_value("sn", surname.as_bytes());
+        person.add_value("mail", format!("{}@{}", uid, domain).as_bytes());
+        let phone = format!("+1 555 {:03} {:04}", rng.gen_range(100..1000), rng.gen_range(0..10000));
+        person.add_value("telephoneNumber", phone.as_bytes());
+        person.add_value("departmentNumber", DEPARTMENTS.choose(rng).unwrap().as_bytes());
+        person.add_value("employeeNumber", (index + 1).to_string().as_bytes());
+        dest.write_entry(&person)?;
+        person_dns.push(dn);
+    }
+
+    // groupOfNames requires at least one member
+    if person_dns.is_empty() || params.fan_out == 0 {
+        return Ok(());
+    }
+    for index in 0..params.groups {
+        let cn = format!("group-{}", index + 1);
+        let mut group = OwnedEntry::default();
+        group.add_value("dn", format!("cn={},{}", cn, groups_dn).as_bytes());
+        group.add_value("objectClass", b"top");
+        group.add_value("objectClass", b"groupOfNames");
+        group.add_value("cn", cn.as_bytes());
+        let mut members = person_dns.iter().choose_multiple(rng, params.fan_out);
+        members.sort();
+        for member in members {
+            group.add_value("member", member.as_bytes());
+        }
+        dest.write_entry(&group)?;
+    }
+    Ok(())
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut rng = match params.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut dest = BufWriter::new(stdout());
+    let mut writer = LdifEntryWriter::new(&mut dest);
+    writer.set_sort_attributes(false);
+    generate(&params, &mut rng, writer)?;
+    dest.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lgen", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generate() -> std::io::Result<()> {
+        let params = Parameters{
+            base: String::from("dc=example,dc=org"),
+            count: 40,
+            groups: 3,
+            fan_out: 5,
+            seed: Some(1),
+        };
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        generate(&params, &mut StdRng::seed_from_u64(1), &mut entries)?;
+        assert_eq!(entries.len(), 2 + 40 + 3);
+        let uids: std::collections::HashSet<String> = entries.iter()
+            .filter_map(|entry| entry.get_one_str("uid").map(String::from))
+            .collect();
+        assert_eq!(uids.len(), 40);
+        assert!(entries[2].get_one_str("mail").is_some_and(|mail| mail.ends_with("@example.org")));
+        assert_eq!(entries[44].get("member").count(), 5);
+
+        let mut again: Vec<OwnedEntry> = Vec::new();
+        generate(&params, &mut StdRng::seed_from_u64(1), &mut again)?;
+        assert!(entries.iter().zip(again.iter()).all(|(a, b)| a.get_one("dn") == b.get_one("dn")));
+        Ok(())
+    }
+}
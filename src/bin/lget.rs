@@ -1,15 +1,30 @@
-use clap::{arg, command, Arg};
+use clap::{arg, command, Command, Arg, ValueSource};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
 use ltools::base64::{DecodeState, DecodeWriter};
 use ltools::crstrip::CrStripper;
 use ltools::lexer::{Lexer, WriteToken, Token, TokenKind};
-use ltools::loc::WriteLocWrapper;
+use ltools::loc::{base64_error, WriteLocWrapper};
 use ltools::unfold::Unfolder;
 use ltools::tsv::TsvEntryWriter;
-use ltools::csv::CsvEntryWriter;
-use ltools::json::JsonEntryWriter;
-use ltools::entry::EntryTokenWriter;
-use ltools::attrspec::AttrSpec;
-use std::io::{copy, stdin, stdout, Write};
+use ltools::csv::{CsvEntryWriter, ExcelPolicy};
+use ltools::json::{JsonEntryWriter, JsonTreeWriter};
+use ltools::template::{Template, TemplateEntryWriter};
+use ltools::channel::pipelined;
+use ltools::jobs::{check_inputs, process_inputs};
+use ltools::entry::{EntryTokenWriter, OwnedEntry, WriteEntry};
+use ltools::attrspec::{expand_wildcards, AttrSpec, ValueFilter};
+use ltools::extsort::ExternalSorter;
+use ltools::compress::decompress;
+use ltools::progress::{total_file_size, Progress, ProgressReader, ProgressTokenWriter};
+use ltools::output::OutputBuffer;
+use ltools::rename::{AttributeMap, AttributeRenameTokenWriter};
+use ltools::separator::RecordSeparator;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{copy, stdin, stdout, Error, Read, Write};
 
 #[derive(PartialEq)]
 enum ValueType {
@@ -17,13 +32,50 @@ enum ValueType {
     Base64,
 }
 
+// How each value is delimited in single-attribute output.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    // followed by the record separator, which is ambiguous for values that contain it
+    Delimited,
+    // as a netstring, such as 3:foo,
+    Netstring,
+    // preceded by its length as a 32-bit big-endian integer
+    Length,
+}
+
+impl Framing {
+    fn write_frame<W: Write>(&self, dest: &mut W, value: &[u8]) -> std::io::Result<()> {
+        match self {
+            Framing::Delimited => unreachable!("delimited values are not buffered"),
+            Framing::Netstring => {
+                write!(dest, "{}:", value.len())?;
+                dest.write_all(value)?;
+                dest.write_all(b",")
+            },
+            Framing::Length => {
+                let len = u32::try_from(value.len())
+                    .map_err(|_| Error::other("value too long for a 32-bit length prefix"))?;
+                dest.write_all(&len.to_be_bytes())?;
+                dest.write_all(value)
+            },
+        }
+    }
+}
+
 struct OctetStreamTokenWriter<W: Write> {
     attrtype: String,
     ismatch: bool,
     dest: W,
     valuetype: ValueType,
     b64state: DecodeState,
-    delimiter: u8,
+    delimiter: RecordSeparator,
+    framing: Framing,
+    value: Vec<u8>, // the value so far, when framed, since the frame starts with its length
+    with_dn: bool,
+    first_attribute: bool, // whether the next attribute type is the first of its entry
+    in_dn: bool,
+    dn: Vec<u8>, // of the current entry, as in the input until it has been decoded
+    dn_base64: bool,
 }
 
 impl<W: Write> OctetStreamTokenWriter<W> {
@@ -34,14 +86,58 @@ impl<W: Write> OctetStreamTokenWriter<W> {
             dest,
             valuetype: ValueType::Text,
             b64state: DecodeState::default(),
-            delimiter: b'\n',
+            delimiter: RecordSeparator::LF,
+            framing: Framing::Delimited,
+            value: Vec::new(),
+            with_dn: false,
+            first_attribute: true,
+            in_dn: false,
+            dn: Vec::new(),
+            dn_base64: false,
         }
     }
 
-    fn set_delimiter(&mut self, delimiter: u8) -> &mut Self {
+    fn set_delimiter(&mut self, delimiter: RecordSeparator) -> &mut Self {
         self.delimiter = delimiter;
         self
     }
+
+    fn set_framing(&mut self, framing: Framing) -> &mut Self {
+        self.framing = framing;
+        self
+    }
+
+    // Writes the DN of the entry before each value, followed by a tab or as a frame of its own.
+    fn set_with_dn(&mut self, with_dn: bool) -> &mut Self {
+        self.with_dn = with_dn;
+        self
+    }
+
+    fn write_dn(&mut self) -> std::io::Result<()> {
+        if self.framing == Framing::Delimited {
+            self.dest.write_all(&self.dn)?;
+            self.dest.write_all(b"\t")
+        } else {
+            self.framing.write_frame(&mut self.dest, &self.dn)
+        }
+    }
+
+    fn finish_dn(&mut self) -> std::io::Result<()> {
+        if self.dn_base64 {
+            let mut decoded: Vec<u8> = Vec::new();
+            let mut state = DecodeState::default();
+            state.decode(&self.dn, &mut decoded)?;
+            state.finish()?;
+            self.dn = decoded;
+        }
+        self.in_dn = false;
+        Ok(())
+    }
+
+    // Where the pieces of a value go.
+    fn value_dest(&mut self) -> &mut dyn Write {
+        if self.framing == Framing::Delimited { &mut self.dest } else { &mut self.value }
+    }
 }
 
 impl<W: Write> WriteToken for OctetStreamTokenWriter<W> {
@@ -50,53 +146,302 @@ impl<W: Write> WriteToken for OctetStreamTokenWriter<W> {
             TokenKind::AttributeType => {
                 self.ismatch =
                     token.segment.to_ascii_lowercase() == self.attrtype;
+                if self.with_dn {
+                    // the version specifier can come right before the DN of the first entry
+                    if self.first_attribute && !token.segment.eq_ignore_ascii_case("version") {
+                        self.dn.clear();
+                        self.in_dn = token.segment.eq_ignore_ascii_case("dn");
+                        self.dn_base64 = false;
+                        self.first_attribute = false;
+                    }
+                    if self.ismatch {
+                        self.write_dn()?;
+                    }
+                }
             }
             TokenKind::ValueText => {
+                if self.in_dn {
+                    self.dn.extend_from_slice(token.segment.as_bytes());
+                }
                 if self.ismatch {
-                    self.dest.write_all(token.segment.as_bytes())?;
+                    self.value_dest().write_all(token.segment.as_bytes())?;
                     self.valuetype = ValueType::Text;
                 }
             }
             TokenKind::ValueBase64 => {
+                if self.in_dn {
+                    self.dn.extend_from_slice(token.segment.as_bytes());
+                    self.dn_base64 = true;
+                }
                 if self.ismatch {
-                    let mut decoder = DecodeWriter::new_with_state(&mut self.dest, self.b64state);
-                    decoder.write_all(token.segment.as_bytes())?;
-                    self.b64state = decoder.get_state();
+                    let b64state = self.b64state;
+                    let mut decoder = DecodeWriter::new_with_state(self.value_dest(), b64state);
+                    decoder.write_all(token.segment.as_bytes()).map_err(|err| base64_error(token.loc, err))?;
+                    let b64state = decoder.get_state();
+                    self.b64state = b64state;
                     self.valuetype = ValueType::Base64;
                 }
             }
             TokenKind::ValueFinish => {
+                if self.in_dn {
+                    self.finish_dn()?;
+                }
                 if self.ismatch {
                     if self.valuetype == ValueType::Base64 {
                         // TODO: consider raising an error if it isn't in a valid end state
                         self.b64state = DecodeState::default();
                     }
-                    self.dest.write_all(&[self.delimiter])?;
-                    self.dest.flush()?;
+                    if self.framing == Framing::Delimited {
+                        self.dest.write_all(self.delimiter.as_bytes())?;
+                    } else {
+                        self.framing.write_frame(&mut self.dest, &self.value)?;
+                        self.value.clear();
+                    }
                 }
                 self.ismatch = true;
             }
-            TokenKind::EntryFinish => {}
+            TokenKind::EntryFinish => {
+                self.first_attribute = true;
+                self.dn.clear();
+            }
         }
         Ok(())
     }
 }
 
-#[derive(PartialEq, Eq)]
+// Records the attribute types that appear in the input, in lowercase.
+struct SeenAttributesTokenWriter<'a, TR: WriteToken> {
+    seen: Option<&'a mut HashSet<String>>,
+    inner: TR,
+}
+
+impl<TR: WriteToken> WriteToken for SeenAttributesTokenWriter<'_, TR> {
+    fn write_token(&mut self, token: Token) -> std::io::Result<()> {
+        if let (TokenKind::AttributeType, Some(ref mut seen)) = (&token.kind, &mut self.seen) {
+            if !seen.contains(token.segment) && !seen.contains(&token.segment.to_ascii_lowercase()) {
+                seen.insert(token.segment.to_ascii_lowercase());
+            }
+        }
+        self.inner.write_token(token)
+    }
+}
+
+// How output records are sorted.
+#[derive(Clone, Copy)]
+struct SortKey {
+    column: Option<usize>, // the whole record is compared if None
+    numeric: bool,
+    delimiter: RecordSeparator,
+    csv: bool,
+}
+
+impl SortKey {
+    fn key<'a>(&self, record: &'a [u8]) -> Cow<'a, [u8]> {
+        let record = record.strip_suffix(self.delimiter.as_bytes()).unwrap_or(record);
+        match self.column {
+            None => Cow::Borrowed(record),
+            Some(column) if self.csv => Cow::Owned(csv_fields(record).into_iter().nth(column).unwrap_or_default()),
+            Some(column) => Cow::Borrowed(record.split(|byte| *byte == b'\t').nth(column).unwrap_or_default()),
+        }
+    }
+
+    // Numbers come before other values when sorting numerically.
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        let (a, b) = (self.key(a), self.key(b));
+        if !self.numeric {
+            return a.cmp(&b);
+        }
+        let number = |key: &[u8]| std::str::from_utf8(key).ok()
+            .and_then(|key| key.trim().parse::<f64>().ok())
+            .filter(|number| !number.is_nan());
+        match (number(&a), number(&b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.cmp(&b),
+        }
+    }
+}
+
+fn csv_fields(record: &[u8]) -> Vec<Vec<u8>> {
+    let mut fields: Vec<Vec<u8>> = vec![Vec::new()];
+    let mut quoted = false;
+    let mut bytes = record.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'"' if quoted && bytes.peek() == Some(&b'"') => {
+                bytes.next();
+                fields.last_mut().unwrap().push(b'"');
+            },
+            b'"' => quoted = !quoted,
+            b',' if !quoted => fields.push(Vec::new()),
+            _ => fields.last_mut().unwrap().push(byte),
+        }
+    }
+    fields
+}
+
+type CompareRecords = Box<dyn Fn(&[u8], &[u8]) -> Ordering + Send>;
+
+// Sorts output records or drops records that have already been written, or both. Records end with
+// the delimiter, except that in CSV a newline within a quoted field does not end the record, and
+// the CSV header is kept first. With neither, output is passed straight through.
+struct RecordWriter<W: Write> {
+    dest: W,
+    delimiter: u8,
+    csv: bool,
+    quoted: bool,
+    record: Vec<u8>,
+    written: Option<HashSet<Vec<u8>>>, // None unless repeated records are dropped
+    sorter: Option<ExternalSorter<CompareRecords>>,
+    has_header: bool,
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn new(dest: W, delimiter: u8, csv: bool) -> RecordWriter<W> {
+        RecordWriter {
+            dest,
+            delimiter,
+            csv,
+            quoted: false,
+            record: Vec::new(),
+            written: None,
+            sorter: None,
+            has_header: csv,
+        }
+    }
+
+    fn set_distinct(&mut self, distinct: bool) -> &mut Self {
+        self.written = distinct.then(HashSet::new);
+        self
+    }
+
+    fn set_sort_key(&mut self, sort_key: Option<SortKey>) -> &mut Self {
+        self.sorter = sort_key.map(|sort_key| {
+            let compare: CompareRecords = Box::new(move |a, b| sort_key.compare(a, b));
+            ExternalSorter::new(compare)
+        });
+        self
+    }
+
+    fn end_record(&mut self) -> std::io::Result<()> {
+        let record = std::mem::take(&mut self.record);
+        match self.sorter {
+            Some(ref mut sorter) if !self.has_header => sorter.push(record),
+            _ => {
+                self.has_header = false;
+                self.write_record(&record)
+            },
+        }
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> std::io::Result<()> {
+        if let Some(ref mut written) = self.written {
+            if written.contains(record) {
+                return Ok(());
+            }
+            written.insert(record.to_vec());
+        }
+        self.dest.write_all(record)
+    }
+
+    // Writes the sorted records, and the last record even if it lacks a delimiter.
+    fn finish(&mut self) -> std::io::Result<()> {
+        if !self.record.is_empty() {
+            self.end_record()?;
+        }
+        if let Some(sorter) = self.sorter.take() {
+            sorter.finish(|record| self.write_record(record))?;
+        }
+        self.dest.flush()
+    }
+}
+
+impl<W: Write> Write for RecordWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.written.is_none() && self.sorter.is_none() {
+            return self.dest.write(buf);
+        }
+        for byte in buf.iter().copied() {
+            self.record.push(byte);
+            if self.csv && byte == b'"' {
+                self.quoted = !self.quoted;
+            } else if byte == self.delimiter && !self.quoted {
+                self.end_record()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.dest.flush()
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum MissingAttributes {
+    Ignore,
+    Warn,
+    Fail,
+}
+
+#[derive(Clone, PartialEq, Eq)]
 enum OutputFormat {
     Tsv,
     Csv,
     Json,
+    JsonTree,
+    Template(String),
 }
 
-fn parse_arguments() -> Result<(Vec<String>, u8, OutputFormat), &'static str> {
-    let mut delimiter = b'\n';
-    let mut output_format = OutputFormat::Tsv;
+impl OutputFormat {
+    // JSON objects only have the attributes of their entry, so wildcards are expanded for each.
+    fn is_json(&self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::JsonTree)
+    }
+}
+
+struct Parameters {
+    attrspecs: Vec<String>,
+    inputs: Vec<String>, // empty means standard input
+    delimiter: RecordSeparator,
+    framing: Framing, // for a single attribute in the default format
+    with_dn: bool, // likewise
+    output_format: OutputFormat,
+    excel_policy: Option<ExcelPolicy>, // for CSV output
+    jobs: usize,
+    threads: usize, // with 2 or more, entries are assembled and written on separate threads
+    missing: MissingAttributes, // what to do about requested attributes that never appear
+    distinct: bool,
+    sort_key: Option<SortKey>,
+    progress: Option<Progress>,
+    map_file: Option<String>,
+    map: Option<AttributeMap>, // to rename attributes of the input with, read from map_file
+}
 
-    let matches = command!("lget")
+// The command without the defaults from the configuration file, which the tests use.
+fn command() -> Command<'static> {
+    command!("lget")
         .disable_colored_help(true)
         .about("Parses LDIF entries and outputs attribute values for the given attributes type names. By default, lget will output tab-separated values if multiple attributes are specified.")
-        .arg(arg!(<ATTRIBUTES> ... "The attribute type names to get values of. The attribute type name can be suffixed with .hex or .base64 to get the corresponding encoding. It can futher be suffixed with :- if you want lget to provide a default value for entries that lack the given attribute (this syntax is borrowed from bash)."))
+        .arg(arg!(<ATTRIBUTES> ... "The attribute type names to get values of. The attribute type name can be suffixed with .hex or .base64 to get the corresponding encoding, or with .uniq to drop values repeated within an entry. It can futher be suffixed with :- if you want lget to provide a default value for entries that lack the given attribute (this syntax is borrowed from bash). The attribute type name * stands for every attribute in the input that isn't otherwise given, and dirxml-* for every such attribute starting with dirxml-. Attributes matched by wildcards can be excluded with !, as in !userPassword. LDIF files to read instead of standard input are best given with -i or after --. Without either, the attributes can be followed by the files, and the file list starts at the first argument that is not an attribute type name, so a file such as 'users' would be taken as an attribute.")
+             .required(false)
+             .required_unless_present_any(["format", "all"]))
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input), given after --.")
+             .last(true))
+        .arg(Arg::new("input")
+             .short('i')
+             .long("input")
+             .value_name("FILE")
+             .action(clap::ArgAction::Append)
+             .help("Read LDIF from FILE instead of standard input ('-' means standard input). Can be given multiple times, in which case the files are read one after another, before any given after --. Every argument is then read as an attribute."),
+        )
+        .arg(Arg::new("all")
+             .short('a')
+             .long("all")
+             .action(clap::ArgAction::SetTrue)
+             .help("Write every attribute in the input, as if * was given as ATTRIBUTES. Every argument is read as an input file."),
+        )
         .arg(
             Arg::new("null-delimit")
                 .short('0')
@@ -105,98 +450,758 @@ fn parse_arguments() -> Result<(Vec<String>, u8, OutputFormat), &'static str> {
                 .action(clap::ArgAction::SetTrue)
                 .help("Terminate output values with null bytes (0x00) instead of newlines."),
         )
+        .arg(
+            Arg::new("crlf")
+                .long("crlf")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("null-delimit")
+                .help("Terminate output values with CRLF instead of newlines. CSV output always uses CRLF unless -0 is given."),
+        )
+        .arg(Arg::new("framed")
+             .long("framed")
+             .value_name("FRAMING")
+             .value_parser(["netstring", "length"])
+             .min_values(0)
+             .max_values(1)
+             .require_equals(true)
+             .default_missing_value("netstring")
+             .conflicts_with_all(&["null-delimit", "crlf"])
+             .help("When getting a single attribute, write each value as a frame that gives its length instead of terminating it, so that values with newlines or null bytes can be told apart. With netstring, the default, each value is written as a netstring, as in 3:foo, and with length it is preceded by its length in bytes as a 32-bit big-endian integer."),
+        )
+        .arg(Arg::new("with-dn")
+             .long("with-dn")
+             .action(clap::ArgAction::SetTrue)
+             .help("When getting a single attribute, write the DN of the entry and a tab before each value, or with --framed, the DN as a frame of its own before the frame of each value."),
+        )
         .arg(Arg::new("json")
              .short('j')
              .long("json")
              .action(clap::ArgAction::SetTrue)
              .help("Write specified attributes for each entry as a JSON object with string array values."),
         )
+        .arg(Arg::new("json-tree")
+             .long("json-tree")
+             .action(clap::ArgAction::SetTrue)
+             .help("Write one JSON object that mirrors the directory tree, in which each entry is keyed by its RDN under its parent and has an attributes object with the specified attributes and a children object."),
+        )
         .arg(Arg::new("csv")
              .short('c')
              .long("csv")
              .action(clap::ArgAction::SetTrue)
              .help("Write values using the CSV format, including a header."),
         )
-        .get_matches();
+        .arg(Arg::new("excel-safe")
+             .long("excel-safe")
+             .value_name("POLICY")
+             .value_parser(["formula", "prefix"])
+             .help("In CSV output, protect values that Excel would take for formulas or turn into numbers, such as ones starting with =, numbers with leading zeros and numbers of more than 15 digits. With the formula policy they are written as formulas that give the value as text, as in =\"007\", and with the prefix policy they are prefixed with an apostrophe."),
+        )
+        .arg(Arg::new("format")
+             .short('f')
+             .long("format")
+             .value_name("TEMPLATE")
+             .help("Write a line for each entry according to TEMPLATE, such as 'uid={uid} mail={mail:-<none>}'. Placeholders take the same syntax as ATTRIBUTES, and every argument is read as an input file."),
+        )
+        .arg(Arg::new("jobs")
+             .long("jobs")
+             .value_name("N")
+             .value_parser(clap::value_parser!(usize))
+             .help("Process up to N input files at the same time. Output is still written in the order the files are given."),
+        )
+        .arg(Arg::new("threads")
+             .long("threads")
+             .value_name("N")
+             .value_parser(clap::value_parser!(u64).range(1..=2))
+             .help("With N of 2, parse the input and assemble entries on one thread while formatting and writing the output on another, which speeds up large extractions on multi-core machines. Extractions of a single attribute in the default format are always done on one thread."),
+        )
+        .arg(Arg::new("require")
+             .long("require")
+             .action(clap::ArgAction::SetTrue)
+             .help("Fail if one of the requested attributes does not appear in any entry, such as because of a misspelled attribute type name."),
+        )
+        .arg(Arg::new("warn-missing")
+             .long("warn-missing")
+             .action(clap::ArgAction::SetTrue)
+             .help("Warn on standard error if one of the requested attributes does not appear in any entry."),
+        )
+        .arg(Arg::new("distinct")
+             .short('D')
+             .long("distinct")
+             .action(clap::ArgAction::SetTrue)
+             .help("Write each distinct output line only once, keeping the order in which they first appear. Values repeated within an entry can instead be dropped by suffixing the attribute type name with .uniq."),
+        )
+        .arg(Arg::new("sort")
+             .short('s')
+             .long("sort")
+             .action(clap::ArgAction::SetTrue)
+             .help("Sort the output lines. Large outputs are sorted in temporary files."),
+        )
+        .arg(Arg::new("numeric-sort")
+             .short('n')
+             .long("numeric-sort")
+             .action(clap::ArgAction::SetTrue)
+             .help("Sort the output lines by numeric value, with values that are not numbers last."),
+        )
+        .arg(Arg::new("sort-by")
+             .long("sort-by")
+             .value_name("ATTRIBUTE")
+             .help("Sort the rows of TSV or CSV output by the column of ATTRIBUTE, which has to be one of ATTRIBUTES."),
+        )
+        .arg(Arg::new("map")
+             .long("map")
+             .value_name("FILE")
+             .help("Rename attributes of the input according to FILE, which has lines such as mail=rfc822Mailbox, before getting ATTRIBUTES. ATTRIBUTES are then given by their new names."),
+        )
+        .arg(Arg::new("progress")
+             .long("progress")
+             .action(clap::ArgAction::SetTrue)
+             .help("Report the bytes read, the entries processed and the rate to standard error, as a progress bar if it is a terminal."),
+        )
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+}
 
-    if matches.get_flag("null-delimit") {
-        delimiter = 0x00;
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    parse_arguments_from(with_defaults(command()), std::env::args_os())
+}
+
+fn parse_arguments_from<I, T>(cmd: Command, args: I) -> Result<Parameters, &'static str>
+    where I: IntoIterator<Item = T>, T: Into<std::ffi::OsString> + Clone
+{
+    let mut params = Parameters {
+        attrspecs: Vec::new(),
+        inputs: Vec::new(),
+        delimiter: RecordSeparator::LF,
+        framing: Framing::Delimited,
+        with_dn: false,
+        output_format: OutputFormat::Tsv,
+        excel_policy: None,
+        jobs: 1,
+        threads: 1,
+        missing: MissingAttributes::Ignore,
+        distinct: false,
+        sort_key: None,
+        progress: None,
+        map_file: None,
+        map: None,
+    };
+
+    let matches = cmd.get_matches_from(args);
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    params.distinct = matches.get_flag("distinct");
+    params.map_file = matches.get_one::<String>("map").cloned();
+    if matches.get_flag("progress") {
+        params.progress = Some(Progress::new("lget", None));
     }
 
-    let output_formats = ["json", "csv"];
-    if output_formats.iter().filter(|format| matches.get_flag(format)).count() > 1 {
+    if let Some(jobs) = matches.get_one::<usize>("jobs") {
+        params.jobs = *jobs;
+    }
+    if let Some(threads) = matches.get_one::<u64>("threads") {
+        params.threads = *threads as usize;
+    }
+
+    if matches.get_flag("require") {
+        params.missing = MissingAttributes::Fail;
+    } else if matches.get_flag("warn-missing") {
+        params.missing = MissingAttributes::Warn;
+    }
+
+    // an output format given on the command line takes precedence over one from ltools.toml
+    let is_set = |format: &&str, source: ValueSource| matches.value_source(format) == Some(source)
+        && (*format == "format" || matches.get_flag(format));
+    let output_formats = ["json", "json-tree", "csv", "format"];
+    let source = if output_formats.iter().any(|format| is_set(format, ValueSource::CommandLine)) {
+        ValueSource::CommandLine
+    } else {
+        ValueSource::DefaultValue
+    };
+    let output_formats: Vec<&str> = output_formats.into_iter().filter(|format| is_set(format, source)).collect();
+    if output_formats.len() > 1 {
         return Err("options specify mutually exclusive output formats")
     }
-    if matches.get_flag("json") {
-        output_format = OutputFormat::Json;
+    match output_formats.first() {
+        Some(&"json") => params.output_format = OutputFormat::Json,
+        Some(&"json-tree") => params.output_format = OutputFormat::JsonTree,
+        Some(&"csv") => params.output_format = OutputFormat::Csv,
+        _ => {},
+    }
+    let template = matches.get_one::<String>("format").filter(|_| output_formats.contains(&"format"));
+
+    let csv = params.output_format == OutputFormat::Csv;
+    params.excel_policy = matches.get_one::<String>("excel-safe").and_then(|policy| ExcelPolicy::parse(policy));
+    if params.excel_policy.is_some() && !csv {
+        return Err("--excel-safe only applies to CSV output");
+    }
+    if matches.get_flag("null-delimit") {
+        params.delimiter = RecordSeparator::NUL;
+    } else if matches.get_flag("crlf") || csv {
+        params.delimiter = RecordSeparator::CrLf;
+    }
+    let sort_by = matches.contains_id("sort-by");
+    if sort_by && !matches!(params.output_format, OutputFormat::Tsv | OutputFormat::Csv) {
+        return Err("--sort-by only applies to TSV and CSV output");
     }
-    if matches.get_flag("csv") {
-        output_format = OutputFormat::Csv;
+    if matches.get_flag("sort") || matches.get_flag("numeric-sort") || sort_by {
+        params.sort_key = Some(SortKey {
+            column: None,
+            numeric: matches.get_flag("numeric-sort"),
+            delimiter: params.delimiter,
+            csv,
+        });
     }
 
-    if let Some(attrtype) = matches.get_many::<String>("ATTRIBUTES") {
-        Ok((attrtype.cloned().collect(), delimiter, output_format))
+    let mut args: Vec<String> = matches.get_many::<String>("ATTRIBUTES")
+        .map(|args| args.cloned().collect())
+        .unwrap_or_default();
+    // files given with -i or after -- end the guessing of where the attributes end
+    let explicit_inputs = matches.contains_id("input") || matches.contains_id("FILES");
+    let inputs = |args: Vec<String>| matches.get_many::<String>("input").into_iter().flatten().cloned()
+        .chain(args)
+        .chain(matches.get_many::<String>("FILES").into_iter().flatten().cloned())
+        .collect();
+    if let Some(template) = template {
+        params.output_format = OutputFormat::Template(template.clone());
+        params.inputs = inputs(args);
+        return Ok(params);
+    }
+    if matches.get_flag("all") {
+        if sort_by {
+            return Err("the --sort-by attribute has to be given before any wildcard");
+        }
+        params.attrspecs = vec![String::from("*")];
+        params.inputs = inputs(args);
+        return Ok(params);
+    }
+
+    if !explicit_inputs {
+        let attrspec_count = args.iter()
+            .take_while(|arg| AttrSpec::parse_syntax(arg).is_ok())
+            .count();
+        params.inputs = args.split_off(attrspec_count);
     } else {
-        // shouldn't happen when the argument is required
-        Err("missing attribute type name on command line")
+        params.inputs = inputs(Vec::new());
+    }
+    params.attrspecs = args;
+    if params.attrspecs.is_empty() {
+        return Err("missing attribute type name on command line");
+    }
+    params.framing = match matches.get_one::<String>("framed").map(String::as_str) {
+        Some("netstring") => Framing::Netstring,
+        Some("length") => Framing::Length,
+        _ => Framing::Delimited,
+    };
+    params.with_dn = matches.get_flag("with-dn");
+    let single = params.attrspecs.len() == 1 && params.output_format == OutputFormat::Tsv && AttrSpec::parse_syntax(&params.attrspecs[0])
+        .map(|attrspec| attrspec.value_filters.is_empty() && !attrspec.is_wildcard() && !attrspec.is_pseudo())
+        .unwrap_or(false);
+    if params.framing != Framing::Delimited {
+        if !single {
+            return Err("--framed only applies to a single attribute without suffixes in the default output format");
+        }
+        if params.distinct || params.sort_key.is_some() {
+            return Err("--framed can't be combined with sorting or --distinct");
+        }
+    }
+    if params.with_dn {
+        if !single {
+            return Err("--with-dn only applies to a single attribute without suffixes in the default output format; give dn as an attribute otherwise");
+        }
+        if params.attrspecs[0].eq_ignore_ascii_case("dn") {
+            return Err("--with-dn can't be combined with getting dn");
+        }
     }
+    if let (Some(sort_key), Some(attribute)) = (params.sort_key.as_mut(), matches.get_one::<String>("sort-by")) {
+        sort_key.column = Some(sort_column(&params.attrspecs, attribute)?);
+    }
+
+    Ok(params)
 }
 
-fn write_tokens<TR: WriteToken>(tr: TR) -> std::io::Result<()> {
-    let lexer = Lexer::new(tr);
+// Returns the output column of the given attribute. Wildcards stand for a number of columns that
+// isn't known until the input has been read, so the attribute has to come before them.
+fn sort_column(attrspecs: &[String], attribute: &str) -> Result<usize, &'static str> {
+    let attrspecs = attrspecs.iter()
+        .filter_map(|spec| AttrSpec::parse_syntax(spec).ok())
+        .filter(|attrspec| !attrspec.exclude);
+    for (column, attrspec) in attrspecs.enumerate() {
+        if attrspec.is_wildcard() {
+            return Err("the --sort-by attribute has to be given before any wildcard");
+        }
+        if attrspec.attribute.eq_ignore_ascii_case(attribute) {
+            return Ok(column);
+        }
+    }
+    Err("the --sort-by attribute is not one of the requested attributes")
+}
+
+fn write_tokens<TR: WriteToken, R: Read + ?Sized>(tr: TR, input: &mut R, seen: &mut Option<HashSet<String>>, params: &Parameters) -> std::io::Result<()> {
+    let tr = ProgressTokenWriter::new(tr, params.progress.as_ref());
+    let tr = SeenAttributesTokenWriter{ seen: seen.as_mut(), inner: tr };
+    let lexer = Lexer::new(AttributeRenameTokenWriter::new(params.map.as_ref(), tr));
     let unfolder = Unfolder::new(lexer);
     let crstripper = CrStripper::new(unfolder);
     let mut wrapper = WriteLocWrapper::new(crstripper);
-    copy(&mut stdin(), &mut wrapper)?;
+    copy(input, &mut wrapper)?;
     wrapper.flush()?;
     Ok(())
 }
 
-fn get_result() -> Result<(), Box<dyn std::error::Error>> {
-    let (attrspec_strings, delimiter, output_format) = parse_arguments()?;
+// Calls f on each input in sequence, or on standard input if there are none, decompressing
+// gzip and zstd compressed inputs. f is given the name of the input, or None for standard input.
+// The bytes read are counted if progress is given.
+fn for_each_input<F>(inputs: &[String], progress: Option<&Progress>, mut f: F) -> std::io::Result<()>
+    where F: FnMut(Option<&str>, &mut dyn Read) -> std::io::Result<()>
+{
+    // the compressed bytes are counted, as the total size is that of the compressed files
+    let mut f = |file: Option<&str>, input: &mut dyn Read| match progress {
+        Some(progress) => f(file, &mut decompress(ProgressReader::new(input, progress))?),
+        None => f(file, &mut decompress(input)?),
+    };
+    if inputs.is_empty() {
+        return f(None, &mut stdin());
+    }
+    for input in inputs.iter() {
+        let result = if input == "-" {
+            f(None, &mut stdin())
+        } else {
+            File::open(input).and_then(|mut file| f(Some(input), &mut file))
+        };
+        result.map_err(|err| FileError::new(input, err))?;
+    }
+    Ok(())
+}
+
+// Assembles the entries of the inputs and writes them to entry_writer, on a thread of its own if
+// --threads is 2. Only the given attributes are assembled, or every attribute if None.
+fn write_input_entries<EW: WriteEntry + Send>(
+    params: &Parameters,
+    inputs: &[String],
+    attributes: Option<&[String]>,
+    ignore_entries_without_dn: bool,
+    entry_writer: &mut EW,
+    seen: &mut Option<HashSet<String>>,
+) -> std::io::Result<()> {
+    let mut write_input = |file: Option<&str>, input: &mut dyn Read, dest: &mut dyn WriteEntry| {
+        let mut token_writer = match attributes {
+            Some(attributes) => EntryTokenWriter::new_for_attributes(attributes.to_vec(), dest),
+            None => EntryTokenWriter::new(dest),
+        };
+        token_writer.set_ignore_entries_without_dn(ignore_entries_without_dn)
+            .set_file(file);
+        write_tokens(token_writer, input, seen, params)
+    };
+    if params.threads < 2 {
+        return for_each_input(inputs, params.progress.as_ref(), |file, input| write_input(file, input, entry_writer));
+    }
+    pipelined(|sender| for_each_input(inputs, params.progress.as_ref(), |file, input| write_input(file, input, sender)), entry_writer)
+}
+
+// Returns the lowercase attributes that entries have to be assembled with for the given
+// specifications. The pseudo-attributes that aren't provenance are computed from the DN.
+fn assembled_attributes(attrspecs: &[AttrSpec]) -> Vec<String> {
+    let mut attributes: Vec<String> = Vec::new();
+    for attrspec in attrspecs.iter() {
+        let attribute = if attrspec.is_pseudo() { "dn" } else { attrspec.attribute_lowercase.as_str() };
+        if !attributes.iter().any(|attr| attr == attribute) {
+            attributes.push(attribute.to_string());
+        }
+    }
+    attributes
+}
+
+// Adds the attribute types that appear in the inputs to seen unless it is None.
+fn write_output<W: Write + Send>(params: &Parameters, inputs: &[String], mut dest: W, csv_header: bool, seen: &mut Option<HashSet<String>>) -> std::io::Result<()> {
+    let delimiter = params.delimiter;
+    if let OutputFormat::Template(ref template) = params.output_format {
+        let template = Template::parse(template)?;
+        let attributes = assembled_attributes(template.attrspecs());
+        let mut entry_writer = TemplateEntryWriter::new(template, dest);
+        entry_writer.set_record_separator(delimiter);
+        return write_input_entries(params, inputs, Some(&attributes), false, &mut entry_writer, seen);
+    }
     let mut attrspecs: Vec<AttrSpec> = Vec::new();
-    for spec in attrspec_strings.iter() {
+    for spec in params.attrspecs.iter() {
         attrspecs.push(AttrSpec::parse(spec)?);
     }
+    let has_wildcard = attrspecs.iter().any(AttrSpec::is_wildcard);
+    if !has_wildcard && attrspecs.iter().any(|attrspec| attrspec.exclude) {
+        return Err(Error::other("exclusions such as !userPassword only apply together with wildcards such as *"));
+    }
+    if has_wildcard && !params.output_format.is_json() {
+        return write_output_all_attributes(params, attrspecs, inputs, dest, csv_header, seen);
+    }
     if attrspecs.len() == 1
         && attrspecs[0].value_filters.is_empty()
-        && output_format == OutputFormat::Tsv
+        && !attrspecs[0].is_pseudo()
+        && params.output_format == OutputFormat::Tsv
     {
-        let mut token_receiver = OctetStreamTokenWriter::new(&attrspecs[0].attribute.to_ascii_lowercase(), stdout());
-        token_receiver.set_delimiter(delimiter);
-        write_tokens(token_receiver)?;
+        let attribute = attrspecs[0].attribute.to_ascii_lowercase();
+        for_each_input(inputs, params.progress.as_ref(), |_, input| {
+            let mut token_receiver = OctetStreamTokenWriter::new(&attribute, &mut dest);
+            token_receiver.set_delimiter(delimiter).set_framing(params.framing).set_with_dn(params.with_dn);
+            write_tokens(token_receiver, input, seen, params)
+        })?;
     } else {
-        let attributes = attrspecs.iter()
-            .map(|spec| spec.attribute.to_ascii_lowercase())
-            .collect();
-        match output_format {
+        let attributes = assembled_attributes(&attrspecs);
+        match params.output_format {
             OutputFormat::Tsv => {
-                let mut entry_writer = TsvEntryWriter::new(attrspecs, stdout());
+                let mut entry_writer = TsvEntryWriter::new(attrspecs, dest);
                 entry_writer.set_record_separator(delimiter);
-                let token_writer = EntryTokenWriter::new_for_attributes(attributes, &mut entry_writer);
-                write_tokens(token_writer)?;
+                write_input_entries(params, inputs, Some(&attributes), false, &mut entry_writer, seen)?;
             },
             OutputFormat::Csv => {
-                let mut entry_writer = CsvEntryWriter::new(attrspecs, stdout());
-                let token_writer = EntryTokenWriter::new_for_attributes(attributes, &mut entry_writer);
-                write_tokens(token_writer)?;
+                let mut entry_writer = CsvEntryWriter::new(attrspecs, dest);
+                entry_writer.set_write_header(csv_header)
+                    .set_record_separator(delimiter)
+                    .set_excel_policy(params.excel_policy);
+                write_input_entries(params, inputs, Some(&attributes), false, &mut entry_writer, seen)?;
             },
             OutputFormat::Json => {
-                let mut entry_writer = JsonEntryWriter::new(attrspecs, stdout());
+                let mut entry_writer = JsonEntryWriter::new(attrspecs, dest);
+                entry_writer.set_record_separator(delimiter);
+                let attributes = if has_wildcard { None } else { Some(&attributes[..]) };
+                write_input_entries(params, inputs, attributes, true, &mut entry_writer, seen)?;
+            },
+            OutputFormat::JsonTree => {
+                let mut entry_writer = JsonTreeWriter::new(attrspecs, dest);
                 entry_writer.set_record_separator(delimiter);
-                let mut token_writer = EntryTokenWriter::new_for_attributes(attributes, &mut entry_writer);
-                token_writer.set_ignore_entries_without_dn(true);
-                write_tokens(token_writer)?;
+                let mut attributes = if has_wildcard { None } else { Some(attributes) };
+                // the entries are placed in the tree by their DNs
+                if let Some(ref mut attributes) = attributes.as_mut().filter(|attributes| !attributes.iter().any(|attr| attr == "dn")) {
+                    attributes.push(String::from("dn"));
+                }
+                write_input_entries(params, inputs, attributes.as_deref(), true, &mut entry_writer, seen)?;
+                entry_writer.finish()?;
             },
+            OutputFormat::Template(_) => unreachable!("handled above"),
         }
     };
     Ok(())
 }
 
+// Writes the attributes that wildcards stand for in TSV or CSV. The entries are kept in memory until every input
+// has been read, so that each entry has a column for every attribute in the input. The names are
+// written as they are first spelled in the input.
+fn write_output_all_attributes<W: Write + Send>(params: &Parameters, mut attrspecs: Vec<AttrSpec>, inputs: &[String], dest: W, csv_header: bool, seen: &mut Option<HashSet<String>>) -> std::io::Result<()> {
+    let mut entries: Vec<OwnedEntry> = Vec::new();
+    for_each_input(inputs, params.progress.as_ref(), |file, input| {
+        let mut token_writer = EntryTokenWriter::new(&mut entries);
+        // records such as the result of ldapsearch would otherwise add columns
+        token_writer.set_ignore_entries_without_dn(true)
+            .set_file(file);
+        write_tokens(token_writer, input, seen, params)
+    })?;
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut attributes: Vec<String> = Vec::new();
+    for entry in entries.iter() {
+        for attr in entry.attributes() {
+            if seen.insert(attr.lowercase.to_string()) {
+                attributes.push(attr.name.to_string());
+            }
+        }
+    }
+    // entries lacking some of the attributes would otherwise give no rows
+    for attrspec in attrspecs.iter_mut().filter(|attrspec| attrspec.is_wildcard()) {
+        let has_default = attrspec.value_filters.iter()
+            .any(|filter| matches!(filter, ValueFilter::NullCoalesce(_)));
+        if !has_default {
+            attrspec.value_filters.push(ValueFilter::NullCoalesce(vec![Cow::Borrowed(b"")]));
+        }
+    }
+    let attrspecs = expand_wildcards(&attrspecs, &attributes);
+    match params.output_format {
+        OutputFormat::Tsv => {
+            let mut entry_writer = TsvEntryWriter::new(attrspecs, dest);
+            entry_writer.set_record_separator(params.delimiter);
+            write_entries(&entries, entry_writer)
+        },
+        OutputFormat::Csv => {
+            let mut entry_writer = CsvEntryWriter::new(attrspecs, dest);
+            entry_writer.set_write_header(csv_header)
+                .set_record_separator(params.delimiter)
+                .set_excel_policy(params.excel_policy);
+            write_entries(&entries, entry_writer)
+        },
+        OutputFormat::Json | OutputFormat::JsonTree | OutputFormat::Template(_) => unreachable!("handled by write_output"),
+    }
+}
+
+fn write_entries<EW: WriteEntry>(entries: &[OwnedEntry], mut entry_writer: EW) -> std::io::Result<()> {
+    for entry in entries.iter() {
+        entry_writer.write_entry(entry)?;
+    }
+    Ok(())
+}
+
+// Processes each input file in a worker thread and writes the output of each file in the order
+// the files were given. The output of a file is buffered in memory until it can be written.
+fn write_output_parallel(params: &Parameters, dest: &mut (dyn Write + Send), seen: &mut Option<HashSet<String>>) -> std::io::Result<()> {
+    let mut csv_header = params.output_format == OutputFormat::Csv;
+    process_inputs(&params.inputs, params.jobs, |input| {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut input_seen = seen_attributes(params);
+        write_output(params, &[input.to_string()], &mut buf, false, &mut input_seen)?;
+        Ok((buf, input_seen))
+    }, |(buf, input_seen)| {
+        if let (Some(seen), Some(input_seen)) = (seen.as_mut(), input_seen) {
+            seen.extend(input_seen);
+        }
+        if csv_header && !buf.is_empty() {
+            let mut attrspecs: Vec<AttrSpec> = Vec::new();
+            for spec in params.attrspecs.iter() {
+                attrspecs.push(AttrSpec::parse_syntax(spec)?);
+            }
+            CsvEntryWriter::new(attrspecs, &mut *dest)
+                .set_record_separator(params.delimiter)
+                .write_header_line()?;
+            csv_header = false;
+        }
+        dest.write_all(&buf)
+    })?;
+    dest.flush()
+}
+
+fn seen_attributes(params: &Parameters) -> Option<HashSet<String>> {
+    match params.missing {
+        MissingAttributes::Ignore => None,
+        MissingAttributes::Warn | MissingAttributes::Fail => Some(HashSet::new()),
+    }
+}
+
+// Returns the attributes that are explicitly requested, rather than through wildcards, and
+// that are not among the seen attributes.
+fn missing_attributes(params: &Parameters, seen: &HashSet<String>) -> std::io::Result<Vec<String>> {
+    let attrspecs: Vec<AttrSpec> = match params.output_format {
+        OutputFormat::Template(ref template) => Template::parse(template)?.attrspecs().to_vec(),
+        _ => params.attrspecs.iter()
+            .map(|spec| AttrSpec::parse_syntax(spec))
+            .collect::<std::io::Result<Vec<AttrSpec>>>()?,
+    };
+    let mut missing: Vec<String> = Vec::new();
+    for attrspec in attrspecs.iter().filter(|attrspec| !attrspec.is_wildcard() && !attrspec.exclude && !attrspec.is_pseudo()) {
+        let attribute = attrspec.attribute.to_ascii_lowercase();
+        if !seen.contains(&attribute) && !missing.contains(&attrspec.attribute) {
+            missing.push(attrspec.attribute.clone());
+        }
+    }
+    Ok(missing)
+}
+
+// Tells about the requested attributes that are not among the seen attributes, as an error with
+// --require and as a warning with --warn-missing.
+fn check_missing(params: &Parameters, seen: &HashSet<String>) -> std::io::Result<Option<Diagnostic>> {
+    let missing = missing_attributes(params, seen)?;
+    if missing.is_empty() {
+        return Ok(None);
+    }
+    let severity = match params.missing {
+        MissingAttributes::Fail => Severity::Error,
+        _ => Severity::Warning,
+    };
+    let message = format!("requested attributes not found in any entry: {}", missing.join(", "));
+    Ok(Some(Diagnostic::new(severity, "missing-attributes", message)))
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut params = parse_arguments()?;
+    if params.jobs > 1 {
+        check_inputs(&params.inputs)?;
+    }
+    if let Some(ref path) = params.map_file {
+        params.map = Some(AttributeMap::load(path).map_err(|err| FileError::new(path, err))?);
+    }
+    if let Some(ref mut progress) = params.progress {
+        progress.set_total_bytes(if params.inputs.is_empty() { None } else { total_file_size(&params.inputs) });
+    }
+    let mut seen = seen_attributes(&params);
+    // the TSV and CSV columns that wildcards stand for depend on every input, so the inputs can't
+    // be processed separately
+    let has_wildcard = params.attrspecs.iter()
+        .filter_map(|spec| AttrSpec::parse_syntax(spec).ok())
+        .any(|attrspec| attrspec.is_wildcard());
+    let csv = params.output_format == OutputFormat::Csv;
+    let mut records = RecordWriter::new(OutputBuffer::new(stdout()), params.delimiter.last_byte(), csv);
+    records.set_distinct(params.distinct).set_sort_key(params.sort_key);
+    // the tree of --json-tree spans the inputs
+    let separable = match params.output_format {
+        OutputFormat::JsonTree => false,
+        OutputFormat::Json => true,
+        _ => !has_wildcard,
+    };
+    if params.jobs > 1 && params.inputs.len() > 1 && separable {
+        write_output_parallel(&params, &mut records, &mut seen)?;
+    } else {
+        write_output(&params, &params.inputs, &mut records, true, &mut seen)?;
+    }
+    records.finish()?;
+    if let Some(ref progress) = params.progress {
+        progress.finish();
+    }
+    if let Some(ref seen) = seen {
+        match check_missing(&params, seen)? {
+            Some(diagnostic) if diagnostic.severity == Severity::Error => return Err(diagnostic.message.into()),
+            Some(diagnostic) => report("lget", &diagnostic),
+            None => {},
+        }
+    }
+    Ok(())
+}
+
 fn main() {
     let result = get_result();
     if let Err(err) = result {
-        eprintln!("lget: {}", err);
+        report_error("lget", &*err);
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ltools::loc::{Loc, LocWrite};
+
+    fn octet_stream(ldif: &[u8], framing: Framing, with_dn: bool) -> std::io::Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut writer = OctetStreamTokenWriter::new("description", &mut output);
+        writer.set_framing(framing).set_with_dn(with_dn);
+        let mut lexer = Lexer::new(writer);
+        lexer.loc_write(Loc::default(), ldif)?;
+        lexer.loc_flush(Loc::default())?;
+        drop(lexer);
+        Ok(output)
+    }
+
+    #[test]
+    fn test_framing() -> std::io::Result<()> {
+        let ldif = b"dn: cn=a\ndescription: foo\ndescription:: YQpiAGM=\n\ndn: cn=b\ndescription:\n";
+        assert_eq!(octet_stream(ldif, Framing::Delimited, false)?, b"foo\na\nb\0c\n\n");
+        assert_eq!(octet_stream(ldif, Framing::Netstring, false)?, b"3:foo,5:a\nb\0c,0:,");
+        assert_eq!(octet_stream(ldif, Framing::Length, false)?, b"\0\0\0\x03foo\0\0\0\x05a\nb\0c\0\0\0\0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_dn() -> std::io::Result<()> {
+        let ldif = b"version: 1\ndn: cn=a\ndescription: foo\ndescription: bar\n\ndn:: Y249YsOl\ncn: b\ndescription: baz\n\ndn: cn=c\ncn: c\n";
+        assert_eq!(String::from_utf8_lossy(&octet_stream(ldif, Framing::Delimited, true)?), "cn=a\tfoo\ncn=a\tbar\ncn=b\u{e5}\tbaz\n");
+        assert_eq!(octet_stream(ldif, Framing::Netstring, true)?, "4:cn=a,3:foo,4:cn=a,3:bar,6:cn=b\u{e5},3:baz,".as_bytes());
+        Ok(())
+    }
+
+    fn parameters(attrspecs: &[&str], inputs: Vec<String>, output_format: OutputFormat, jobs: usize) -> Parameters {
+        Parameters{
+            attrspecs: attrspecs.iter().map(|spec| spec.to_string()).collect(),
+            inputs,
+            delimiter: RecordSeparator::LF,
+            framing: Framing::Delimited,
+            with_dn: false,
+            output_format,
+            excel_policy: None,
+            jobs,
+            threads: 1,
+            missing: MissingAttributes::Ignore,
+            distinct: false,
+            sort_key: None,
+            progress: None,
+            map_file: None,
+            map: None,
+        }
+    }
+
+    #[test]
+    fn test_jobs() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("ltools-lget-jobs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let mut inputs: Vec<String> = Vec::new();
+        for i in 0..5 {
+            let path = dir.join(format!("{}.ldif", i));
+            let mut ldif = String::new();
+            for j in 0..50 {
+                ldif.push_str(&format!("dn: uid=u{i}-{j},dc=example\nuid: u{i}-{j}\nmail: u{i}-{j}@example.com\n\n"));
+            }
+            std::fs::write(&path, ldif)?;
+            inputs.push(path.to_string_lossy().into_owned());
+        }
+        for (attrspecs, output_format) in [(&["uid"][..], OutputFormat::Tsv), (&["dn", "mail"][..], OutputFormat::Csv), (&["uid", "mail"][..], OutputFormat::Json)] {
+            let csv = output_format == OutputFormat::Csv;
+            let serial = parameters(attrspecs, inputs.clone(), output_format.clone(), 1);
+            let mut serial_output: Vec<u8> = Vec::new();
+            write_output(&serial, &serial.inputs, &mut serial_output, csv, &mut None)?;
+            let parallel = parameters(attrspecs, inputs.clone(), output_format, 3);
+            let mut parallel_output: Vec<u8> = Vec::new();
+            write_output_parallel(&parallel, &mut parallel_output, &mut None)?;
+            assert!(!serial_output.is_empty());
+            assert_eq!(String::from_utf8_lossy(&parallel_output), String::from_utf8_lossy(&serial_output));
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing() -> std::io::Result<()> {
+        let path = std::env::temp_dir().join(format!("ltools-lget-missing-{}.ldif", std::process::id()));
+        std::fs::write(&path, b"dn: cn=foo,dc=example\ncn: foo\nmail: foo@example.com\n\n")?;
+        let inputs = vec![path.to_string_lossy().into_owned()];
+        let mut params = parameters(&["cn", "mial", "Mail", "@line", "*"], inputs, OutputFormat::Json, 1);
+        for (missing, severity) in [(MissingAttributes::Fail, Severity::Error), (MissingAttributes::Warn, Severity::Warning)] {
+            params.missing = missing;
+            let mut seen = seen_attributes(&params);
+            write_output(&params, &params.inputs, std::io::sink(), false, &mut seen)?;
+            let diagnostic = check_missing(&params, seen.as_ref().unwrap())?.unwrap();
+            assert_eq!(diagnostic.severity, severity);
+            assert_eq!(diagnostic.message, "requested attributes not found in any entry: mial");
+        }
+        params.attrspecs.retain(|spec| spec != "mial");
+        let mut seen = seen_attributes(&params);
+        write_output(&params, &params.inputs, std::io::sink(), false, &mut seen)?;
+        assert!(check_missing(&params, seen.as_ref().unwrap())?.is_none());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    fn distinct(input: &[u8], csv: bool, sort_key: Option<SortKey>) -> std::io::Result<String> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut records = RecordWriter::new(&mut output, b'\n', csv);
+        records.set_distinct(true).set_sort_key(sort_key);
+        records.write_all(input)?;
+        records.finish()?;
+        drop(records);
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[test]
+    fn test_distinct() -> std::io::Result<()> {
+        assert_eq!(distinct(b"b\na\nb\nc\na\n", false, None)?, "b\na\nc\n");
+        // the newline in the quoted CSV field doesn't end the record, and the header is kept first
+        let csv = b"mail\r\n\"x\r\ny\"\r\nz\r\n\"x\r\ny\"\r\ny\r\n";
+        assert_eq!(distinct(csv, true, None)?, "mail\r\n\"x\r\ny\"\r\nz\r\ny\r\n");
+        let sort_key = SortKey{ column: None, numeric: false, delimiter: RecordSeparator::LF, csv: false };
+        assert_eq!(distinct(b"b\na\nb\nc\na\n", false, Some(sort_key))?, "a\nb\nc\n");
+        // without --distinct or --sort-by, records are written as they are
+        let mut output: Vec<u8> = Vec::new();
+        let mut records = RecordWriter::new(&mut output, b'\n', false);
+        records.write_all(b"b\nb\na")?;
+        records.finish()?;
+        drop(records);
+        assert_eq!(output, b"b\nb\na");
+        Ok(())
+    }
+
+    #[test]
+    fn test_inputs() {
+        let params = parse_arguments_from(command(), ["lget", "cn", "mail", "users.ldif", "-"]).unwrap();
+        assert_eq!((params.attrspecs, params.inputs), (vec!["cn".to_string(), "mail".to_string()], vec!["users.ldif".to_string(), "-".to_string()]));
+        // a file named like an attribute is only taken as a file when given explicitly
+        let params = parse_arguments_from(command(), ["lget", "cn", "users"]).unwrap();
+        assert_eq!((params.attrspecs.len(), params.inputs.len()), (2, 0));
+        let params = parse_arguments_from(command(), ["lget", "cn", "--", "users", "more"]).unwrap();
+        assert_eq!((params.attrspecs, params.inputs), (vec!["cn".to_string()], vec!["users".to_string(), "more".to_string()]));
+        let params = parse_arguments_from(command(), ["lget", "-i", "users", "cn", "mail", "-i", "more", "--", "last"]).unwrap();
+        assert_eq!((params.attrspecs, params.inputs), (vec!["cn".to_string(), "mail".to_string()], vec!["users".to_string(), "more".to_string(), "last".to_string()]));
+        let params = parse_arguments_from(command(), ["lget", "-a", "-i", "users", "more"]).unwrap();
+        assert_eq!((params.attrspecs, params.inputs), (vec!["*".to_string()], vec!["users".to_string(), "more".to_string()]));
+    }
+}
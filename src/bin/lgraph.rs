@@ -0,0 +1,251 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::dn::{normalize, parent, rdn};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::schema::dn_attributes_from_schema;
+use std::collections::{HashMap, HashSet};
+use std::io::{stdout, BufWriter, Write};
+
+const DEFAULT_REFERENCES: &[&str] = &["member", "uniqueMember", "manager", "seeAlso"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Dot,
+    Mermaid,
+}
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+    format: Format,
+    references: Vec<String>, // lowercase
+    classes: Vec<String>, // lowercase, empty meaning every entry
+    tree: bool,
+    schema: Option<String>,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lgraph")
+        .disable_colored_help(true)
+        .about("Writes a GraphViz DOT or Mermaid graph of LDIF entries, with an edge for each value of the member, uniqueMember, manager and seeAlso attributes that is the DN of another entry in the input, for reviewing group and manager hierarchies. Nodes are labelled with the RDN of their entry.")
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input)."))
+        .arg(arg!(--mermaid "Write a Mermaid flowchart instead of DOT.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(-r --reference <ATTRIBUTE> "Draw edges for the DN values of ATTRIBUTE instead of the default attributes. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--schema <FILE> "Also draw edges for the values of every attribute with DN syntax according to the subschema entry in FILE, such as one exported from cn=schema.")
+            .required(false))
+        .arg(arg!(--class <CLASS> "Only include entries of the object class CLASS. Can be given multiple times to include entries of any of the classes.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--tree "Also draw dashed edges from each entry to the entries right below it in the directory tree.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let lowercase = |id: &str| -> Vec<String> {
+        matches.get_many::<String>(id)
+            .map(|values| values.map(|value| value.to_ascii_lowercase()).collect())
+            .unwrap_or_default()
+    };
+    let mut references = lowercase("reference");
+    if references.is_empty() {
+        references = DEFAULT_REFERENCES.iter().map(|attr| attr.to_ascii_lowercase()).collect();
+    }
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        format: if matches.get_flag("mermaid") { Format::Mermaid } else { Format::Dot },
+        references,
+        classes: lowercase("class"),
+        tree: matches.get_flag("tree"),
+        schema: matches.get_one::<String>("schema").cloned(),
+    })
+}
+
+struct Node {
+    dn: String,
+    references: Vec<(String, String)>, // attribute as spelled and normalized DN
+}
+
+// Gathers the DNs and references of the included entries.
+struct Graph {
+    references: HashSet<String>,
+    classes: Vec<String>,
+    nodes: Vec<Node>,
+    index: HashMap<String, usize>, // normalized DN to node
+}
+
+impl Graph {
+    fn new(references: HashSet<String>, classes: Vec<String>) -> Graph {
+        Graph{ references, classes, nodes: Vec::new(), index: HashMap::new() }
+    }
+
+    // The edges between nodes, as the source, the target and the label, which is None for
+    // the edges of the tree.
+    fn edges(&self, tree: bool) -> Vec<(usize, usize, Option<&str>)> {
+        let mut edges: Vec<(usize, usize, Option<&str>)> = Vec::new();
+        for (source, node) in self.nodes.iter().enumerate() {
+            if tree {
+                if let Some(parent) = parent(&node.dn).and_then(|parent| self.index.get(&normalize(parent))) {
+                    edges.push((*parent, source, None));
+                }
+            }
+            for (attr, dn) in node.references.iter() {
+                if let Some(target) = self.index.get(dn) {
+                    edges.push((source, *target, Some(attr)));
+                }
+            }
+        }
+        edges
+    }
+}
+
+impl WriteEntry for Graph {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let Some(dn) = entry.get_one_str("dn") else {
+            return Ok(());
+        };
+        let included = self.classes.is_empty()
+            || entry.get_str("objectclass").any(|class| self.classes.contains(&class.to_ascii_lowercase()));
+        if !included {
+            return Ok(());
+        }
+        let references = entry.attributes()
+            .filter(|attr| self.references.contains(attr.lowercase))
+            .flat_map(|attr| entry.get_str(attr.lowercase).map(move |value| (attr.name.to_string(), normalize(&value))))
+            .collect();
+        self.index.insert(normalize(&dn), self.nodes.len());
+        self.nodes.push(Node{ dn: dn.into_owned(), references });
+        Ok(())
+    }
+}
+
+fn dot_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn write_dot<W: Write>(graph: &Graph, tree: bool, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "digraph ldif {{")?;
+    writeln!(w, "    node [shape=box];")?;
+    for (i, node) in graph.nodes.iter().enumerate() {
+        writeln!(w, "    n{} [label={}, tooltip={}];", i, dot_string(rdn(&node.dn)), dot_string(&node.dn))?;
+    }
+    for (source, target, label) in graph.edges(tree) {
+        match label {
+            Some(label) => writeln!(w, "    n{} -> n{} [label={}];", source, target, dot_string(label))?,
+            None => writeln!(w, "    n{} -> n{} [style=dashed];", source, target)?,
+        }
+    }
+    writeln!(w, "}}")
+}
+
+// Mermaid has no escapes in quoted labels, but takes HTML entities.
+fn mermaid_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "#quot;"))
+}
+
+fn write_mermaid<W: Write>(graph: &Graph, tree: bool, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "flowchart LR")?;
+    for (i, node) in graph.nodes.iter().enumerate() {
+        writeln!(w, "    n{}[{}]", i, mermaid_string(rdn(&node.dn)))?;
+    }
+    for (source, target, label) in graph.edges(tree) {
+        match label {
+            Some(label) => writeln!(w, "    n{} -->|{}| n{}", source, mermaid_string(label), target)?,
+            None => writeln!(w, "    n{} -.-> n{}", source, target)?,
+        }
+    }
+    Ok(())
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut references: HashSet<String> = params.references.iter().cloned().collect();
+    if let Some(ref schema) = params.schema {
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        open_input(schema)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut entries))
+            .map_err(|err| FileError::new(schema, err))?;
+        for entry in entries.iter() {
+            references.extend(dn_attributes_from_schema(entry));
+        }
+    }
+    let mut graph = Graph::new(references, params.classes.clone());
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    for input in inputs.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut graph))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    let mut output = BufWriter::new(stdout().lock());
+    match params.format {
+        Format::Dot => write_dot(&graph, params.tree, &mut output)?,
+        Format::Mermaid => write_mermaid(&graph, params.tree, &mut output)?,
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lgraph", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph(classes: &[&str]) -> std::io::Result<Graph> {
+        let references = ["member", "manager"].iter().map(|attr| attr.to_string()).collect();
+        let mut graph = Graph::new(references, classes.iter().map(|class| class.to_string()).collect());
+        graph.write_entry(&Entry::from([("dn", b"ou=people,dc=example".as_slice()), ("objectClass", b"organizationalUnit")]))?;
+        graph.write_entry(&Entry::from([("dn", b"cn=boss,ou=people,dc=example".as_slice()), ("objectClass", b"person")]))?;
+        graph.write_entry(&Entry::from([("dn", b"cn=a \"b\",ou=people,dc=example".as_slice()), ("objectClass", b"person"), ("manager", b"CN=Boss, ou=People,dc=example")]))?;
+        graph.write_entry(&Entry::from([("dn", b"cn=staff,dc=example".as_slice()), ("objectClass", b"groupOfNames"), ("member", b"cn=boss,ou=people,dc=example"), ("member", b"cn=gone,dc=example")]))?;
+        Ok(graph)
+    }
+
+    #[test]
+    fn test_dot() -> std::io::Result<()> {
+        let mut buf: Vec<u8> = Vec::new();
+        write_dot(&graph(&[])?, true, &mut buf)?;
+        assert_eq!(String::from_utf8_lossy(&buf), concat!(
+            "digraph ldif {\n",
+            "    node [shape=box];\n",
+            "    n0 [label=\"ou=people\", tooltip=\"ou=people,dc=example\"];\n",
+            "    n1 [label=\"cn=boss\", tooltip=\"cn=boss,ou=people,dc=example\"];\n",
+            "    n2 [label=\"cn=a \\\"b\\\"\", tooltip=\"cn=a \\\"b\\\",ou=people,dc=example\"];\n",
+            "    n3 [label=\"cn=staff\", tooltip=\"cn=staff,dc=example\"];\n",
+            "    n0 -> n1 [style=dashed];\n",
+            "    n0 -> n2 [style=dashed];\n",
+            "    n2 -> n1 [label=\"manager\"];\n",
+            "    n3 -> n1 [label=\"member\"];\n",
+            "}\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_mermaid() -> std::io::Result<()> {
+        let mut buf: Vec<u8> = Vec::new();
+        write_mermaid(&graph(&["person"])?, false, &mut buf)?;
+        assert_eq!(String::from_utf8_lossy(&buf), concat!(
+            "flowchart LR\n",
+            "    n0[\"cn=boss\"]\n",
+            "    n1[\"cn=a #quot;b#quot;\"]\n",
+            "    n1 -->|\"manager\"| n0\n",
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use clap::{arg, command, ArgAction};
+use ltools::attrspec::AttrSpec;
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::csv::CsvEntryWriter;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::dn::normalize;
+use ltools::entry::{OwnedEntry, WriteEntry};
+use ltools::group::{expand_members, is_group, member_of, members, with_member_of};
+use ltools::ldif::LdifEntryWriter;
+use ltools::store::EntryStore;
+use ltools::tsv::TsvEntryWriter;
+use std::io::{stdout, BufWriter, Write};
+
+#[derive(PartialEq, Eq)]
+enum OutputFormat {
+    Tsv,
+    Csv,
+    Ldif,
+}
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+    output_format: OutputFormat,
+    joins: Vec<AttrSpec>, // attributes of the members to add to each row
+    member_of: bool,
+    nested: bool,
+    max_depth: Option<usize>, // of nested groups
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lgroup")
+        .disable_colored_help(true)
+        .about("Writes a row with the group DN and the member DN for each member or uniqueMember value of the groups in the input.")
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input)."))
+        .arg(arg!(-c --csv "Write the rows as CSV, including a header.")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-l --ldif "Write each row as an LDIF record with group and member attributes.")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("csv"))
+        .arg(arg!(--join <ATTRIBUTE> "Add the values of ATTRIBUTE of the member entry, if it is in the input, to each row. Takes the same syntax as the attributes of lget, such as mail:- to keep rows for members without mail. Can be given multiple times.")
+            .required(false)
+            .action(ArgAction::Append))
+        .arg(arg!(-m --"member-of" "Instead of rows, write the input entries with memberOf values listing the groups in the input that each entry is a member of. Existing memberOf values are replaced.")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(&["csv", "ldif", "join"]))
+        .arg(arg!(-n --nested "Resolve members that are groups in the input transitively, writing a row for each direct or indirect member of each group. Membership cycles are reported as warnings.")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("member-of"))
+        .arg(arg!(--"max-depth" <DEPTH> "Resolve nested groups at most DEPTH levels down, where 1 means only direct members.")
+            .required(false)
+            .requires("nested"))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let output_format = if matches.get_flag("csv") {
+        OutputFormat::Csv
+    } else if matches.get_flag("ldif") {
+        OutputFormat::Ldif
+    } else {
+        OutputFormat::Tsv
+    };
+
+    let mut joins: Vec<AttrSpec> = Vec::new();
+    for join in matches.get_many::<String>("join").into_iter().flatten() {
+        joins.push(AttrSpec::parse(join).map_err(|_| "failed to parse joined attribute")?);
+    }
+
+    let max_depth = match matches.get_one::<String>("max-depth").map(|depth| depth.parse::<usize>()) {
+        Some(Ok(0)) => return Err("the maximum depth must be at least 1"),
+        Some(Ok(depth)) => Some(depth),
+        Some(Err(_)) => return Err("failed to parse maximum depth"),
+        None => None,
+    };
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        output_format,
+        joins,
+        member_of: matches.get_flag("member-of"),
+        nested: matches.get_flag("nested"),
+        max_depth,
+    })
+}
+
+fn write_rows<W: WriteEntry>(params: &Parameters, store: &EntryStore, mut dest: W) -> std::io::Result<()> {
+    for group in store.iter().filter(|entry| is_group(entry)) {
+        let Some(group_dn) = group.get_one_str("dn") else {
+            continue;
+        };
+        let group_members: Vec<String> = if params.nested {
+            let expansion = expand_members(store, group, params.max_depth);
+            for cycle in expansion.cycles.iter() {
+                let message = format!("membership cycle: {}", cycle.join(" -> "));
+                report("lgroup", &Diagnostic::new(Severity::Warning, "membership-cycle", message).in_entry(Some(&group_dn)));
+            }
+            expansion.members
+        } else {
+            members(group).map(String::from).collect()
+        };
+        for member in group_members {
+            let mut row = OwnedEntry::from([("group", group_dn.as_bytes()), ("member", member.as_bytes())]);
+            let member_entry = store.get(&member);
+            for join in params.joins.iter() {
+                let values = member_entry.into_iter().flat_map(|entry| entry.get(&join.attribute_lowercase));
+                for value in join.filter_values(values)?.iter() {
+                    row.add_value(&join.attribute, value);
+                }
+            }
+            dest.write_entry(&row)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_member_of<W: Write>(store: &EntryStore, dest: W) -> std::io::Result<()> {
+    let groups = member_of(store);
+    let mut writer = LdifEntryWriter::new(dest);
+    writer.set_sort_attributes(false);
+    for entry in store.iter() {
+        let entry_groups = entry.get_one_str("dn").and_then(|dn| groups.get(&normalize(&dn)));
+        match entry_groups {
+            Some(entry_groups) => writer.write_entry(&with_member_of(entry, entry_groups))?,
+            None => writer.write_entry(entry)?,
+        }
+    }
+    Ok(())
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    let mut store = EntryStore::new();
+    for input in inputs.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut store))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    let columns: Vec<AttrSpec> = ["group", "member"].into_iter()
+        .chain(params.joins.iter().map(|join| join.attribute.as_str()))
+        .map(AttrSpec::parse)
+        .collect::<std::io::Result<_>>()?;
+    let mut dest = BufWriter::new(stdout());
+    if params.member_of {
+        write_member_of(&store, &mut dest)?;
+        dest.flush()?;
+        return Ok(());
+    }
+    match params.output_format {
+        OutputFormat::Tsv => write_rows(&params, &store, TsvEntryWriter::new(columns, &mut dest))?,
+        OutputFormat::Csv => write_rows(&params, &store, CsvEntryWriter::new(columns, &mut dest))?,
+        OutputFormat::Ldif => {
+            let mut writer = LdifEntryWriter::new(&mut dest);
+            writer.set_sort_attributes(false);
+            write_rows(&params, &store, writer)?;
+        },
+    }
+    dest.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lgroup", &*err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,133 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use ltools::passwd::{hash_password, is_hashed, verify_password, PasswordScheme};
+use std::io::{stdout, BufWriter, Write};
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+    scheme: PasswordScheme,
+    attribute: String,
+    verify: Option<String>, // the plaintext to verify the values against
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lpasswd")
+        .disable_colored_help(true)
+        .about("Writes the input entries with their userPassword values hashed, leaving values that already start with a scheme such as {SSHA} as they are.")
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input)."))
+        .arg(arg!(-s --scheme <SCHEME> "Hash the values with SCHEME, which is ssha or argon2.")
+            .required(false)
+            .default_value("ssha"))
+        .arg(arg!(-a --attribute <ATTRIBUTE> "Hash the values of ATTRIBUTE instead of userPassword.")
+            .required(false)
+            .default_value("userPassword"))
+        .arg(arg!(--verify <PASSWORD> "Instead of hashing, write the DN of each entry with password values followed by a tab and match, mismatch or unsupported, telling whether any of the values matches PASSWORD. Values with a scheme other than SSHA, SHA or ARGON2 are unsupported.")
+            .required(false)
+            .conflicts_with("scheme"))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let scheme = PasswordScheme::parse(matches.get_one::<String>("scheme").unwrap())?;
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        scheme,
+        attribute: matches.get_one::<String>("attribute").unwrap().clone(),
+        verify: matches.get_one::<String>("verify").cloned(),
+    })
+}
+
+struct HashWriter<W: Write> {
+    inner: LdifEntryWriter<W>,
+    scheme: PasswordScheme,
+    attribute: String, // lowercase
+}
+
+impl<W: Write> WriteEntry for HashWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let mut hashed = OwnedEntry::default();
+        for attr in entry.attributes() {
+            for value in entry.get(attr.lowercase) {
+                if attr.lowercase == self.attribute && !is_hashed(value) {
+                    hashed.add_value(attr.name, &hash_password(value, self.scheme)?);
+                } else {
+                    hashed.add_value(attr.name, value);
+                }
+            }
+        }
+        self.inner.write_entry(&hashed)
+    }
+}
+
+struct VerifyWriter<W: Write> {
+    dest: W,
+    password: String,
+    attribute: String, // lowercase
+}
+
+impl<W: Write> WriteEntry for VerifyWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let Some(dn) = entry.get_one_str("dn") else {
+            return Ok(());
+        };
+        let results: Vec<Option<bool>> = entry.get(&self.attribute)
+            .map(|value| verify_password(value, self.password.as_bytes()))
+            .collect();
+        let result = if results.is_empty() {
+            return Ok(());
+        } else if results.contains(&Some(true)) {
+            "match"
+        } else if results.contains(&None) {
+            "unsupported"
+        } else {
+            "mismatch"
+        };
+        writeln!(self.dest, "{}\t{}", dn, result)
+    }
+}
+
+fn write_all<W: WriteEntry>(inputs: &[String], mut writer: W) -> Result<W, Box<dyn std::error::Error>> {
+    for input in inputs.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut writer))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    Ok(writer)
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    let dest = BufWriter::new(stdout());
+    let attribute = params.attribute.to_lowercase();
+    match params.verify {
+        Some(password) => {
+            let writer = write_all(&inputs, VerifyWriter{ dest, password, attribute })?;
+            writer.dest.into_inner()?.flush()?;
+        },
+        None => {
+            let mut inner = LdifEntryWriter::new(dest);
+            inner.set_sort_attributes(false);
+            let mut writer = write_all(&inputs, HashWriter{ inner, scheme: params.scheme, attribute })?;
+            writer.inner.get_mut().flush()?;
+        },
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lpasswd", &*err);
+        std::process::exit(1);
+    }
+}
@@ -1,21 +1,39 @@
 use clap::{arg, command, ArgAction};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
 use ltools::crstrip::CrStripper;
 use ltools::lexer::Lexer;
 use ltools::loc::WriteLocWrapper;
 use ltools::unfold::Unfolder;
 use ltools::entry::{Entry, WriteEntry, EntryTokenWriter, write_attrval, write_entry_normally};
-use std::io::{copy, Read, Write, Stdout, ErrorKind};
+use ltools::inplace::InPlaceFile;
+use std::fs::File;
+use std::io::{copy, stdin, stdout, BufWriter, Read, Write};
 use std::process::{Command, Stdio};
 use ltools::filter::Filter;
+use ltools::filter::dnglob::DnGlob;
+use ltools::filter::substring::SubstringMatcher;
 
-struct EntryProcessor<W: Write> {
+struct EntryProcessor {
     command: Command,
-    output: W,
     attrs: Option<Vec<String>>,
     filter: Option<Filter>,
+    dn_filters: Vec<DnGlob>, // of which the DN has to match one
+    value_filter: Option<SubstringMatcher>,
+}
+
+struct Parameters {
+    processor: EntryProcessor,
+    in_place: Vec<String>, // files to edit instead of reading standard input
+}
+
+// Processes entries into the given output
+struct ProcessorOutput<'a, W: Write> {
+    processor: &'a mut EntryProcessor,
+    output: W,
 }
 
-impl<W: Write> EntryProcessor<W> {
+impl EntryProcessor {
     fn should_process_attr(&self, attr_lowercase: &str) -> bool {
         if let Some(ref attrs) = self.attrs {
             attrs.iter().any(|arg_attr| arg_attr == attr_lowercase)
@@ -23,11 +41,20 @@ impl<W: Write> EntryProcessor<W> {
             true
         }
     }
+
+    fn should_process_entry(&self, entry: &Entry) -> bool {
+        self.filter.as_ref().map(|filter| filter.is_match(entry)).unwrap_or(true)
+            && (self.dn_filters.is_empty() || entry.get_one_str("dn").is_some_and(|dn| self.dn_filters.iter().any(|glob| glob.is_match(&dn))))
+    }
+
+    fn should_process_value(&self, value: &[u8]) -> bool {
+        self.value_filter.as_ref().map(|matcher| matcher.is_match(value)).unwrap_or(true)
+    }
 }
 
-fn parse_arguments() -> Result<EntryProcessor<Stdout>, &'static str> {
+fn parse_arguments() -> Result<Parameters, Box<dyn std::error::Error>> {
 
-    let matches = command!("lprocess")
+    let matches = with_defaults(command!("lprocess")
         .disable_colored_help(true)
         .allow_external_subcommands(true)
         .arg(arg!(ATTRIBUTE: -a --attribute <ATTRIBUTE> "Limit processing to the given attribute(s). Multiple attributes can be provided either by space-separating them or by providing this option multiple times.")
@@ -35,10 +62,22 @@ fn parse_arguments() -> Result<EntryProcessor<Stdout>, &'static str> {
             .value_delimiter(' ')
             .action(ArgAction::Append))
         .arg(arg!(FILTER: -f --filter <FILTER> "Limit processing to entries matching the given LDAP filter.")
+            .required(false))
+        .arg(arg!(--"strict-filter" "Parse filters strictly as RFC 4515 has them, in parentheses and without whitespace between their parts. By default, a filter such as cn=foo needn't be in parentheses, and there may be whitespace between parentheses and operators, as in '( & (a=1) (b=2) )'.")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"dn-filter" <GLOB> "Limit processing to entries whose DN matches GLOB, in which * matches any characters, such as '*,ou=sa,o=system' for the entries below ou=sa,o=system. Case and spaces after commas are ignored. Can be given multiple times, in which case a DN that matches any of them matches.")
             .required(false)
-            .value_delimiter(' ')
             .action(ArgAction::Append))
-        .get_matches();
+        .arg(arg!(VALUE_FILTER: -v --"value-filter" <PATTERN> "Limit processing to values matching PATTERN, such as 'smtp:*'. The pattern takes the syntax of the value of an LDAP substring filter and is matched regardless of case. Other values are written unchanged.")
+            .required(false))
+        .arg(arg!(IN_PLACE: -i --"in-place" <FILE> "Edit FILE in place instead of reading standard input and writing standard output. The file is only replaced once it has been processed completely. Can be given multiple times.")
+            .required(false)
+            .action(ArgAction::Append))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
 
     let command: Command = if let Some((subcommand, args)) = matches.subcommand() {
         let args: Vec<String> = match args.get_many::<String>("") {
@@ -51,7 +90,7 @@ fn parse_arguments() -> Result<EntryProcessor<Stdout>, &'static str> {
             .stdin(Stdio::piped());
         command
     } else {
-        return Err("missing argument SUBCOMMAND");
+        return Err("missing argument SUBCOMMAND".into());
     };
 
     let attrs: Option<Vec<String>> = matches.get_many::<String>("ATTRIBUTE")
@@ -60,17 +99,24 @@ fn parse_arguments() -> Result<EntryProcessor<Stdout>, &'static str> {
 
     let filter: Option<Filter> = match matches.get_one::<String>("FILTER") {
         None => None,
-        Some(filter) => match Filter::parse(filter) {
-            Ok(filter) => Some(filter),
-            Err(_) => return Err("failed to parse filter"),
-        },
+        Some(filter) if matches.get_flag("strict-filter") => Some(Filter::parse_strict(filter)?),
+        Some(filter) => Some(Filter::parse(filter)?),
     };
 
-    Ok(EntryProcessor{
-        command,
-        output: std::io::stdout(),
-        attrs,
-        filter,
+    let value_filter: Option<SubstringMatcher> = match matches.get_one::<String>("VALUE_FILTER") {
+        None => None,
+        Some(pattern) => Some(SubstringMatcher::parse(pattern)?),
+    };
+
+    Ok(Parameters{
+        processor: EntryProcessor{
+            command,
+            attrs,
+            filter,
+            dn_filters: matches.get_many::<String>("dn-filter").into_iter().flatten().map(|glob| DnGlob::new(glob)).collect(),
+            value_filter,
+        },
+        in_place: matches.get_many::<String>("IN_PLACE").into_iter().flatten().cloned().collect(),
     })
 }
 
@@ -89,22 +135,28 @@ fn process_value(command: &mut Command, value: &[u8]) -> std::io::Result<Vec<u8>
     if exit_status.success() {
         Ok(value)
     } else {
-        Err(std::io::Error::new(ErrorKind::Other, exit_status.to_string()))
+        Err(std::io::Error::other(exit_status.to_string()))
     }
 }
 
-impl<W: Write> WriteEntry for EntryProcessor<W> {
+impl<W: Write> WriteEntry for ProcessorOutput<'_, W> {
     fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
-        if self.filter.as_ref().map(|filter| !filter.is_match(entry)).unwrap_or(false) {
-            write_entry_normally(&mut self.output, entry)?;
+        self.processor.process_entry(entry, &mut self.output)
+    }
+}
+
+impl EntryProcessor {
+    fn process_entry<W: Write>(&mut self, entry: &Entry, output: &mut W) -> std::io::Result<()> {
+        if !self.should_process_entry(entry) {
+            write_entry_normally(output, entry)?;
             return Ok(());
         }
         if let Some(dn) = entry.get_one("dn") {
-            if self.should_process_attr("dn") {
+            if self.should_process_attr("dn") && self.should_process_value(dn) {
                 let dn = process_value(&mut self.command, dn)?;
-                write_attrval(&mut self.output, "dn", dn.as_slice())?;
+                write_attrval(output, "dn", dn.as_slice())?;
             } else {
-                write_attrval(&mut self.output, "dn", dn)?;
+                write_attrval(output, "dn", dn)?;
             }
         }
         for attr in entry.attributes() {
@@ -113,35 +165,54 @@ impl<W: Write> WriteEntry for EntryProcessor<W> {
             }
             let should_process_attr = self.should_process_attr(attr.lowercase);
             for value in entry.get(attr.name) {
-                if should_process_attr {
+                if should_process_attr && self.should_process_value(value) {
                     let value = process_value(&mut self.command, value)?;
-                    write_attrval(&mut self.output, attr.name, value.as_slice())?;
+                    write_attrval(output, attr.name, value.as_slice())?;
                 } else {
-                    write_attrval(&mut self.output, attr.name, value)?;
+                    write_attrval(output, attr.name, value)?;
                 }
             }
         }
-        self.output.write_all(b"\n")?;
+        output.write_all(b"\n")?;
         Ok(())
     }
 }
 
-fn get_result() -> Result<(), Box<dyn std::error::Error>> {
-    let mut processor = parse_arguments()?;
-    let token_writer = EntryTokenWriter::new(&mut processor);
+fn process<R: Read, W: Write>(processor: &mut EntryProcessor, input: &mut R, output: W) -> std::io::Result<()> {
+    let mut processor_output = ProcessorOutput{
+        processor,
+        output,
+    };
+    let token_writer = EntryTokenWriter::new(&mut processor_output);
     let lexer = Lexer::new(token_writer);
     let unfolder = Unfolder::new(lexer);
     let crstripper = CrStripper::new(unfolder);
     let mut wrapper = WriteLocWrapper::new(crstripper);
-    copy(&mut std::io::stdin(), &mut wrapper)?;
+    copy(input, &mut wrapper)?;
     wrapper.flush()?;
+    processor_output.output.flush()
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut params = parse_arguments()?;
+    if params.in_place.is_empty() {
+        process(&mut params.processor, &mut stdin(), BufWriter::new(stdout().lock()))?;
+    }
+    for path in params.in_place.iter() {
+        let result = File::open(path).and_then(|mut input| {
+            let mut output = InPlaceFile::create(path)?;
+            process(&mut params.processor, &mut input, BufWriter::new(&mut output))?;
+            output.commit()
+        });
+        result.map_err(|err| FileError::new(path, err))?;
+    }
     Ok(())
 }
 
 fn main() {
     let result = get_result();
     if let Err(err) = result {
-        eprintln!("lprocess: {}", err);
+        report_error("lprocess", &*err);
         std::process::exit(1);
     }
 }
@@ -0,0 +1,177 @@
+use clap::{arg, command};
+use ltools::attrspec::{expand_wildcards, AttrSpec, ValueFilter};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::csv::CsvEntryWriter;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::filter::syntax::Syntax;
+use ltools::filter::MatchOptions;
+use ltools::json::JsonEntryWriter;
+use ltools::output::OutputBuffer;
+use ltools::query::Query;
+use ltools::tsv::TsvEntryWriter;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::{stdout, Write};
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum OutputFormat {
+    Tsv,
+    Csv,
+    Json,
+}
+
+struct Parameters {
+    query: String,
+    output_format: OutputFormat,
+    match_options: MatchOptions,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lquery")
+        .disable_colored_help(true)
+        .about("Queries LDIF with a statement such as \"SELECT dn, mail FROM users.ldif WHERE (objectClass=person) ORDER BY sn DESC, cn LIMIT 100\", and writes the selected attributes of the matching entries as tab-separated values. The attributes take the same syntax as for lget, with * for every attribute, and are separated by commas, as are the files after FROM, where - means standard input. WHERE takes an LDAP filter in parentheses. ORDER BY sorts by the first value of each attribute, and entries without it come last. Keywords are case-insensitive.")
+        .arg(arg!(<QUERY> "The query."))
+        .arg(arg!(-c --csv "Write the values in the CSV format, including a header.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(-j --json "Write each entry as a JSON object with string array values.")
+            .action(clap::ArgAction::SetTrue)
+            .conflicts_with("csv"))
+        .arg(arg!(--syntax <SYNTAX> "Compare values of an attribute in the filter and when sorting according to a syntax, given as ATTRIBUTE=SYNTAX where SYNTAX is string (default), integer or telephone, which ignores spaces and hyphens. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let mut match_options = MatchOptions::default();
+    for syntax in matches.get_many::<String>("syntax").into_iter().flatten() {
+        let (attr, syntax) = match syntax.split_once('=') {
+            Some(pair) => pair,
+            None => return Err("syntaxes must be given as ATTRIBUTE=SYNTAX"),
+        };
+        match_options.set_syntax(attr, Syntax::parse(syntax)?);
+    }
+
+    let output_format = if matches.get_flag("csv") {
+        OutputFormat::Csv
+    } else if matches.get_flag("json") {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Tsv
+    };
+
+    Ok(Parameters{
+        query: matches.get_one::<String>("QUERY").unwrap().clone(),
+        output_format,
+        match_options,
+    })
+}
+
+// Passes on the entries that match the query until the limit, if any, has been reached.
+struct Selected<'a, W: WriteEntry> {
+    query: &'a Query,
+    match_options: &'a MatchOptions,
+    remaining: Option<usize>,
+    dest: W,
+}
+
+impl<'a, W: WriteEntry> Selected<'a, W> {
+    fn new(query: &'a Query, match_options: &'a MatchOptions, dest: W) -> Selected<'a, W> {
+        Selected{ query, match_options, remaining: query.limit, dest }
+    }
+}
+
+impl<W: WriteEntry> WriteEntry for Selected<'_, W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        if self.remaining == Some(0) || !self.query.is_match(entry, self.match_options) {
+            return Ok(());
+        }
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= 1;
+        }
+        self.dest.write_entry(entry)
+    }
+}
+
+fn read_entries<W: WriteEntry>(query: &Query, dest: &mut W) -> std::io::Result<()> {
+    for input in query.from.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut *dest))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    Ok(())
+}
+
+// The TSV and CSV columns of wildcards are the attributes of the selected entries, as they are
+// first spelled, and entries lacking some of them get empty values rather than no rows.
+fn expand_columns(select: &[AttrSpec], entries: &[OwnedEntry]) -> Vec<AttrSpec> {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut attributes: Vec<&str> = Vec::new();
+    for attr in entries.iter().flat_map(|entry| entry.attributes()) {
+        if seen.insert(attr.lowercase) {
+            attributes.push(attr.name);
+        }
+    }
+    let mut select = select.to_vec();
+    for attrspec in select.iter_mut().filter(|attrspec| attrspec.is_wildcard()) {
+        if !attrspec.value_filters.iter().any(|filter| matches!(filter, ValueFilter::NullCoalesce(_))) {
+            attrspec.value_filters.push(ValueFilter::NullCoalesce(vec![Cow::Borrowed(b"")]));
+        }
+    }
+    expand_wildcards(&select, &attributes)
+}
+
+fn write_entries<W: WriteEntry>(entries: &[OwnedEntry], mut dest: W) -> std::io::Result<()> {
+    for entry in entries.iter() {
+        dest.write_entry(entry)?;
+    }
+    Ok(())
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let query = Query::parse(&params.query)?;
+    let mut dest = OutputBuffer::new(stdout().lock());
+
+    // without sorting or wildcard columns to find, entries are written as they are read
+    let has_columns = params.output_format != OutputFormat::Json && query.select.iter().any(AttrSpec::is_wildcard);
+    if query.order_by.is_empty() && !has_columns {
+        let select = query.select.clone();
+        match params.output_format {
+            OutputFormat::Tsv => read_entries(&query, &mut Selected::new(&query, &params.match_options, TsvEntryWriter::new(select, &mut dest)))?,
+            OutputFormat::Csv => read_entries(&query, &mut Selected::new(&query, &params.match_options, CsvEntryWriter::new(select, &mut dest)))?,
+            OutputFormat::Json => read_entries(&query, &mut Selected::new(&query, &params.match_options, JsonEntryWriter::new(select, &mut dest)))?,
+        }
+        dest.flush()?;
+        return Ok(());
+    }
+
+    let mut entries: Vec<OwnedEntry> = Vec::new();
+    let mut selected = Selected::new(&query, &params.match_options, &mut entries);
+    // the limit applies after sorting
+    selected.remaining = None;
+    read_entries(&query, &mut selected)?;
+    // the sort is stable, so entries that compare equal keep the order of the input
+    entries.sort_by(|a, b| query.compare(a, b, &params.match_options));
+    entries.truncate(query.limit.unwrap_or(entries.len()));
+    let select = if has_columns { expand_columns(&query.select, &entries) } else { query.select.clone() };
+    match params.output_format {
+        OutputFormat::Tsv => write_entries(&entries, TsvEntryWriter::new(select, &mut dest))?,
+        OutputFormat::Csv => write_entries(&entries, CsvEntryWriter::new(select, &mut dest))?,
+        OutputFormat::Json => write_entries(&entries, JsonEntryWriter::new(select, &mut dest))?,
+    }
+    dest.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lquery", &*err);
+        std::process::exit(1);
+    }
+}
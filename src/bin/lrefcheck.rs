@@ -0,0 +1,97 @@
+use clap::{arg, command, Arg};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::entry::OwnedEntry;
+use ltools::refcheck::{ReferenceChecker, DEFAULT_REFERENCES};
+use ltools::schema::dn_attributes_from_schema;
+use std::io::{stdout, BufWriter, Write};
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+    references: Vec<String>,
+    schema: Option<String>,
+    json: bool,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lrefcheck")
+        .disable_colored_help(true)
+        .about("Checks that the values of member, uniqueMember, manager, secretary and seeAlso are DNs of entries in the input, which may span several files, and reports each value that is not with its file and line. Exits with status 1 if any value is reported.")
+        .arg(arg!([FILES] ... "LDIF files to check instead of standard input ('-' means standard input)."))
+        .arg(arg!(-r --reference <ATTRIBUTE> "Check the values of ATTRIBUTE instead of the default attributes. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--schema <FILE> "Also check the values of every attribute with DN syntax according to the subschema entry in FILE, such as one exported from cn=schema.")
+            .required(false))
+        .arg(Arg::new("json")
+             .long("json")
+             .action(clap::ArgAction::SetTrue)
+             .help("Write each problem as a JSON object on a line of its own."))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let mut references: Vec<String> = matches.get_many::<String>("reference")
+        .map(|references| references.cloned().collect())
+        .unwrap_or_default();
+    if references.is_empty() {
+        references = DEFAULT_REFERENCES.iter().map(|attr| attr.to_string()).collect();
+    }
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        references,
+        schema: matches.get_one::<String>("schema").cloned(),
+        json: matches.get_flag("json"),
+    })
+}
+
+// Returns the number of problems found.
+fn get_result() -> Result<usize, Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut checker = ReferenceChecker::new(&params.references);
+    if let Some(ref schema) = params.schema {
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        open_input(schema)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut entries))
+            .map_err(|err| FileError::new(schema, err))?;
+        for entry in entries.iter() {
+            checker.add_attributes(dn_attributes_from_schema(entry));
+        }
+    }
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    for input in inputs.iter() {
+        let file = Some(input.as_str()).filter(|input| *input != "-");
+        open_input(input)
+            .and_then(|mut reader| checker.read(&mut reader, file))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    let diagnostics = checker.dangling_references();
+    let mut dest = BufWriter::new(stdout());
+    for diagnostic in diagnostics.iter() {
+        if params.json {
+            diagnostic.write_json(&mut dest)?;
+        } else {
+            diagnostic.write_text(&mut dest)?;
+        }
+    }
+    dest.flush()?;
+    Ok(diagnostics.len())
+}
+
+fn main() {
+    match get_result() {
+        Ok(0) => {},
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            report_error("lrefcheck", &*err);
+            std::process::exit(2);
+        },
+    }
+}
@@ -0,0 +1,210 @@
+use clap::{arg, command, Arg, ArgGroup};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::bulk::BulkParser;
+use ltools::dn::{avas, escape_value, rdns};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use ltools::rename::{AttributeMap, AttributeRenamer, DnMap, DnRewriter};
+use ltools::schema::{dn_attributes_from_schema, DN_ATTRIBUTES};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{stdin, stdout, BufWriter, Write};
+
+struct LRename {
+    suffix: Option<(String, String)>, // old and new suffix
+    rdn_attributes: Option<(String, String)>, // lowercase old and new RDN attribute types
+    changes: Option<String>, // file with modrdn change records
+    schema: Option<String>, // file with a subschema entry
+    references: bool,
+    dn_attributes: HashSet<String>,
+    entries: Vec<OwnedEntry>,
+    map: DnMap,
+    attribute_map: Option<String>, // file with attribute renames
+}
+
+fn parse_arguments() -> Result<LRename, &'static str> {
+    let matches = with_defaults(command!("lrename")
+        .disable_colored_help(true)
+        .about("Rewrites the DNs of LDIF entries from standard input, such as for importing them into a different tree.")
+        .arg(Arg::new("suffix")
+            .long("suffix")
+            .value_names(&["OLD", "NEW"])
+            .number_of_values(2)
+            .help("Replace the suffix OLD of DNs with NEW, such as dc=example,dc=com with dc=example,dc=org."))
+        .arg(Arg::new("rdn")
+            .long("rdn")
+            .value_names(&["OLD", "NEW"])
+            .number_of_values(2)
+            .help("Rename entries whose RDN has the attribute type OLD to use the value of attribute NEW instead, such as cn and uid. Entries without a value of NEW keep their RDN."))
+        .arg(arg!(--changes <FILE> "Rename entries according to the modrdn and moddn change records in FILE. The records apply to the DNs of the input, so they shouldn't depend on each other.")
+            .required(false))
+        .arg(arg!(--map <FILE> "Rename attributes according to FILE, which has lines such as mail=rfc822Mailbox, such as for importing entries into a directory server that names attributes differently. --rdn takes the new names.")
+            .required(false))
+        .group(ArgGroup::new("rename")
+            .args(&["suffix", "rdn", "changes", "map"])
+            .multiple(true)
+            .required(true))
+        .arg(arg!(-r --references "Also rewrite the values of attributes that hold DNs, such as member, manager and seeAlso.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"dn-attribute" <ATTRIBUTE> "Treat values of ATTRIBUTE as DNs when rewriting references. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--schema <FILE> "Also treat values of attributes as DNs when they have the DN syntax according to the subschema entry in FILE, such as one exported from cn=schema.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let pair = |name: &str| -> Option<(String, String)> {
+        let mut values = matches.get_many::<String>(name)?;
+        Some((values.next()?.clone(), values.next()?.clone()))
+    };
+    let suffix = pair("suffix");
+    let rdn_attributes = pair("rdn").map(|(old, new)| (old.to_ascii_lowercase(), new.to_ascii_lowercase()));
+
+    let mut dn_attributes: HashSet<String> = matches.get_many::<String>("dn-attribute")
+        .into_iter()
+        .flatten()
+        .map(|attr| attr.to_ascii_lowercase())
+        .collect();
+    dn_attributes.extend(DN_ATTRIBUTES.iter().map(|attr| attr.to_string()));
+
+    Ok(LRename{
+        suffix,
+        rdn_attributes,
+        changes: matches.get_one::<String>("changes").cloned(),
+        schema: matches.get_one::<String>("schema").cloned(),
+        references: matches.get_flag("references"),
+        dn_attributes,
+        entries: Vec::new(),
+        map: DnMap::new(),
+        attribute_map: matches.get_one::<String>("map").cloned(),
+    })
+}
+
+impl LRename {
+    fn is_renamed(&self, rdn: &str) -> bool {
+        let old_attr = match self.rdn_attributes {
+            Some((ref old_attr, _)) => old_attr,
+            None => return false,
+        };
+        let avas: Vec<(&str, &str)> = avas(rdn).collect();
+        avas.len() == 1 && avas[0].0.eq_ignore_ascii_case(old_attr)
+    }
+
+    // Returns the RDN of the entry with the new RDN attribute, if the entry has a value of it
+    fn new_rdn(&self, entry: &Entry) -> Option<String> {
+        let (_, new_attr) = self.rdn_attributes.as_ref()?;
+        let value = entry.get_one(new_attr)?;
+        let attr = entry.attributes()
+            .find(|attr| attr.lowercase == new_attr)
+            .map(|attr| attr.name)
+            .unwrap_or(new_attr);
+        Some(format!("{}={}", attr, escape_value(value)))
+    }
+
+    fn write_entries<W: WriteEntry>(&mut self, dest: W) -> std::io::Result<()> {
+        let mut map = std::mem::take(&mut self.map);
+        if let Some((ref old_suffix, ref new_suffix)) = self.suffix {
+            map.move_to(old_suffix, new_suffix);
+        }
+        for entry in self.entries.iter() {
+            let dn = match entry.get_one_str("dn") {
+                Some(dn) => dn,
+                None => continue,
+            };
+            if !rdns(&dn).next().map(|rdn| self.is_renamed(rdn)).unwrap_or(false) {
+                continue;
+            }
+            if let Some(new_rdn) = self.new_rdn(entry) {
+                map.rename(&dn, &new_rdn);
+            }
+        }
+        let mut rewriter = DnRewriter::new(map, dest);
+        if self.references {
+            rewriter.set_dn_attributes(self.dn_attributes.clone());
+        } else {
+            rewriter.set_dn_attributes(HashSet::new());
+        }
+        for entry in self.entries.iter() {
+            rewriter.write_entry(entry)?;
+        }
+        Ok(())
+    }
+}
+
+// DNs can only be rewritten once every renamed entry is known, so entries are kept until the
+// input has been read
+impl WriteEntry for LRename {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.entries.push(entry.into());
+        Ok(())
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut lrename = parse_arguments()?;
+    if let Some(ref changes) = lrename.changes {
+        let mut file = File::open(changes).map_err(|err| FileError::new(changes, err))?;
+        BulkParser::new().write_entries(&mut file, &mut lrename.map).map_err(|err| FileError::new(changes, err))?;
+    }
+    if let Some(ref schema) = lrename.schema {
+        let mut file = File::open(schema).map_err(|err| FileError::new(schema, err))?;
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        BulkParser::new().write_entries(&mut file, &mut entries).map_err(|err| FileError::new(schema, err))?;
+        for entry in entries.iter() {
+            lrename.dn_attributes.extend(dn_attributes_from_schema(entry));
+        }
+    }
+    let attribute_map = match lrename.attribute_map {
+        Some(ref path) => AttributeMap::load(path).map_err(|err| FileError::new(path, err))?,
+        None => AttributeMap::new(),
+    };
+    BulkParser::new()
+        .set_ignore_entries_without_dn(true)
+        .write_entries(&mut stdin().lock(), AttributeRenamer::new(attribute_map, &mut lrename))?;
+    let mut writer = LdifEntryWriter::new(BufWriter::new(stdout().lock()));
+    writer.set_sort_attributes(false);
+    lrename.write_entries(&mut writer)?;
+    writer.get_mut().flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lrename", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> std::io::Result<()> {
+        let mut lrename = LRename{
+            suffix: Some((String::from("dc=example,dc=com"), String::from("dc=example,dc=org"))),
+            rdn_attributes: Some((String::from("cn"), String::from("uid"))),
+            changes: None,
+            schema: None,
+            references: true,
+            dn_attributes: HashSet::from([String::from("member")]),
+            entries: Vec::new(),
+            map: DnMap::new(),
+            attribute_map: None,
+        };
+        lrename.write_entry(&Entry::from([("dn", b"ou=people,dc=example,dc=com".as_slice())]))?;
+        lrename.write_entry(&Entry::from([("dn", b"cn=John Smith,ou=people, DC=example,dc=com".as_slice()), ("uid", b"jsmith")]))?;
+        lrename.write_entry(&Entry::from([("dn", b"cn=admins,dc=example,dc=com".as_slice()), ("member", b"cn=john smith,ou=people,dc=example,dc=com")]))?;
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        lrename.write_entries(&mut entries)?;
+        let dns: Vec<String> = entries.iter().filter_map(|entry| entry.get_one_str("dn")).map(|dn| dn.into_owned()).collect();
+        assert_eq!(dns, ["ou=people,dc=example,dc=org", "uid=jsmith,ou=people,dc=example,dc=org", "cn=admins,dc=example,dc=org"]);
+        assert_eq!(entries[2].get_one_str("member").as_deref(), Some("uid=jsmith,ou=people,dc=example,dc=org"));
+        Ok(())
+    }
+}
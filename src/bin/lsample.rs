@@ -0,0 +1,182 @@
+use clap::{arg, command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ltools::bulk::BulkParser;
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use std::collections::HashMap;
+use std::io::{stdin, stdout, BufWriter, Stdout, Write};
+
+enum SampleSize {
+    Count(usize),
+    Fraction(f64),
+}
+
+impl SampleSize {
+    fn parse(size: &str) -> Result<SampleSize, &'static str> {
+        match size.strip_suffix('%') {
+            Some(percent) => match percent.parse::<f64>() {
+                Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(SampleSize::Fraction(percent / 100.0)),
+                _ => Err("sample percentage must be a number between 0 and 100"),
+            },
+            None => match size.parse::<usize>() {
+                Ok(count) => Ok(SampleSize::Count(count)),
+                Err(_) => Err("failed to parse sample size"),
+            },
+        }
+    }
+}
+
+// Uniform sample of fixed size from a stream of unknown length
+#[derive(Default)]
+struct Reservoir {
+    seen: usize,
+    entries: Vec<(usize, OwnedEntry)>, // index in input, entry
+}
+
+impl Reservoir {
+    fn add(&mut self, count: usize, index: usize, entry: &Entry, rng: &mut StdRng) {
+        if self.entries.len() < count {
+            self.entries.push((index, entry.into()));
+        } else {
+            let j = rng.gen_range(0..=self.seen);
+            if j < count {
+                self.entries[j] = (index, entry.into());
+            }
+        }
+        self.seen += 1;
+    }
+}
+
+struct LSample {
+    size: SampleSize,
+    stratify: Option<String>,
+    rng: StdRng,
+    index: usize,
+    strata: HashMap<String, Reservoir>,
+    writer: LdifEntryWriter<BufWriter<Stdout>>,
+}
+
+fn parse_arguments() -> Result<LSample, &'static str> {
+    let matches = with_defaults(command!("lsample")
+        .disable_colored_help(true)
+        .arg(arg!(<SIZE> "Number of entries to sample, or a percentage of the entries such as 5%."))
+        .arg(arg!(-s --stratify <ATTRIBUTE> "Sample SIZE entries for each distinct set of values of ATTRIBUTE, such as each combination of object classes.")
+            .required(false))
+        .arg(arg!(--seed <SEED> "Seed for the random number generator, for reproducible samples.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let size = match matches.get_one::<String>("SIZE") {
+        Some(size) => SampleSize::parse(size)?,
+        None => return Err("missing argument SIZE"),
+    };
+
+    let rng = match matches.get_one::<String>("seed").map(|seed| seed.parse::<u64>()) {
+        Some(Ok(seed)) => StdRng::seed_from_u64(seed),
+        Some(Err(_)) => return Err("seed must be a non-negative integer"),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut writer = LdifEntryWriter::new(BufWriter::new(stdout()));
+    writer.set_sort_attributes(false);
+
+    Ok(LSample{
+        size,
+        stratify: matches.get_one::<String>("stratify").map(|attr| attr.to_ascii_lowercase()),
+        rng,
+        index: 0,
+        strata: HashMap::new(),
+        writer,
+    })
+}
+
+impl LSample {
+    fn stratum(&self, entry: &Entry) -> String {
+        let attr = match self.stratify {
+            Some(ref attr) => attr,
+            None => return String::new(),
+        };
+        let mut values: Vec<String> = entry.get(attr)
+            .map(|value| String::from_utf8_lossy(value).to_lowercase())
+            .collect();
+        values.sort();
+        values.dedup();
+        values.join("\n")
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        let mut sample: Vec<(usize, OwnedEntry)> = self.strata.drain()
+            .flat_map(|(_, reservoir)| reservoir.entries)
+            .collect();
+        sample.sort_by_key(|(index, _)| *index);
+        for (_, entry) in sample.iter() {
+            self.writer.write_entry(entry)?;
+        }
+        self.writer.get_mut().flush()
+    }
+}
+
+impl WriteEntry for LSample {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        match self.size {
+            SampleSize::Fraction(fraction) => {
+                if self.rng.gen_bool(fraction) {
+                    self.writer.write_entry(entry)?;
+                }
+            },
+            SampleSize::Count(count) => {
+                let stratum = self.stratum(entry);
+                let reservoir = self.strata.entry(stratum).or_default();
+                reservoir.add(count, self.index, entry, &mut self.rng);
+            },
+        }
+        self.index += 1;
+        Ok(())
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut lsample = parse_arguments()?;
+    BulkParser::new().write_entries(&mut stdin().lock(), &mut lsample)?;
+    lsample.finish()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lsample", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() {
+        assert!(matches!(SampleSize::parse("10"), Ok(SampleSize::Count(10))));
+        assert!(matches!(SampleSize::parse("2.5%"), Ok(SampleSize::Fraction(f)) if f == 0.025));
+        assert!(SampleSize::parse("101%").is_err());
+        assert!(SampleSize::parse("-1").is_err());
+    }
+
+    #[test]
+    fn test_reservoir() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut reservoir = Reservoir::default();
+        let entry = Entry::from([("dn", b"cn=foo".as_slice())]);
+        for index in 0..100 {
+            reservoir.add(5, index, &entry, &mut rng);
+        }
+        assert_eq!(reservoir.seen, 100);
+        assert_eq!(reservoir.entries.len(), 5);
+    }
+}
@@ -0,0 +1,130 @@
+use clap::{arg, command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output};
+use ltools::attrspec::{expand_wildcards, AttrSpec};
+use ltools::bulk::BulkParser;
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use std::io::{stdin, stdout, BufWriter, Stdout, Write};
+
+struct LSed {
+    attrspecs: Vec<AttrSpec>,
+    writer: LdifEntryWriter<BufWriter<Stdout>>,
+}
+
+fn parse_arguments() -> Result<LSed, Box<dyn std::error::Error>> {
+    let matches = with_defaults(command!("lsed")
+        .disable_colored_help(true)
+        .about("Rewrites the values of attributes of LDIF entries from standard input with the value filters of attribute specifications, such as 'employeeNumber.encrypt(backup)', and writes the entries to standard output.")
+        .arg(arg!(<ATTRIBUTES> ... "Attribute specifications such as 'userPassword.ssha' or 'employee*.decrypt(backup)'. Other attributes are left as they are."))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let mut attrspecs: Vec<AttrSpec> = Vec::new();
+    for attrspec in matches.get_many::<String>("ATTRIBUTES").into_iter().flatten() {
+        let attrspec = AttrSpec::parse(attrspec)?;
+        if attrspec.is_pseudo() || attrspec.exclude {
+            return Err(format!("'{}' doesn't name attributes to rewrite", attrspec.attribute).into());
+        }
+        attrspecs.push(attrspec);
+    }
+
+    let mut writer = LdifEntryWriter::new(BufWriter::new(stdout()));
+    writer.set_sort_attributes(false);
+
+    Ok(LSed{
+        attrspecs,
+        writer,
+    })
+}
+
+// Rewrites the values of the attributes with a specification. Attributes that the entry lacks
+// are added if their specification gives a default.
+fn rewrite(attrspecs: &[AttrSpec], entry: &Entry) -> std::io::Result<OwnedEntry> {
+    let attributes: Vec<&str> = entry.attributes().map(|attr| attr.name).collect();
+    let attrspecs = expand_wildcards(attrspecs, &attributes);
+    let mut rewritten = OwnedEntry::default();
+    for attr in entry.attributes() {
+        match attrspecs.iter().find(|attrspec| attrspec.attribute_lowercase == attr.lowercase) {
+            Some(attrspec) => {
+                for value in attrspec.filter_values(entry.get(attr.lowercase))?.iter() {
+                    rewritten.add_value(attr.name, value);
+                }
+            },
+            None => {
+                for (value, base64) in entry.get_with_encoding(attr.lowercase) {
+                    rewritten.add_value_with_encoding(attr.name, value, base64);
+                }
+            },
+        }
+    }
+    for attrspec in attrspecs.iter() {
+        if entry.get_one(&attrspec.attribute_lowercase).is_some() {
+            continue;
+        }
+        for value in attrspec.filter_values(std::iter::empty())?.iter() {
+            rewritten.add_value(&attrspec.attribute, value);
+        }
+    }
+    Ok(rewritten)
+}
+
+impl WriteEntry for LSed {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let rewritten = rewrite(&self.attrspecs, entry)?;
+        self.writer.write_entry(&rewritten)
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut lsed = parse_arguments()?;
+    BulkParser::new().write_entries(&mut stdin().lock(), &mut lsed)?;
+    lsed.writer.get_mut().flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lsed", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn attrspecs(specs: &[&str]) -> Vec<AttrSpec> {
+        specs.iter().map(|spec| AttrSpec::parse(spec).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_rewrite() -> std::io::Result<()> {
+        let entry = Entry::from([("dn", b"cn=foo".as_slice()), ("cn", b"foo"), ("mail", b"a@example.com"), ("mail", b"a@example.com")]);
+        let rewritten = rewrite(&attrspecs(&["cn.hex", "mail.uniq", "sn:-none"]), &entry)?;
+        assert_eq!(rewritten.get_one("dn"), Some(b"cn=foo".as_slice()));
+        assert_eq!(rewritten.get_one("cn"), Some(b"666f6f".as_slice()));
+        assert_eq!(rewritten.get("mail").count(), 1);
+        assert_eq!(rewritten.get_one("sn"), Some(b"none".as_slice()));
+        let rewritten = rewrite(&attrspecs(&["c*.base64", "cn"]), &entry)?;
+        assert_eq!(rewritten.get_one("cn"), Some(b"foo".as_slice()));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "encrypt")]
+    fn test_crypt() -> std::io::Result<()> {
+        std::env::set_var("LTOOLS_KEY_LSED_TEST", "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=");
+        let entry = Entry::from([("dn", b"cn=foo".as_slice()), ("employeeNumber", b"1234")]);
+        let encrypted = rewrite(&attrspecs(&["employeeNumber.encrypt(lsed-test)"]), &entry)?;
+        assert!(encrypted.get_one("employeenumber").unwrap().starts_with(b"{AESGCM:lsed-test}"));
+        let decrypted = rewrite(&attrspecs(&["employeeNumber.decrypt(lsed-test)"]), &encrypted)?;
+        assert_eq!(decrypted.get_one("employeenumber"), Some(b"1234".as_slice()));
+        let tampered = Entry::from([("employeeNumber", b"{AESGCM:lsed-test}AAAAAAAAAAAAAAAAAAAAAAAA".as_slice())]);
+        assert!(rewrite(&attrspecs(&["employeeNumber.decrypt(lsed-test)"]), &tampered).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::dn::escape_value;
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::ldif::LdifEntryWriter;
+use ltools::rows::{read_rows, RowFormat};
+use ltools::template::Template;
+use std::io::{stdout, BufWriter, Write};
+
+struct Parameters {
+    template: String,
+    data: String, // - means standard input
+    format: RowFormat,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("ltemplate")
+        .disable_colored_help(true)
+        .about("Writes the entries of an LDIF template once for each row of a CSV, TSV or JSON data source, with placeholders such as {uid} in the values filled in from the row.")
+        .arg(arg!(<TEMPLATE> "LDIF file with the entries to write for each row. Placeholders take the same syntax as the attributes of lget, such as {mail:-none}, and literal braces are written as {{ and }}. A value is written once for each value of a multi-valued placeholder, and left out if a placeholder has no value."))
+        .arg(arg!([DATA] "The file with the rows, instead of standard input ('-' means standard input). The header row of CSV and TSV names the placeholders."))
+        .arg(arg!(-f --format <FORMAT> "Read the data as FORMAT, which is csv, tsv or json, instead of according to the file extension, or csv for standard input.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let data = matches.get_one::<String>("DATA").cloned().unwrap_or_else(|| String::from("-"));
+    let format = match matches.get_one::<String>("format") {
+        Some(format) => RowFormat::parse(format)?,
+        None => RowFormat::from_path(&data).unwrap_or(RowFormat::Csv),
+    };
+
+    Ok(Parameters{
+        template: matches.get_one::<String>("TEMPLATE").unwrap().clone(),
+        data,
+        format,
+    })
+}
+
+// An entry of the template with each value parsed as a template of its own.
+struct EntryTemplate {
+    attributes: Vec<(String, Vec<Template>)>,
+}
+
+impl EntryTemplate {
+    fn parse(entry: &Entry) -> std::io::Result<EntryTemplate> {
+        let mut attributes: Vec<(String, Vec<Template>)> = Vec::new();
+        for attr in entry.attributes() {
+            let templates = entry.get_str(attr.lowercase)
+                .map(|value| Template::parse(&value))
+                .collect::<std::io::Result<_>>()?;
+            attributes.push((attr.name.to_string(), templates));
+        }
+        Ok(EntryTemplate{
+            attributes,
+        })
+    }
+
+    // Renders the entry for a row, or returns None if the DN doesn't render to a single value.
+    fn render(&self, row: &Entry, escaped_row: &Entry) -> std::io::Result<Option<OwnedEntry>> {
+        let mut rendered = OwnedEntry::default();
+        for (attr, templates) in self.attributes.iter() {
+            // values put into the DN are escaped, so that a comma in a cn doesn't split an RDN
+            let is_dn = attr.eq_ignore_ascii_case("dn");
+            let mut values: Vec<Vec<u8>> = Vec::new();
+            for template in templates.iter() {
+                values.extend(template.render(if is_dn { escaped_row } else { row })?);
+            }
+            if is_dn && values.len() != 1 {
+                return Ok(None);
+            }
+            for value in values.iter() {
+                rendered.add_value(attr, value);
+            }
+        }
+        Ok(Some(rendered))
+    }
+}
+
+fn escape_row(row: &Entry) -> OwnedEntry {
+    let mut escaped = OwnedEntry::default();
+    for attr in row.attributes() {
+        for value in row.get(attr.lowercase) {
+            escaped.add_value(attr.name, escape_value(value).as_bytes());
+        }
+    }
+    escaped
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut template_entries: Vec<OwnedEntry> = Vec::new();
+    open_input(&params.template)
+        .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut template_entries))
+        .map_err(|err| FileError::new(&params.template, err))?;
+    let templates: Vec<EntryTemplate> = template_entries.iter()
+        .map(|entry| EntryTemplate::parse(entry))
+        .collect::<std::io::Result<_>>()
+        .map_err(|err| FileError::new(&params.template, err))?;
+    let rows = open_input(&params.data)
+        .and_then(|reader| read_rows(reader, params.format))
+        .map_err(|err| FileError::new(&params.data, err))?;
+    write_entries(&templates, &rows)?;
+    Ok(())
+}
+
+fn write_entries(templates: &[EntryTemplate], rows: &[OwnedEntry]) -> std::io::Result<()> {
+    let mut dest = BufWriter::new(stdout());
+    let mut writer = LdifEntryWriter::new(&mut dest);
+    writer.set_sort_attributes(false);
+    for (index, row) in rows.iter().enumerate() {
+        let escaped_row = escape_row(row);
+        for template in templates.iter() {
+            match template.render(row, &escaped_row)? {
+                Some(entry) => writer.write_entry(&entry)?,
+                None => {
+                    let message = format!("left out an entry for data row {}, as its DN has a placeholder without exactly one value", index + 1);
+                    report("ltemplate", &Diagnostic::new(Severity::Warning, "unrendered-dn", message));
+                },
+            }
+        }
+    }
+    dest.flush()
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("ltemplate", &*err);
+        std::process::exit(1);
+    }
+}
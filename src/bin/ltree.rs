@@ -0,0 +1,185 @@
+use clap::{arg, command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output};
+use ltools::bulk::BulkParser;
+use ltools::dn::rdns;
+use ltools::entry::{Entry, WriteEntry};
+use std::collections::BTreeMap;
+use std::io::{stdin, stdout, BufWriter, Write};
+
+#[derive(Default)]
+struct Node {
+    rdn: String,
+    is_entry: bool, // false for nodes that only appear as part of other DNs
+    values: Vec<String>,
+    count: usize, // number of distinct entries in the subtree, including this one
+    children: BTreeMap<String, usize>, // lowercase RDN to node index
+}
+
+struct LTree {
+    attribute: Option<String>,
+    max_level: Option<usize>,
+    nodes: Vec<Node>, // nodes[0] is the root above all DNs
+}
+
+fn parse_arguments() -> Result<LTree, &'static str> {
+    let matches = with_defaults(command!("ltree")
+        .disable_colored_help(true)
+        .arg(arg!(-a --attribute <ATTRIBUTE> "Show the values of ATTRIBUTE next to each entry.")
+            .required(false))
+        .arg(arg!(-L --level <LEVEL> "Do not show entries deeper than LEVEL below the top of the tree.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let max_level = match matches.get_one::<String>("level").map(|level| level.parse::<usize>()) {
+        Some(Ok(level)) => Some(level),
+        Some(Err(_)) => return Err("failed to parse level"),
+        None => None,
+    };
+
+    Ok(LTree{
+        attribute: matches.get_one::<String>("attribute").map(|attr| attr.to_ascii_lowercase()),
+        max_level,
+        nodes: vec![Node::default()],
+    })
+}
+
+impl LTree {
+    fn child(&mut self, parent: usize, rdn: &str) -> usize {
+        let key = rdn.to_ascii_lowercase();
+        if let Some(index) = self.nodes[parent].children.get(&key) {
+            return *index;
+        }
+        let index = self.nodes.len();
+        self.nodes.push(Node{
+            rdn: rdn.to_string(),
+            ..Node::default()
+        });
+        self.nodes[parent].children.insert(key, index);
+        index
+    }
+
+    fn write_tree<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for index in self.nodes[0].children.values() {
+            self.write_node(w, *index, "", None, 0)?;
+        }
+        Ok(())
+    }
+
+    // is_last is None for the top-level nodes, which are written without branch lines
+    fn write_node<W: Write>(&self, w: &mut W, index: usize, prefix: &str, is_last: Option<bool>, level: usize) -> std::io::Result<()> {
+        let node = &self.nodes[index];
+        let (branch, child_prefix) = match is_last {
+            None => ("", String::new()),
+            Some(false) => ("├── ", format!("{}│   ", prefix)),
+            Some(true) => ("└── ", format!("{}    ", prefix)),
+        };
+        write!(w, "{}{}{}", prefix, branch, node.rdn)?;
+        let below = node.count - if node.is_entry { 1 } else { 0 };
+        if below != 0 {
+            write!(w, " ({})", below)?;
+        }
+        if !node.values.is_empty() {
+            write!(w, " [{}]", node.values.join(", "))?;
+        }
+        writeln!(w)?;
+        if self.max_level.map(|max_level| level >= max_level).unwrap_or(false) {
+            return Ok(());
+        }
+        let last = node.children.len().saturating_sub(1);
+        for (i, child) in node.children.values().enumerate() {
+            self.write_node(w, *child, &child_prefix, Some(i == last), level + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl WriteEntry for LTree {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let dn = match entry.get_one_str("dn") {
+            Some(dn) => dn,
+            None => return Ok(()),
+        };
+        let rdns: Vec<&str> = rdns(&dn).collect();
+        let mut path: Vec<usize> = vec![0];
+        for rdn in rdns.iter().rev() {
+            path.push(self.child(*path.last().unwrap(), rdn));
+        }
+        let index = *path.last().unwrap();
+        // an entry whose DN was already seen is the same entry, which is counted once and shown
+        // with the values of its last occurrence
+        if !self.nodes[index].is_entry {
+            for index in path.iter() {
+                self.nodes[*index].count += 1;
+            }
+        }
+        let values: Vec<String> = match self.attribute {
+            Some(ref attr) => entry.get_str(attr).map(|value| value.into_owned()).collect(),
+            None => Vec::new(),
+        };
+        let node = &mut self.nodes[index];
+        node.is_entry = true;
+        node.values = values;
+        Ok(())
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let mut ltree = parse_arguments()?;
+    BulkParser::new().write_entries(&mut stdin().lock(), &mut ltree)?;
+    let mut output = BufWriter::new(stdout().lock());
+    ltree.write_tree(&mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("ltree", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tree(ldif: &[u8]) -> std::io::Result<String> {
+        let mut ltree = LTree{
+            attribute: Some(String::from("cn")),
+            max_level: None,
+            nodes: vec![Node::default()],
+        };
+        BulkParser::new().write_entries(&mut &ldif[..], &mut ltree)?;
+        let mut output: Vec<u8> = Vec::new();
+        ltree.write_tree(&mut output)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[test]
+    fn test_tree() -> std::io::Result<()> {
+        let fixture = include_bytes!("../../test.ldif");
+        let expected = "dc=com (5)\n\
+                        └── dc=example (5)\n\
+                        \x20   ├── cn=admin [admin]\n\
+                        \x20   ├── cn=bar [bar]\n\
+                        \x20   ├── cn=baz [baz]\n\
+                        \x20   ├── cn=foo [foo]\n\
+                        \x20   └── cn=group [group]\n";
+        assert_eq!(tree(fixture)?, expected);
+        // the entries of the fixture given twice are the same entries
+        assert_eq!(tree(&[&fixture[..], b"\n", &fixture[..]].concat())?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_dns() -> std::io::Result<()> {
+        let ldif = b"dn: ou=people,dc=example\n\ndn: cn=a,ou=people,dc=example\ncn: a\n\ndn: CN=A,ou=People,dc=example\ncn: A\n\n";
+        assert_eq!(tree(ldif)?, "dc=example (2)\n└── ou=people (1)\n    └── cn=a [A]\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::changerecord::{ChangeRecord, ChangeRecordTokenWriter, ModRdnChangeRecord, ModifyChangeRecord, ModifyChangeRecordOp, ModifyChangeRecordOpType, WriteChangeRecord, WriteRecord};
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::dn::{avas, normalize, parent, rdn, rdns, unescape_value};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use std::collections::HashMap;
+use std::io::{stdout, BufWriter, Error, ErrorKind, Write};
+
+struct Parameters {
+    snapshot: String,
+    changes: String, // - means standard input
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lundo")
+        .disable_colored_help(true)
+        .about("Writes the change records that roll back the change records in CHANGES, given the SNAPSHOT of the directory they were applied to, in the order they are to be applied.")
+        .arg(arg!(<SNAPSHOT> "LDIF file with the entries as they were before the changes."))
+        .arg(arg!([CHANGES] "LDIF file with the change records to roll back, instead of standard input ('-' means standard input)."))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    Ok(Parameters{
+        snapshot: matches.get_one::<String>("SNAPSHOT").unwrap().clone(),
+        changes: matches.get_one::<String>("CHANGES").cloned().unwrap_or_else(|| String::from("-")),
+    })
+}
+
+// Computes the inverse of each change record against the entries of the snapshot, which the
+// changes are applied to as they are read, so that a later change is undone against the state
+// that an earlier change left.
+#[derive(Default)]
+struct Inverter {
+    entries: HashMap<String, OwnedEntry>, // by normalized DN
+    inverses: Vec<Vec<u8>>, // LDIF of the inverse of each change, in the order of the changes
+    unknown: usize, // changes to entries that are not in the snapshot
+    skipped: usize, // entries that are not change records
+}
+
+impl Inverter {
+    fn invert(&mut self, record: &ChangeRecord) -> std::io::Result<()> {
+        let mut inverse: Vec<u8> = Vec::new();
+        let key = normalize(&record.dn);
+        if record.is_changetype("add") {
+            let mut entry = OwnedEntry::default();
+            entry.add_value("dn", record.dn.as_bytes());
+            for (attr, value) in record.lines.iter() {
+                entry.add_value(attr, value);
+            }
+            inverse.write_delete(&record.dn)?;
+            self.entries.insert(key, entry);
+        } else if record.is_changetype("delete") {
+            let Some(entry) = self.entries.remove(&key) else {
+                self.unknown += 1;
+                return Ok(());
+            };
+            inverse.write_add(&entry)?;
+        } else if record.is_changetype("modify") {
+            let modify = record.modify()?;
+            let Some(entry) = self.entries.get_mut(&key) else {
+                self.unknown += 1;
+                return Ok(());
+            };
+            let ops = invert_modify(&modify, entry);
+            if ops.is_empty() {
+                return Ok(());
+            }
+            inverse.write_modify(&ModifyChangeRecord{
+                dn: record.dn.clone(),
+                ops: ops.iter()
+                    .map(|(typ, attr, values)| ModifyChangeRecordOp{
+                        typ: *typ,
+                        attr: attr.clone(),
+                        values: values.iter().map(Vec::as_slice).collect(),
+                    })
+                    .collect(),
+            })?;
+        } else if record.is_changetype("modrdn") {
+            let Some(mut entry) = self.entries.remove(&key) else {
+                self.unknown += 1;
+                return Ok(());
+            };
+            let new_rdn = record.get_one("newrdn")
+                .map(|new_rdn| String::from_utf8_lossy(new_rdn).into_owned())
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("the modrdn of {} lacks newrdn", record.dn)))?;
+            let delete_old_rdn = record.get_one("deleteoldrdn") == Some(b"1");
+            let new_superior = record.get_one("newsuperior").map(|dn| String::from_utf8_lossy(dn).into_owned());
+            let old_rdn = rdn(&record.dn);
+            let old_parent = parent(&record.dn);
+            let new_dn = match new_superior.as_deref().or(old_parent) {
+                Some(superior) if !superior.is_empty() => format!("{},{}", new_rdn, superior),
+                _ => new_rdn.clone(),
+            };
+
+            // the values of the new RDN are only deleted again if the entry didn't have them
+            let mut had_new_rdn = true;
+            for (attr, value) in avas(&new_rdn) {
+                let value = unescape_value(value);
+                if !entry.get(attr.trim()).any(|existing| existing == value) {
+                    had_new_rdn = false;
+                    entry.add_value(attr.trim(), &value);
+                }
+            }
+            if delete_old_rdn {
+                for (attr, value) in avas(old_rdn) {
+                    let value = unescape_value(value);
+                    let is_in_new_rdn = avas(&new_rdn).any(|(new_attr, new_value)| new_attr.trim().eq_ignore_ascii_case(attr.trim()) && unescape_value(new_value) == value);
+                    if !is_in_new_rdn {
+                        entry.remove_value(attr.trim(), &value);
+                    }
+                }
+            }
+            inverse.write_modrdn(&ModRdnChangeRecord{
+                dn: &new_dn,
+                new_rdn: old_rdn,
+                delete_old_rdn: !had_new_rdn,
+                new_superior: new_superior.as_ref().map(|_| old_parent.unwrap_or("")),
+            })?;
+            entry.remove_attribute("dn");
+            entry.add_value("dn", new_dn.as_bytes());
+            self.move_subtree(&key, &normalize(&new_dn), entry);
+        } else {
+            return Err(Error::new(ErrorKind::InvalidData, format!("unsupported changetype {} of {}", record.changetype, record.dn)));
+        }
+        self.inverses.push(inverse);
+        Ok(())
+    }
+
+    // Moves the entries below old_key along with the entry that was renamed.
+    fn move_subtree(&mut self, old_key: &str, new_key: &str, entry: OwnedEntry) {
+        let suffix = format!(",{}", old_key);
+        let descendants: Vec<String> = self.entries.keys()
+            .filter(|key| key.ends_with(&suffix))
+            .cloned()
+            .collect();
+        for key in descendants {
+            let mut descendant = self.entries.remove(&key).unwrap();
+            let dn = descendant.get_one_str("dn").unwrap_or_default().into_owned();
+            let relative: Vec<&str> = rdns(&dn).take(rdns(&key).count() - rdns(old_key).count()).collect();
+            let new_dn = format!("{},{}", relative.join(","), entry.get_one_str("dn").unwrap_or_default());
+            descendant.remove_attribute("dn");
+            descendant.add_value("dn", new_dn.as_bytes());
+            self.entries.insert(normalize(&new_dn), descendant);
+        }
+        self.entries.insert(new_key.to_string(), entry);
+    }
+}
+
+// Applies the operations of a modify to the entry, and returns the operations that undo them, in
+// the order they are to be applied.
+fn invert_modify(modify: &ModifyChangeRecord, entry: &mut OwnedEntry) -> Vec<(ModifyChangeRecordOpType, String, Vec<Vec<u8>>)> {
+    let mut inverse_ops: Vec<(ModifyChangeRecordOpType, String, Vec<Vec<u8>>)> = Vec::new();
+    for op in modify.ops.iter() {
+        let old_values: Vec<Vec<u8>> = entry.get(&op.attr).map(<[u8]>::to_vec).collect();
+        match op.typ {
+            ModifyChangeRecordOpType::Add => {
+                let added: Vec<Vec<u8>> = op.values.iter()
+                    .filter(|value| !old_values.iter().any(|old_value| old_value == *value))
+                    .map(|value| value.to_vec())
+                    .collect();
+                for value in added.iter() {
+                    entry.add_value(&op.attr, value);
+                }
+                if !added.is_empty() {
+                    inverse_ops.push((ModifyChangeRecordOpType::Delete, op.attr.clone(), added));
+                }
+            },
+            ModifyChangeRecordOpType::Delete if op.values.is_empty() => {
+                entry.remove_attribute(&op.attr);
+                if !old_values.is_empty() {
+                    inverse_ops.push((ModifyChangeRecordOpType::Add, op.attr.clone(), old_values));
+                }
+            },
+            ModifyChangeRecordOpType::Delete => {
+                let removed: Vec<Vec<u8>> = op.values.iter()
+                    .filter(|value| entry.remove_value(&op.attr, value))
+                    .map(|value| value.to_vec())
+                    .collect();
+                if !removed.is_empty() {
+                    inverse_ops.push((ModifyChangeRecordOpType::Add, op.attr.clone(), removed));
+                }
+            },
+            ModifyChangeRecordOpType::Replace => {
+                entry.remove_attribute(&op.attr);
+                for value in op.values.iter() {
+                    entry.add_value(&op.attr, value);
+                }
+                inverse_ops.push((ModifyChangeRecordOpType::Replace, op.attr.clone(), old_values));
+            },
+        }
+    }
+    inverse_ops.reverse();
+    inverse_ops
+}
+
+// Entries among the change records are left out.
+impl WriteEntry for Inverter {
+    fn write_entry(&mut self, _entry: &Entry) -> std::io::Result<()> {
+        self.skipped += 1;
+        Ok(())
+    }
+}
+
+impl WriteRecord for Inverter {
+    fn write_change_record(&mut self, record: &ChangeRecord) -> std::io::Result<()> {
+        self.invert(record)
+    }
+}
+
+struct Snapshot<'a>(&'a mut HashMap<String, OwnedEntry>);
+
+impl WriteEntry for Snapshot<'_> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        if let Some(dn) = entry.get_one_str("dn") {
+            self.0.insert(normalize(&dn), entry.into());
+        }
+        Ok(())
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut inverter = Inverter::default();
+    open_input(&params.snapshot)
+        .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, Snapshot(&mut inverter.entries)))
+        .map_err(|err| FileError::new(&params.snapshot, err))?;
+    open_input(&params.changes)
+        .and_then(|mut reader| BulkParser::new().write_tokens(&mut reader, ChangeRecordTokenWriter::new(&mut inverter)))
+        .map_err(|err| FileError::new(&params.changes, err))?;
+
+    // the changes are undone in the opposite order of how they were made
+    let mut dest = BufWriter::new(stdout());
+    for inverse in inverter.inverses.iter().rev() {
+        dest.write_all(inverse)?;
+    }
+    dest.flush()?;
+    if inverter.unknown != 0 {
+        let message = format!("left out the rollback of {} changes to entries that are not in the snapshot", inverter.unknown);
+        report("lundo", &Diagnostic::new(Severity::Warning, "unknown-entry", message));
+    }
+    if inverter.skipped != 0 {
+        let message = format!("left out {} entries that are not change records", inverter.skipped);
+        report("lundo", &Diagnostic::new(Severity::Warning, "not-a-change", message));
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lundo", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ltools::bulk::parse_entries;
+
+    fn undo(snapshot: &str, changes: &str) -> std::io::Result<String> {
+        let mut inverter = Inverter::default();
+        parse_entries(snapshot.as_bytes(), Snapshot(&mut inverter.entries))?;
+        BulkParser::new().write_tokens(&mut changes.as_bytes(), ChangeRecordTokenWriter::new(&mut inverter))?;
+        let rollback: Vec<u8> = inverter.inverses.iter().rev().flatten().copied().collect();
+        Ok(String::from_utf8_lossy(&rollback).into_owned())
+    }
+
+    #[test]
+    fn test_undo() -> std::io::Result<()> {
+        let snapshot = concat!(
+            "dn: ou=a,dc=example\nou: a\n\n",
+            "dn: cn=foo,ou=a,dc=example\ncn: foo\nmail: a@example.com\nsn: Foo\n\n",
+            "dn: cn=bar,ou=a,dc=example\ncn: bar\n\n",
+        );
+        let changes = concat!(
+            "dn: cn=foo,ou=a,dc=example\nchangetype: modify\nadd: mail\nmail: a@example.com\nmail: b@example.com\n-\n",
+            "replace: sn\nsn: Bar\n-\ndelete: cn\ncn: foo\n-\n\n",
+            "dn: cn=bar,ou=a,dc=example\nchangetype: delete\n\n",
+            "dn: cn=baz,ou=a,dc=example\nchangetype: add\ncn: baz\n\n",
+            "dn: ou=a,dc=example\nchangetype: modrdn\nnewrdn: ou=b\ndeleteoldrdn: 1\n\n",
+            "dn: cn=baz,ou=b,dc=example\nchangetype: delete\n\n",
+        );
+        assert_eq!(undo(snapshot, changes)?, concat!(
+            "dn: cn=baz,ou=b,dc=example\nchangetype: add\ncn: baz\n\n",
+            "dn: ou=b,dc=example\nchangetype: modrdn\nnewrdn: ou=a\ndeleteoldrdn: 1\n\n",
+            "dn: cn=baz,ou=a,dc=example\nchangetype: delete\n\n",
+            "dn: cn=bar,ou=a,dc=example\nchangetype: add\ncn: bar\n\n",
+            "dn: cn=foo,ou=a,dc=example\nchangetype: modify\n",
+            "add: cn\ncn: foo\n-\nreplace: sn\nsn: Foo\n-\ndelete: mail\nmail: b@example.com\n-\n\n",
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use clap::{arg, command};
+use ltools::bulk::BulkParser;
+use ltools::compress::open_input;
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::entry::{Entry, WriteEntry};
+use std::collections::HashMap;
+use std::io::{stdout, BufWriter, Write};
+
+const DEFAULT_ATTRIBUTES: &[&str] = &["mail", "uid", "sAMAccountName"];
+
+struct Parameters {
+    attributes: Vec<String>,
+    inputs: Vec<String>, // empty means standard input
+    case_sensitive: bool,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("luniq-check")
+        .disable_colored_help(true)
+        .about("Reports values of the given attributes that appear in more than one entry, such as e-mail addresses and user names that should be unique before a migration. Each value is written on a line with the attribute, the value and the DNs of the entries it appears in, separated by tabs. Exits with status 1 if any value is repeated.")
+        .arg(arg!(-a --attribute <ATTRIBUTE> "Check the values of ATTRIBUTE instead of mail, uid and sAMAccountName. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!([FILES] ... "LDIF files to read instead of standard input ('-' means standard input)."))
+        .arg(arg!(--"case-sensitive" "Compare values exactly instead of ignoring ASCII case, as matching rules such as caseIgnoreMatch do.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let mut attributes: Vec<String> = matches.get_many::<String>("attribute")
+        .map(|attributes| attributes.cloned().collect())
+        .unwrap_or_default();
+    if attributes.is_empty() {
+        attributes = DEFAULT_ATTRIBUTES.iter().map(|attr| attr.to_string()).collect();
+    }
+
+    Ok(Parameters{
+        attributes,
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        case_sensitive: matches.get_flag("case-sensitive"),
+    })
+}
+
+struct Occurrence {
+    value: Vec<u8>, // as first spelled
+    dns: Vec<String>,
+}
+
+// Gathers the DNs of the entries each value appears in, for each attribute.
+struct UniqueCheck {
+    attributes: Vec<String>, // lowercase
+    case_sensitive: bool,
+    values: Vec<HashMap<Vec<u8>, usize>>, // index into occurrences by value, for each attribute
+    occurrences: Vec<(usize, Occurrence)>, // the attribute and the occurrence, in order of appearance
+}
+
+impl UniqueCheck {
+    fn new(attributes: &[String], case_sensitive: bool) -> UniqueCheck {
+        UniqueCheck{
+            attributes: attributes.iter().map(|attr| attr.to_ascii_lowercase()).collect(),
+            case_sensitive,
+            values: vec![HashMap::new(); attributes.len()],
+            occurrences: Vec::new(),
+        }
+    }
+
+    // The attribute, the value and the DNs of the values that appear in more than one entry.
+    fn conflicts(&self) -> impl Iterator<Item = (usize, &Occurrence)> {
+        self.occurrences.iter()
+            .filter(|(_, occurrence)| occurrence.dns.len() > 1)
+            .map(|(attr, occurrence)| (*attr, occurrence))
+    }
+}
+
+impl WriteEntry for UniqueCheck {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let Some(dn) = entry.get_one_str("dn") else {
+            return Ok(());
+        };
+        for (attr, values) in self.values.iter_mut().enumerate() {
+            for value in entry.get(&self.attributes[attr]) {
+                let key = if self.case_sensitive { value.to_vec() } else { value.to_ascii_lowercase() };
+                let index = *values.entry(key).or_insert_with(|| {
+                    self.occurrences.push((attr, Occurrence{ value: value.to_vec(), dns: Vec::new() }));
+                    self.occurrences.len() - 1
+                });
+                let dns = &mut self.occurrences[index].1.dns;
+                // a value repeated within an entry is no conflict
+                if dns.last().map(String::as_str) != Some(&dn) {
+                    dns.push(dn.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_conflicts<W: Write>(check: &UniqueCheck, attributes: &[String], dest: &mut W) -> std::io::Result<usize> {
+    let mut count: usize = 0;
+    for (attr, occurrence) in check.conflicts() {
+        write!(dest, "{}\t", attributes[attr])?;
+        dest.write_all(&occurrence.value)?;
+        for dn in occurrence.dns.iter() {
+            write!(dest, "\t{}", dn)?;
+        }
+        writeln!(dest)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// Returns the number of repeated values.
+fn get_result() -> Result<usize, Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut check = UniqueCheck::new(&params.attributes, params.case_sensitive);
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    for input in inputs.iter() {
+        open_input(input)
+            .and_then(|mut reader| BulkParser::new().write_entries(&mut reader, &mut check))
+            .map_err(|err| FileError::new(input, err))?;
+    }
+    let mut dest = BufWriter::new(stdout().lock());
+    let count = write_conflicts(&check, &params.attributes, &mut dest)?;
+    dest.flush()?;
+    Ok(count)
+}
+
+fn main() {
+    match get_result() {
+        Ok(0) => {},
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            report_error("luniq-check", &*err);
+            std::process::exit(2);
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conflicts() -> std::io::Result<()> {
+        let attributes = vec![String::from("mail"), String::from("uid")];
+        let mut check = UniqueCheck::new(&attributes, false);
+        check.write_entry(&Entry::from([("dn", b"uid=a,dc=example".as_slice()), ("uid", b"a"), ("mail", b"A@example.com"), ("mail", b"a@example.com")]))?;
+        check.write_entry(&Entry::from([("dn", b"uid=b,dc=example".as_slice()), ("uid", b"b"), ("mail", b"b@example.com")]))?;
+        check.write_entry(&Entry::from([("dn", b"uid=c,dc=example".as_slice()), ("uid", b"B"), ("mail", b"a@example.com")]))?;
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(write_conflicts(&check, &attributes, &mut output)?, 2);
+        assert_eq!(String::from_utf8_lossy(&output), concat!(
+            "mail\tA@example.com\tuid=a,dc=example\tuid=c,dc=example\n",
+            "uid\tb\tuid=b,dc=example\tuid=c,dc=example\n",
+        ));
+        let mut check = UniqueCheck::new(&attributes, true);
+        check.write_entry(&Entry::from([("dn", b"uid=b,dc=example".as_slice()), ("uid", b"b")]))?;
+        check.write_entry(&Entry::from([("dn", b"uid=c,dc=example".as_slice()), ("uid", b"B")]))?;
+        assert_eq!(write_conflicts(&check, &attributes, &mut Vec::new())?, 0);
+        Ok(())
+    }
+}
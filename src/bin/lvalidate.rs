@@ -0,0 +1,138 @@
+use clap::{arg, command, Arg};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output, FileError};
+use ltools::lint::Linter;
+use ltools::refcheck::ReferenceChecker;
+use std::fs::File;
+use std::io::{stdin, stdout, BufWriter, Read, Write};
+
+struct Parameters {
+    inputs: Vec<String>, // empty means standard input
+    json: bool,
+    max_line_length: Option<usize>,
+    orphans: bool,
+    existing: Vec<String>, // DNs assumed to exist along with their ancestors
+    missing_parents: bool,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lvalidate")
+        .disable_colored_help(true)
+        .about("Checks LDIF for structural problems, such as entries without a DN, values that should be base64-encoded and lines that should be folded.")
+        .arg(arg!([FILES] ... "LDIF files to check instead of standard input ('-' means standard input)."))
+        .arg(Arg::new("json")
+             .long("json")
+             .action(clap::ArgAction::SetTrue)
+             .help("Write each problem as a JSON object on a line of its own."))
+        .arg(arg!(--"max-line-length" <LENGTH> "Report lines longer than LENGTH bytes (default 76). 0 disables the check.")
+             .required(false))
+        .arg(arg!(--orphans "Also report entries whose parent is neither in the input, which may span several files, nor known to exist through --assume-exists.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"assume-exists" <DN> "Take the entry DN and its ancestors to exist when checking for orphans, such as the base DN of the directory the input is imported into. Can be given multiple times.")
+            .required(false)
+            .action(clap::ArgAction::Append))
+        .arg(arg!(--"missing-parents" "Instead of problems, write the DNs of the entries that are missing for every entry to have a parent, one per line and parents first, as in --orphans.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let max_line_length = match matches.get_one::<String>("max-line-length").map(|length| length.parse::<usize>()) {
+        Some(Ok(0)) => None,
+        Some(Ok(length)) => Some(length),
+        Some(Err(_)) => return Err("failed to parse maximum line length"),
+        None => Some(76),
+    };
+
+    Ok(Parameters{
+        inputs: matches.get_many::<String>("FILES")
+            .map(|files| files.cloned().collect())
+            .unwrap_or_default(),
+        json: matches.get_flag("json"),
+        max_line_length,
+        orphans: matches.get_flag("orphans"),
+        existing: matches.get_many::<String>("assume-exists")
+            .map(|dns| dns.cloned().collect())
+            .unwrap_or_default(),
+        missing_parents: matches.get_flag("missing-parents"),
+    })
+}
+
+// Returns the number of problems found.
+fn get_result() -> Result<usize, Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut linter = Linter::new();
+    linter.set_max_line_length(params.max_line_length);
+    let inputs = if params.inputs.is_empty() { vec![String::from("-")] } else { params.inputs.clone() };
+    let mut dest = BufWriter::new(stdout());
+    let mut count: usize = 0;
+    // orphans are found once every input has been read, so each input is read into memory
+    let mut checker = (params.orphans || params.missing_parents).then(ReferenceChecker::default);
+    for input in inputs.iter() {
+        let diagnostics = match checker {
+            Some(ref mut checker) => {
+                let mut buf: Vec<u8> = Vec::new();
+                let result = if input == "-" {
+                    stdin().read_to_end(&mut buf)
+                } else {
+                    File::open(input).and_then(|mut file| file.read_to_end(&mut buf))
+                };
+                result.map_err(|err| FileError::new(input, err))?;
+                // syntax errors are reported by the linter
+                _ = checker.read(&mut buf.as_slice(), Some(input.as_str()).filter(|input| *input != "-"));
+                linter.lint(&mut buf.as_slice())
+            },
+            None if input == "-" => linter.lint(&mut stdin()),
+            None => File::open(input).and_then(|mut file| linter.lint(&mut file)),
+        };
+        let diagnostics = diagnostics.map_err(|err| FileError::new(input, err))?;
+        if params.missing_parents {
+            continue;
+        }
+        count += diagnostics.len();
+        for mut diagnostic in diagnostics {
+            if input != "-" {
+                diagnostic.file = Some(input.clone());
+            }
+            if params.json {
+                diagnostic.write_json(&mut dest)?;
+            } else {
+                diagnostic.write_text(&mut dest)?;
+            }
+        }
+    }
+    if let Some(ref checker) = checker {
+        if params.missing_parents {
+            let missing = checker.missing_parents(&params.existing);
+            for dn in missing.iter() {
+                writeln!(dest, "{}", dn)?;
+            }
+            count = missing.len();
+        } else {
+            let orphans = checker.orphans(&params.existing);
+            count += orphans.len();
+            for orphan in orphans.iter() {
+                if params.json {
+                    orphan.write_json(&mut dest)?;
+                } else {
+                    orphan.write_text(&mut dest)?;
+                }
+            }
+        }
+    }
+    dest.flush()?;
+    Ok(count)
+}
+
+fn main() {
+    match get_result() {
+        Ok(0) => {},
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            report_error("lvalidate", &*err);
+            std::process::exit(2);
+        },
+    }
+}
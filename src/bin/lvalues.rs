@@ -0,0 +1,173 @@
+use clap::{arg, command};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report_error, set_json_output};
+use ltools::attrspec::AttrSpec;
+use ltools::base64::EncodeWriter;
+use ltools::bulk::BulkParser;
+use ltools::entry::{Entry, EntryValue, WriteEntry};
+use std::collections::HashMap;
+use std::io::{stdin, stdout, BufWriter, Write};
+
+struct LValues {
+    attrspecs: Vec<AttrSpec>,
+    min_count: usize,
+    percentages: bool,
+    entry_count: usize,
+    counts: Vec<HashMap<Vec<u8>, usize>>, // value counts for each attrspec
+}
+
+struct Parameters {
+    attrspecs: Vec<String>,
+    min_count: usize,
+    percentages: bool,
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lvalues")
+        .disable_colored_help(true)
+        .arg(arg!(<ATTRIBUTES> ... "The attribute type names to count values of."))
+        .arg(arg!(--"min-count" <COUNT> "Only show values that occur in at least COUNT entries.")
+            .required(false))
+        .arg(arg!(-p --percent "Show the percentage of entries each value occurs in.")
+            .action(clap::ArgAction::SetTrue))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(clap::ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let min_count = match matches.get_one::<String>("min-count").map(|count| count.parse::<usize>()) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => return Err("failed to parse minimum count"),
+        None => 1,
+    };
+
+    Ok(Parameters{
+        attrspecs: matches.get_many::<String>("ATTRIBUTES").into_iter().flatten().cloned().collect(),
+        min_count,
+        percentages: matches.get_flag("percent"),
+    })
+}
+
+impl WriteEntry for LValues {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.entry_count += 1;
+        for (attrspec, counts) in self.attrspecs.iter().zip(self.counts.iter_mut()) {
+            let mut values: Vec<EntryValue> = attrspec.entry_values(entry)?.into_owned();
+            // values are counted once for each entry they occur in
+            values.sort();
+            values.dedup();
+            for value in values {
+                match counts.get_mut(value.as_ref()) {
+                    Some(count) => *count += 1,
+                    None => {
+                        counts.insert(value.into_owned(), 1);
+                    },
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Values that can't be written as text are base64-encoded and prefixed with "::", as in LDIF.
+fn write_value<W: Write>(w: &mut W, value: &[u8]) -> std::io::Result<()> {
+    let printable = std::str::from_utf8(value)
+        .map(|value| !value.starts_with("::") && !value.chars().any(char::is_control))
+        .unwrap_or(false);
+    if printable {
+        return w.write_all(value);
+    }
+    w.write_all(b"::")?;
+    let mut base64 = EncodeWriter::new(&mut *w);
+    base64.write_all(value)?;
+    base64.flush()
+}
+
+impl LValues {
+    fn write_counts<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for (attrspec, counts) in self.attrspecs.iter().zip(self.counts.iter()) {
+            let mut counts: Vec<(&Vec<u8>, usize)> = counts.iter()
+                .map(|(value, count)| (value, *count))
+                .filter(|(_, count)| *count >= self.min_count)
+                .collect();
+            counts.sort_by(|(a_value, a_count), (b_value, b_count)| b_count.cmp(a_count).then(a_value.cmp(b_value)));
+            for (value, count) in counts {
+                if self.attrspecs.len() > 1 {
+                    write!(w, "{}\t", attrspec.attribute)?;
+                }
+                write!(w, "{}\t", count)?;
+                if self.percentages {
+                    write!(w, "{:.1}%\t", 100.0 * count as f64 / self.entry_count as f64)?;
+                }
+                write_value(w, value)?;
+                writeln!(w)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut attrspecs: Vec<AttrSpec> = Vec::new();
+    for spec in params.attrspecs.iter() {
+        attrspecs.push(AttrSpec::parse(spec)?);
+    }
+    let mut lvalues = LValues{
+        counts: attrspecs.iter().map(|_| HashMap::new()).collect(),
+        attrspecs,
+        min_count: params.min_count,
+        percentages: params.percentages,
+        entry_count: 0,
+    };
+    BulkParser::new()
+        .set_ignore_entries_without_dn(true)
+        .write_entries(&mut stdin().lock(), &mut lvalues)?;
+    let mut output = BufWriter::new(stdout().lock());
+    lvalues.write_counts(&mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lvalues", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn count(specs: &[&str], ldif: &[u8], min_count: usize, percentages: bool) -> std::io::Result<String> {
+        let attrspecs: Vec<AttrSpec> = specs.iter().map(|spec| AttrSpec::parse(spec)).collect::<std::io::Result<_>>()?;
+        let mut lvalues = LValues{
+            counts: attrspecs.iter().map(|_| HashMap::new()).collect(),
+            attrspecs,
+            min_count,
+            percentages,
+            entry_count: 0,
+        };
+        BulkParser::new()
+            .set_ignore_entries_without_dn(true)
+            .write_entries(&mut &ldif[..], &mut lvalues)?;
+        let mut output: Vec<u8> = Vec::new();
+        lvalues.write_counts(&mut output)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[test]
+    fn test_counts() -> std::io::Result<()> {
+        let fixture = include_bytes!("../../test.ldif");
+        assert_eq!(count(&["objectClass"], fixture, 1, true)?, "5\t100.0%\ttop\n3\t60.0%\tperson\n1\t20.0%\tgroupOfNames\n");
+        assert_eq!(count(&["objectClass"], fixture, 2, false)?, "5\ttop\n3\tperson\n");
+
+        // values repeated within an entry count once, entries without the attribute count for the
+        // default, and values that aren't printable are base64-encoded
+        let ldif = b"dn: cn=a\nmail: x\nmail: x\nsn:: AAE=\n\ndn: cn=b\nmail: x\n\ndn: cn=c\n";
+        assert_eq!(count(&["mail:-none", "sn"], ldif, 1, false)?, "mail\t2\tx\nmail\t1\tnone\nsn\t1\t::AAE=\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use clap::{arg, command, ArgAction};
+use ltools::bulk::parse_entries;
+use ltools::changerecord::{ModifyChangeRecord, WriteChangeRecord};
+use ltools::compress::{decompress, open_input};
+use ltools::config::with_defaults;
+use ltools::diagnostic::{report, report_error, set_json_output, Diagnostic, FileError, Severity};
+use ltools::dn::normalize;
+use ltools::store::EntryStore;
+use std::collections::HashSet;
+use std::io::{stdout, Read, Write};
+use std::process::Command;
+use std::time::Duration;
+
+struct Parameters {
+    source: String, // a file, or a shell command with command set
+    command: bool,
+    interval: Duration,
+    count: Option<usize>, // of snapshots, None means until interrupted
+}
+
+fn parse_arguments() -> Result<Parameters, &'static str> {
+    let matches = with_defaults(command!("lwatch")
+        .disable_colored_help(true)
+        .about("Reads a snapshot of LDIF entries from a file or command at an interval and writes changerecords for the changes since the previous snapshot, like lcompare.")
+        .arg(arg!(<SOURCE> "The LDIF file to re-read, or with --command, the shell command to run, such as an ldapsearch."))
+        .arg(arg!(-c --command "Run SOURCE with sh -c and read the snapshots from its standard output.")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-n --interval <SECONDS> "Wait SECONDS between snapshots.")
+            .required(false)
+            .default_value("2"))
+        .arg(arg!(--count <COUNT> "Stop after COUNT snapshots, including the first.")
+            .required(false))
+        .arg(arg!(--"diagnostics-json" "Write warnings and errors to standard error as JSON objects, one per line, with the code, message, file, line, column and DN of each.")
+            .action(ArgAction::SetTrue))
+        ).get_matches();
+
+    set_json_output(matches.get_flag("diagnostics-json"));
+
+    let interval = match matches.get_one::<String>("interval").map(|interval| interval.parse::<f64>()) {
+        Some(Ok(seconds)) if seconds > 0.0 && seconds.is_finite() => Duration::from_secs_f64(seconds),
+        Some(_) => return Err("the interval must be a positive number of seconds"),
+        None => Duration::from_secs(2),
+    };
+
+    let count = match matches.get_one::<String>("count").map(|count| count.parse::<usize>()) {
+        Some(Ok(0)) => return Err("the count must be at least 1"),
+        Some(Ok(count)) => Some(count),
+        Some(Err(_)) => return Err("failed to parse count"),
+        None => None,
+    };
+
+    Ok(Parameters{
+        source: matches.get_one::<String>("SOURCE").unwrap().clone(),
+        command: matches.get_flag("command"),
+        interval,
+        count,
+    })
+}
+
+fn read_snapshot(params: &Parameters) -> Result<EntryStore, Box<dyn std::error::Error>> {
+    let mut input: Vec<u8> = Vec::new();
+    if params.command {
+        let output = Command::new("sh").arg("-c").arg(&params.source).output()?;
+        if !output.status.success() {
+            return Err(format!("command failed with {}", output.status).into());
+        }
+        decompress(output.stdout.as_slice())?.read_to_end(&mut input)?;
+    } else {
+        open_input(&params.source)
+            .and_then(|mut reader| reader.read_to_end(&mut input))
+            .map_err(|err| FileError::new(&params.source, err))?;
+    }
+    let mut store = EntryStore::new();
+    parse_entries(&input, &mut store)?;
+    Ok(store)
+}
+
+// Writes the changerecords that turn old into new and returns how many there were. Deletes are
+// written last, and ordered child-first by being written in reverse.
+fn write_changes<W: WriteChangeRecord>(old: &EntryStore, new: &EntryStore, dest: &mut W) -> std::io::Result<usize> {
+    let mut count = 0;
+    let mut new_dns: HashSet<String> = HashSet::new();
+    for entry in new.iter() {
+        let Some(dn) = entry.get_one_str("dn") else {
+            continue;
+        };
+        new_dns.insert(normalize(&dn));
+        match old.get(&dn) {
+            Some(old_entry) => {
+                if let Some(modify) = ModifyChangeRecord::new(Some(old_entry), entry, &[], true, false) {
+                    dest.write_modify(&modify)?;
+                    count += 1;
+                }
+            },
+            None => {
+                dest.write_add(entry)?;
+                count += 1;
+            },
+        }
+    }
+    let deleted: Vec<String> = old.iter()
+        .filter_map(|entry| entry.get_one_str("dn"))
+        .filter(|dn| !new_dns.contains(&normalize(dn)))
+        .map(String::from)
+        .collect();
+    for dn in deleted.iter().rev() {
+        dest.write_delete(dn)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn get_result() -> Result<(), Box<dyn std::error::Error>> {
+    let params = parse_arguments()?;
+    let mut snapshot = read_snapshot(&params)?;
+    let mut dest = stdout();
+    let mut taken = 1;
+    while params.count.is_none_or(|count| taken < count) {
+        std::thread::sleep(params.interval);
+        taken += 1;
+        let next = match read_snapshot(&params) {
+            Ok(next) => next,
+            Err(err) => {
+                // a failed snapshot may be temporary, so the watch goes on from the last good one
+                let message = format!("failed to read snapshot {}: {}", taken, err);
+                report("lwatch", &Diagnostic::new(Severity::Warning, "snapshot-failed", message));
+                continue;
+            },
+        };
+        let mut changes: Vec<u8> = Vec::new();
+        if write_changes(&snapshot, &next, &mut changes)? != 0 {
+            writeln!(dest, "# snapshot {}", taken)?;
+            dest.write_all(&changes)?;
+            dest.flush()?;
+        }
+        snapshot = next;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = get_result() {
+        report_error("lwatch", &*err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_changes() -> std::io::Result<()> {
+        let mut old = EntryStore::new();
+        parse_entries(b"dn: ou=a\nou: a\n\ndn: cn=x,ou=a\ncn: x\n\ndn: cn=y\ncn: y\nsn: y\n", &mut old)?;
+        let mut new = EntryStore::new();
+        parse_entries(b"dn: CN=Y\ncn: y\nsn: z\n\ndn: cn=z\ncn: z\n", &mut new)?;
+        let mut changes: Vec<u8> = Vec::new();
+        assert_eq!(write_changes(&old, &new, &mut changes)?, 4);
+        assert_eq!(String::from_utf8_lossy(&changes), concat!(
+            "dn: CN=Y\nchangetype: modify\nreplace: sn\nsn: z\n-\n\n",
+            "dn: cn=z\nchangetype: add\ncn: z\n\n",
+            "dn: cn=x,ou=a\nchangetype: delete\n\n",
+            "dn: ou=a\nchangetype: delete\n\n",
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::io::{ Read, Result, ErrorKind, Write };
+use crate::crstrip::CrStripper;
+use crate::entry::{ EntryTokenWriter, OwnedEntry, ReadEntry, WriteEntry };
+use crate::lexer::{ Lexer, WriteToken };
+use crate::loc::WriteLocWrapper;
+use crate::unfold::Unfolder;
+
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Parses LDIF from readers into entries, reusing its read buffer between inputs.
+///
+/// This is the same pipeline that the ltools binaries use (CR stripping, unfolding and lexing),
+/// without any per-call setup beyond the pipeline itself.
+pub struct BulkParser {
+    buf: Vec<u8>,
+    continuation_chars: Vec<u8>,
+    ignore_entries_without_dn: bool,
+}
+
+impl Default for BulkParser {
+    fn default() -> Self {
+        BulkParser::with_capacity(DEFAULT_BUFFER_SIZE)
+    }
+}
+
+impl BulkParser {
+    pub fn new() -> BulkParser {
+        BulkParser::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> BulkParser {
+        BulkParser{
+            buf: vec![0u8; capacity.max(1)],
+            continuation_chars: vec![b' '],
+            ignore_entries_without_dn: false,
+        }
+    }
+
+    /// See [`Unfolder::set_continuation_chars`].
+    pub fn set_continuation_chars(&mut self, continuation_chars: &[u8]) -> &mut Self {
+        self.continuation_chars = continuation_chars.to_vec();
+        self
+    }
+
+    /// See [`EntryTokenWriter::set_ignore_entries_without_dn`].
+    pub fn set_ignore_entries_without_dn(&mut self, value: bool) -> &mut Self {
+        self.ignore_entries_without_dn = value;
+        self
+    }
+
+    pub fn write_tokens<R: Read + ?Sized, TR: WriteToken>(&mut self, input: &mut R, token_writer: TR) -> Result<()> {
+        let mut wrapper = pipeline(token_writer, &self.continuation_chars);
+        loop {
+            let len = match input.read(&mut self.buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            wrapper.write_all(&self.buf[..len])?;
+        }
+        wrapper.flush()
+    }
+
+    pub fn write_entries<R: Read + ?Sized, W: WriteEntry>(&mut self, input: &mut R, dest: W) -> Result<()> {
+        let mut token_writer = EntryTokenWriter::new(dest);
+        token_writer.set_ignore_entries_without_dn(self.ignore_entries_without_dn);
+        self.write_tokens(input, token_writer)
+    }
+}
+
+type EntryPipeline = WriteLocWrapper<CrStripper<Unfolder<Lexer<EntryTokenWriter<VecDeque<OwnedEntry>>>>>>;
+
+/// Parses LDIF from a reader into entries as they are asked for, for pulling entries through
+/// `ReadEntry` rather than having them pushed to a `WriteEntry`. Input is read a buffer at a time,
+/// so entries are parsed a few at a time rather than all at once.
+pub struct EntryReader<R: Read> {
+    input: R,
+    pipeline: EntryPipeline,
+    buf: Vec<u8>,
+    finished: bool,
+}
+
+impl<R: Read> EntryReader<R> {
+    pub fn new(input: R) -> EntryReader<R> {
+        EntryReader::with_parser(input, &BulkParser::new())
+    }
+
+    /// Creates a reader with the buffer size and settings of the given parser.
+    pub fn with_parser(input: R, parser: &BulkParser) -> EntryReader<R> {
+        let mut token_writer = EntryTokenWriter::new(VecDeque::new());
+        token_writer.set_ignore_entries_without_dn(parser.ignore_entries_without_dn);
+        EntryReader{
+            input,
+            pipeline: pipeline(token_writer, &parser.continuation_chars),
+            buf: vec![0u8; parser.buf.len()],
+            finished: false,
+        }
+    }
+
+    fn queue(&mut self) -> &mut VecDeque<OwnedEntry> {
+        self.pipeline.get_mut().get_mut().get_mut().get_mut().get_mut()
+    }
+}
+
+impl<R: Read> ReadEntry for EntryReader<R> {
+    fn read_entry(&mut self) -> Result<Option<OwnedEntry>> {
+        loop {
+            if let Some(entry) = self.queue().pop_front() {
+                return Ok(Some(entry));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            match self.input.read(&mut self.buf) {
+                Ok(0) => {
+                    self.finished = true;
+                    self.pipeline.flush()?;
+                },
+                Ok(len) => self.pipeline.write_all(&self.buf[..len])?,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Parses LDIF held in memory into entries.
+pub fn parse_entries<W: WriteEntry>(input: &[u8], dest: W) -> Result<()> {
+    let mut wrapper = pipeline(EntryTokenWriter::new(dest), b" ");
+    wrapper.write_all(input)?;
+    wrapper.flush()
+}
+
+pub(crate) fn pipeline<TR: WriteToken>(token_writer: TR, continuation_chars: &[u8]) -> WriteLocWrapper<CrStripper<Unfolder<Lexer<TR>>>> {
+    let lexer = Lexer::new(token_writer);
+    let mut unfolder = Unfolder::new(lexer);
+    unfolder.set_continuation_chars(continuation_chars);
+    let crstripper = CrStripper::new(unfolder);
+    WriteLocWrapper::new(crstripper)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::OwnedEntry;
+
+    const LDIF: &[u8] = b"dn: cn=foo\r\ncn: foo\r\ndescription: a\r\n  b\r\n\r\ndn: cn=bar\r\ncn:: YmFy\r\n";
+
+    #[test]
+    fn test_parse_entries() -> Result<()> {
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        parse_entries(LDIF, &mut entries)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get_one("description"), Some(b"a b".as_slice()));
+        assert_eq!(entries[1].get_one("cn"), Some(b"bar".as_slice()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_small_buffer() -> Result<()> {
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let mut parser = BulkParser::with_capacity(3);
+        parser.write_entries(&mut &LDIF[..], &mut entries)?;
+        parser.write_entries(&mut &LDIF[..], &mut entries)?;
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[2].get_one("description"), Some(b"a b".as_slice()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_reader() -> Result<()> {
+        let mut parser = BulkParser::with_capacity(3);
+        parser.set_ignore_entries_without_dn(true);
+        let input = [&b"version: 1\n\n"[..], LDIF].concat();
+        let reader = EntryReader::with_parser(&input[..], &parser);
+        let dns: Vec<String> = reader.entries()
+            .map(|entry| entry.map(|entry| entry.get_one_str("dn").unwrap_or_default().into_owned()))
+            .collect::<Result<_>>()?;
+        assert_eq!(dns, ["cn=foo", "cn=bar"]);
+
+        let mut reader = EntryReader::new(&b"dn: cn=foo\ncn:: !!!\n"[..]);
+        assert!(reader.read_entry().is_err());
+
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let count = crate::entry::copy_entries(&mut EntryReader::new(LDIF), &mut entries)?;
+        assert_eq!(count, 2);
+        assert_eq!(entries[1].get_one("cn"), Some(b"bar".as_slice()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tab_continuation() -> Result<()> {
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let mut parser = BulkParser::new();
+        parser.set_continuation_chars(b" \t");
+        parser.write_entries(&mut &b"dn: cn=foo\ncn: f\n\too\n"[..], &mut entries)?;
+        assert_eq!(entries[0].get_one("cn"), Some(b"foo".as_slice()));
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! A C ABI for parsing LDIF, enabled by the `capi` feature, for use from C or through FFI
+//! libraries such as Python's ctypes. The shared library is built by the `ltools-capi` crate in
+//! `capi/`, which `cargo build --release` builds along with the tools as
+//! `target/release/libltools.so`; the declarations are in `include/ltools.h`.
+//!
+//! A parser is created with `ltools_parse_begin`, fed input in chunks of any size with
+//! `ltools_feed`, and finished with `ltools_parse_end`, which signals the end of the input.
+//! The attribute callback is called for every value of an entry, the DN first, and the entry
+//! callback after the last value of the entry. A callback returning nonzero stops parsing.
+//!
+//! Filters are parsed with `ltools_filter_parse` and matched against entries built with
+//! `ltools_entry_new` and `ltools_entry_add_value`.
+
+use crate::bulk::pipeline;
+use crate::crstrip::CrStripper;
+use crate::entry::{Entry, EntryTokenWriter, OwnedEntry, WriteEntry};
+use crate::filter::Filter;
+use crate::lexer::Lexer;
+use crate::loc::WriteLocWrapper;
+use crate::unfold::Unfolder;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::io::{Result, Write};
+use std::ptr;
+
+pub type AttributeCallback = extern "C" fn(userdata: *mut c_void, name: *const c_char, value: *const u8, value_len: usize) -> c_int;
+pub type EntryCallback = extern "C" fn(userdata: *mut c_void) -> c_int;
+
+struct CallbackWriter {
+    on_attribute: Option<AttributeCallback>,
+    on_entry: Option<EntryCallback>,
+    userdata: *mut c_void,
+    name: Vec<u8>, // NUL-terminated name of the current attribute
+}
+
+impl WriteEntry for CallbackWriter {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        if let Some(on_attribute) = self.on_attribute {
+            for attr in entry.attributes() {
+                self.name.clear();
+                self.name.extend_from_slice(attr.name.as_bytes());
+                self.name.push(0);
+                for value in entry.get(attr.lowercase) {
+                    if on_attribute(self.userdata, self.name.as_ptr() as *const c_char, value.as_ptr(), value.len()) != 0 {
+                        return Err(std::io::Error::other("stopped by attribute callback"));
+                    }
+                }
+            }
+        }
+        if let Some(on_entry) = self.on_entry {
+            if on_entry(self.userdata) != 0 {
+                return Err(std::io::Error::other("stopped by entry callback"));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Parser {
+    pipeline: WriteLocWrapper<CrStripper<Unfolder<Lexer<EntryTokenWriter<CallbackWriter>>>>>,
+    error: Option<CString>,
+}
+
+impl Parser {
+    fn result(&mut self, result: Result<()>) -> c_int {
+        match result {
+            Ok(()) => 0,
+            Err(err) => {
+                let message = err.to_string().replace('\0', "");
+                self.error = CString::new(message).ok();
+                -1
+            },
+        }
+    }
+}
+
+/// Creates a parser that calls the given callbacks, either of which may be NULL, with userdata
+/// as the first argument.
+#[no_mangle]
+pub extern "C" fn ltools_parse_begin(on_attribute: Option<AttributeCallback>, on_entry: Option<EntryCallback>, userdata: *mut c_void) -> *mut Parser {
+    let writer = CallbackWriter{
+        on_attribute,
+        on_entry,
+        userdata,
+        name: Vec::new(),
+    };
+    let parser = Parser{
+        pipeline: pipeline(EntryTokenWriter::new(writer), b" "),
+        error: None,
+    };
+    Box::into_raw(Box::new(parser))
+}
+
+/// Parses the next len bytes of input, calling the callbacks for the entries they complete.
+/// Returns 0 on success and -1 on error, after which the parser should not be fed more input.
+///
+/// # Safety
+///
+/// parser must come from `ltools_parse_begin` and not have been freed, and buf must point to
+/// len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_feed(parser: *mut Parser, buf: *const u8, len: usize) -> c_int {
+    let parser = &mut *parser;
+    if len == 0 {
+        return 0;
+    }
+    let buf = std::slice::from_raw_parts(buf, len);
+    let result = parser.pipeline.write_all(buf);
+    parser.result(result)
+}
+
+/// Signals the end of the input, calling the callbacks for the last entry. Returns 0 on success
+/// and -1 on error.
+///
+/// # Safety
+///
+/// parser must come from `ltools_parse_begin` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_parse_end(parser: *mut Parser) -> c_int {
+    let parser = &mut *parser;
+    let result = parser.pipeline.flush();
+    parser.result(result)
+}
+
+/// Returns the message of the last error, or NULL if there has been none. The message is valid
+/// until the next call with the parser.
+///
+/// # Safety
+///
+/// parser must come from `ltools_parse_begin` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_error(parser: *const Parser) -> *const c_char {
+    match (*parser).error {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Frees the parser. Passing NULL does nothing.
+///
+/// # Safety
+///
+/// parser must come from `ltools_parse_begin` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_free(parser: *mut Parser) {
+    if !parser.is_null() {
+        drop(Box::from_raw(parser));
+    }
+}
+
+/// Parses a filter such as `(&(objectClass=person)(mail=*))`. Returns NULL if the filter is
+/// invalid.
+///
+/// # Safety
+///
+/// text must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_filter_parse(text: *const c_char) -> *mut Filter {
+    let filter = CStr::from_ptr(text).to_str().ok()
+        .and_then(|text| Filter::parse(text).ok());
+    match filter {
+        Some(filter) => Box::into_raw(Box::new(filter)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Returns 1 if the entry matches the filter and 0 otherwise.
+///
+/// # Safety
+///
+/// filter and entry must come from `ltools_filter_parse` and `ltools_entry_new` and not have
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_filter_match(filter: *const Filter, entry: *const OwnedEntry) -> c_int {
+    (*filter).is_match(&*entry) as c_int
+}
+
+/// Frees the filter. Passing NULL does nothing.
+///
+/// # Safety
+///
+/// filter must come from `ltools_filter_parse` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_filter_free(filter: *mut Filter) {
+    if !filter.is_null() {
+        drop(Box::from_raw(filter));
+    }
+}
+
+/// Creates an empty entry, for matching against filters.
+#[no_mangle]
+pub extern "C" fn ltools_entry_new() -> *mut OwnedEntry {
+    Box::into_raw(Box::new(OwnedEntry::default()))
+}
+
+/// Appends a value to the given attribute of the entry, adding the attribute if the entry lacks
+/// it.
+///
+/// # Safety
+///
+/// entry must come from `ltools_entry_new` and not have been freed, name must be a
+/// NUL-terminated string and value must point to value_len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_entry_add_value(entry: *mut OwnedEntry, name: *const c_char, value: *const u8, value_len: usize) {
+    let name = CStr::from_ptr(name).to_string_lossy();
+    let value = if value_len == 0 { &[] } else { std::slice::from_raw_parts(value, value_len) };
+    (*entry).add_value(&name, value);
+}
+
+/// Frees the entry. Passing NULL does nothing.
+///
+/// # Safety
+///
+/// entry must come from `ltools_entry_new` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn ltools_entry_free(entry: *mut OwnedEntry) {
+    if !entry.is_null() {
+        drop(Box::from_raw(entry));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CStr;
+
+    extern "C" fn on_attribute(userdata: *mut c_void, name: *const c_char, value: *const u8, value_len: usize) -> c_int {
+        let lines = unsafe { &mut *(userdata as *mut Vec<String>) };
+        let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+        let value = unsafe { std::slice::from_raw_parts(value, value_len) };
+        lines.push(format!("{}: {}", name, String::from_utf8_lossy(value)));
+        0
+    }
+
+    extern "C" fn on_entry(userdata: *mut c_void) -> c_int {
+        let lines = unsafe { &mut *(userdata as *mut Vec<String>) };
+        lines.push(String::new());
+        0
+    }
+
+    #[test]
+    fn test_a() {
+        let mut lines: Vec<String> = Vec::new();
+        let userdata = &mut lines as *mut Vec<String> as *mut c_void;
+        unsafe {
+            let parser = ltools_parse_begin(Some(on_attribute), Some(on_entry), userdata);
+            let input = b"dn: cn=foo\ncn: f\n oo\n\ndn: cn=bar\ncn:: YmFy\n";
+            assert_eq!(ltools_feed(parser, input.as_ptr(), 15), 0);
+            assert_eq!(ltools_feed(parser, input[15..].as_ptr(), input.len() - 15), 0);
+            assert_eq!(ltools_parse_end(parser), 0);
+            assert!(ltools_error(parser).is_null());
+            ltools_free(parser);
+        }
+        assert_eq!(lines, ["dn: cn=foo", "cn: foo", "", "dn: cn=bar", "cn: bar", ""]);
+    }
+
+    #[test]
+    fn test_error() {
+        unsafe {
+            let parser = ltools_parse_begin(None, None, ptr::null_mut());
+            let input = b"dn: cn=foo\n;cn: foo\n";
+            assert_eq!(ltools_feed(parser, input.as_ptr(), input.len()), -1);
+            let message = CStr::from_ptr(ltools_error(parser)).to_string_lossy();
+            assert!(message.starts_with("unexpected"), "{}", message);
+            ltools_free(parser);
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        unsafe {
+            assert!(ltools_filter_parse(c"(cn=foo".as_ptr()).is_null());
+            let filter = ltools_filter_parse(c"(&(objectClass=person)(cn=f*))".as_ptr());
+            let entry = ltools_entry_new();
+            ltools_entry_add_value(entry, c"cn".as_ptr(), b"foo".as_ptr(), 3);
+            assert_eq!(ltools_filter_match(filter, entry), 0);
+            ltools_entry_add_value(entry, c"objectClass".as_ptr(), b"person".as_ptr(), 6);
+            assert_eq!(ltools_filter_match(filter, entry), 1);
+            ltools_entry_free(entry);
+            ltools_filter_free(filter);
+        }
+    }
+}
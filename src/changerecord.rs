@@ -0,0 +1,668 @@
+use crate::base64::DecodeState;
+use crate::dn::avas;
+use crate::entry::{write_attrval, write_attrval_with_policy, Entry, EntryTokenWriter, OwnedEntry, WriteEntry};
+use crate::ldif::Base64Policy;
+use crate::lexer::{Token, TokenKind, WriteToken};
+use crate::loc::{base64_error, Loc};
+use crate::normalize::Normalizers;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::io::{Error, ErrorKind, Result, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifyChangeRecordOpType {
+    Add,
+    Delete,
+    Replace,
+}
+
+/// An operation of a modify change record, such as `add: mail` followed by the values to add.
+pub struct ModifyChangeRecordOp<'a> {
+    pub typ: ModifyChangeRecordOpType,
+    pub attr: String,
+    pub values: Vec<&'a [u8]>,
+}
+
+/// How `ModifyChangeRecord::with_options` compares the values of attributes.
+#[derive(Debug, Clone, Default)]
+pub struct ModifyOptions {
+    /// Replace each attribute with differences with its complete new set of values rather than
+    /// adding and deleting the values that differ.
+    pub replace_all: bool,
+    /// Attributes, in lowercase, whose values are compared as ordered lists rather than as sets,
+    /// so that values that are only reordered are replaced.
+    pub ordered: Vec<String>,
+    /// Normalizes values before comparing them, so that values that only differ in ways that the
+    /// directory ignores, such as case, are taken to be equal. The new values are written as
+    /// they are.
+    pub normalizers: Normalizers,
+}
+
+/// A modify change record, with values borrowed from the entries it was computed from.
+pub struct ModifyChangeRecord<'a> {
+    pub dn: String,
+    pub ops: Vec<ModifyChangeRecordOp<'a>>,
+}
+
+impl<'a> ModifyChangeRecord<'a> {
+    /// Computes the modifications that turn old into new, or that add the attributes of new if
+    /// there is no old entry. Only the attributes in attrs are compared, or with invert, every
+    /// attribute except for those in attrs, which should be lowercase. With replace_all, each
+    /// attribute with differences is replaced with its complete new set of values rather than
+    /// having the differing values added and deleted.
+    ///
+    /// Returns None if there are no differences.
+    pub fn new(
+        old: Option<&'a Entry<'_>>,
+        new: &'a Entry<'_>,
+        attrs: &[String],
+        invert: bool,
+        replace_all: bool,
+    ) -> Option<ModifyChangeRecord<'a>> {
+        let options = ModifyOptions{ replace_all, ..ModifyOptions::default() };
+        ModifyChangeRecord::with_options(old, new, attrs, invert, &options)
+    }
+
+    /// Computes the modifications as `new` does, comparing values according to options.
+    pub fn with_options(
+        old: Option<&'a Entry<'_>>,
+        new: &'a Entry<'_>,
+        attrs: &[String],
+        invert: bool,
+        options: &ModifyOptions,
+    ) -> Option<ModifyChangeRecord<'a>> {
+        let replace_all = options.replace_all;
+        let dn = new.get_one_str("dn")?;
+        let mut modify = ModifyChangeRecord{
+            dn: dn.into_owned(),
+            ops: Vec::new(),
+        };
+
+        let is_compared = |lattr: &&str| *lattr != "dn" && invert != attrs.iter().any(|arg_attr| arg_attr == *lattr);
+        let old_attrs: BTreeSet<&str> = match old {
+            Some(old) => old.attributes()
+                .map(|attr| attr.lowercase)
+                .filter(is_compared)
+                .collect(),
+            None => BTreeSet::new(),
+        };
+        let new_attrs: BTreeSet<&str> = new.attributes()
+            .map(|attr| attr.lowercase)
+            .filter(is_compared)
+            .collect();
+
+        for attr in old_attrs.union(&new_attrs).copied() {
+            let old = old.filter(|_| old_attrs.contains(attr));
+            let is_new = new_attrs.contains(attr);
+            match old {
+                None => {
+                    let op = ModifyChangeRecordOp{
+                        typ: if replace_all { ModifyChangeRecordOpType::Replace } else { ModifyChangeRecordOpType::Add },
+                        attr: attr.to_string(),
+                        values: new.get(attr).collect(),
+                    };
+                    if !op.values.is_empty() {
+                        modify.ops.push(op);
+                    }
+                },
+                Some(old) if !is_new => {
+                    let op = ModifyChangeRecordOp{
+                        typ: ModifyChangeRecordOpType::Delete,
+                        attr: attr.to_string(),
+                        values: old.get(attr).collect(),
+                    };
+                    if op.values.is_empty() {
+                        continue;
+                    }
+                    if replace_all {
+                        // replacing with no values removes the attribute
+                        modify.ops.push(ModifyChangeRecordOp{
+                            typ: ModifyChangeRecordOpType::Replace,
+                            attr: op.attr,
+                            values: Vec::new(),
+                        });
+                    } else {
+                        modify.ops.push(op);
+                    }
+                },
+                Some(old) if options.ordered.iter().any(|ordered| ordered == attr) => {
+                    let normalize = |value| options.normalizers.normalize(attr, value);
+                    if !old.get(attr).map(normalize).eq(new.get(attr).map(normalize)) {
+                        modify.ops.push(ModifyChangeRecordOp{
+                            typ: ModifyChangeRecordOpType::Replace,
+                            attr: attr.to_string(),
+                            values: new.get(attr).collect(),
+                        });
+                    }
+                },
+                Some(old) => modify.push_differences(attr, old, new, options),
+            }
+        }
+        if modify.ops.is_empty() {
+            None
+        } else {
+            Some(modify)
+        }
+    }
+
+    fn push_differences(&mut self, attr: &str, old: &'a Entry<'_>, new: &'a Entry<'_>, options: &ModifyOptions) {
+        let normalized = |entry: &'a Entry<'_>| -> Vec<Cow<'a, [u8]>> {
+            entry.get(attr).map(|value| options.normalizers.normalize(attr, value)).collect()
+        };
+        let (old_normalized, new_normalized) = (normalized(old), normalized(new));
+        let del_values: Vec<&[u8]> = old.get(attr)
+            .zip(old_normalized.iter())
+            .filter(|(_, old_value)| !new_normalized.contains(old_value))
+            .map(|(value, _)| value)
+            .collect();
+        let add_values: Vec<&[u8]> = new.get(attr)
+            .zip(new_normalized.iter())
+            .filter(|(_, new_value)| !old_normalized.contains(new_value))
+            .map(|(value, _)| value)
+            .collect();
+        if options.replace_all {
+            if !add_values.is_empty() || !del_values.is_empty() {
+                self.ops.push(ModifyChangeRecordOp{
+                    typ: ModifyChangeRecordOpType::Replace,
+                    attr: attr.to_string(),
+                    values: new.get(attr).collect(),
+                });
+            }
+        } else if add_values.len() == 1 && del_values.len() == 1 && new.get(attr).count() == 1 {
+            // at least on eDirectory, replace works better on single-valued attributes
+            self.ops.push(ModifyChangeRecordOp{
+                typ: ModifyChangeRecordOpType::Replace,
+                attr: attr.to_string(),
+                values: add_values,
+            });
+        } else {
+            if !del_values.is_empty() {
+                self.ops.push(ModifyChangeRecordOp{
+                    typ: ModifyChangeRecordOpType::Delete,
+                    attr: attr.to_string(),
+                    values: del_values,
+                });
+            }
+            if !add_values.is_empty() {
+                self.ops.push(ModifyChangeRecordOp{
+                    typ: ModifyChangeRecordOpType::Add,
+                    attr: attr.to_string(),
+                    values: add_values,
+                });
+            }
+        }
+    }
+}
+
+/// A modrdn change record, which renames an entry or moves it to a new parent.
+pub struct ModRdnChangeRecord<'a> {
+    pub dn: &'a str,
+    pub new_rdn: &'a str,
+    pub delete_old_rdn: bool,
+    pub new_superior: Option<&'a str>,
+}
+
+/// A destination for change records. Any `Write` receives them as LDIF.
+pub trait WriteChangeRecord {
+    /// Writes an add change record with the attributes of the entry.
+    fn write_add(&mut self, entry: &Entry) -> Result<()>;
+    fn write_delete(&mut self, dn: &str) -> Result<()>;
+    fn write_modify(&mut self, modify: &ModifyChangeRecord) -> Result<()>;
+    fn write_modrdn(&mut self, modrdn: &ModRdnChangeRecord) -> Result<()>;
+}
+
+impl<W: Write> WriteChangeRecord for W {
+    fn write_add(&mut self, entry: &Entry) -> Result<()> {
+        LdifChangeRecordWriter::new(self).write_add(entry)
+    }
+
+    fn write_delete(&mut self, dn: &str) -> Result<()> {
+        LdifChangeRecordWriter::new(self).write_delete(dn)
+    }
+
+    fn write_modify(&mut self, modify: &ModifyChangeRecord) -> Result<()> {
+        LdifChangeRecordWriter::new(self).write_modify(modify)
+    }
+
+    fn write_modrdn(&mut self, modrdn: &ModRdnChangeRecord) -> Result<()> {
+        LdifChangeRecordWriter::new(self).write_modrdn(modrdn)
+    }
+}
+
+/// Writes change records as LDIF, like any `Write` does, except that a base64 policy can be set
+/// to decide which values are base64-encoded.
+pub struct LdifChangeRecordWriter<W: Write> {
+    dest: W,
+    base64_policy: Option<Base64Policy>,
+}
+
+impl<W: Write> LdifChangeRecordWriter<W> {
+    pub fn new(dest: W) -> LdifChangeRecordWriter<W> {
+        LdifChangeRecordWriter{ dest, base64_policy: None }
+    }
+
+    pub fn set_base64_policy(&mut self, base64_policy: Base64Policy) -> &mut Self {
+        self.base64_policy = Some(base64_policy);
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+
+    fn write_attrval(&mut self, attr: &str, value: &[u8]) -> Result<()> {
+        match self.base64_policy {
+            Some(ref policy) => write_attrval_with_policy(&mut self.dest, attr, value, policy),
+            None => write_attrval(&mut self.dest, attr, value),
+        }
+    }
+}
+
+impl<W: Write> WriteChangeRecord for LdifChangeRecordWriter<W> {
+    fn write_add(&mut self, entry: &Entry) -> Result<()> {
+        let dn = match entry.get_one("dn") {
+            Some(dn) => dn,
+            None => return Ok(()),
+        };
+        self.write_attrval("dn", dn)?;
+        writeln!(self.dest, "changetype: add")?;
+        for attr in entry.attributes().filter(|attr| attr.lowercase != "dn") {
+            for value in entry.get(attr.lowercase) {
+                self.write_attrval(attr.name, value)?;
+            }
+        }
+        writeln!(self.dest)
+    }
+
+    fn write_delete(&mut self, dn: &str) -> Result<()> {
+        self.write_attrval("dn", dn.as_bytes())?;
+        writeln!(self.dest, "changetype: delete")?;
+        writeln!(self.dest)
+    }
+
+    fn write_modify(&mut self, modify: &ModifyChangeRecord) -> Result<()> {
+        self.write_attrval("dn", modify.dn.as_bytes())?;
+        writeln!(self.dest, "changetype: modify")?;
+        for op in modify.ops.iter() {
+            match op.typ {
+                ModifyChangeRecordOpType::Add => writeln!(self.dest, "add: {}", op.attr)?,
+                ModifyChangeRecordOpType::Delete => writeln!(self.dest, "delete: {}", op.attr)?,
+                ModifyChangeRecordOpType::Replace => writeln!(self.dest, "replace: {}", op.attr)?,
+            }
+            for value in op.values.iter() {
+                self.write_attrval(&op.attr, value)?;
+            }
+            writeln!(self.dest, "-")?;
+        }
+        writeln!(self.dest)
+    }
+
+    fn write_modrdn(&mut self, modrdn: &ModRdnChangeRecord) -> Result<()> {
+        self.write_attrval("dn", modrdn.dn.as_bytes())?;
+        writeln!(self.dest, "changetype: modrdn")?;
+        self.write_attrval("newrdn", modrdn.new_rdn.as_bytes())?;
+        writeln!(self.dest, "deleteoldrdn: {}", if modrdn.delete_old_rdn { 1 } else { 0 })?;
+        if let Some(new_superior) = modrdn.new_superior {
+            self.write_attrval("newsuperior", new_superior.as_bytes())?;
+        }
+        writeln!(self.dest)
+    }
+}
+
+/// A change record as read from LDIF, with the lines that follow the changetype kept in order,
+/// including the "-" lines that end the operations of a modify, which have empty values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeRecord {
+    pub dn: String,
+    pub changetype: String,
+    pub lines: Vec<(String, Vec<u8>)>,
+}
+
+impl ChangeRecord {
+    /// Tells whether the record has the given changetype, with moddn and modrdn taken as the
+    /// same.
+    pub fn is_changetype(&self, changetype: &str) -> bool {
+        let is_moddn = |changetype: &str| changetype.eq_ignore_ascii_case("modrdn") || changetype.eq_ignore_ascii_case("moddn");
+        self.changetype.eq_ignore_ascii_case(changetype) || (is_moddn(&self.changetype) && is_moddn(changetype))
+    }
+
+    /// Returns the value of the first line of the record with the given attribute.
+    pub fn get_one(&self, attr: &str) -> Option<&[u8]> {
+        self.lines.iter()
+            .find(|(line_attr, _)| line_attr.eq_ignore_ascii_case(attr))
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Returns the operations of a modify record.
+    pub fn modify(&self) -> Result<ModifyChangeRecord<'_>> {
+        let mut modify = ModifyChangeRecord{
+            dn: self.dn.clone(),
+            ops: Vec::new(),
+        };
+        let mut in_op = false;
+        for (attr, value) in self.lines.iter() {
+            if attr == "-" {
+                in_op = false;
+            } else if in_op {
+                if let Some(op) = modify.ops.last_mut() {
+                    op.values.push(value);
+                }
+            } else {
+                let typ = match attr.to_ascii_lowercase().as_str() {
+                    "add" => ModifyChangeRecordOpType::Add,
+                    "delete" => ModifyChangeRecordOpType::Delete,
+                    "replace" => ModifyChangeRecordOpType::Replace,
+                    _ => return Err(Error::new(ErrorKind::InvalidData, format!("unexpected {} in the modify of {}", attr, self.dn))),
+                };
+                modify.ops.push(ModifyChangeRecordOp{
+                    typ,
+                    attr: String::from_utf8_lossy(value).into_owned(),
+                    values: Vec::new(),
+                });
+                in_op = true;
+            }
+        }
+        Ok(modify)
+    }
+
+    /// Returns the lowercase names of the attributes that the record changes: every attribute
+    /// of an add, the attributes of the operations of a modify and the attributes of the new RDN
+    /// of a modrdn. A delete changes no attribute in particular.
+    pub fn touched_attributes(&self) -> BTreeSet<String> {
+        let mut touched: BTreeSet<String> = BTreeSet::new();
+        if self.is_changetype("add") {
+            touched.extend(self.lines.iter().map(|(attr, _)| attr.to_ascii_lowercase()));
+        } else if self.is_changetype("modify") {
+            if let Ok(modify) = self.modify() {
+                touched.extend(modify.ops.iter().map(|op| op.attr.to_ascii_lowercase()));
+            }
+        } else if self.is_changetype("modrdn") {
+            for (_, value) in self.lines.iter().filter(|(attr, _)| attr.eq_ignore_ascii_case("newrdn")) {
+                let new_rdn = String::from_utf8_lossy(value);
+                touched.extend(avas(&new_rdn).map(|(attr, _)| attr.trim().to_ascii_lowercase()));
+            }
+        }
+        touched
+    }
+
+    /// Returns the DN, the changetype and the other lines of the record as an entry, for
+    /// matching against search filters.
+    pub fn as_entry(&self) -> OwnedEntry {
+        let mut entry = OwnedEntry::default();
+        entry.add_value("dn", self.dn.as_bytes());
+        entry.add_value("changetype", self.changetype.as_bytes());
+        for (attr, value) in self.lines.iter().filter(|(attr, _)| attr != "-") {
+            entry.add_value(attr, value);
+        }
+        entry
+    }
+
+    /// Writes the record as LDIF, with its lines in the order they were read.
+    pub fn write_ldif<W: Write>(&self, w: &mut W) -> Result<()> {
+        write_attrval(w, "dn", self.dn.as_bytes())?;
+        writeln!(w, "changetype: {}", self.changetype)?;
+        for (attr, value) in self.lines.iter() {
+            if attr == "-" {
+                writeln!(w, "-")?;
+            } else {
+                write_attrval(w, attr, value)?;
+            }
+        }
+        writeln!(w)
+    }
+}
+
+/// A destination for LDIF input that may have both entries and change records.
+pub trait WriteRecord: WriteEntry {
+    fn write_change_record(&mut self, record: &ChangeRecord) -> Result<()>;
+}
+
+impl<W: WriteRecord> WriteRecord for &mut W {
+    fn write_change_record(&mut self, record: &ChangeRecord) -> Result<()> {
+        (*self).write_change_record(record)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum RecordState {
+    // the DN line, which is held back until the next line tells whether this is a change record
+    Start,
+    Version,
+    Entry,
+    Change,
+}
+
+/// Passes the records of LDIF that have a changetype to the destination as change records, and
+/// the others on to an `EntryTokenWriter` for the same destination.
+pub struct ChangeRecordTokenWriter<W: WriteRecord> {
+    entries: EntryTokenWriter<W>,
+    state: RecordState,
+    held: Vec<(TokenKind, Loc, Loc, String)>, // tokens of the DN line
+    lines: Vec<(String, Vec<u8>)>,
+    b64state: DecodeState,
+}
+
+impl<W: WriteRecord> ChangeRecordTokenWriter<W> {
+    pub fn new(dest: W) -> ChangeRecordTokenWriter<W> {
+        ChangeRecordTokenWriter{
+            entries: EntryTokenWriter::new(dest),
+            state: RecordState::Start,
+            held: Vec::new(),
+            lines: Vec::new(),
+            b64state: DecodeState::default(),
+        }
+    }
+
+    fn release_held(&mut self) -> Result<()> {
+        for (kind, loc, logical_loc, segment) in std::mem::take(&mut self.held) {
+            self.entries.write_token(Token{ kind, loc, logical_loc, segment: &segment })?;
+        }
+        self.lines.clear();
+        Ok(())
+    }
+
+    fn add_to_line(&mut self, token: &Token) -> Result<()> {
+        match token.kind {
+            TokenKind::AttributeType => self.lines.push((token.segment.to_string(), Vec::new())),
+            TokenKind::ValueText => if let Some((_, value)) = self.lines.last_mut() {
+                value.extend_from_slice(token.segment.as_bytes());
+            },
+            TokenKind::ValueBase64 => if let Some((_, value)) = self.lines.last_mut() {
+                self.b64state.decode(token.segment.as_bytes(), value).map_err(|err| base64_error(token.loc, err))?;
+            },
+            TokenKind::ValueFinish => self.b64state = DecodeState::default(),
+            TokenKind::EntryFinish => {},
+        }
+        Ok(())
+    }
+}
+
+impl<W: WriteRecord> WriteToken for ChangeRecordTokenWriter<W> {
+    fn write_token(&mut self, token: Token) -> Result<()> {
+        match self.state {
+            RecordState::Start => match token.kind {
+                TokenKind::AttributeType if self.held.is_empty() && token.segment.eq_ignore_ascii_case("version") => {
+                    self.state = RecordState::Version;
+                    self.entries.write_token(token)?;
+                },
+                TokenKind::AttributeType if self.held.is_empty() && !token.segment.eq_ignore_ascii_case("dn") => {
+                    self.state = RecordState::Entry;
+                    self.entries.write_token(token)?;
+                },
+                TokenKind::AttributeType if !self.held.is_empty() => {
+                    if token.segment.eq_ignore_ascii_case("changetype") {
+                        self.held.clear();
+                        self.add_to_line(&token)?;
+                        self.state = RecordState::Change;
+                    } else {
+                        self.release_held()?;
+                        self.state = RecordState::Entry;
+                        self.entries.write_token(token)?;
+                    }
+                },
+                TokenKind::EntryFinish => {
+                    self.release_held()?;
+                    self.entries.write_token(token)?;
+                },
+                kind => {
+                    self.add_to_line(&token)?;
+                    self.held.push((kind, token.loc, token.logical_loc, token.segment.to_string()));
+                },
+            },
+            // the version line may be followed directly by the first record
+            RecordState::Version => {
+                if token.kind == TokenKind::ValueFinish {
+                    self.state = RecordState::Start;
+                }
+                self.entries.write_token(token)?;
+            },
+            RecordState::Entry => {
+                if token.kind == TokenKind::EntryFinish {
+                    self.state = RecordState::Start;
+                }
+                self.entries.write_token(token)?;
+            },
+            RecordState::Change if token.kind == TokenKind::EntryFinish => {
+                let mut lines = std::mem::take(&mut self.lines).into_iter();
+                let dn = lines.next().map(|(_, dn)| String::from_utf8_lossy(&dn).into_owned()).unwrap_or_default();
+                let changetype = lines.next().map(|(_, changetype)| String::from_utf8_lossy(&changetype).into_owned()).unwrap_or_default();
+                self.entries.get_mut().write_change_record(&ChangeRecord{
+                    dn,
+                    changetype,
+                    lines: lines.collect(),
+                })?;
+                self.state = RecordState::Start;
+            },
+            RecordState::Change => self.add_to_line(&token)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_modify() -> Result<()> {
+        let old = Entry::from([("dn", b"cn=foo".as_slice()), ("mail", b"a@example.com"), ("mail", b"b@example.com"), ("sn", b"Foo"), ("l", b"Oslo")]);
+        let new = Entry::from([("dn", b"cn=foo".as_slice()), ("mail", b"a@example.com"), ("mail", b"c@example.com"), ("sn", b"Bar"), ("title", b"Boss")]);
+        let mut buf: Vec<u8> = Vec::new();
+        if let Some(modify) = ModifyChangeRecord::new(Some(&old), &new, &[], true, false) {
+            buf.write_modify(&modify)?;
+        }
+        assert_eq!(String::from_utf8_lossy(&buf), concat!(
+            "dn: cn=foo\nchangetype: modify\n",
+            "delete: l\nl: Oslo\n-\n",
+            "delete: mail\nmail: b@example.com\n-\n",
+            "add: mail\nmail: c@example.com\n-\n",
+            "replace: sn\nsn: Bar\n-\n",
+            "add: title\ntitle: Boss\n-\n\n",
+        ));
+        assert!(ModifyChangeRecord::new(Some(&old), &old, &[], true, false).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_all() -> Result<()> {
+        let old = Entry::from([("dn", b"cn=foo".as_slice()), ("mail", b"a@example.com"), ("mail", b"b@example.com"), ("sn", b"Foo"), ("l", b"Oslo")]);
+        let new = Entry::from([("dn", b"cn=foo".as_slice()), ("mail", b"a@example.com"), ("mail", b"c@example.com"), ("sn", b"Foo"), ("title", b"Boss")]);
+        let mut buf: Vec<u8> = Vec::new();
+        if let Some(modify) = ModifyChangeRecord::new(Some(&old), &new, &[], true, true) {
+            buf.write_modify(&modify)?;
+        }
+        assert_eq!(String::from_utf8_lossy(&buf), "dn: cn=foo\nchangetype: modify\nreplace: l\n-\nreplace: mail\nmail: a@example.com\nmail: c@example.com\n-\nreplace: title\ntitle: Boss\n-\n\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordered() -> Result<()> {
+        let old = Entry::from([("dn", b"cn=foo".as_slice()), ("olcAccess", b"{0}self"), ("olcAccess", b"{1}read"), ("mail", b"a"), ("mail", b"b")]);
+        let new = Entry::from([("dn", b"cn=foo".as_slice()), ("olcAccess", b"{1}read"), ("olcAccess", b"{0}self"), ("mail", b"b"), ("mail", b"a")]);
+        assert!(ModifyChangeRecord::new(Some(&old), &new, &[], true, false).is_none());
+        let options = ModifyOptions{ ordered: vec![String::from("olcaccess")], ..ModifyOptions::default() };
+        let mut buf: Vec<u8> = Vec::new();
+        if let Some(modify) = ModifyChangeRecord::with_options(Some(&old), &new, &[], true, &options) {
+            buf.write_modify(&modify)?;
+        }
+        assert_eq!(String::from_utf8_lossy(&buf), "dn: cn=foo\nchangetype: modify\nreplace: olcaccess\nolcaccess: {1}read\nolcaccess: {0}self\n-\n\n");
+        assert!(ModifyChangeRecord::with_options(Some(&old), &old, &[], true, &options).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalizers() -> Result<()> {
+        let old = Entry::from([("dn", b"cn=foo".as_slice()), ("manager", b"CN=Boss, DC=example"), ("mail", b"A@example.com"), ("mail", b"b@example.com")]);
+        let new = Entry::from([("dn", b"cn=foo".as_slice()), ("manager", b"cn=boss,dc=example"), ("mail", b"a@example.com"), ("mail", b"c@example.com")]);
+        let mut options = ModifyOptions::default();
+        options.normalizers.parse_and_add("manager=dn").and_then(|normalizers| normalizers.parse_and_add("mail=case"))
+            .map_err(Error::other)?;
+        let mut buf: Vec<u8> = Vec::new();
+        if let Some(modify) = ModifyChangeRecord::with_options(Some(&old), &new, &[], true, &options) {
+            buf.write_modify(&modify)?;
+        }
+        assert_eq!(String::from_utf8_lossy(&buf), "dn: cn=foo\nchangetype: modify\ndelete: mail\nmail: b@example.com\n-\nadd: mail\nmail: c@example.com\n-\n\n");
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct Records {
+        entries: Vec<OwnedEntry>,
+        changes: Vec<ChangeRecord>,
+    }
+
+    impl WriteEntry for Records {
+        fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+            self.entries.push(entry.into());
+            Ok(())
+        }
+    }
+
+    impl WriteRecord for Records {
+        fn write_change_record(&mut self, record: &ChangeRecord) -> Result<()> {
+            self.changes.push(record.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_change_record_token_writer() -> Result<()> {
+        let ldif = concat!(
+            "version: 1\ndn: cn=foo\nchangetype: modify\nadd: mail\nmail:: YUBleGFtcGxlLmNvbQ==\n-\ndelete: l\n-\n\n",
+            "dn: cn=bar\ncn: bar\n\n",
+            "dn: cn=baz\nchangetype: moddn\nnewrdn: uid=baz+cn=baz\ndeleteoldrdn: 1\n\n",
+            "dn: cn=qux\n",
+        );
+        let mut records = Records::default();
+        crate::bulk::BulkParser::with_capacity(5).write_tokens(&mut ldif.as_bytes(), ChangeRecordTokenWriter::new(&mut records))?;
+        assert_eq!(records.entries.len(), 2);
+        assert_eq!(records.entries[0].get_one_str("cn").as_deref(), Some("bar"));
+        assert_eq!(records.entries[1].get_one_str("dn").as_deref(), Some("cn=qux"));
+        assert_eq!(records.changes.len(), 2);
+        assert_eq!(records.changes[0].touched_attributes().into_iter().collect::<Vec<_>>(), ["l", "mail"]);
+        assert!(records.changes[1].is_changetype("modrdn"));
+        assert_eq!(records.changes[1].touched_attributes().into_iter().collect::<Vec<_>>(), ["cn", "uid"]);
+        assert_eq!(records.changes[0].as_entry().get_one_str("mail").as_deref(), Some("a@example.com"));
+        let modify = records.changes[0].modify()?;
+        assert_eq!(modify.ops.len(), 2);
+        assert_eq!(modify.ops[0].values, [b"a@example.com".as_slice()]);
+        assert!(modify.ops[1].values.is_empty());
+        let mut buf: Vec<u8> = Vec::new();
+        records.changes[0].write_ldif(&mut buf)?;
+        assert_eq!(String::from_utf8_lossy(&buf), "dn: cn=foo\nchangetype: modify\nadd: mail\nmail: a@example.com\n-\ndelete: l\n-\n\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_delete() -> Result<()> {
+        let mut buf: Vec<u8> = Vec::new();
+        buf.write_add(&Entry::from([("dn", b"cn=foo".as_slice()), ("cn", b"foo")]))?;
+        buf.write_delete("cn=bar")?;
+        buf.write_modrdn(&ModRdnChangeRecord{ dn: "cn=baz,ou=a", new_rdn: "cn=qux", delete_old_rdn: true, new_superior: Some("ou=b") })?;
+        assert_eq!(String::from_utf8_lossy(&buf), concat!(
+            "dn: cn=foo\nchangetype: add\ncn: foo\n\ndn: cn=bar\nchangetype: delete\n\n",
+            "dn: cn=baz,ou=a\nchangetype: modrdn\nnewrdn: cn=qux\ndeleteoldrdn: 1\nnewsuperior: ou=b\n\n",
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Adapters for passing entries between threads, so that parsing and entry assembly can run on
+//! one thread while formatting and output run on another. `EntrySender` is a WriteEntry that
+//! sends the entries it is given in batches over a bounded channel, and `EntryReceiver` is the
+//! ReadEntry they are pulled from at the other end. `pipelined` runs both sides.
+
+use crate::entry::{copy_entries, Entry, OwnedEntry, ReadEntry, WriteEntry};
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+// Entries are sent in batches, as sending each entry on its own costs more than formatting it.
+const BATCH_SIZE: usize = 64;
+
+/// The number of batches that may wait in the channel of `pipelined`, which bounds the memory
+/// used when the receiving side is the slower one.
+pub const DEFAULT_BOUND: usize = 16;
+
+/// Creates a channel for entries that holds up to `bound` batches of entries.
+pub fn entry_channel(bound: usize) -> (EntrySender, EntryReceiver) {
+    let (sender, receiver) = sync_channel(bound);
+    (
+        EntrySender{ sender, batch: Vec::with_capacity(BATCH_SIZE) },
+        EntryReceiver{ receiver, batch: VecDeque::new() },
+    )
+}
+
+/// The sending side of an entry channel. Entries are sent when a batch is full, when the sender
+/// is flushed and when it is dropped. Writing fails with BrokenPipe once the receiver is gone.
+pub struct EntrySender {
+    sender: SyncSender<Vec<OwnedEntry>>,
+    batch: Vec<OwnedEntry>,
+}
+
+impl EntrySender {
+    /// Sends the entries of a batch that isn't full yet.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        self.sender.send(batch)
+            .map_err(|_| Error::new(ErrorKind::BrokenPipe, "the receiver of the entries is gone"))
+    }
+}
+
+impl WriteEntry for EntrySender {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        self.batch.push(entry.into());
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for EntrySender {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// The receiving side of an entry channel, which reads entries until every sender is gone.
+pub struct EntryReceiver {
+    receiver: Receiver<Vec<OwnedEntry>>,
+    batch: VecDeque<OwnedEntry>,
+}
+
+impl ReadEntry for EntryReceiver {
+    fn read_entry(&mut self) -> Result<Option<OwnedEntry>> {
+        if self.batch.is_empty() {
+            match self.receiver.recv() {
+                Ok(batch) => self.batch = batch.into(),
+                Err(_) => return Ok(None),
+            }
+        }
+        Ok(self.batch.pop_front())
+    }
+}
+
+/// Calls `produce` with a WriteEntry on the calling thread while the entries it writes are
+/// written to `dest` on another. An error on either side stops both. The error of `dest` is
+/// returned in preference to the BrokenPipe that `produce` then sees.
+pub fn pipelined<P, W>(produce: P, dest: &mut W) -> Result<()>
+where
+    P: FnOnce(&mut EntrySender) -> Result<()>,
+    W: WriteEntry + Send + ?Sized,
+{
+    let (mut sender, mut receiver) = entry_channel(DEFAULT_BOUND);
+    thread::scope(|scope| {
+        let consumer = scope.spawn(move || copy_entries(&mut receiver, dest));
+        let produced = produce(&mut sender).and_then(|_| sender.flush());
+        // the receiver ends once the sender is gone
+        drop(sender);
+        let consumed = consumer.join()
+            .unwrap_or_else(|_| Err(Error::other("the thread writing the entries panicked")));
+        match (produced, consumed) {
+            (_, Err(err)) => Err(err),
+            (Err(err), Ok(_)) => Err(err),
+            (Ok(()), Ok(_)) => Ok(()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pipelined() -> Result<()> {
+        let mut written: Vec<OwnedEntry> = Vec::new();
+        pipelined(|sender| {
+            for i in 0..1000 {
+                sender.write_entry(&Entry::from([("dn", format!("cn={i}").as_bytes())]))?;
+            }
+            Ok(())
+        }, &mut written)?;
+        assert_eq!(written.len(), 1000);
+        assert_eq!(written[999].get_one_str("dn").as_deref(), Some("cn=999"));
+
+        // an error of the destination is returned rather than that of the sender
+        struct Failing;
+        impl WriteEntry for Failing {
+            fn write_entry(&mut self, _entry: &Entry) -> Result<()> {
+                Err(Error::other("disk full"))
+            }
+        }
+        let err = pipelined(|sender| {
+            loop {
+                sender.write_entry(&Entry::from([("dn", b"cn=foo".as_slice())]))?;
+            }
+        }, &mut Failing).unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+        Ok(())
+    }
+}
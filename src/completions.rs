@@ -0,0 +1,261 @@
+//! Shell completion scripts for the tools. A tool writes the script for itself instead of running
+//! when `LTOOLS_COMPLETIONS` is set to `bash`, `zsh` or `fish`, which `config::with_defaults`
+//! takes care of, and `lcompletions` puts the scripts of every tool together.
+//!
+//! Options and arguments that take attribute names complete them by running
+//! `lcompletions --attributes`, which lists the attribute types of a schema and the attributes
+//! found in a sample LDIF file, such as those configured in `ltools.toml`.
+
+use clap::{Arg, ArgAction, Command};
+use std::io::{Error, ErrorKind, Result, Write};
+
+pub const VARIABLE: &str = "LTOOLS_COMPLETIONS";
+
+// Lists the attribute names at completion time. Errors are left out of the completions.
+const ATTRIBUTES_COMMAND: &str = "lcompletions --attributes 2>/dev/null";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn parse(name: &str) -> Result<Shell> {
+        match name {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("unknown shell '{}', expected bash, zsh or fish", name))),
+        }
+    }
+}
+
+// What the value of an option or argument completes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Values {
+    Any,
+    Files,
+    Directories,
+    Attributes,
+    AttributesAndFiles, // lget takes attributes followed by files
+    Choices(Vec<String>),
+}
+
+fn values(arg: &Arg) -> Values {
+    if let Some(choices) = arg.get_value_parser().possible_values() {
+        return Values::Choices(choices.map(|choice| choice.get_name().to_string()).collect());
+    }
+    let name = arg.get_value_names().and_then(|names| names.first().copied()).unwrap_or(arg.get_id());
+    match name {
+        _ if name.starts_with("ATTRIBUTE") && arg.is_positional() => Values::AttributesAndFiles,
+        _ if name.starts_with("ATTRIBUTE") => Values::Attributes,
+        "FILE" | "FILES" | "OLD" | "NEW" | "BASE" | "OURS" | "THEIRS" | "SNAPSHOT" | "DATA" | "CHANGES" | "OUTPUT" => Values::Files,
+        "DIRECTORY" => Values::Directories,
+        _ => Values::Any,
+    }
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.is_takes_value_set() && !matches!(arg.get_action(), ArgAction::SetTrue | ArgAction::SetFalse | ArgAction::Count)
+}
+
+// The first sentence of the help of an argument.
+fn description<'a>(arg: &Arg<'a>) -> &'a str {
+    let help = arg.get_help().unwrap_or_default();
+    help.split_once(". ").map(|(sentence, _)| sentence).unwrap_or(help).trim_end_matches('.')
+}
+
+/// Writes the completion script of a command for a shell.
+pub fn generate(shell: Shell, cmd: &Command, dest: &mut dyn Write) -> Result<()> {
+    match shell {
+        Shell::Bash => generate_bash(cmd, dest),
+        Shell::Zsh => generate_zsh(cmd, dest),
+        Shell::Fish => generate_fish(cmd, dest),
+    }
+}
+
+// The values of the positional arguments, which are completed alike.
+fn positional_values(cmd: &Command) -> Option<Values> {
+    let positionals: Vec<Values> = cmd.get_positionals().map(values).collect();
+    [Values::AttributesAndFiles, Values::Files].into_iter()
+        .find(|wanted| positionals.contains(wanted))
+        .or_else(|| positionals.into_iter().next())
+}
+
+fn bash_words(values: &Values) -> String {
+    match values {
+        Values::Any => String::from("COMPREPLY=()"),
+        Values::Files => String::from("COMPREPLY=($(compgen -f -- \"$cur\"))"),
+        Values::Directories => String::from("COMPREPLY=($(compgen -d -- \"$cur\"))"),
+        Values::Attributes => format!("COMPREPLY=($(compgen -W \"$({})\" -- \"$cur\"))", ATTRIBUTES_COMMAND),
+        Values::AttributesAndFiles => format!("COMPREPLY=($(compgen -W \"$({})\" -- \"$cur\") $(compgen -f -- \"$cur\"))", ATTRIBUTES_COMMAND),
+        Values::Choices(choices) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", choices.join(" ")),
+    }
+}
+
+fn generate_bash(cmd: &Command, dest: &mut dyn Write) -> Result<()> {
+    let name = cmd.get_name();
+    let function = format!("_ltools_{}", name);
+    writeln!(dest, "{}() {{", function)?;
+    writeln!(dest, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(dest, "    case \"$prev\" in")?;
+    for arg in cmd.get_opts().filter(|arg| takes_value(arg)) {
+        let mut flags: Vec<String> = arg.get_long().map(|long| format!("--{}", long)).into_iter().collect();
+        flags.extend(arg.get_short().map(|short| format!("-{}", short)));
+        writeln!(dest, "        {})", flags.join("|"))?;
+        writeln!(dest, "            {}", bash_words(&values(arg)))?;
+        writeln!(dest, "            return;;")?;
+    }
+    writeln!(dest, "    esac")?;
+    let mut flags: Vec<String> = Vec::new();
+    for arg in cmd.get_arguments().filter(|arg| !arg.is_positional()) {
+        flags.extend(arg.get_long().map(|long| format!("--{}", long)));
+        flags.extend(arg.get_short().map(|short| format!("-{}", short)));
+    }
+    writeln!(dest, "    if [[ \"$cur\" == -* ]]; then")?;
+    writeln!(dest, "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", flags.join(" "))?;
+    writeln!(dest, "        return")?;
+    writeln!(dest, "    fi")?;
+    writeln!(dest, "    {}", bash_words(&positional_values(cmd).unwrap_or(Values::Any)))?;
+    writeln!(dest, "}}")?;
+    writeln!(dest, "complete -o filenames -F {} {}", function, name)?;
+    Ok(())
+}
+
+fn zsh_action(values: &Values) -> String {
+    match values {
+        Values::Any => String::from(" "),
+        Values::Files => String::from("_files"),
+        Values::Directories => String::from("_files -/"),
+        Values::Attributes => String::from("_ltools_attributes"),
+        Values::AttributesAndFiles => String::from("{_ltools_attributes; _files}"),
+        Values::Choices(choices) => format!("({})", choices.join(" ")),
+    }
+}
+
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:")
+}
+
+fn generate_zsh(cmd: &Command, dest: &mut dyn Write) -> Result<()> {
+    let name = cmd.get_name();
+    writeln!(dest, "(( $+functions[_ltools_attributes] )) || _ltools_attributes() {{")?;
+    writeln!(dest, "    local -a attributes")?;
+    writeln!(dest, "    attributes=(${{(f)\"$({})\"}})", ATTRIBUTES_COMMAND)?;
+    writeln!(dest, "    compadd -a attributes")?;
+    writeln!(dest, "}}")?;
+    writeln!(dest, "_ltools_{}() {{", name)?;
+    writeln!(dest, "    _arguments -s \\")?;
+    for arg in cmd.get_arguments().filter(|arg| !arg.is_positional()) {
+        let repeated = if matches!(arg.get_action(), ArgAction::Append | ArgAction::Count) { "*" } else { "" };
+        let flags: Vec<String> = arg.get_short().map(|short| format!("-{}", short)).into_iter()
+            .chain(arg.get_long().map(|long| format!("--{}", long)))
+            .collect();
+        let value = if takes_value(arg) {
+            let value_name = arg.get_value_names().and_then(|names| names.first().copied()).unwrap_or(arg.get_id());
+            format!(":{}:{}", value_name, zsh_action(&values(arg)))
+        } else {
+            String::new()
+        };
+        for flag in flags {
+            let equals = if takes_value(arg) && flag.starts_with("--") { "=" } else { "" };
+            writeln!(dest, "        '{}{}{}[{}]{}' \\", repeated, flag, equals, zsh_escape(description(arg)), zsh_escape_action(&value))?;
+        }
+    }
+    if let Some(values) = positional_values(cmd) {
+        writeln!(dest, "        '*:argument:{}' \\", zsh_action(&values))?;
+    }
+    writeln!(dest, "        && return 0")?;
+    writeln!(dest, "}}")?;
+    writeln!(dest, "compdef _ltools_{} {}", name, name)?;
+    Ok(())
+}
+
+// Only quotes need escaping in actions, which are otherwise taken as they are.
+fn zsh_escape_action(action: &str) -> String {
+    action.replace('\'', "'\\''")
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_values(values: &Values) -> String {
+    match values {
+        Values::Any => String::from(" -x"),
+        Values::Files => String::from(" -r -F"),
+        Values::Directories => String::from(" -x -a '(__fish_complete_directories)'"),
+        Values::Attributes => format!(" -x -a '({})'", ATTRIBUTES_COMMAND),
+        Values::AttributesAndFiles => format!(" -a '({})'", ATTRIBUTES_COMMAND),
+        Values::Choices(choices) => format!(" -x -a '{}'", fish_escape(&choices.join(" "))),
+    }
+}
+
+fn generate_fish(cmd: &Command, dest: &mut dyn Write) -> Result<()> {
+    let name = cmd.get_name();
+    for arg in cmd.get_arguments().filter(|arg| !arg.is_positional()) {
+        let mut line = format!("complete -c {}", name);
+        if let Some(short) = arg.get_short() {
+            line.push_str(&format!(" -s {}", short));
+        }
+        if let Some(long) = arg.get_long() {
+            line.push_str(&format!(" -l {}", long));
+        }
+        if takes_value(arg) {
+            line.push_str(&fish_values(&values(arg)));
+        }
+        writeln!(dest, "{} -d '{}'", line, fish_escape(description(arg)))?;
+    }
+    match positional_values(cmd) {
+        None | Some(Values::Any) => writeln!(dest, "complete -c {} -f", name)?,
+        Some(Values::Files) => {},
+        Some(values) => writeln!(dest, "complete -c {}{}", name, fish_values(&values))?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::arg;
+
+    fn command() -> Command<'static> {
+        Command::new("lget")
+            .arg(arg!([ATTRIBUTES] ... "The attributes. And files."))
+            .arg(arg!(-c --csv "Write CSV.").action(ArgAction::SetTrue))
+            .arg(arg!(--defer <ATTRIBUTE> "Defer [it].").required(false).action(ArgAction::Append))
+            .arg(arg!(--compress <METHOD> "Compress it.").required(false).value_parser(["gzip", "zstd"]))
+            .arg(arg!(--map <FILE> "Rename attributes.").required(false))
+    }
+
+    #[test]
+    fn test_generate() -> Result<()> {
+        let mut bash: Vec<u8> = Vec::new();
+        generate(Shell::Bash, &command(), &mut bash)?;
+        let bash = String::from_utf8(bash).unwrap();
+        assert!(bash.contains("        --defer)\n            COMPREPLY=($(compgen -W \"$(lcompletions --attributes 2>/dev/null)\" -- \"$cur\"))\n"));
+        assert!(bash.contains("        --compress)\n            COMPREPLY=($(compgen -W \"gzip zstd\" -- \"$cur\"))\n"));
+        assert!(bash.contains("        --map)\n            COMPREPLY=($(compgen -f -- \"$cur\"))\n"));
+        assert!(bash.contains("--csv -c"));
+        assert!(bash.ends_with("complete -o filenames -F _ltools_lget lget\n"));
+
+        let mut zsh: Vec<u8> = Vec::new();
+        generate(Shell::Zsh, &command(), &mut zsh)?;
+        let zsh = String::from_utf8(zsh).unwrap();
+        assert!(zsh.contains("        '-c[Write CSV]' \\\n        '--csv[Write CSV]' \\\n"));
+        assert!(zsh.contains("        '*--defer=[Defer \\[it\\]]:ATTRIBUTE:_ltools_attributes' \\\n"));
+        assert!(zsh.contains("        '--compress=[Compress it]:METHOD:(gzip zstd)' \\\n"));
+        assert!(zsh.contains("        '*:argument:{_ltools_attributes; _files}' \\\n"));
+
+        let mut fish: Vec<u8> = Vec::new();
+        generate(Shell::Fish, &command(), &mut fish)?;
+        let fish = String::from_utf8(fish).unwrap();
+        assert!(fish.contains("complete -c lget -s c -l csv -d 'Write CSV'\n"));
+        assert!(fish.contains("complete -c lget -l map -r -F -d 'Rename attributes'\n"));
+        assert!(fish.contains("complete -c lget -a '(lcompletions --attributes 2>/dev/null)'\n"));
+        assert!(Shell::parse("tcsh").is_err());
+        Ok(())
+    }
+}
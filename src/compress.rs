@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::{stdin, Cursor, Read, Result, Write};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Recognizes compressed data by the magic bytes it starts with.
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Chooses the compression for an output file by its extension, `.gz` or `.zst`.
+    pub fn from_path(path: &str) -> Compression {
+        if path.ends_with(".gz") {
+            Compression::Gzip
+        } else if path.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Parses a compression method given as `none`, `gzip` or `zstd`.
+    pub fn parse(method: &str) -> std::result::Result<Compression, &'static str> {
+        match method {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err("compression method must be none, gzip or zstd"),
+        }
+    }
+}
+
+/// Decompresses the input on the fly if it is gzip or zstd compressed, and otherwise passes it
+/// through unchanged. The input is recognized by its first bytes rather than by a file name, so
+/// that compressed data on standard input is decompressed as well.
+pub fn decompress<'a, R: Read + 'a>(mut input: R) -> Result<Box<dyn Read + 'a>> {
+    let mut magic: Vec<u8> = Vec::with_capacity(ZSTD_MAGIC.len());
+    input.by_ref().take(ZSTD_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    let compression = Compression::detect(&magic);
+    let input = Cursor::new(magic).chain(input);
+    match compression {
+        Compression::None => Ok(Box::new(input)),
+        #[cfg(feature = "compress")]
+        Compression::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(input))),
+        #[cfg(feature = "compress")]
+        Compression::Zstd => Ok(Box::new(zstd::Decoder::new(input)?)),
+        #[cfg(not(feature = "compress"))]
+        _ => Err(unsupported()),
+    }
+}
+
+#[cfg(not(feature = "compress"))]
+fn unsupported() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "ltools was built without the compress feature")
+}
+
+/// Opens a file for reading, or standard input if the path is `-`, and decompresses it as by
+/// `decompress`.
+pub fn open_input(path: &str) -> Result<Box<dyn Read>> {
+    if path == "-" {
+        return decompress(stdin());
+    }
+    decompress(File::open(path)?)
+}
+
+enum Encoder<W: Write> {
+    None(W),
+    #[cfg(feature = "compress")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "compress")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+/// Compresses what is written to it as it goes, so that large outputs are compressed without a
+/// second pass. `finish` must be called to write the end of the compressed data.
+pub struct CompressWriter<W: Write> {
+    encoder: Encoder<W>,
+}
+
+impl<W: Write> CompressWriter<W> {
+    pub fn new(dest: W, compression: Compression) -> Result<CompressWriter<W>> {
+        let encoder = match compression {
+            Compression::None => Encoder::None(dest),
+            #[cfg(feature = "compress")]
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(dest, flate2::Compression::default())),
+            #[cfg(feature = "compress")]
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(dest, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+            #[cfg(not(feature = "compress"))]
+            _ => return Err(unsupported()),
+        };
+        Ok(CompressWriter{ encoder })
+    }
+
+    /// Writes the end of the compressed data and flushes it, and returns the destination.
+    pub fn finish(self) -> Result<W> {
+        #[cfg(feature = "compress")]
+        let mut dest = match self.encoder {
+            Encoder::None(dest) => dest,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        #[cfg(not(feature = "compress"))]
+        let Encoder::None(mut dest) = self.encoder;
+        dest.flush()?;
+        Ok(dest)
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self.encoder {
+            Encoder::None(ref mut dest) => dest.write(buf),
+            #[cfg(feature = "compress")]
+            Encoder::Gzip(ref mut encoder) => encoder.write(buf),
+            #[cfg(feature = "compress")]
+            Encoder::Zstd(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.encoder {
+            Encoder::None(ref mut dest) => dest.flush(),
+            #[cfg(feature = "compress")]
+            Encoder::Gzip(ref mut encoder) => encoder.flush(),
+            #[cfg(feature = "compress")]
+            Encoder::Zstd(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Creates an output file, compressed according to its extension unless a compression is
+/// given, or writes to standard output if the path is `-`.
+pub fn create_output(path: &str, compression: Option<Compression>) -> Result<CompressWriter<Box<dyn Write>>> {
+    if path == "-" {
+        return CompressWriter::new(Box::new(std::io::stdout()), compression.unwrap_or(Compression::None));
+    }
+    let file = File::create(path)?;
+    CompressWriter::new(Box::new(file), compression.unwrap_or_else(|| Compression::from_path(path)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let ldif = b"dn: cn=foo\ncn: foo\n";
+        let mut plain = String::new();
+        decompress(ldif.as_slice())?.read_to_string(&mut plain)?;
+        assert_eq!(plain.as_bytes(), ldif);
+        let mut short = String::new();
+        decompress(b"\n".as_slice())?.read_to_string(&mut short)?;
+        assert_eq!(short, "\n");
+        Ok(())
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn test_compressed() -> Result<()> {
+        let ldif = b"dn: cn=foo\ncn: foo\n";
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let mut writer = CompressWriter::new(Vec::new(), compression)?;
+            writer.write_all(ldif)?;
+            let compressed = writer.finish()?;
+            assert_eq!(Compression::detect(&compressed), compression);
+            let mut decompressed: Vec<u8> = Vec::new();
+            decompress(compressed.as_slice())?.read_to_end(&mut decompressed)?;
+            assert_eq!(decompressed, ldif);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn test_output_by_extension() -> Result<()> {
+        let ldif = b"dn: cn=foo\ncn: foo\n";
+        let path = |name: &str| std::env::temp_dir().join(format!("ltools-compress-{}-{}", std::process::id(), name)).to_string_lossy().into_owned();
+        let cases = [
+            ("out.ldif.gz", None, Compression::Gzip),
+            ("out.ldif.zst", None, Compression::Zstd),
+            ("out.ldif", None, Compression::None),
+            ("plain.ldif.gz", Some(Compression::None), Compression::None),
+        ];
+        for (name, compression, expected) in cases {
+            let path = path(name);
+            let mut output = create_output(&path, compression)?;
+            output.write_all(ldif)?;
+            output.finish()?;
+            assert_eq!(Compression::detect(&std::fs::read(&path)?), expected, "{}", name);
+            let mut read: Vec<u8> = Vec::new();
+            open_input(&path)?.read_to_end(&mut read)?;
+            assert_eq!(read, ldif, "{}", name);
+            std::fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
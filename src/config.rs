@@ -0,0 +1,428 @@
+//! Defaults for the options of the tools, read from an `ltools.toml` file so that long option
+//! strings don't have to be repeated in every invocation:
+//!
+//! ```toml
+//! # options of lcompare
+//! [lcompare]
+//! invert = true
+//! attributes = ["modifyTimestamp", "modifiersName"]
+//!
+//! [lget]
+//! csv = true
+//!
+//! [lfmt]
+//! schema = "/etc/ltools/schema.ldif"
+//! ```
+//!
+//! Each section is named after a tool, and its keys are the long names of the options of that
+//! tool, or the lowercase names of its arguments, such as `attributes` for ATTRIBUTES. Values are
+//! strings, integers, booleans or arrays of those, for options that can be given several times.
+//! Options given on the command line replace the defaults from the file. A flag that the file
+//! turns on, such as `invert`, is turned back off with `--no-invert`, which is added for it. The
+//! tools tell on standard error which file they take defaults from.
+//!
+//! The file is looked for in the working directory and its ancestors up to the root of the
+//! repository that it is in, the first with a `.git`, so that a project can have its own. Outside
+//! a repository, only the working directory is looked in, so that a file in a shared directory
+//! such as `/tmp` isn't picked up. Then the file is looked for as `ltools/ltools.toml` in `$XDG_CONFIG_HOME`, or `~/.config`. The
+//! `LTOOLS_CONFIG` environment variable names another file, or disables the file if it is empty.
+//!
+//! Only the subset of TOML above is read: sections, keys, basic and literal strings on one line,
+//! decimal integers, booleans and arrays, which may span lines. Tables of tables, dotted keys,
+//! multi-line strings, floats and dates are not.
+
+use crate::completions::{self, Shell};
+use clap::{Arg, ArgAction, Command};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = "ltools.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    sections: Vec<(String, Vec<(String, Value)>)>,
+}
+
+#[derive(Clone)]
+enum Line {
+    Blank,
+    Section(String),
+    KeyValue(String, Value),
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config> {
+        let mut config = Config::default();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let line_number = text[..text.len() - rest.len()].matches('\n').count() + 1;
+            let invalid = |message: String| Error::new(ErrorKind::InvalidData, format!("line {}: {}", line_number, message));
+            let line = match parser::line(rest) {
+                Ok((remaining, line)) => {
+                    rest = remaining;
+                    line
+                },
+                Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+                    let unparsed = err.input.lines().next().unwrap_or_default();
+                    return Err(invalid(format!("failed to parse starting at '{}'", unparsed)));
+                },
+                Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers"),
+            };
+            match line {
+                Line::Blank => {},
+                Line::Section(name) => {
+                    if config.sections.iter().any(|(section, _)| *section == name) {
+                        return Err(invalid(format!("duplicate section [{}]", name)));
+                    }
+                    config.sections.push((name, Vec::new()));
+                },
+                Line::KeyValue(key, value) => {
+                    let (_, section) = config.sections.last_mut()
+                        .ok_or_else(|| invalid(format!("'{}' comes before any section", key)))?;
+                    if section.iter().any(|(existing, _)| *existing == key) {
+                        return Err(invalid(format!("duplicate key '{}'", key)));
+                    }
+                    section.push((key, value));
+                },
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Config> {
+        std::fs::read_to_string(path)
+            .and_then(|text| Config::parse(&text))
+            .map_err(|err| Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+    }
+
+    /// The path of the configuration file, if there is one. See the module documentation.
+    pub fn find() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("LTOOLS_CONFIG") {
+            return if path.is_empty() { None } else { Some(PathBuf::from(path)) };
+        }
+        if let Ok(dir) = std::env::current_dir() {
+            if let Some(path) = project_file(&dir) {
+                return Some(path);
+            }
+        }
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("ltools").join(FILE_NAME)).filter(|path| path.is_file())
+    }
+
+    /// The keys and values of the section of a tool, in the order of the file.
+    pub fn section(&self, name: &str) -> &[(String, Value)] {
+        self.sections.iter()
+            .find(|(section, _)| section == name)
+            .map(|(_, values)| values.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Sets the values of the section named after the command as the defaults of its options.
+    pub fn apply<'a>(&self, cmd: Command<'a>) -> Result<Command<'a>> {
+        let values = self.section(cmd.get_name());
+        for (key, _) in values.iter() {
+            if !cmd.get_arguments().any(|arg| option_key(arg.get_long(), arg.get_id()) == *key) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("{} has no option '{}'", cmd.get_name(), key)));
+            }
+        }
+        // mut_arg moves the argument last, so every argument is moved to keep positional
+        // arguments and the help in order, except for the generated help and version flags
+        let ids: Vec<&'a str> = cmd.get_arguments()
+            .filter(|arg| !matches!(arg.get_id(), "help" | "version"))
+            .map(|arg| arg.get_id())
+            .collect();
+        let mut cmd = cmd;
+        for id in ids {
+            let arg = cmd.get_arguments().find(|arg| arg.get_id() == id).unwrap();
+            let defaults = match values.iter().find(|(key, _)| *key == option_key(arg.get_long(), id)) {
+                Some((key, value)) => Some(defaults(key, value, arg.get_action(), arg.is_takes_value_set(), arg.is_multiple_values_set() || arg.is_multiple_occurrences_set())?),
+                None => None,
+            };
+            let negation = match (arg.get_action(), arg.get_long(), defaults) {
+                (ArgAction::SetTrue, Some(long), Some(["true"])) => Some(leak(format!("no-{}", long))),
+                _ => None,
+            }.filter(|negation| !cmd.get_arguments().any(|arg| arg.get_id() == *negation));
+            cmd = cmd.mut_arg(id, |arg| {
+                let arg = match defaults {
+                    Some(defaults) => arg.default_values(defaults),
+                    None => arg,
+                };
+                match negation {
+                    Some(negation) => arg.default_value_if(negation, Some("true"), Some("false")).overrides_with(negation),
+                    None => arg,
+                }
+            });
+            if let Some(negation) = negation {
+                cmd = cmd.arg(Arg::new(negation)
+                    .long(negation)
+                    .action(ArgAction::SetTrue)
+                    .overrides_with(id)
+                    .help(leak(format!("Turn off --{}, which is on by default in the configuration file.", &negation[3..]))));
+            }
+        }
+        Ok(cmd)
+    }
+}
+
+/// Sets the defaults from the configuration file, if there is one, on the options of a command.
+/// Like `Command::get_matches`, this exits with an error message if the file can't be used.
+/// When `LTOOLS_COMPLETIONS` names a shell, the completion script of the command is written
+/// instead, and the process exits.
+pub fn with_defaults(mut cmd: Command) -> Command {
+    if let Some(shell) = std::env::var_os(completions::VARIABLE) {
+        let written = Shell::parse(&shell.to_string_lossy())
+            .and_then(|shell| completions::generate(shell, &cmd, &mut std::io::stdout().lock()));
+        match written {
+            Ok(()) => std::process::exit(0),
+            Err(err) => cmd.error(clap::error::ErrorKind::InvalidValue, err).exit(),
+        }
+    }
+    let path = match Config::find() {
+        Some(path) => path,
+        None => return cmd,
+    };
+    let applied = Config::load(&path).and_then(|config| {
+        let cmd = config.apply(cmd.clone())
+            .map_err(|err| Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        if !config.section(cmd.get_name()).is_empty() {
+            eprintln!("{}: using defaults from {}", cmd.get_name(), path.display());
+        }
+        Ok(cmd)
+    });
+    match applied {
+        Ok(cmd) => cmd,
+        Err(err) => cmd.error(clap::error::ErrorKind::InvalidValue, err).exit(),
+    }
+}
+
+// The configuration file of the project that the directory is in. See the module documentation.
+fn project_file(dir: &Path) -> Option<PathBuf> {
+    let root = dir.ancestors().find(|dir| dir.join(".git").exists()).unwrap_or(dir);
+    for dir in dir.ancestors() {
+        let path = dir.join(FILE_NAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        if dir == root {
+            break;
+        }
+    }
+    None
+}
+
+fn option_key(long: Option<&str>, id: &str) -> String {
+    long.map(String::from).unwrap_or_else(|| id.to_ascii_lowercase())
+}
+
+// The defaults live as long as the command, which lives as long as the process.
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+fn defaults(key: &str, value: &Value, action: &ArgAction, takes_value: bool, multiple: bool) -> Result<&'static [&'static str]> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidInput, format!("'{}' {}", key, message));
+    let is_flag = matches!(action, ArgAction::SetTrue | ArgAction::SetFalse);
+    let values: Vec<&Value> = match value {
+        Value::Boolean(_) if is_flag => vec![value],
+        _ if is_flag => return Err(invalid("is a flag, which takes true or false")),
+        _ if !takes_value => return Err(invalid("can't be set in the configuration file")),
+        Value::Array(values) if multiple || matches!(action, ArgAction::Append) => values.iter().collect(),
+        Value::Array(_) => return Err(invalid("takes one value")),
+        value => vec![value],
+    };
+    let values: Vec<&'static str> = values.into_iter()
+        .map(|value| match value {
+            Value::String(s) => Ok(s.clone()),
+            Value::Integer(n) => Ok(n.to_string()),
+            Value::Boolean(b) => Ok(b.to_string()),
+            Value::Array(_) => Err(invalid("takes no nested arrays")),
+        })
+        .map(|value| value.map(leak))
+        .collect::<Result<_>>()?;
+    Ok(values.leak())
+}
+
+mod parser {
+    use super::{Line, Value};
+    use nom::{
+        IResult,
+        branch::alt,
+        bytes::complete::{escaped_transform, is_not, tag, take_while, take_while1},
+        character::complete::{char, digit1, line_ending, not_line_ending},
+        combinator::{eof, map, map_res, opt, recognize, value},
+        multi::{many0, separated_list0},
+        sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+    };
+
+    pub(super) fn line(input: &str) -> IResult<&str, Line> {
+        delimited(
+            space,
+            alt((
+                map(delimited(char('['), delimited(space, key, space), char(']')), Line::Section),
+                map(separated_pair(key, tuple((space, char('='), space)), value_), |(key, value)| Line::KeyValue(key, value)),
+                value(Line::Blank, space),
+            )),
+            tuple((space, opt(comment), alt((line_ending, eof)))),
+        )(input)
+    }
+
+    fn space(input: &str) -> IResult<&str, &str> {
+        take_while(|c| c == ' ' || c == '\t')(input)
+    }
+
+    fn comment(input: &str) -> IResult<&str, &str> {
+        preceded(char('#'), not_line_ending)(input)
+    }
+
+    // Whitespace, comments and line breaks, as allowed between the values of an array.
+    fn blank(input: &str) -> IResult<&str, &str> {
+        recognize(pair(many0(tuple((space, opt(comment), line_ending))), space))(input)
+    }
+
+    fn key(input: &str) -> IResult<&str, String> {
+        alt((
+            map(take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_'), String::from),
+            basic_string,
+            literal_string,
+        ))(input)
+    }
+
+    fn basic_string(input: &str) -> IResult<&str, String> {
+        delimited(
+            char('"'),
+            map(
+                opt(escaped_transform(
+                    is_not("\\\"\r\n"),
+                    '\\',
+                    alt((
+                        value("\\", char('\\')),
+                        value("\"", char('"')),
+                        value("\n", char('n')),
+                        value("\r", char('r')),
+                        value("\t", char('t')),
+                    )),
+                )),
+                Option::unwrap_or_default,
+            ),
+            char('"'),
+        )(input)
+    }
+
+    fn literal_string(input: &str) -> IResult<&str, String> {
+        map(delimited(char('\''), take_while(|c| c != '\'' && c != '\r' && c != '\n'), char('\'')), String::from)(input)
+    }
+
+    fn value_(input: &str) -> IResult<&str, Value> {
+        alt((
+            map(basic_string, Value::String),
+            map(literal_string, Value::String),
+            value(Value::Boolean(true), tag("true")),
+            value(Value::Boolean(false), tag("false")),
+            map(map_res(recognize(pair(opt(alt((char('-'), char('+')))), digit1)), str::parse), Value::Integer),
+            map(
+                delimited(
+                    terminated(char('['), blank),
+                    separated_list0(tuple((blank, char(','), blank)), value_),
+                    tuple((blank, opt(terminated(char(','), blank)), char(']'))),
+                ),
+                Value::Array,
+            ),
+        ))(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::{arg, Arg};
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let config = Config::parse("# defaults\n\n[lcompare]\ninvert = true # ignore these\nattributes = [\n  \"modifyTimestamp\", # by the server\n  'modifiersName',\n]\n[ lget ]\n\"sort-by\" = \"a\\tb\"\njobs = 4\nempty = \"\"\n")?;
+        assert_eq!(config.section("lcompare"), [
+            (String::from("invert"), Value::Boolean(true)),
+            (String::from("attributes"), Value::Array(vec![Value::String(String::from("modifyTimestamp")), Value::String(String::from("modifiersName"))])),
+        ]);
+        assert_eq!(config.section("lget"), [
+            (String::from("sort-by"), Value::String(String::from("a\tb"))),
+            (String::from("jobs"), Value::Integer(4)),
+            (String::from("empty"), Value::String(String::new())),
+        ]);
+        assert!(config.section("lfmt").is_empty());
+
+        let err = Config::parse("[lget]\ncsv = true\njson = yes\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 3: failed to parse starting at 'json = yes'");
+        assert!(Config::parse("csv = true\n").is_err());
+        assert!(Config::parse("[lget]\ncsv = true\ncsv = false\n").is_err());
+        assert!(Config::parse("[lget]\nschema = \"unterminated\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply() -> Result<()> {
+        let command = || Command::new("lcompare")
+            .arg(arg!(<OLD> "old"))
+            .arg(arg!([ATTRIBUTES] ... "attributes"))
+            .arg(arg!(-v --invert "invert").action(ArgAction::SetTrue))
+            .arg(arg!(--filter <FILTER> "filter").required(false))
+            .arg(Arg::new("defer").long("defer").takes_value(true).action(ArgAction::Append));
+        let config = Config::parse("[lcompare]\ninvert = true\nattributes = [\"a\", \"b\"]\nfilter = \"(cn=*)\"\ndefer = [\"member\"]\n")?;
+
+        let matches = config.apply(command())?.get_matches_from(["lcompare", "old.ldif"]);
+        assert!(matches.get_flag("invert"));
+        assert_eq!(matches.get_many::<String>("ATTRIBUTES").unwrap().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(matches.get_one::<String>("filter").map(String::as_str), Some("(cn=*)"));
+        assert_eq!(matches.get_many::<String>("defer").unwrap().collect::<Vec<_>>(), ["member"]);
+
+        // the command line replaces the defaults
+        let matches = config.apply(command())?.get_matches_from(["lcompare", "old.ldif", "c", "--filter", "(sn=*)"]);
+        assert_eq!(matches.get_many::<String>("ATTRIBUTES").unwrap().collect::<Vec<_>>(), ["c"]);
+        assert_eq!(matches.get_one::<String>("filter").map(String::as_str), Some("(sn=*)"));
+        assert_eq!(matches.get_one::<String>("OLD").map(String::as_str), Some("old.ldif"));
+
+        // flags that the file turns on can be turned off
+        let matches = config.apply(command())?.get_matches_from(["lcompare", "old.ldif", "--no-invert"]);
+        assert!(!matches.get_flag("invert"));
+        let matches = config.apply(command())?.get_matches_from(["lcompare", "old.ldif", "--no-invert", "-v"]);
+        assert!(matches.get_flag("invert"));
+        let matches = Config::parse("[lcompare]\ninvert = false\n")?.apply(command())?.get_matches_from(["lcompare", "old.ldif"]);
+        assert!(!matches.get_flag("invert"));
+        assert!(Config::parse("[lcompare]\ninvert = false\n")?.apply(command())?.try_get_matches_from(["lcompare", "old.ldif", "--no-invert"]).is_err());
+
+        assert!(Config::parse("[lcompare]\nunknown = 1\n")?.apply(command()).is_err());
+        assert!(Config::parse("[lcompare]\ninvert = \"yes\"\n")?.apply(command()).is_err());
+        assert!(Config::parse("[lcompare]\nfilter = [\"(cn=*)\", \"(sn=*)\"]\n")?.apply(command()).is_err());
+        assert!(Config::parse("[lget]\nunknown = 1\n")?.apply(command()).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_project_file() -> Result<()> {
+        let base = std::env::temp_dir().join(format!("ltools-config-{}", std::process::id()));
+        let repository = base.join("repository");
+        let dir = repository.join("dir");
+        std::fs::create_dir_all(repository.join(".git"))?;
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(base.join(FILE_NAME), "")?;
+        std::fs::write(repository.join(FILE_NAME), "")?;
+        assert_eq!(project_file(&dir), Some(repository.join(FILE_NAME)));
+        // files above the root of the repository aren't used
+        std::fs::remove_file(repository.join(FILE_NAME))?;
+        assert_eq!(project_file(&dir), None);
+        std::fs::write(dir.join(FILE_NAME), "")?;
+        assert_eq!(project_file(&dir), Some(dir.join(FILE_NAME)));
+        std::fs::remove_dir_all(&base)?;
+        Ok(())
+    }
+}
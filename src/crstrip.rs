@@ -1,4 +1,4 @@
-use std::io::Result;
+use crate::sink::Result;
 use crate::loc::{ Loc, LocWrite };
 use crate::skip::{ Skipper, SkipState };
 
@@ -18,11 +18,15 @@ impl<LW: LocWrite> CrStripper<LW> {
     pub fn new(inner: LW) -> CrStripper<LW> {
         CrStripper{ inner, state: State::Normal, skipstate: SkipState::default() }
     }
+
+    pub fn get_mut(&mut self) -> &mut LW {
+        &mut self.inner
+    }
 }
 
 impl<LW: LocWrite> LocWrite for CrStripper<LW> {
     fn loc_write(&mut self, loc: Loc, buf: &[u8]) -> Result<usize> {
-        let mut skipper = Skipper::new_with_state(&mut self.inner, loc, buf, self.skipstate);
+        let mut skipper = Skipper::new_with_state(&mut self.inner, loc, buf, core::mem::take(&mut self.skipstate));
         while let Some(c) = skipper.lookahead() {
             self.state = match (self.state, c) {
                 (State::Normal, b'\r') => {
@@ -0,0 +1,119 @@
+use crate::base64::{DecodeState, EncodeState};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::io::{Error, ErrorKind, Result};
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// A named AES-256-GCM key for encrypting values, such as those of sensitive attributes in an
+/// export that is to be stored. Encrypted values are written as `{AESGCM:keyid}` followed by the
+/// base64 of the random nonce and the ciphertext, so that a value names the key that decrypts it.
+#[derive(Clone)]
+pub struct ValueKey {
+    pub id: String,
+    cipher: Aes256Gcm,
+}
+
+/// The name of the environment variable with the key of the given ID, such as `LTOOLS_KEY_BACKUP`
+/// for `backup`.
+pub fn key_variable(id: &str) -> String {
+    format!("LTOOLS_KEY_{}", id.to_ascii_uppercase().replace('-', "_"))
+}
+
+impl ValueKey {
+    /// Creates a key from its 32 raw bytes.
+    pub fn new(id: &str, key: &[u8]) -> Result<ValueKey> {
+        if key.len() != KEY_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, format!("key '{}' must be {} bytes, not {}", id, KEY_LENGTH, key.len())));
+        }
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|err| Error::other(err.to_string()))?;
+        Ok(ValueKey{ id: id.to_string(), cipher })
+    }
+
+    /// Loads the key of the given ID from the environment. The key is the base64 of 32 bytes,
+    /// either in a variable such as `LTOOLS_KEY_BACKUP`, or in the file named by a variable such
+    /// as `LTOOLS_KEY_BACKUP_FILE`, which keeps it out of the environment of other processes.
+    pub fn load(id: &str) -> Result<ValueKey> {
+        let variable = key_variable(id);
+        let file_variable = format!("{}_FILE", variable);
+        let encoded = match (std::env::var(&variable), std::env::var(&file_variable)) {
+            (Ok(encoded), _) => encoded,
+            (Err(_), Ok(path)) => std::fs::read_to_string(&path)
+                .map_err(|err| Error::new(err.kind(), format!("{}: {}", path, err)))?,
+            (Err(_), Err(_)) => return Err(Error::new(ErrorKind::NotFound, format!("no key '{}': set {} or {}", id, variable, file_variable))),
+        };
+        let mut key: Vec<u8> = Vec::new();
+        let mut state = DecodeState::default();
+        state.decode(encoded.trim().as_bytes(), &mut key)
+            .and_then(|_| state.finish())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, format!("key '{}' is not valid base64", id)))?;
+        ValueKey::new(id, &key)
+    }
+
+    fn prefix(&self) -> String {
+        format!("{{AESGCM:{}}}", self.id)
+    }
+
+    /// Encrypts a value with a random nonce.
+    pub fn encrypt(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), value)
+            .map_err(|err| Error::other(err.to_string()))?;
+        let mut encrypted = self.prefix().into_bytes();
+        let mut state = EncodeState::default();
+        state.encode(&nonce, &mut encrypted)?;
+        state.encode(&ciphertext, &mut encrypted)?;
+        state.finish(&mut encrypted)?;
+        Ok(encrypted)
+    }
+
+    /// Decrypts a value encrypted with this key. Returns None if the value doesn't start with
+    /// the prefix of the key, as with plain values and values encrypted with another key, and
+    /// an error if it does but can't be decrypted, as when it has been tampered with.
+    pub fn decrypt(&self, value: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(encoded) = value.strip_prefix(self.prefix().as_bytes()) else {
+            return Ok(None);
+        };
+        let invalid = || Error::new(ErrorKind::InvalidData, format!("failed to decrypt value encrypted with key '{}'", self.id));
+        let mut decoded: Vec<u8> = Vec::new();
+        let mut state = DecodeState::default();
+        state.decode(encoded, &mut decoded).and_then(|_| state.finish()).map_err(|_| invalid())?;
+        if decoded.len() < NONCE_LENGTH {
+            return Err(invalid());
+        }
+        let (nonce, ciphertext) = decoded.split_at(NONCE_LENGTH);
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map(Some)
+            .map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() -> Result<()> {
+        let key = ValueKey::new("backup", &[7u8; KEY_LENGTH])?;
+        let encrypted = key.encrypt(b"secret")?;
+        assert!(encrypted.starts_with(b"{AESGCM:backup}"));
+        assert_ne!(encrypted, key.encrypt(b"secret")?);
+        assert_eq!(key.decrypt(&encrypted)?.as_deref(), Some(&b"secret"[..]));
+        assert_eq!(key.decrypt(b"secret")?, None);
+
+        let other = ValueKey::new("other", &[7u8; KEY_LENGTH])?;
+        assert_eq!(other.decrypt(&encrypted)?, None);
+        let mut tampered = encrypted.clone();
+        let i = b"{AESGCM:backup}".len() + 20;
+        tampered[i] = if tampered[i] == b'A' { b'B' } else { b'A' };
+        assert!(key.decrypt(&tampered).is_err());
+        assert!(key.decrypt(b"{AESGCM:backup}AAAA").is_err());
+        assert!(key.decrypt(b"{AESGCM:backup}not base64!").is_err());
+        assert!(ValueKey::new("short", &[7u8; 16]).is_err());
+        Ok(())
+    }
+}
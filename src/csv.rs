@@ -1,9 +1,11 @@
+use std::borrow::Cow;
 use std::io::{
     Write,
     Result,
 };
 use crate::cartesian::cartesian_product;
 use crate::attrspec::AttrSpec;
+use crate::separator::RecordSeparator;
 use crate::entry::{
     Entry,
     EntryValue,
@@ -14,6 +16,67 @@ pub struct CsvEntryWriter<W: Write> {
     attrspecs: Vec<AttrSpec>,
     dest: W,
     write_header: bool,
+    record_separator: RecordSeparator,
+    excel_policy: Option<ExcelPolicy>,
+}
+
+/// How values that Excel would change when opening the CSV are protected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcelPolicy {
+    /// Write the value as a formula that gives the value as text, as in `="007"`.
+    Formula,
+    /// Prefix the value with an apostrophe, which Excel shows but which keeps the rest as written.
+    Prefix,
+}
+
+impl ExcelPolicy {
+    pub fn parse(s: &str) -> Option<ExcelPolicy> {
+        match s {
+            "formula" => Some(ExcelPolicy::Formula),
+            "prefix" => Some(ExcelPolicy::Prefix),
+            _ => None,
+        }
+    }
+
+    /// Protects the value if Excel would take it for a formula or a number it can't keep as
+    /// written, which are numbers with leading zeros, numbers of more than 15 digits and numbers
+    /// that read as scientific notation, such as parts of GUIDs.
+    pub fn protect<'a>(&self, value: &'a [u8]) -> Cow<'a, [u8]> {
+        if !excel_mangles(value) {
+            return Cow::Borrowed(value);
+        }
+        let mut protected: Vec<u8> = Vec::with_capacity(value.len() + 4);
+        match self {
+            ExcelPolicy::Formula => {
+                protected.extend_from_slice(b"=\"");
+                for c in value.iter().copied() {
+                    if c == b'"' {
+                        protected.push(b'"');
+                    }
+                    protected.push(c);
+                }
+                protected.push(b'"');
+            },
+            ExcelPolicy::Prefix => {
+                protected.push(b'\'');
+                protected.extend_from_slice(value);
+            },
+        }
+        Cow::Owned(protected)
+    }
+}
+
+fn excel_mangles(value: &[u8]) -> bool {
+    if matches!(value.first(), Some(b'=' | b'+' | b'-' | b'@' | b'\t' | b'\r')) {
+        return true;
+    }
+    let digits = value.iter().take_while(|c| c.is_ascii_digit()).count();
+    if digits == value.len() {
+        return (digits > 1 && value[0] == b'0') || digits > 15;
+    }
+    // such as 1E5, which would become 100000
+    let exponent = &value[digits..];
+    digits > 0 && exponent.len() > 1 && matches!(exponent[0], b'e' | b'E') && exponent[1..].iter().all(u8::is_ascii_digit)
 }
 
 impl<W: Write> CsvEntryWriter<W> {
@@ -23,24 +86,54 @@ impl<W: Write> CsvEntryWriter<W> {
             attrspecs,
             dest,
             write_header: true,
+            record_separator: RecordSeparator::CrLf, // as in RFC 4180
+            excel_policy: None,
+        }
+    }
+
+    pub fn set_record_separator<S: Into<RecordSeparator>>(&mut self, record_separator: S) -> &mut Self {
+        self.record_separator = record_separator.into();
+        self
+    }
+
+    pub fn set_excel_policy(&mut self, excel_policy: Option<ExcelPolicy>) -> &mut Self {
+        self.excel_policy = excel_policy;
+        self
+    }
+
+    pub fn set_write_header(&mut self, value: bool) -> &mut Self {
+        self.write_header = value;
+        self
+    }
+
+    pub fn write_header_line(&mut self) -> Result<()> {
+        for (i, attrspec) in self.attrspecs.iter().enumerate() {
+            if i != 0 {
+                self.dest.write_all(b",")?;
+            }
+            csv_escape(&mut self.dest, attrspec.attribute.as_bytes(), self.record_separator)?;
         }
+        self.dest.write_all(self.record_separator.as_bytes())?;
+        self.write_header = false;
+        Ok(())
     }
 }
 
-fn csv_escape<W: Write> (dest: &mut W, field: &[u8]) -> Result<()> {
+fn csv_escape<W: Write> (dest: &mut W, field: &[u8], record_separator: RecordSeparator) -> Result<()> {
     let field_needs_escaping = field.iter()
         .copied()
-        .any(|c| matches!(c, b',' | b'\n' | b'\r' | b'"'));
+        .any(|c| matches!(c, b',' | b'\n' | b'\r' | b'"') || c == record_separator.last_byte());
     if !field_needs_escaping {
         dest.write_all(field)?;
         return Ok(());
     }
     dest.write_all(b"\"")?;
-    for c in field.iter().copied() {
-        if c == b'"' {
+    // the runs between quotes are written whole, each followed by its quote doubled
+    let mut runs = field.split(|c| *c == b'"').peekable();
+    while let Some(run) = runs.next() {
+        dest.write_all(run)?;
+        if runs.peek().is_some() {
             dest.write_all(b"\"\"")?;
-        } else {
-            dest.write_all(&[c])?;
         }
     }
     dest.write_all(b"\"")?;
@@ -50,26 +143,23 @@ fn csv_escape<W: Write> (dest: &mut W, field: &[u8]) -> Result<()> {
 impl<W: Write> WriteEntry for CsvEntryWriter<W> {
     fn write_entry(&mut self, attr2values: &Entry) -> Result<()> {
         if self.write_header {
-            for (i, attrspec) in self.attrspecs.iter().enumerate() {
-                if i != 0 {
-                    self.dest.write_all(b",")?;
-                }
-                csv_escape(&mut self.dest, attrspec.attribute.as_bytes())?;
-            }
-            self.dest.write_all(b"\r\n")?;
-            self.write_header = false;
+            self.write_header_line()?;
         }
         let attrvalues: Vec<Vec<EntryValue>> = self.attrspecs.iter()
-            .map(|attrspec| attrspec.filter_values(attr2values.get(&attrspec.attribute_lowercase)).into_owned())
-            .collect();
+            .map(|attrspec| attrspec.entry_values(attr2values).map(|values| values.into_owned()))
+            .collect::<Result<_>>()?;
         for record in cartesian_product(&attrvalues) {
             for (i, value) in record.iter().enumerate() {
                 if i != 0 {
                     self.dest.write_all(b",")?;
                 }
-                csv_escape(&mut self.dest, value)?;
+                let value = match self.excel_policy {
+                    Some(policy) => policy.protect(value),
+                    None => Cow::Borrowed(&value[..]),
+                };
+                csv_escape(&mut self.dest, &value, self.record_separator)?;
             }
-            self.dest.write_all(b"\r\n")?;
+            self.dest.write_all(self.record_separator.as_bytes())?;
         }
         Ok(())
     }
@@ -92,5 +182,36 @@ mod test {
         assert_eq!(String::from_utf8_lossy(output.as_slice()), String::from_utf8_lossy(expected));
         Ok(())
     }
-}
 
+    #[test]
+    fn test_record_separator() -> Result<()> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut csv_entry_writer = CsvEntryWriter::new(vec![AttrSpec::parse("cn")?], &mut output);
+        csv_entry_writer.set_record_separator(RecordSeparator::NUL);
+        csv_entry_writer.write_entry(&Entry::from([("cn", b"foo\0bar".as_slice())]))?;
+        assert_eq!(String::from_utf8_lossy(&output), "cn\0\"foo\0bar\"\0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_excel_policy() -> Result<()> {
+        let values: [&[u8]; 8] = [b"007", b"0", b"1234567890123456", b"12E45", b"=1+1", b"-x", b"a\"b", b"123456789012345"];
+        for (policy, expected) in [
+            (ExcelPolicy::Formula, "\"=\"\"007\"\"\",0,\"=\"\"1234567890123456\"\"\",\"=\"\"12E45\"\"\",\"=\"\"=1+1\"\"\",\"=\"\"-x\"\"\",\"a\"\"b\",123456789012345\r\n"),
+            (ExcelPolicy::Prefix, "'007,0,'1234567890123456,'12E45,'=1+1,'-x,\"a\"\"b\",123456789012345\r\n"),
+        ] {
+            let attrspecs = (0..values.len()).map(|i| AttrSpec::parse(&format!("a{}", i))).collect::<Result<Vec<AttrSpec>>>()?;
+            let mut entry = Entry::default();
+            for (i, value) in values.iter().enumerate() {
+                entry.add_value(&format!("a{}", i), value);
+            }
+            let mut output: Vec<u8> = Vec::new();
+            CsvEntryWriter::new(attrspecs, &mut output)
+                .set_write_header(false)
+                .set_excel_policy(Some(policy))
+                .write_entry(&entry)?;
+            assert_eq!(String::from_utf8_lossy(&output), expected);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use crate::json::write_json_string;
+use crate::loc::{Loc, LocError};
+use std::error::Error;
+use std::fmt;
+use std::io::{stderr, Result, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in the input, such as by the linter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub severity: Severity,
+    /// A short name for the kind of problem, such as `missing-dn`.
+    pub code: &'static str,
+    pub message: String,
+    pub loc: Option<Loc>,
+    /// The DN of the entry the problem was found in, if known.
+    pub dn: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: String) -> Diagnostic {
+        Diagnostic{
+            file: None,
+            severity,
+            code,
+            message,
+            loc: None,
+            dn: None,
+        }
+    }
+
+    /// Describes an error, with the file and location it carries, if any. Errors at a location in
+    /// the input get the code `syntax`, other I/O errors the code `io` and other errors the code
+    /// `error`.
+    pub fn from_error(err: &(dyn Error + 'static)) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(Severity::Error, "error", err.to_string());
+        let mut err = err;
+        loop {
+            if let Some(file_err) = err.downcast_ref::<FileError>() {
+                diagnostic.file = Some(file_err.path.clone());
+                diagnostic.message = file_err.source.to_string();
+                err = &file_err.source;
+            } else if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+                diagnostic.code = "io";
+                match io_err.get_ref() {
+                    Some(inner) => err = inner,
+                    None => break,
+                }
+            } else {
+                if let Some(locerr) = err.downcast_ref::<LocError>() {
+                    diagnostic.code = "syntax";
+                    diagnostic.message = locerr.message.clone();
+                    diagnostic.loc = Some(locerr.loc);
+                }
+                break;
+            }
+        }
+        diagnostic
+    }
+
+    pub fn in_file(mut self, file: &str) -> Diagnostic {
+        self.file = Some(String::from(file));
+        self
+    }
+
+    pub fn at(mut self, loc: Loc) -> Diagnostic {
+        self.loc = Some(loc);
+        self
+    }
+
+    pub fn in_entry(mut self, dn: Option<&str>) -> Diagnostic {
+        self.dn = dn.map(String::from);
+        self
+    }
+
+    /// Writes the diagnostic as a line such as `users.ldif:3:1: error: entry lacks a DN [missing-dn]`.
+    pub fn write_text<W: Write>(&self, dest: &mut W) -> Result<()> {
+        write!(dest, "{}:", self.file.as_deref().unwrap_or("<stdin>"))?;
+        if let Some(loc) = self.loc {
+            write!(dest, "{}:{}:", loc.line, loc.column)?;
+        }
+        writeln!(dest, " {}: {} [{}]", self.severity, self.message, self.code)
+    }
+
+    /// Writes the diagnostic as a JSON object on a single line, with `null` for an unknown file,
+    /// location or DN.
+    pub fn write_json<W: Write>(&self, dest: &mut W) -> Result<()> {
+        dest.write_all(b"{\"file\":")?;
+        match self.file {
+            Some(ref file) => write_json_string(dest, file)?,
+            None => dest.write_all(b"null")?,
+        }
+        write!(dest, ",\"severity\":\"{}\",\"code\":", self.severity)?;
+        write_json_string(dest, self.code)?;
+        dest.write_all(b",\"message\":")?;
+        write_json_string(dest, &self.message)?;
+        match self.loc {
+            Some(loc) => write!(dest, ",\"line\":{},\"column\":{}", loc.line, loc.column)?,
+            None => dest.write_all(b",\"line\":null,\"column\":null")?,
+        }
+        dest.write_all(b",\"dn\":")?;
+        match self.dn {
+            Some(ref dn) => write_json_string(dest, dn)?,
+            None => dest.write_all(b"null")?,
+        }
+        dest.write_all(b"}\n")
+    }
+}
+
+/// An error reading or writing a file, which is displayed as the path followed by the error.
+#[derive(Debug)]
+pub struct FileError {
+    pub path: String,
+    pub source: std::io::Error,
+}
+
+impl FileError {
+    pub fn new(path: &str, source: std::io::Error) -> FileError {
+        FileError{
+            path: String::from(path),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.source)
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<FileError> for std::io::Error {
+    fn from(err: FileError) -> std::io::Error {
+        std::io::Error::new(err.source.kind(), err)
+    }
+}
+
+/// Makes `report` and `report_error` write JSON objects as by `Diagnostic::write_json` instead of
+/// text.
+pub fn set_json_output(json_output: bool) {
+    JSON_OUTPUT.store(json_output, Ordering::Relaxed);
+}
+
+/// Writes a warning or error to standard error, either as text prefixed by the program name or
+/// as JSON.
+pub fn report(program: &str, diagnostic: &Diagnostic) {
+    let mut dest = stderr().lock();
+    _ = if JSON_OUTPUT.load(Ordering::Relaxed) {
+        diagnostic.write_json(&mut dest)
+    } else {
+        writeln!(dest, "{}: {}: {}", program, diagnostic.severity, diagnostic.message)
+    };
+}
+
+/// Writes an error that ends the program to standard error, as `report` does. Text is written as
+/// the program name followed by the error.
+pub fn report_error(program: &str, err: &(dyn Error + 'static)) {
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        report(program, &Diagnostic::from_error(err));
+    } else {
+        eprintln!("{}: {}", program, err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let diagnostic = Diagnostic::new(Severity::Warning, "trailing-whitespace", String::from("value ends with whitespace"))
+            .at(Loc{ line: 2, column: 5, offset: 20 })
+            .in_entry(Some("cn=foo"))
+            .in_file("a.ldif");
+        let mut text: Vec<u8> = Vec::new();
+        diagnostic.write_text(&mut text)?;
+        assert_eq!(String::from_utf8_lossy(&text), "a.ldif:2:5: warning: value ends with whitespace [trailing-whitespace]\n");
+        let mut json: Vec<u8> = Vec::new();
+        diagnostic.write_json(&mut json)?;
+        assert_eq!(String::from_utf8_lossy(&json), concat!(
+            r#"{"file":"a.ldif","severity":"warning","code":"trailing-whitespace","message":"value ends with whitespace","#,
+            r#""line":2,"column":5,"dn":"cn=foo"}"#, "\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_error() {
+        let locerr = LocError::new(Loc{ line: 3, column: 7, offset: 30 }, String::from("unexpected character"));
+        let err: std::io::Error = FileError::new("a.ldif", locerr.into()).into();
+        let diagnostic = Diagnostic::from_error(&err);
+        assert_eq!(diagnostic.code, "syntax");
+        assert_eq!(diagnostic.file.as_deref(), Some("a.ldif"));
+        assert_eq!(diagnostic.loc.map(|loc| (loc.line, loc.column)), Some((3, 7)));
+        assert_eq!(err.to_string(), "a.ldif: unexpected character");
+        let err: Box<dyn Error> = "missing attribute type name".into();
+        assert_eq!(Diagnostic::from_error(&*err).code, "error");
+    }
+}
@@ -0,0 +1,122 @@
+//! Differences between two sequences sorted in the same order, found by merging them in a single
+//! pass, as lcompare does with the entries of two LDIF files sorted by DN.
+
+use core::cmp::Ordering;
+use core::iter::{FusedIterator, Peekable};
+
+/// A difference between an old and a new sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diff<T> {
+    /// An item that is only in the new sequence.
+    Add(T),
+    /// An item that is only in the old sequence.
+    Delete(T),
+    /// An old and a new item that compare as equal, such as entries with the same DN, which may
+    /// still differ otherwise.
+    Modify(T, T),
+}
+
+/// Iterates over the differences between two sequences that are sorted by the same comparison,
+/// in that order. Items that are in both sequences give a `Diff::Modify`, whether or not they
+/// differ otherwise. Items are moved out of the sequences, so they can be references into
+/// collections that outlive the comparison, as in
+///
+/// ```
+/// use ltools::diff::{Diff, SortedComparison};
+///
+/// let old = vec![("a", 1), ("b", 2)];
+/// let new = vec![("b", 3), ("c", 4)];
+/// let diffs: Vec<Diff<&(&str, i32)>> = SortedComparison::new(&old, &new, |x, y| x.0.cmp(y.0)).collect();
+/// assert_eq!(diffs, [Diff::Delete(&("a", 1)), Diff::Modify(&("b", 2), &("b", 3)), Diff::Add(&("c", 4))]);
+/// ```
+pub struct SortedComparison<O, N, F>
+where
+    O: Iterator,
+    N: Iterator<Item = O::Item>,
+    F: FnMut(&O::Item, &O::Item) -> Ordering,
+{
+    old: Peekable<O>,
+    new: Peekable<N>,
+    compare: F,
+}
+
+impl<O, N, F> SortedComparison<O, N, F>
+where
+    O: Iterator,
+    N: Iterator<Item = O::Item>,
+    F: FnMut(&O::Item, &O::Item) -> Ordering,
+{
+    /// Compares the old and new sequences, which should both be sorted by compare. Unsorted
+    /// sequences give spurious adds and deletes rather than an error.
+    pub fn new<IO, IN>(old: IO, new: IN, compare: F) -> Self
+    where
+        IO: IntoIterator<IntoIter = O>,
+        IN: IntoIterator<IntoIter = N>,
+    {
+        SortedComparison{
+            old: old.into_iter().peekable(),
+            new: new.into_iter().peekable(),
+            compare,
+        }
+    }
+}
+
+impl<O, N, F> Iterator for SortedComparison<O, N, F>
+where
+    O: Iterator,
+    N: Iterator<Item = O::Item>,
+    F: FnMut(&O::Item, &O::Item) -> Ordering,
+{
+    type Item = Diff<O::Item>;
+
+    fn next(&mut self) -> Option<Diff<O::Item>> {
+        let ordering = match (self.old.peek(), self.new.peek()) {
+            (Some(old), Some(new)) => (self.compare)(old, new),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+        match ordering {
+            Ordering::Less => self.old.next().map(Diff::Delete),
+            Ordering::Greater => self.new.next().map(Diff::Add),
+            Ordering::Equal => Some(Diff::Modify(self.old.next()?, self.new.next()?)),
+        }
+    }
+}
+
+impl<O, N, F> FusedIterator for SortedComparison<O, N, F>
+where
+    O: Iterator,
+    N: Iterator<Item = O::Item>,
+    F: FnMut(&O::Item, &O::Item) -> Ordering,
+{
+}
+
+/// The comparison of sequences sorted by their natural order that `sorted_diff` returns.
+pub type NaturalComparison<O, N, T> = SortedComparison<O, N, fn(&T, &T) -> Ordering>;
+
+/// Compares two sequences sorted by their natural order, as by `SortedComparison`.
+pub fn sorted_diff<T, IO, IN>(old: IO, new: IN) -> NaturalComparison<IO::IntoIter, IN::IntoIter, T>
+where
+    T: Ord,
+    IO: IntoIterator<Item = T>,
+    IN: IntoIterator<Item = T>,
+{
+    SortedComparison::new(old, new, Ord::cmp)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_sorted_diff() {
+        let old = [1, 3, 5];
+        let new = [2, 3, 4];
+        let diffs: Vec<Diff<&i32>> = sorted_diff(&old, &new).collect();
+        assert_eq!(diffs, [Diff::Delete(&1), Diff::Add(&2), Diff::Modify(&3, &3), Diff::Add(&4), Diff::Delete(&5)]);
+        let diffs: Vec<Diff<i32>> = sorted_diff(Vec::new(), [1]).collect();
+        assert_eq!(diffs, [Diff::Add(1)]);
+    }
+}
@@ -0,0 +1,188 @@
+/// Iterates over the parts of a string separated by a character that is not escaped with a
+/// backslash.
+pub struct SplitUnescaped<'a> {
+    remainder: Option<&'a str>,
+    separator: u8,
+}
+
+impl<'a> Iterator for SplitUnescaped<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let s = self.remainder?;
+        match find_unescaped(s, self.separator) {
+            Some(i) => {
+                self.remainder = Some(&s[i + 1..]);
+                Some(&s[..i])
+            },
+            None => {
+                self.remainder = None;
+                Some(s)
+            },
+        }
+    }
+}
+
+fn split_unescaped(s: &str, separator: u8) -> SplitUnescaped<'_> {
+    SplitUnescaped{
+        remainder: Some(s),
+        separator,
+    }
+}
+
+fn find_unescaped(s: &str, separator: u8) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.bytes().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            c if c == separator => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Iterates over the RDNs of a DN, starting with the leftmost one. Spaces after the separating
+/// commas are skipped.
+pub fn rdns(dn: &str) -> impl Iterator<Item = &str> {
+    split_unescaped(dn, b',')
+        .map(|rdn| rdn.trim_start_matches(' '))
+        .filter(|rdn| !rdn.is_empty())
+}
+
+/// Returns the DN in a form for comparing it with other DNs, in lowercase and without spaces
+/// after the separating commas.
+pub fn normalize(dn: &str) -> String {
+    rdns(dn).collect::<Vec<&str>>().join(",").to_ascii_lowercase()
+}
+
+/// Returns the DN of the parent entry, or None if the DN has a single RDN.
+pub fn parent(dn: &str) -> Option<&str> {
+    let i = find_unescaped(dn, b',')?;
+    let parent = dn[i + 1..].trim_start_matches(' ');
+    if parent.is_empty() {
+        None
+    } else {
+        Some(parent)
+    }
+}
+
+/// Returns whether the DN is the same as another DN or one of its ancestors, where both are
+/// normalized as by `normalize`.
+pub fn is_ancestor_or_self(dn: &str, other: &str) -> bool {
+    match other.len().checked_sub(dn.len() + 1) {
+        _ if other == dn => true,
+        Some(i) => other.ends_with(dn) && other.as_bytes()[i] == b',' && !other[..i].ends_with('\\'),
+        None => false,
+    }
+}
+
+/// Returns the leftmost RDN of a DN.
+pub fn rdn(dn: &str) -> &str {
+    rdns(dn).next().unwrap_or("")
+}
+
+/// Iterates over the attribute type and escaped value of each attribute-value assertion in an
+/// RDN, such as `cn=foo+uid=bar`.
+pub fn avas(rdn: &str) -> impl Iterator<Item = (&str, &str)> {
+    split_unescaped(rdn, b'+')
+        .map(|ava| ava.split_once('=').unwrap_or((ava, "")))
+}
+
+/// Removes the escaping of an attribute value in a DN.
+pub fn unescape_value(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut unescaped: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' || i + 1 == bytes.len() {
+            unescaped.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex = bytes.get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(c) => {
+                unescaped.push(c);
+                i += 3;
+            },
+            None => {
+                unescaped.push(bytes[i + 1]);
+                i += 2;
+            },
+        }
+    }
+    unescaped
+}
+
+/// Escapes an attribute value for use in a DN, as RFC 4514 requires: `"`, `+`, `,`, `;`, `<`, `>`
+/// and `\` are escaped with a backslash, as are a leading space or `#` and a trailing space. NUL
+/// and other control characters, and bytes that aren't part of valid UTF-8, are escaped as two hex
+/// digits. Other characters, including `=` and those outside ASCII, are left as they are.
+pub fn escape_value(value: &[u8]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut end = 0; // where the current character ends in value
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            let start = end;
+            end += c.len_utf8();
+            match c {
+                '"' | '+' | ',' | ';' | '<' | '>' | '\\' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                },
+                ' ' | '#' if start == 0 => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                },
+                ' ' if end == value.len() => escaped.push_str("\\ "),
+                c if c.is_ascii_control() => escaped.push_str(&format!("\\{:02x}", c as u8)),
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            end += 1;
+            escaped.push_str(&format!("\\{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rdns() {
+        let dn = "cn=Smith\\, John+uid=jsmith, ou=people,dc=example";
+        let rdns: Vec<&str> = rdns(dn).collect();
+        assert_eq!(rdns, ["cn=Smith\\, John+uid=jsmith", "ou=people", "dc=example"]);
+        assert_eq!(parent(dn), Some("ou=people,dc=example"));
+        assert_eq!(parent("dc=example"), None);
+        assert_eq!(rdn(dn), "cn=Smith\\, John+uid=jsmith");
+        assert_eq!(normalize(dn), "cn=smith\\, john+uid=jsmith,ou=people,dc=example");
+        assert!(is_ancestor_or_self("ou=people,dc=example", "cn=a,ou=people,dc=example"));
+        assert!(is_ancestor_or_self("dc=example", "dc=example"));
+        assert!(!is_ancestor_or_self("dc=example", "cn=a\\,dc=example"));
+        assert!(!is_ancestor_or_self("dc=example", "ou=people,dc=example2"));
+        let avas: Vec<(&str, &str)> = avas(rdn(dn)).collect();
+        assert_eq!(avas, [("cn", "Smith\\, John"), ("uid", "jsmith")]);
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(unescape_value("Smith\\, John"), b"Smith, John");
+        assert_eq!(unescape_value("\\c3\\a5\\2B"), "å+".as_bytes());
+        assert_eq!(unescape_value("trailing\\"), b"trailing\\");
+        assert_eq!(unescape_value("a\\+1"), b"a+1");
+        assert_eq!(escape_value(b" Smith, John "), "\\ Smith\\, John\\ ");
+        assert_eq!(escape_value("å=1".as_bytes()), "å=1");
+        assert_eq!(escape_value(b"a\0\n\xff"), "a\\00\\0a\\ff");
+        let value = b"#a=b+c;<d>\\";
+        assert_eq!(unescape_value(&escape_value(value)), value);
+    }
+}
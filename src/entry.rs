@@ -1,8 +1,13 @@
-use std::collections::HashMap;
-use std::borrow::{ Cow, Borrow };
-use std::io::{ Result, Write };
-use std::ops::Deref;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::borrow::Cow;
+use std::io::{ Result, Write, IoSlice };
+use std::ops::Range;
+use std::sync::Arc;
 use crate::base64::{EncodeWriter, DecodeWriter, DecodeState};
+use crate::dn::{avas, parent, rdn, rdns, unescape_value};
+use crate::ldif::Base64Policy;
+use crate::output::write_all_vectored;
+use crate::loc::base64_error;
 use crate::lexer::{
     Token,
     TokenKind,
@@ -14,104 +19,331 @@ pub struct AttributeType<'a> {
     pub lowercase: &'a str,
 }
 
+/// Where an entry was read from, as recorded by `EntryTokenWriter`, for tracing entries back to
+/// their input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// The name of the input, or None for standard input.
+    pub file: Option<Arc<str>>,
+    /// The line the entry starts on, counting from 1.
+    pub line: usize,
+    /// The number of the entry among the entries of its input, counting from 1.
+    pub ordinal: usize,
+}
+
+/// Tells whether the attribute is a pseudo-attribute such as `@line`, whose values are computed
+/// by `Entry::pseudo_value` rather than read.
+pub fn is_pseudo_attribute(attr: &str) -> bool {
+    attr.starts_with('@')
+}
+
+/// The values of an attribute that differ between two entries, as returned by `Entry::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttrDiff<'a> {
+    /// The name of the attribute in lowercase.
+    pub attr: String,
+    /// The values that only the first entry has.
+    pub removed: Vec<&'a [u8]>,
+    /// The values that only the second entry has.
+    pub added: Vec<&'a [u8]>,
+}
+
 #[derive(PartialEq)]
 enum ValueType {
     Text,
     Base64,
 }
 
-pub struct Entry<'a, 'b>
-where 'a: 'b
-{
-    attrnames: Option<HashMap<String, String>>, // original case names
-    attr2values: HashMap<String, Cow<'a, Vec<EntryValue<'b>>>>,
+// An attribute of an entry, with its values given as ranges into the entry's value arena.
+#[derive(Clone)]
+struct EntryAttribute {
+    name: String, // original case
+    lowercase: String,
+    values: Vec<Range<usize>>,
+    base64: Vec<bool>, // for each value, whether it was base64-encoded in the input
 }
 
-pub type EntryValue<'a> = Cow<'a, Vec<u8>>;
+/// An LDIF entry. The values of all attributes are stored back to back in a single byte buffer
+/// (the arena), which the token writer reuses between entries so that parsing does not need to
+/// allocate for every value. Attributes are kept in order and looked up through an index by
+/// lowercase name, since the token writer's list of attributes is every attribute seen so far.
+pub struct Entry<'a> {
+    arena: Cow<'a, [u8]>,
+    attrs: Cow<'a, [EntryAttribute]>,
+    attr2index: Cow<'a, HashMap<String, usize>>,
+    provenance: Option<Provenance>,
+}
+
+pub type EntryValue<'a> = Cow<'a, [u8]>;
+
+impl<'a> Entry<'a> {
+    fn index(&self, attr: &str) -> Option<usize> {
+        match self.attr2index.get(attr) {
+            Some(index) => Some(*index),
+            None if attr.bytes().any(|c| c.is_ascii_uppercase()) => self.attr2index.get(&attr.to_ascii_lowercase()).copied(),
+            None => None,
+        }
+    }
 
-const NO_VALUES: &Vec<EntryValue<'static>> = &vec![];
+    fn find(&self, attr: &str) -> Option<&EntryAttribute> {
+        self.index(attr).map(|index| &self.attrs[index])
+    }
 
-impl<'a, 'b> Entry<'a, 'b> {
     pub fn get(&self, attr: &str) -> impl Iterator<Item = &[u8]> {
-        let attr = attr.to_ascii_lowercase();
-        let values: Option<&Vec<Cow<Vec<u8>>>> = self.attr2values.get(&attr)
-            .map(|values| values.borrow());
-        let values: &Vec<Cow<Vec<u8>>> = values.unwrap_or(NO_VALUES);
-        values.iter()
-            .map(|value: &Cow<Vec<u8>>| {
-                let value: &Vec<u8> = value.borrow();
-                let value: &[u8] = &value[..];
-                value
-            })
+        self.find(attr)
+            .into_iter()
+            .flat_map(|entryattr| entryattr.values.iter())
+            .map(|range| &self.arena[range.clone()])
+    }
+
+    /// Returns the values of the attribute along with whether each of them was base64-encoded
+    /// in the input, for writing them back out the same way.
+    pub fn get_with_encoding(&self, attr: &str) -> impl Iterator<Item = (&[u8], bool)> {
+        self.find(attr)
+            .into_iter()
+            .flat_map(|entryattr| entryattr.values.iter().zip(entryattr.base64.iter()))
+            .map(|(range, base64)| (&self.arena[range.clone()], *base64))
     }
 
     pub fn get_one(&self, attr: &str) -> Option<&[u8]> {
-        if let Some(value) = self.get(attr).next() {
-            Some(value)
-        } else {
-            None
-        }
+        self.get(attr).next()
     }
 
-    pub fn get_str(&self, attr: &str) -> impl Iterator<Item = Cow<str>> {
-        // lifetimes are confusing
-        let values: Vec<Cow<str>> = self.get(attr)
+    pub fn get_str(&self, attr: &str) -> impl Iterator<Item = Cow<'_, str>> {
+        self.get(attr)
             .map(String::from_utf8_lossy)
-            .collect();
-        values.into_iter()
     }
 
-    pub fn get_one_str(&self, attr: &str) -> Option<Cow<str>> {
+    pub fn get_one_str(&self, attr: &str) -> Option<Cow<'_, str>> {
         self.get_one(attr)
             .map(String::from_utf8_lossy)
     }
 
-    // first is original case, second is lowercase
     pub fn attributes(&self) -> impl Iterator<Item = AttributeType<'_>>
     {
-        let attrs: Vec<AttributeType<'_>> = self.attr2values.iter()
-            .filter(|(_, values)| {
-                !values.is_empty()
-            })
-            .map(|(attr, _)| {
-                let mut attrname: &str = attr.borrow();
-                if let Some(ref attrnames) = self.attrnames {
-                    if let Some(attr) = attrnames.get(attr) {
-                        attrname = attr.borrow();
-                    }
-                }
-                AttributeType{
-                    name: attrname,
-                    lowercase: attr.borrow()
-                }
+        self.attrs.iter()
+            .filter(|entryattr| !entryattr.values.is_empty())
+            .map(|entryattr| AttributeType{
+                name: &entryattr.name,
+                lowercase: &entryattr.lowercase,
             })
+    }
+
+    /// Returns where the entry was read from, if it was read by an `EntryTokenWriter`.
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    pub fn set_provenance(&mut self, provenance: Option<Provenance>) {
+        self.provenance = provenance;
+    }
+
+    /// Returns the value of a pseudo-attribute, which is computed rather than read: `@file`,
+    /// `@line` and `@ordinal` give the fields of the provenance of the entry, and `@rdn`,
+    /// `@parent` and `@depth` the unescaped value of the first attribute of the RDN, the DN of
+    /// the parent and the number of RDNs of the DN. Returns None for other attributes and where
+    /// there is no value, such as for the parent of an entry with a single RDN.
+    pub fn pseudo_value(&self, attr: &str) -> Option<Vec<u8>> {
+        let provenance = self.provenance.as_ref();
+        if attr.eq_ignore_ascii_case("@rdn") {
+            let dn = self.get_one_str("dn")?;
+            let value = avas(rdn(&dn)).next().map(|(_, value)| unescape_value(value));
+            value
+        } else if attr.eq_ignore_ascii_case("@parent") {
+            let dn = self.get_one_str("dn")?;
+            parent(&dn).map(|parent| parent.as_bytes().to_vec())
+        } else if attr.eq_ignore_ascii_case("@depth") {
+            let dn = self.get_one_str("dn")?;
+            Some(rdns(&dn).count().to_string().into_bytes())
+        } else if attr.eq_ignore_ascii_case("@file") {
+            provenance?.file.as_ref().map(|file| file.as_bytes().to_vec())
+        } else if attr.eq_ignore_ascii_case("@line") {
+            provenance.map(|provenance| provenance.line.to_string().into_bytes())
+        } else if attr.eq_ignore_ascii_case("@ordinal") {
+            provenance.map(|provenance| provenance.ordinal.to_string().into_bytes())
+        } else {
+            None
+        }
+    }
+
+    /// Appends a value to the given attribute, adding the attribute if the entry lacks it.
+    pub fn add_value(&mut self, attr: &str, value: &[u8]) {
+        self.add_value_with_encoding(attr, value, false)
+    }
+
+    /// Appends a value like add_value, noting whether it was base64-encoded in the input.
+    pub fn add_value_with_encoding(&mut self, attr: &str, value: &[u8], base64: bool) {
+        let arena = self.arena.to_mut();
+        let range = arena.len()..arena.len() + value.len();
+        arena.extend_from_slice(value);
+        match self.index(attr) {
+            Some(index) => {
+                let entryattr = &mut self.attrs.to_mut()[index];
+                entryattr.values.push(range);
+                entryattr.base64.push(base64);
+            },
+            None => {
+                let attrs = self.attrs.to_mut();
+                self.attr2index.to_mut().insert(attr.to_ascii_lowercase(), attrs.len());
+                attrs.push(EntryAttribute{
+                    name: attr.to_string(),
+                    lowercase: attr.to_ascii_lowercase(),
+                    values: vec![range],
+                    base64: vec![base64],
+                });
+            },
+        }
+    }
+
+    /// Removes a value of the given attribute. Returns false if the entry lacks the value. The
+    /// space of the value is not reclaimed until the entry is copied.
+    pub fn remove_value(&mut self, attr: &str, value: &[u8]) -> bool {
+        let Some(index) = self.index(attr) else {
+            return false;
+        };
+        let arena = &self.arena;
+        let entryattr = &mut self.attrs.to_mut()[index];
+        match entryattr.values.iter().position(|range| &arena[range.clone()] == value) {
+            Some(index) => {
+                entryattr.values.remove(index);
+                entryattr.base64.remove(index);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Removes every value of the given attribute.
+    pub fn remove_attribute(&mut self, attr: &str) {
+        if let Some(index) = self.index(attr) {
+            let entryattr = &mut self.attrs.to_mut()[index];
+            entryattr.values.clear();
+            entryattr.base64.clear();
+        }
+    }
+
+    /// Returns a hash of the attributes and values of the entry other than the DN, for telling
+    /// whether an entry has changed between two snapshots without comparing it in full. The hash
+    /// does not depend on the order of attributes and values, the case of attribute names or
+    /// repeated values, and is the same across runs and platforms.
+    pub fn fingerprint(&self) -> u64 {
+        let mut attrvals: Vec<(&str, &[u8])> = self.attrs.iter()
+            .filter(|entryattr| entryattr.lowercase != "dn")
+            .flat_map(|entryattr| entryattr.values.iter()
+                .map(|range| (entryattr.lowercase.as_str(), &self.arena[range.clone()])))
+            .collect();
+        attrvals.sort_unstable();
+        attrvals.dedup();
+        let mut hash = Fnv1a::default();
+        for (attr, value) in attrvals {
+            // the lengths keep the boundaries between names and values from being ambiguous
+            hash.write(&(attr.len() as u64).to_le_bytes());
+            hash.write(attr.as_bytes());
+            hash.write(&(value.len() as u64).to_le_bytes());
+            hash.write(value);
+        }
+        hash.0
+    }
+
+    /// Returns whether the entries have the same values for the same attributes, ignoring the
+    /// ASCII case of values as well as of attribute names. The order and repetition of values do
+    /// not matter, and the DN is compared like any other attribute.
+    pub fn eq_ignore_case(&self, other: &Entry) -> bool {
+        self.is_subset_by(other, <[u8]>::eq_ignore_ascii_case) && other.is_subset_by(self, <[u8]>::eq_ignore_ascii_case)
+    }
+
+    /// Returns whether every value of the entry, including its DN, is also a value of the same
+    /// attribute of other, as when other is the entry with values added.
+    pub fn is_subset_of(&self, other: &Entry) -> bool {
+        self.is_subset_by(other, |value, other_value| value == other_value)
+    }
+
+    fn is_subset_by<F: Fn(&[u8], &[u8]) -> bool>(&self, other: &Entry, eq: F) -> bool {
+        self.attrs.iter().all(|entryattr| entryattr.values.iter().all(|range| {
+            let value = &self.arena[range.clone()];
+            other.get(&entryattr.lowercase).any(|other_value| eq(value, other_value))
+        }))
+    }
+
+    /// Returns the values that would have to be removed from and added to the entry for it to
+    /// become other, comparing the values of each attribute as a set, as lcompare does. Only the
+    /// attributes that differ are included, in alphabetical order. The DN is not compared.
+    pub fn diff<'b>(&'b self, other: &'b Entry) -> Vec<AttrDiff<'b>> {
+        let attrs: BTreeSet<&str> = self.attributes().chain(other.attributes())
+            .map(|attr| attr.lowercase)
+            .filter(|attr| *attr != "dn")
             .collect();
         attrs.into_iter()
+            .filter_map(|attr| {
+                let old: Vec<&[u8]> = self.get(attr).collect();
+                let new: Vec<&[u8]> = other.get(attr).collect();
+                let old_set: HashSet<&[u8]> = old.iter().copied().collect();
+                let new_set: HashSet<&[u8]> = new.iter().copied().collect();
+                let diff = AttrDiff{
+                    attr: attr.to_string(),
+                    removed: values_missing_from(&old, &new_set),
+                    added: values_missing_from(&new, &old_set),
+                };
+                Some(diff).filter(|diff| !diff.removed.is_empty() || !diff.added.is_empty())
+            })
+            .collect()
     }
 }
 
-pub type OwnedEntry = Entry<'static, 'static>;
+// The distinct values that others lacks, in order.
+fn values_missing_from<'a>(values: &[&'a [u8]], others: &HashSet<&[u8]>) -> Vec<&'a [u8]> {
+    let mut seen: HashSet<&[u8]> = HashSet::new();
+    values.iter()
+        .copied()
+        .filter(|value| !others.contains(value) && seen.insert(value))
+        .collect()
+}
 
-impl<'a, 'b> From<&Entry<'a, 'b>> for OwnedEntry {
-    fn from(entry: &Entry<'a, 'b>) -> OwnedEntry {
-        let attr2values: HashMap<String, Cow<'static, Vec<EntryValue<'static>>>> = entry.attr2values.iter()
-            .map(|(attr, values): (&String, &Cow<Vec<EntryValue>>)| {
-                let attr = attr.to_ascii_lowercase();
-                let values = Cow::Owned(
-                    values.iter()
-                        .map(|value: &EntryValue| Cow::Owned(value.deref().clone()))
-                        .collect()
-                );
-                (attr, values)
-            })
-            .collect();
+// The 64-bit FNV-1a hash, which unlike the hashers of the standard library is specified to
+// stay the same.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+impl Default for Entry<'_> {
+    fn default() -> Self {
         Entry{
-            attrnames: entry.attrnames.clone(),
-            attr2values,
+            arena: Cow::Owned(Vec::new()),
+            attrs: Cow::Owned(Vec::new()),
+            attr2index: Cow::Owned(HashMap::new()),
+            provenance: None,
         }
     }
 }
 
+pub type OwnedEntry = Entry<'static>;
+
+impl<'a> From<&Entry<'a>> for OwnedEntry {
+    fn from(entry: &Entry<'a>) -> OwnedEntry {
+        let mut owned = OwnedEntry::default();
+        for attr in entry.attributes() {
+            for (value, base64) in entry.get_with_encoding(attr.lowercase) {
+                owned.add_value_with_encoding(attr.name, value, base64);
+            }
+        }
+        owned.provenance = entry.provenance.clone();
+        owned
+    }
+}
+
 impl WriteEntry for Vec<OwnedEntry> {
     fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
         self.push(entry.into());
@@ -119,24 +351,13 @@ impl WriteEntry for Vec<OwnedEntry> {
     }
 }
 
-impl<const N: usize> From<[(&str, &[u8]); N]> for Entry<'static, 'static> {
-    fn from(array: [(&str, &[u8]); N]) -> Entry<'static, 'static> {
-        let mut attr2values: HashMap<String, Cow<Vec<EntryValue>>> = HashMap::new();
+impl<const N: usize> From<[(&str, &[u8]); N]> for OwnedEntry {
+    fn from(array: [(&str, &[u8]); N]) -> OwnedEntry {
+        let mut entry = OwnedEntry::default();
         for (attr, value) in array.into_iter() {
-            let mut values: Option<&mut Cow<Vec<EntryValue>>> = attr2values.get_mut(attr);
-            if values.is_none() {
-                attr2values.insert(attr.to_owned(), Cow::Owned(Vec::new()));
-                values = attr2values.get_mut(attr)
-            }
-            if let Some(values) = values {
-                let values: &mut Vec<EntryValue> = values.to_mut();
-                values.push(Cow::Owned(Vec::from(value)));
-            }
-        }
-        Entry{
-            attrnames: None,
-            attr2values,
+            entry.add_value(attr, value);
         }
+        entry
     }
 }
 
@@ -144,12 +365,77 @@ pub trait WriteEntry {
     fn write_entry(&mut self, attr2values: &Entry) -> Result<()>;
 }
 
-impl<W: WriteEntry> WriteEntry for &mut W {
+impl<W: WriteEntry + ?Sized> WriteEntry for &mut W {
     fn write_entry(&mut self, attr2values: &Entry) -> Result<()> {
         (*self).write_entry(attr2values)
     }
 }
 
+impl WriteEntry for VecDeque<OwnedEntry> {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        self.push_back(entry.into());
+        Ok(())
+    }
+}
+
+/// A source of entries to pull from, the counterpart of WriteEntry for code that is easier to
+/// write as a loop or with iterators than as a destination that entries are pushed to.
+pub trait ReadEntry {
+    /// Returns the next entry, or None at the end of the entries.
+    fn read_entry(&mut self) -> Result<Option<OwnedEntry>>;
+
+    /// Returns an iterator over the entries, which ends after the first error.
+    fn entries(self) -> Entries<Self> where Self: Sized {
+        Entries{ reader: self, failed: false }
+    }
+}
+
+impl<R: ReadEntry + ?Sized> ReadEntry for &mut R {
+    fn read_entry(&mut self) -> Result<Option<OwnedEntry>> {
+        (**self).read_entry()
+    }
+}
+
+impl ReadEntry for VecDeque<OwnedEntry> {
+    fn read_entry(&mut self) -> Result<Option<OwnedEntry>> {
+        Ok(self.pop_front())
+    }
+}
+
+/// An iterator over the entries of a ReadEntry, as returned by `ReadEntry::entries`.
+pub struct Entries<R: ReadEntry> {
+    reader: R,
+    failed: bool,
+}
+
+impl<R: ReadEntry> Iterator for Entries<R> {
+    type Item = Result<OwnedEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.reader.read_entry() {
+            Ok(entry) => entry.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            },
+        }
+    }
+}
+
+/// Pushes every entry of the reader to the writer, like `std::io::copy`, and returns the number
+/// of entries.
+pub fn copy_entries<R: ReadEntry + ?Sized, W: WriteEntry + ?Sized>(reader: &mut R, writer: &mut W) -> Result<u64> {
+    let mut count: u64 = 0;
+    while let Some(entry) = reader.read_entry()? {
+        writer.write_entry(&entry)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
 #[derive(Eq, PartialEq)]
 enum WriterState {
     Start,
@@ -159,68 +445,82 @@ enum WriterState {
     Processing,
 }
 
-pub struct EntryTokenWriter<'a, W: WriteEntry> {
+pub struct EntryTokenWriter<W: WriteEntry> {
     all_attributes: bool,
     state: WriterState,
     attr2index: HashMap<String, usize>,
-    attrnames: Vec<String>, // original case attribute names
-    attrvalues: Vec<Vec<EntryValue<'a>>>,
+    attrs: Vec<EntryAttribute>, // attributes seen so far, reused between entries
+    arena: Vec<u8>, // values of the current entry
     attrmatch: Option<usize>, // index of currently matched attribute
-    valuebuf: Vec<u8>,
+    value_start: usize, // arena offset of the current value
     dest: W,
     valuetype: ValueType,
     b64state: DecodeState,
     ignore_entries_without_dn: bool,
+    file: Option<Arc<str>>,
+    line: usize, // that the current entry starts on
+    ordinal: usize, // of the last entry written
 }
 
-impl<'a, W: WriteEntry> EntryTokenWriter<'a, W> {
-    pub fn new(dest: W) -> EntryTokenWriter<'a, W> {
+impl<W: WriteEntry> EntryTokenWriter<W> {
+    pub fn new(dest: W) -> EntryTokenWriter<W> {
         EntryTokenWriter{
             all_attributes: true,
             state: WriterState::Start,
             attr2index: HashMap::new(),
-            attrnames: Vec::new(),
-            attrvalues: Vec::new(),
+            attrs: Vec::new(),
+            arena: Vec::new(),
             attrmatch: None,
-            valuebuf: Vec::new(),
+            value_start: 0,
             dest,
             valuetype: ValueType::Text,
             b64state: DecodeState::default(),
             ignore_entries_without_dn: false,
+            file: None,
+            line: 0,
+            ordinal: 0,
         }
     }
 
-    pub fn new_for_attributes(attributes: Vec<String>, dest: W) -> EntryTokenWriter<'a, W> {
-        let attrvalues = attributes.iter()
-            .map(|_| Vec::new())
-            .collect();
-        let attr2index = attributes.iter()
-            .map(|attr| attr.to_ascii_lowercase())
-            .enumerate()
-            .map(|(v, k)| (k, v))
-            .collect();
-        EntryTokenWriter{
-            all_attributes: false,
-            state: WriterState::Start,
-            attrnames: attributes,
-            attr2index,
-            attrvalues,
-            attrmatch: None,
-            valuebuf: Vec::new(),
-            dest,
-            valuetype: ValueType::Text,
-            b64state: DecodeState::default(),
-            ignore_entries_without_dn: false,
+    pub fn new_for_attributes(attributes: Vec<String>, dest: W) -> EntryTokenWriter<W> {
+        let mut token_writer = EntryTokenWriter::new(dest);
+        token_writer.all_attributes = false;
+        for attr in attributes.into_iter() {
+            token_writer.add_attribute(attr);
         }
+        token_writer
+    }
+
+    fn add_attribute(&mut self, name: String) -> usize {
+        let lowercase = name.to_ascii_lowercase();
+        let index = self.attrs.len();
+        self.attr2index.insert(lowercase.clone(), index);
+        self.attrs.push(EntryAttribute{
+            name,
+            lowercase,
+            values: Vec::new(),
+            base64: Vec::new(),
+        });
+        index
     }
 
     pub fn set_ignore_entries_without_dn(&mut self, value: bool) -> &mut Self {
         self.ignore_entries_without_dn = value;
         self
     }
+
+    /// Sets the name of the input that the provenance of the entries gives.
+    pub fn set_file(&mut self, file: Option<&str>) -> &mut Self {
+        self.file = file.map(Arc::from);
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
 }
 
-impl<'a, W: WriteEntry> WriteToken for EntryTokenWriter<'a, W> {
+impl<W: WriteEntry> WriteToken for EntryTokenWriter<W> {
     fn write_token(&mut self, token: Token) -> Result<()> {
         match token.kind {
             TokenKind::AttributeType => {
@@ -243,36 +543,34 @@ impl<'a, W: WriteEntry> WriteToken for EntryTokenWriter<'a, W> {
                     } else {
                         WriterState::Ignoring
                     };
+                    self.line = token.loc.line;
                 }
                 self.attrmatch = if self.state == WriterState::Processing {
                     let index: Option<usize> = self.attr2index.get(&attrlowercase).copied();
                     if index.is_none() && self.all_attributes {
-                        let index: usize = self.attrvalues.len();
-                        self.attrvalues.push(Vec::new());
-                        self.attrnames.push(attrname.to_string());
-                        self.attr2index.insert(attrlowercase, index);
-                        Some(index)
+                        Some(self.add_attribute(attrname.to_string()))
                     } else {
                         index
                     }
                 } else {
                     None
                 };
+                self.value_start = self.arena.len();
             }
             TokenKind::ValueText => {
                 if self.state == WriterState::Version {
                     self.state = WriterState::BeforeEntry;
                 }
                 if self.attrmatch.is_some() {
-                    self.valuebuf.write_all(token.segment.as_bytes())?;
+                    self.arena.extend_from_slice(token.segment.as_bytes());
                     self.valuetype = ValueType::Text;
                 }
             }
             TokenKind::ValueBase64 => {
                 if self.attrmatch.is_some() {
                     let mut decoder =
-                        DecodeWriter::new_with_state(&mut self.valuebuf, self.b64state);
-                    decoder.write_all(token.segment.as_bytes())?;
+                        DecodeWriter::new_with_state(&mut self.arena, self.b64state);
+                    decoder.write_all(token.segment.as_bytes()).map_err(|err| base64_error(token.loc, err))?;
                     self.b64state = decoder.get_state();
                     self.valuetype = ValueType::Base64;
                 }
@@ -283,25 +581,30 @@ impl<'a, W: WriteEntry> WriteToken for EntryTokenWriter<'a, W> {
                         // TODO: consider raising an error if it isn't in a valid end state
                         self.b64state = DecodeState::default();
                     }
-                    self.attrvalues[attridx].push(Cow::Owned(self.valuebuf.clone()));
-                    self.valuebuf.clear();
+                    self.attrs[attridx].values.push(self.value_start..self.arena.len());
+                    self.attrs[attridx].base64.push(self.valuetype == ValueType::Base64);
+                    self.value_start = self.arena.len();
                 }
             }
             TokenKind::EntryFinish => {
                 if self.state == WriterState::Processing {
-                    let attrnames: HashMap<String, String> = self.attrnames.iter()
-                        .map(|attr| (attr.to_lowercase(), attr.to_string()))
-                        .collect();
-                    let attr2values: HashMap<String, Cow<Vec<EntryValue>>> = self.attr2index.iter()
-                        .map(|(attr, index)| (attr.clone(), Cow::Borrowed(&self.attrvalues[*index])))
-                        .collect();
+                    self.ordinal += 1;
                     self.dest.write_entry(&Entry{
-                        attrnames: Some(attrnames),
-                        attr2values,
+                        arena: Cow::Borrowed(&self.arena),
+                        attrs: Cow::Borrowed(&self.attrs),
+                        attr2index: Cow::Borrowed(&self.attr2index),
+                        provenance: Some(Provenance{
+                            file: self.file.clone(),
+                            line: self.line,
+                            ordinal: self.ordinal,
+                        }),
                     })?;
-                    for values in self.attrvalues.iter_mut() {
-                        values.clear();
+                    for attr in self.attrs.iter_mut() {
+                        attr.values.clear();
+                        attr.base64.clear();
                     }
+                    self.arena.clear();
+                    self.value_start = 0;
                 }
                 self.state = WriterState::BeforeEntry;
             }
@@ -311,11 +614,10 @@ impl<'a, W: WriteEntry> WriteToken for EntryTokenWriter<'a, W> {
 }
 
 pub fn write_attrval<W: Write>(w: &mut W, attr: &str, value: &[u8]) -> std::io::Result<()> {
-    write!(w, "{}:", attr)?;
     if is_ldif_safe_string(value) {
-        writeln!(w, " {}", String::from_utf8_lossy(value))?;
+        write_all_vectored(w, &mut [IoSlice::new(attr.as_bytes()), IoSlice::new(b": "), IoSlice::new(value), IoSlice::new(b"\n")])?;
     } else {
-        write!(w, ":")?;
+        write!(w, "{}::", attr)?;
         let mut w = w;
         let mut base64 = EncodeWriter::new(&mut w);
         base64.write_all(value)?;
@@ -325,7 +627,35 @@ pub fn write_attrval<W: Write>(w: &mut W, attr: &str, value: &[u8]) -> std::io::
     Ok(())
 }
 
+/// Writes an attribute value line like write_attrval, except that the policy decides whether the
+/// value is base64-encoded.
+pub fn write_attrval_with_policy<W: Write>(w: &mut W, attr: &str, value: &[u8], policy: &Base64Policy) -> std::io::Result<()> {
+    if !policy.needs_base64(attr, value) {
+        return write_all_vectored(w, &mut [IoSlice::new(attr.as_bytes()), IoSlice::new(b": "), IoSlice::new(value), IoSlice::new(b"\n")]);
+    }
+    write!(w, "{}::", attr)?;
+    let mut w = w;
+    let mut base64 = EncodeWriter::new(&mut w);
+    base64.write_all(value)?;
+    base64.flush()?;
+    writeln!(w)
+}
+
 pub fn write_entry_normally<W: Write>(w: &mut W, entry: &Entry) -> std::io::Result<()> {
+    write_entry_with(w, entry, write_attrval)
+}
+
+/// Writes an entry like write_entry_normally, except that the policy decides which values are
+/// base64-encoded.
+pub fn write_entry_with_policy<W: Write>(w: &mut W, entry: &Entry, policy: &Base64Policy) -> std::io::Result<()> {
+    write_entry_with(w, entry, |w, attr, value| write_attrval_with_policy(w, attr, value, policy))
+}
+
+fn write_entry_with<W, F>(w: &mut W, entry: &Entry, mut write_attrval: F) -> std::io::Result<()>
+where
+    W: Write,
+    F: FnMut(&mut W, &str, &[u8]) -> std::io::Result<()>,
+{
     if let Some(dn) = entry.get_one("dn") {
         write_attrval(w, "dn", dn)?;
     }
@@ -394,5 +724,114 @@ CN: bar
         Ok(())
     }
 
+    #[test]
+    fn entry_token_writer_all_attributes_test() -> Result<()> {
+        let ldif = b"dn: cn=foo\ncn: foo\nsn: f\n\ndn: cn=bar\nMail: bar@example.com\n";
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let mut lexer = Lexer::new(EntryTokenWriter::new(&mut entries));
+        lexer.loc_write(Loc::default(), ldif)?;
+        lexer.loc_flush(Loc::default())?;
+        assert_eq!(entries[0].get_one_str("SN"), Some(Cow::Borrowed("f")));
+        assert_eq!(entries[0].get_one("mail"), None);
+        assert_eq!(entries[1].get_one_str("mail"), Some(Cow::Borrowed("bar@example.com")));
+        assert_eq!(entries[1].get_one("cn"), None);
+        let mut entry = entries.remove(1);
+        entry.add_value("MAIL", b"b@example.com");
+        entry.add_value("uid", b"bar");
+        assert_eq!(entry.get("mail").count(), 2);
+        assert_eq!(entry.get_one_str("UID"), Some(Cow::Borrowed("bar")));
+        Ok(())
+    }
+
+    #[test]
+    fn entry_provenance_test() -> Result<()> {
+        let ldif = b"version: 1\ndn: cn=foo\ncn: foo\n\n# comment\ndn: cn=bar\ncn: bar\n";
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let mut token_writer = EntryTokenWriter::new(&mut entries);
+        token_writer.set_file(Some("a.ldif"));
+        let mut lexer = Lexer::new(token_writer);
+        lexer.loc_write(Loc::default(), ldif)?;
+        lexer.loc_flush(Loc::default())?;
+        drop(lexer);
+        let provenance = entries[1].provenance().unwrap();
+        assert_eq!((provenance.file.as_deref(), provenance.line, provenance.ordinal), (Some("a.ldif"), 6, 2));
+        assert_eq!(entries[0].pseudo_value("@LINE"), Some(b"2".to_vec()));
+        assert_eq!(entries[0].pseudo_value("@file"), Some(b"a.ldif".to_vec()));
+        assert_eq!(entries[0].pseudo_value("@ordinal"), Some(b"1".to_vec()));
+        assert_eq!(OwnedEntry::default().pseudo_value("@line"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn entry_dn_pseudo_value_test() {
+        let entry = OwnedEntry::from([("dn", b"cn=Smith\\, John+uid=js,ou=People,dc=example".as_slice())]);
+        assert_eq!(entry.pseudo_value("@rdn"), Some(b"Smith, John".to_vec()));
+        assert_eq!(entry.pseudo_value("@Parent"), Some(b"ou=People,dc=example".to_vec()));
+        assert_eq!(entry.pseudo_value("@depth"), Some(b"3".to_vec()));
+        let top = OwnedEntry::from([("dn", b"dc=com".as_slice())]);
+        assert_eq!(top.pseudo_value("@parent"), None);
+        assert_eq!(OwnedEntry::default().pseudo_value("@depth"), None);
+    }
+
+    #[test]
+    fn entry_add_value_test() {
+        let mut entry = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("objectClass", b"top".as_slice())]);
+        entry.add_value("objectclass", b"person");
+        assert_eq!(entry.get("OBJECTCLASS").collect::<Vec<&[u8]>>(), vec![b"top".as_slice(), b"person".as_slice()]);
+        let names: Vec<&str> = entry.attributes().map(|attr| attr.name).collect();
+        assert_eq!(names, vec!["dn", "objectClass"]);
+        assert_eq!(entry.get("cn").next(), None);
+    }
+
+    #[test]
+    fn entry_remove_value_test() {
+        let mut entry = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("mail", b"a"), ("mail", b"b"), ("sn", b"c")]);
+        assert!(entry.remove_value("MAIL", b"a"));
+        assert!(!entry.remove_value("mail", b"c"));
+        assert_eq!(entry.get("mail").collect::<Vec<&[u8]>>(), vec![b"b".as_slice()]);
+        entry.remove_attribute("sn");
+        let names: Vec<&str> = entry.attributes().map(|attr| attr.name).collect();
+        assert_eq!(names, vec!["dn", "mail"]);
+    }
+
+    #[test]
+    fn entry_fingerprint_test() {
+        let entry = OwnedEntry::from([
+            ("dn", b"cn=foo".as_slice()),
+            ("cn", b"foo".as_slice()),
+            ("objectClass", b"top".as_slice()),
+            ("objectClass", b"person".as_slice()),
+        ]);
+        let reordered = OwnedEntry::from([
+            ("dn", b"CN=Foo".as_slice()),
+            ("objectclass", b"person".as_slice()),
+            ("OBJECTCLASS", b"top".as_slice()),
+            ("objectClass", b"top".as_slice()),
+            ("CN", b"foo".as_slice()),
+        ]);
+        assert_eq!(entry.fingerprint(), reordered.fingerprint());
+        assert_eq!(entry.fingerprint(), 0xa46519140d6d1af0);
+        let changed = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("cn", b"Foo".as_slice())]);
+        assert_ne!(entry.fingerprint(), changed.fingerprint());
+    }
+
+    #[test]
+    fn entry_comparison_test() {
+        let entry = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("cn", b"foo"), ("mail", b"a"), ("mail", b"b")]);
+        let similar = OwnedEntry::from([("DN", b"CN=Foo".as_slice()), ("mail", b"B"), ("CN", b"FOO"), ("mail", b"a"), ("mail", b"a")]);
+        assert!(entry.eq_ignore_case(&similar));
+        assert!(!entry.is_subset_of(&similar));
+        let larger = OwnedEntry::from([("dn", b"cn=foo".as_slice()), ("cn", b"foo"), ("mail", b"b"), ("mail", b"a"), ("sn", b"x")]);
+        assert!(entry.is_subset_of(&larger));
+        assert!(!larger.is_subset_of(&entry));
+        assert!(!entry.eq_ignore_case(&larger));
+        assert_eq!(entry.diff(&larger), [AttrDiff{ attr: String::from("sn"), removed: Vec::new(), added: vec![b"x".as_slice()] }]);
+        assert_eq!(entry.diff(&similar), [
+            AttrDiff{ attr: String::from("cn"), removed: vec![b"foo".as_slice()], added: vec![b"FOO".as_slice()] },
+            AttrDiff{ attr: String::from("mail"), removed: vec![b"b".as_slice()], added: vec![b"B".as_slice()] },
+        ]);
+        assert!(entry.diff(&entry).is_empty());
+    }
+
 }
 
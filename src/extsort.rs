@@ -0,0 +1,282 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Result, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+const DEFAULT_FAN_IN: usize = 64;
+
+// What a record costs in memory besides its bytes.
+const RECORD_OVERHEAD: usize = std::mem::size_of::<Vec<u8>>();
+
+static CHUNK_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// A sorted run of records in a temporary file, each record prefixed with its length. The file is
+// only open while it is written or merged, and is removed when the chunk is dropped.
+struct Chunk {
+    path: PathBuf,
+}
+
+impl Chunk {
+    fn create() -> Result<(Chunk, BufWriter<File>)> {
+        let path = std::env::temp_dir().join(format!(
+            "ltools-sort-{}-{}.tmp",
+            std::process::id(),
+            CHUNK_COUNTER.fetch_add(1, AtomicOrdering::Relaxed),
+        ));
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok((Chunk{ path }, BufWriter::new(file)))
+    }
+
+    fn write(records: &[Vec<u8>]) -> Result<Chunk> {
+        let (chunk, mut writer) = Chunk::create()?;
+        for record in records.iter() {
+            write_record(&mut writer, record)?;
+        }
+        writer.flush()?;
+        Ok(chunk)
+    }
+
+    fn open(&self) -> Result<Run> {
+        Ok(Run::File(BufReader::new(File::open(&self.path)?)))
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &[u8]) -> Result<()> {
+    writer.write_all(&(record.len() as u64).to_le_bytes())?;
+    writer.write_all(record)
+}
+
+// A sorted run of records being merged, from a chunk or from memory.
+enum Run {
+    File(BufReader<File>),
+    Memory(std::vec::IntoIter<Vec<u8>>),
+}
+
+impl Run {
+    fn next_record(&mut self) -> Result<Option<Vec<u8>>> {
+        let reader = match self {
+            Run::File(reader) => reader,
+            Run::Memory(records) => return Ok(records.next()),
+        };
+        let mut len = [0u8; 8];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {},
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let mut record = vec![0u8; u64::from_le_bytes(len) as usize];
+        reader.read_exact(&mut record)?;
+        Ok(Some(record))
+    }
+}
+
+// The next record of a run in the merge heap.
+struct Head<'a, F> {
+    record: Vec<u8>,
+    run: usize,
+    compare: &'a F,
+}
+
+impl<F: Fn(&[u8], &[u8]) -> Ordering> Ord for Head<'_, F> {
+    // reversed, so that the max-heap BinaryHeap gives the smallest record first, and among equal
+    // records the one of the earliest run, which keeps the sort stable
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&other.record, &self.record).then(other.run.cmp(&self.run))
+    }
+}
+
+impl<F: Fn(&[u8], &[u8]) -> Ordering> PartialOrd for Head<'_, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<F: Fn(&[u8], &[u8]) -> Ordering> PartialEq for Head<'_, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<F: Fn(&[u8], &[u8]) -> Ordering> Eq for Head<'_, F> {}
+
+// Calls f with the records of the runs in sorted order, where records that compare equal are
+// given in the order of their runs.
+fn merge<F, G>(compare: &F, mut runs: Vec<Run>, mut f: G) -> Result<()>
+    where F: Fn(&[u8], &[u8]) -> Ordering, G: FnMut(&[u8]) -> Result<()>
+{
+    let mut heap: BinaryHeap<Head<F>> = BinaryHeap::with_capacity(runs.len());
+    for (run, records) in runs.iter_mut().enumerate() {
+        if let Some(record) = records.next_record()? {
+            heap.push(Head{ record, run, compare });
+        }
+    }
+    while let Some(Head{ record, run, .. }) = heap.pop() {
+        f(&record)?;
+        if let Some(record) = runs[run].next_record()? {
+            heap.push(Head{ record, run, compare });
+        }
+    }
+    Ok(())
+}
+
+/// Sorts records that may not fit in memory. Records are kept in memory until they exceed the
+/// memory limit, at which point they are sorted and written to a temporary file. The sorted files
+/// are merged by `finish`, at most a fan-in of them at a time. The sort is stable.
+pub struct ExternalSorter<F: Fn(&[u8], &[u8]) -> Ordering> {
+    compare: F,
+    records: Vec<Vec<u8>>,
+    size: usize,
+    memory_limit: usize,
+    fan_in: usize,
+    chunks: Vec<Chunk>,
+}
+
+impl<F: Fn(&[u8], &[u8]) -> Ordering> ExternalSorter<F> {
+    pub fn new(compare: F) -> ExternalSorter<F> {
+        ExternalSorter{
+            compare,
+            records: Vec::new(),
+            size: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            fan_in: DEFAULT_FAN_IN,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Sets the number of bytes of records to keep in memory before writing them to a temporary
+    /// file. Each record counts with its allocation and the vector that holds it.
+    pub fn set_memory_limit(&mut self, memory_limit: usize) -> &mut Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// Sets the number of temporary files to merge at a time, and so to have open at a time. With
+    /// more files than that, they are merged into fewer files first. The least is 2.
+    pub fn set_fan_in(&mut self, fan_in: usize) -> &mut Self {
+        self.fan_in = fan_in.max(2);
+        self
+    }
+
+    pub fn push(&mut self, record: Vec<u8>) -> Result<()> {
+        self.size += record.capacity() + RECORD_OVERHEAD;
+        self.records.push(record);
+        if self.size > self.memory_limit {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        let compare = &self.compare;
+        self.records.sort_by(|a, b| compare(a, b));
+        self.chunks.push(Chunk::write(&self.records)?);
+        self.records.clear();
+        self.size = 0;
+        Ok(())
+    }
+
+    // Merges consecutive chunks into one until there are no more than the fan-in of them. Keeping
+    // the chunks in the order they were written keeps the sort stable.
+    fn merge_chunks(&mut self) -> Result<()> {
+        while self.chunks.len() > self.fan_in {
+            let mut chunks = std::mem::take(&mut self.chunks).into_iter();
+            loop {
+                let group: Vec<Chunk> = chunks.by_ref().take(self.fan_in).collect();
+                if group.len() <= 1 {
+                    self.chunks.extend(group);
+                    break;
+                }
+                let runs = group.iter().map(Chunk::open).collect::<Result<Vec<Run>>>()?;
+                let (chunk, mut writer) = Chunk::create()?;
+                merge(&self.compare, runs, |record| write_record(&mut writer, record))?;
+                writer.flush()?;
+                self.chunks.push(chunk);
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls f with each record in sorted order.
+    pub fn finish<G: FnMut(&[u8]) -> Result<()>>(mut self, mut f: G) -> Result<()> {
+        let compare = &self.compare;
+        self.records.sort_by(|a, b| compare(a, b));
+        if self.chunks.is_empty() {
+            for record in self.records.iter() {
+                f(record)?;
+            }
+            return Ok(());
+        }
+        self.merge_chunks()?;
+        // the records in memory are the newest and so come last among equal records
+        let mut runs = self.chunks.iter().map(Chunk::open).collect::<Result<Vec<Run>>>()?;
+        runs.push(Run::Memory(std::mem::take(&mut self.records).into_iter()));
+        merge(&self.compare, runs, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        // compares only the first byte, so that the stability of the sort can be seen
+        let mut sorter = ExternalSorter::new(|a: &[u8], b: &[u8]| a[..1].cmp(&b[..1]));
+        sorter.set_memory_limit(4);
+        for record in ["c1", "a1", "b1", "a2", "c2", "b2", "a3"] {
+            sorter.push(record.as_bytes().to_vec())?;
+        }
+        assert!(!sorter.chunks.is_empty());
+        let mut sorted: Vec<String> = Vec::new();
+        sorter.finish(|record| {
+            sorted.push(String::from_utf8_lossy(record).into_owned());
+            Ok(())
+        })?;
+        assert_eq!(sorted, ["a1", "a2", "a3", "b1", "b2", "c1", "c2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fan_in() -> Result<()> {
+        let mut sorter = ExternalSorter::new(|a: &[u8], b: &[u8]| a[..1].cmp(&b[..1]));
+        sorter.set_memory_limit(1).set_fan_in(3);
+        let mut records: Vec<String> = Vec::new();
+        for i in 0..50 {
+            records.push(format!("{}{:02}", (b'a' + (i * 7 % 5) as u8) as char, i));
+        }
+        for record in records.iter() {
+            sorter.push(record.as_bytes().to_vec())?;
+        }
+        assert_eq!(sorter.chunks.len(), 50);
+        let mut sorted: Vec<String> = Vec::new();
+        sorter.finish(|record| {
+            sorted.push(String::from_utf8_lossy(record).into_owned());
+            Ok(())
+        })?;
+        // sort_by is stable, so it gives what the merge should
+        records.sort_by(|a, b| a[..1].cmp(&b[..1]));
+        assert_eq!(sorted, records);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit() -> Result<()> {
+        // a hundred one-byte records take more than a hundred bytes
+        let mut sorter = ExternalSorter::new(|a: &[u8], b: &[u8]| a.cmp(b));
+        sorter.set_memory_limit(1000);
+        for _ in 0..100 {
+            sorter.push(vec![b'a'])?;
+        }
+        assert!(!sorter.chunks.is_empty());
+        Ok(())
+    }
+}
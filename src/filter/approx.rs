@@ -0,0 +1,132 @@
+/// How values are compared in approximate (`~=`) filters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ApproxMatch {
+    /// Values match if they have the same Soundex code.
+    #[default]
+    Soundex,
+    /// Values match if their Levenshtein distance, normalized by the length of the longest
+    /// value, gives a similarity of at least the given threshold between 0 and 1.
+    Levenshtein(f64),
+}
+
+const DEFAULT_LEVENSHTEIN_THRESHOLD: f64 = 0.8;
+
+impl ApproxMatch {
+    /// Parses `soundex`, `levenshtein` or `levenshtein:THRESHOLD`.
+    pub fn parse(s: &str) -> Result<ApproxMatch, &'static str> {
+        let (name, threshold) = match s.split_once(':') {
+            Some((name, threshold)) => (name, Some(threshold)),
+            None => (s, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), threshold) {
+            ("soundex", None) => Ok(ApproxMatch::Soundex),
+            ("levenshtein", None) => Ok(ApproxMatch::Levenshtein(DEFAULT_LEVENSHTEIN_THRESHOLD)),
+            ("levenshtein", Some(threshold)) => match threshold.parse::<f64>() {
+                Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(ApproxMatch::Levenshtein(threshold)),
+                _ => Err("Levenshtein threshold must be a number between 0 and 1"),
+            },
+            _ => Err("unknown approximate matching method"),
+        }
+    }
+
+    pub fn is_match(&self, filtervalue: &[u8], value: &[u8]) -> bool {
+        match self {
+            ApproxMatch::Soundex => match (soundex(filtervalue), soundex(value)) {
+                (Some(a), Some(b)) => a == b,
+                _ => filtervalue.eq_ignore_ascii_case(value),
+            },
+            ApproxMatch::Levenshtein(threshold) => {
+                let maxlen = filtervalue.len().max(value.len());
+                if maxlen == 0 {
+                    return true;
+                }
+                let distance = levenshtein(filtervalue, value);
+                1.0 - (distance as f64 / maxlen as f64) >= *threshold
+            },
+        }
+    }
+}
+
+fn soundex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'b' | b'f' | b'p' | b'v' => Some(b'1'),
+        b'c' | b'g' | b'j' | b'k' | b'q' | b's' | b'x' | b'z' => Some(b'2'),
+        b'd' | b't' => Some(b'3'),
+        b'l' => Some(b'4'),
+        b'm' | b'n' => Some(b'5'),
+        b'r' => Some(b'6'),
+        _ => None,
+    }
+}
+
+/// Returns the Soundex code of the letters in value, or None if it contains no letters.
+fn soundex(value: &[u8]) -> Option<[u8; 4]> {
+    let mut letters = value.iter()
+        .copied()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let first = letters.next()?;
+    let mut code = [first.to_ascii_uppercase(), b'0', b'0', b'0'];
+    let mut len = 1;
+    let mut previous = soundex_digit(first);
+    for c in letters {
+        if len == code.len() {
+            break;
+        }
+        let digit = soundex_digit(c);
+        if digit.is_some() && digit != previous {
+            code[len] = digit.unwrap_or(b'0');
+            len += 1;
+        }
+        // 'h' and 'w' do not separate letters with the same code, but vowels do
+        if !matches!(c, b'h' | b'w') {
+            previous = digit;
+        }
+    }
+    Some(code)
+}
+
+fn levenshtein(a: &[u8], b: &[u8]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca.eq_ignore_ascii_case(cb) { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_soundex() {
+        assert_eq!(soundex(b"Robert"), Some(*b"R163"));
+        assert_eq!(soundex(b"Rupert"), Some(*b"R163"));
+        assert_eq!(soundex(b"Tymczak"), Some(*b"T522"));
+        assert_eq!(soundex(b"Ashcraft"), Some(*b"A261"));
+        assert_eq!(soundex(b"Pfister"), Some(*b"P236"));
+        assert_eq!(soundex(b"Lee"), Some(*b"L000"));
+        assert_eq!(soundex(b"1234"), None);
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein(b"kitten", b"sitting"), 3);
+        assert_eq!(levenshtein(b"", b"abc"), 3);
+        assert_eq!(levenshtein(b"ABC", b"abc"), 0);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ApproxMatch::parse("soundex"), Ok(ApproxMatch::Soundex));
+        assert_eq!(ApproxMatch::parse("levenshtein:0.5"), Ok(ApproxMatch::Levenshtein(0.5)));
+        assert!(ApproxMatch::parse("levenshtein:2").is_err());
+        assert!(ApproxMatch::parse("metaphone").is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use crate::dn::normalize;
+use crate::filter::GlobPart;
+use crate::filter::substring::SubstringMatcher;
+
+/// A pattern for DNs such as `*,ou=sa,o=system`, which matches the entries below
+/// ou=sa,o=system. `*` matches any characters, commas included. The pattern and the DNs are
+/// normalized as by `dn::normalize` before matching, so that case and spaces after commas make no
+/// difference. Other characters are written as in DNs, so that `\,` is an escaped comma.
+#[derive(Debug, PartialEq)]
+pub struct DnGlob {
+    matcher: SubstringMatcher,
+}
+
+impl DnGlob {
+    pub fn new(pattern: &str) -> DnGlob {
+        let glob: Vec<GlobPart> = normalize(pattern).bytes()
+            .map(|c| if c == b'*' { GlobPart::Wildcard } else { GlobPart::Literal(c) })
+            .collect();
+        DnGlob{
+            matcher: SubstringMatcher::new(&glob),
+        }
+    }
+
+    pub fn is_match(&self, dn: &str) -> bool {
+        self.matcher.is_match(normalize(dn).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dn_glob() {
+        let glob = DnGlob::new("*, OU=SA,o=system");
+        assert!(glob.is_match("cn=foo,ou=sa,o=system"));
+        assert!(glob.is_match("cn=Foo, ou=Sa, o=System"));
+        assert!(glob.is_match("cn=a,cn=b,ou=sa,o=system"));
+        assert!(!glob.is_match("ou=sa,o=system"));
+        assert!(!glob.is_match("cn=foo,ou=sales,o=system"));
+        assert!(DnGlob::new("cn=*\\, John,*").is_match("cn=Smith\\, John,o=example"));
+        assert!(DnGlob::new("ou=sa,o=system").is_match("OU=SA, O=SYSTEM"));
+    }
+}
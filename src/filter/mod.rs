@@ -1,9 +1,19 @@
 pub mod parser;
+pub mod approx;
+pub mod dnglob;
+pub mod substring;
+pub mod syntax;
 
-use crate::entry::Entry;
-use crate::filter::parser::filter as parse_filter;
-use std::mem::swap;
-use std::collections::BTreeSet;
+use crate::entry::{is_pseudo_attribute, Entry};
+use crate::filter::approx::ApproxMatch;
+use crate::filter::parser::{diagnose, filter_with as parse_filter};
+use crate::filter::substring::SubstringMatcher;
+use crate::filter::syntax::Syntax;
+use crate::schema::attribute_syntaxes_from_schema;
+use std::cmp::Ordering;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug, PartialEq)]
 pub enum Filter {
@@ -12,14 +22,30 @@ pub enum Filter {
     Not(Box<Filter>),
     Simple(AttributeDescription, FilterType, Vec<u8>),
     Present(AttributeDescription),
-    Substring(AttributeDescription, Vec<GlobPart>),
+    Substring(AttributeDescription, SubstringMatcher),
     // TODO: Extensible(...
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct AttributeDescription {
     pub attribute_type: String,
-    // TODO: add options
+    pub options: Vec<String>, // such as lang-en in cn;lang-en
+}
+
+impl AttributeDescription {
+    /// Returns the attribute type followed by the options, as the attribute is looked up in
+    /// entries.
+    pub fn description(&self) -> Cow<'_, str> {
+        if self.options.is_empty() {
+            return Cow::Borrowed(&self.attribute_type);
+        }
+        let mut description = self.attribute_type.clone();
+        for option in self.options.iter() {
+            description.push(';');
+            description.push_str(option);
+        }
+        Cow::Owned(description)
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -30,105 +56,234 @@ pub enum FilterType {
     LessOrEqual,
 }
 
+/// Options that affect how filters are evaluated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchOptions {
+    pub approx: ApproxMatch,
+    pub syntaxes: HashMap<String, Syntax>, // by lowercase attribute, for those not compared as strings
+}
+
+impl MatchOptions {
+    pub fn set_syntax(&mut self, attr: &str, syntax: Syntax) -> &mut Self {
+        self.syntaxes.insert(attr.to_ascii_lowercase(), syntax);
+        self
+    }
+
+    /// Sets the syntaxes of the attributes that have integer or telephone number syntax
+    /// according to a subschema entry, without overriding syntaxes that are already set.
+    pub fn add_schema_syntaxes(&mut self, schema: &Entry) -> &mut Self {
+        for (attr, oid) in attribute_syntaxes_from_schema(schema) {
+            if let Some(syntax) = Syntax::from_oid(&oid) {
+                self.syntaxes.entry(attr).or_insert(syntax);
+            }
+        }
+        self
+    }
+
+    /// Returns the syntax of the attribute. The pseudo-attributes with numbers, such as `@line`,
+    /// are integers unless set otherwise.
+    pub fn syntax(&self, attr: &str) -> Syntax {
+        match self.syntaxes.get(attr) {
+            Some(syntax) => *syntax,
+            None if matches!(attr, "@line" | "@ordinal" | "@depth") => Syntax::Integer,
+            None => Syntax::default(),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum GlobPart {
     Wildcard,
     Literal(u8),
 }
 
+/// Something that filters can be evaluated against, such as an entry or a row read from a
+/// table.
+pub trait AttributeSource {
+    /// Tells whether f returns true for any value of the attribute, which is looked up
+    /// regardless of case.
+    fn any_value(&self, attr: &str, f: &mut dyn FnMut(&[u8]) -> bool) -> bool;
+}
+
+impl<S: AttributeSource + ?Sized> AttributeSource for &S {
+    fn any_value(&self, attr: &str, f: &mut dyn FnMut(&[u8]) -> bool) -> bool {
+        (**self).any_value(attr, f)
+    }
+}
+
+impl AttributeSource for Entry<'_> {
+    fn any_value(&self, attr: &str, f: &mut dyn FnMut(&[u8]) -> bool) -> bool {
+        if is_pseudo_attribute(attr) {
+            return self.pseudo_value(attr).is_some_and(|value| f(&value));
+        }
+        self.get(attr).any(f)
+    }
+}
+
+/// Rows keyed by attribute name, such as those read from JSON or TSV.
+impl AttributeSource for HashMap<String, Vec<Vec<u8>>> {
+    fn any_value(&self, attr: &str, f: &mut dyn FnMut(&[u8]) -> bool) -> bool {
+        let values = self.get(attr)
+            .or_else(|| self.iter().find(|(name, _)| name.eq_ignore_ascii_case(attr)).map(|(_, values)| values));
+        values.into_iter()
+            .flatten()
+            .any(|value| f(value))
+    }
+}
+
+/// Escapes a value for use in a filter, or in an attribute specification's `:-` default, as
+/// `\xx` for each byte that is special in filters, NUL or not ASCII. This is what `lescape`
+/// writes.
+pub fn escape_value(value: &[u8]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.iter().copied() {
+        if !c.is_ascii() || matches!(c, b'\\' | b'*' | b'(' | b')' | b':' | b'\0') {
+            escaped.push_str(&format!("\\{c:02x}"));
+        } else {
+            escaped.push(c as char);
+        }
+    }
+    escaped
+}
+
+/// An error parsing a filter, which tells what was expected where. It is displayed along with
+/// the filter, as in `failed to parse filter "(cn=foo": expected ')' at offset 7`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    pub filter: String,
+    /// The number of characters in the filter before where it goes wrong.
+    pub offset: usize,
+    /// What the filter should have had there, such as `')'` or `an attribute type`.
+    pub expected: &'static str,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to parse filter \"{}\": expected {} at offset {}", self.filter, self.expected, self.offset)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
 impl Filter {
-    pub fn parse(s: &str) -> Result<Filter, &'static str> {
-        let (remainder, filter) = match parse_filter(s) {
-            Ok(filter) => filter,
-            Err(_) => return Err("failed to parse LDAP filter"),
+    /// Parses a filter such as `(&(objectClass=person)(mail=*))`. Filters pasted from other tools
+    /// are taken as well: a filter such as `cn=foo` needn't be in parentheses, and there may be
+    /// whitespace between parentheses and operators, as in `( & (a=1) (b=2) )`.
+    pub fn parse(s: &str) -> Result<Filter, FilterError> {
+        Filter::parse_bytes(s.as_bytes())
+    }
+
+    /// Parses a filter as RFC 4515 has it, in parentheses and without whitespace between its
+    /// parts.
+    pub fn parse_strict(s: &str) -> Result<Filter, FilterError> {
+        Filter::parse_bytes_with(s.as_bytes(), true)
+    }
+
+    /// Parses a filter whose assertion values may contain any byte, such as NUL or bytes that
+    /// aren't UTF-8, without them having to be escaped. The bytes of values are matched as they
+    /// are, so UTF-8 in a filter matches the UTF-8 of values.
+    pub fn parse_bytes(s: &[u8]) -> Result<Filter, FilterError> {
+        Filter::parse_bytes_with(s, false)
+    }
+
+    /// Parses a filter whose assertion values may contain any byte, as `parse_bytes` does, and
+    /// strictly, as `parse_strict` does.
+    pub fn parse_bytes_strict(s: &[u8]) -> Result<Filter, FilterError> {
+        Filter::parse_bytes_with(s, true)
+    }
+
+    fn parse_bytes_with(s: &[u8], strict: bool) -> Result<Filter, FilterError> {
+        // the parser takes escapes for the bytes that it doesn't take as they are
+        let mut escaped = String::with_capacity(s.len());
+        for c in s.iter().copied() {
+            if !c.is_ascii() || matches!(c, b'\0' | b'\x1b') {
+                escaped.push_str(&format!("\\{c:02x}"));
+            } else {
+                escaped.push(c as char);
+            }
+        }
+        // unless strict, whitespace before the filter is skipped, and a filter that isn't in
+        // parentheses is parsed as if it was
+        let start = if strict { 0 } else { escaped.len() - escaped.trim_start().len() };
+        let trimmed = &escaped[start..];
+        let bare = !strict && !trimmed.starts_with('(');
+        let input = match bare {
+            true => Cow::Owned(format!("({})", trimmed.trim_end())),
+            false => Cow::Borrowed(trimmed),
         };
-        if remainder.trim() != "" {
-            return Err("failed to parse LDAP filter");
+        match parse_filter(&input, strict) {
+            Ok((remainder, filter)) if remainder.trim() == "" => Ok(filter),
+            _ => {
+                let (remainder, expected) = diagnose(&input, strict);
+                let mut offset = input.len() - remainder.len();
+                if bare {
+                    // an error at the closing parenthesis that was added is at the end
+                    offset = (offset - 1).min(trimmed.len());
+                }
+                Err(FilterError{
+                    filter: String::from_utf8_lossy(s).into_owned(),
+                    offset: Filter::unescaped_offset(s, start + offset),
+                    expected,
+                })
+            },
         }
-        Ok(filter)
     }
 
-    pub fn is_match(&self, entry: &Entry) -> bool {
+    // Maps an offset in the escaped filter that parse_bytes parses back to a number of characters
+    // in the filter it was given, counting bytes that aren't UTF-8 as characters.
+    fn unescaped_offset(s: &[u8], escaped_offset: usize) -> usize {
+        let mut escaped_len = 0;
+        let mut offset = 0;
+        for c in s.iter().copied() {
+            escaped_len += if !c.is_ascii() || matches!(c, b'\0' | b'\x1b') { 3 } else { 1 };
+            if escaped_len > escaped_offset {
+                break;
+            }
+            if c & 0xc0 != 0x80 {
+                offset += 1;
+            }
+        }
+        offset
+    }
+
+    pub fn is_match<S: AttributeSource + ?Sized>(&self, entry: &S) -> bool {
+        self.is_match_with(entry, &MatchOptions::default())
+    }
+
+    pub fn is_match_with<S: AttributeSource + ?Sized>(&self, entry: &S, options: &MatchOptions) -> bool {
         match self {
             Filter::And(filters) => filters.iter()
-                .all(|filter| filter.is_match(entry)),
+                .all(|filter| filter.is_match_with(entry, options)),
             Filter::Or(filters) => filters.iter()
-                .any(|filter| filter.is_match(entry)),
-            Filter::Not(filter) => !filter.is_match(entry),
+                .any(|filter| filter.is_match_with(entry, options)),
+            Filter::Not(filter) => !filter.is_match_with(entry, options),
             Filter::Present(attrdesc) => {
-                let attr = &attrdesc.attribute_type;
-                entry.get(attr).count() != 0
+                let attr = &attrdesc.description();
+                entry.any_value(attr, &mut |_| true)
             }
             Filter::Simple(attrdesc, filtertype, filtervalue) => {
-                let attr = &attrdesc.attribute_type;
-                let equal = entry.get(attr).any(|value| {
-                    let value = value.to_ascii_lowercase();
-                    value == *filtervalue
-                });
+                let attr = &attrdesc.description();
                 match filtertype {
-                    FilterType::Equal | FilterType::Approx => equal,
-                    FilterType::GreaterOrEqual | FilterType::LessOrEqual => todo!(),
+                    FilterType::Approx => entry.any_value(attr, &mut |value| options.approx.is_match(filtervalue, value)),
+                    _ => {
+                        let syntax = options.syntax(&attrdesc.attribute_type);
+                        let is_match = |ordering: Ordering| match filtertype {
+                            FilterType::GreaterOrEqual => ordering.is_ge(),
+                            FilterType::LessOrEqual => ordering.is_le(),
+                            _ => ordering.is_eq(),
+                        };
+                        entry.any_value(attr, &mut |value| syntax.compare(value, filtervalue).is_some_and(is_match))
+                    },
                 }
             },
-            Filter::Substring(attrdesc, glob) => {
-                let attr = &attrdesc.attribute_type;
-                for value in entry.get(attr) {
-                    if is_match(glob, value) {
-                        return true;
-                    }
-                }
-                false
+            Filter::Substring(attrdesc, matcher) => {
+                let attr = &attrdesc.description();
+                entry.any_value(attr, &mut |value| matcher.is_match(value))
             },
         }
     }
 }
 
-fn is_match(glob: &[GlobPart], value: &[u8]) -> bool {
-    let mut old_states: BTreeSet<usize> = BTreeSet::new(); // indices into glob
-    let mut new_states: BTreeSet<usize> = BTreeSet::new();
-    let mut post_wildcard_states: BTreeSet<usize> = BTreeSet::new();
-
-    // insert initial states
-    old_states.insert(0);
-    if glob.get(0) == Some(&GlobPart::Wildcard) {
-        if let Some(first_non_wildcard) = (1..glob.len()).find(|idx| !matches!(glob.get(*idx), Some(&GlobPart::Wildcard))) {
-            old_states.insert(first_non_wildcard);
-        }
-    }
-
-    for value_byte in value.iter().copied() {
-        let value_byte_lowercased = value_byte.to_ascii_lowercase();
-        for state in old_states.iter() {
-            match glob.get(*state) {
-                Some(GlobPart::Literal(glob_byte)) => {
-                    if value_byte == *glob_byte || value_byte_lowercased == *glob_byte {
-                        new_states.insert(*state + 1);
-                    }
-                },
-                Some(GlobPart::Wildcard) => {
-                    new_states.insert(*state);
-                },
-                None => {},
-            }
-        }
-        for state in new_states.iter().copied() {
-            if glob.get(state) == Some(&GlobPart::Wildcard) {
-                let mut post_wildcard_state = state + 1;
-                while glob.get(post_wildcard_state) == Some(&GlobPart::Wildcard) {
-                    // handle consecutive wildcards as one wildcard
-                    post_wildcard_state += 1;
-                }
-                post_wildcard_states.insert(post_wildcard_state);
-            }
-        }
-        new_states.extend(post_wildcard_states.iter());
-        post_wildcard_states.clear();
-        swap(&mut old_states, &mut new_states);
-        new_states.clear();
-    }
-    old_states.iter().any(|state| *state == glob.len())
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -151,17 +306,17 @@ cn: foo
         wrapper.flush()?;
 
         let filter = Filter::parse("(cn=FOO)")?;
-        if let Some(entry) = entries.get(0) {
+        if let Some(entry) = entries.first() {
             assert!(filter.is_match(entry));
         }
 
         let filter = Filter::parse("(cn=f*)")?;
-        if let Some(entry) = entries.get(0) {
+        if let Some(entry) = entries.first() {
             assert!(filter.is_match(entry));
         }
 
         let filter = Filter::parse("(cn=foo*)")?;
-        if let Some(entry) = entries.get(0) {
+        if let Some(entry) = entries.first() {
             assert!(filter.is_match(entry));
         }
 
@@ -181,7 +336,7 @@ DirXML-ConfigValues:: c3JjUm9vdAo=
         wrapper.write_all(ldif)?;
         wrapper.flush()?;
 
-        let Some(entry) = entries.get(0) else {
+        let Some(entry) = entries.first() else {
             panic!();
         };
         let filter = Filter::parse("(DirXML-ConfigValues=*srcRoot*)")?;
@@ -191,4 +346,105 @@ DirXML-ConfigValues:: c3JjUm9vdAo=
         Ok(())
     }
 
+    #[test]
+    fn test_attribute_options() -> Result<(), Box<dyn std::error::Error>> {
+        let ldif = b"dn: cn=foo\ncn: foo\ncn;lang-en: Foo\ncn;Lang-DE;phonetic: fu\n";
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let mut wrapper = WriteLocWrapper::new(Lexer::new(EntryTokenWriter::new(&mut entries)));
+        wrapper.write_all(ldif)?;
+        wrapper.flush()?;
+        let entry = &entries[0];
+        assert!(Filter::parse("(cn;lang-en=foo)")?.is_match(entry));
+        assert!(Filter::parse("(CN;LANG-DE;Phonetic=f*)")?.is_match(entry));
+        assert!(!Filter::parse("(cn;lang-en=fu)")?.is_match(entry));
+        assert!(!Filter::parse("(cn;lang-fr=*)")?.is_match(entry));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_bytes() -> Result<(), Box<dyn std::error::Error>> {
+        let entry = crate::entry::Entry::from([("cn", "Bjørn".as_bytes()), ("data", b"a\0\xff")]);
+        assert!(Filter::parse("(cn=bjørn)")?.is_match(&entry));
+        assert!(Filter::parse("(cn=*ø*)")?.is_match(&entry));
+        assert!(Filter::parse_bytes(b"(data=a\0\xff)")?.is_match(&entry));
+        assert!(!Filter::parse_bytes(b"(data=a\0\xfe)")?.is_match(&entry));
+        let escaped = escape_value(b"a\0\xff(*)");
+        assert_eq!(escaped, "a\\00\\ff\\28\\2a\\29");
+        assert!(Filter::parse(&format!("(data={})", escape_value(b"a\0\xff")))?.is_match(&entry));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_error() {
+        let err = Filter::parse("(cn=foo").unwrap_err();
+        assert_eq!(err, FilterError{ filter: String::from("(cn=foo"), offset: 7, expected: "')'" });
+        assert_eq!(err.to_string(), "failed to parse filter \"(cn=foo\": expected ')' at offset 7");
+        assert_eq!(Filter::parse("(&(cn=bjørn)(sn>=a*))").unwrap_err().offset, 18);
+        assert_eq!(Filter::parse_bytes(b"(data=a\0\xff(x)").unwrap_err().offset, 9);
+    }
+
+    #[test]
+    fn test_lenient() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Filter::parse("cn=foo")?, Filter::parse_strict("(cn=foo)")?);
+        assert_eq!(Filter::parse(" &(a=1)(b=*) ")?, Filter::parse_strict("(&(a=1)(b=*))")?);
+        assert_eq!(Filter::parse("\n( & (a=1) (b=2) )\n")?, Filter::parse_strict("(&(a=1)(b=2))")?);
+        assert!(Filter::parse_strict("cn=foo").is_err());
+        assert_eq!(Filter::parse_strict("( & (a=1) (b=2) )").unwrap_err().offset, 1);
+        assert_eq!(Filter::parse("  cn=f(x").unwrap_err().offset, 6);
+        assert_eq!(Filter::parse("&(a=1)(b=2").unwrap_err(), FilterError{ filter: String::from("&(a=1)(b=2"), offset: 10, expected: "'(' or ')'" });
+        Ok(())
+    }
+
+    #[test]
+    fn test_approx() -> Result<(), Box<dyn std::error::Error>> {
+        let entry = crate::entry::Entry::from([("sn", b"Jensen".as_slice())]);
+        let filter = Filter::parse("(sn~=jonsen)")?;
+        assert!(filter.is_match(&entry));
+        assert!(!Filter::parse("(sn~=hansen)")?.is_match(&entry));
+
+        let options = MatchOptions{ approx: ApproxMatch::Levenshtein(0.8), ..MatchOptions::default() };
+        assert!(filter.is_match_with(&entry, &options));
+        assert!(!Filter::parse("(sn~=jon)")?.is_match_with(&entry, &options));
+        Ok(())
+    }
+
+    #[test]
+    fn test_attribute_source() -> Result<(), Box<dyn std::error::Error>> {
+        let mut row: HashMap<String, Vec<Vec<u8>>> = HashMap::new();
+        row.insert(String::from("objectClass"), vec![b"top".to_vec(), b"person".to_vec()]);
+        row.insert(String::from("mail"), vec![b"foo@example.com".to_vec()]);
+        assert!(Filter::parse("(&(objectclass=person)(mail=*@example.com))")?.is_match(&row));
+        assert!(!Filter::parse("(|(cn=*)(mail=bar*))")?.is_match(&row));
+        row.insert(String::from("cn;lang-en"), vec![b"Foo".to_vec()]);
+        assert!(Filter::parse("(cn;lang-en=*o)")?.is_match(&row));
+        assert!(!Filter::parse("(cn=*)")?.is_match(&row));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordering() -> Result<(), Box<dyn std::error::Error>> {
+        let entry = crate::entry::Entry::from([("uidNumber", b"9".as_slice()), ("telephoneNumber", b"+47 123 45 678")]);
+        let mut options = MatchOptions::default();
+        assert!(Filter::parse("(uidNumber>=10000)")?.is_match_with(&entry, &options));
+        assert!(!Filter::parse("(telephoneNumber=+4712345678)")?.is_match_with(&entry, &options));
+        options.set_syntax("uidNumber", Syntax::Integer)
+            .set_syntax("telephoneNumber", Syntax::TelephoneNumber);
+        assert!(!Filter::parse("(uidNumber>=10000)")?.is_match_with(&entry, &options));
+        assert!(Filter::parse("(uidNumber<=10000)")?.is_match_with(&entry, &options));
+        assert!(Filter::parse("(telephoneNumber=+47-12345678)")?.is_match_with(&entry, &options));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pseudo_attributes() -> Result<(), Box<dyn std::error::Error>> {
+        let mut entry = crate::entry::Entry::from([("dn", b"cn=foo".as_slice())]);
+        assert!(!Filter::parse("(@line=*)")?.is_match(&entry));
+        entry.set_provenance(Some(crate::entry::Provenance{ file: Some("a.ldif".into()), line: 12, ordinal: 3 }));
+        assert!(Filter::parse("(&(@line>=9)(@ordinal=3)(@file=*.ldif))")?.is_match(&entry));
+        assert!(!Filter::parse("(@line<=9)")?.is_match(&entry));
+        let entry = crate::entry::Entry::from([("dn", b"uid=a,ou=People,dc=example,dc=com".as_slice())]);
+        assert!(Filter::parse("(&(@rdn=a)(@parent=ou=people,*)(@depth>=4))")?.is_match(&entry));
+        assert!(!Filter::parse("(@depth<=3)")?.is_match(&entry));
+        Ok(())
+    }
 }
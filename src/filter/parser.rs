@@ -1,25 +1,48 @@
 use nom::{
     IResult,
     AsChar,
-    character::complete::{ satisfy, char },
+    character::complete::{ satisfy, char, multispace0 },
     bytes::complete::tag,
     branch::alt,
-    sequence::{ preceded, pair, tuple, delimited },
-    multi::{ fold_many0, many1 },
-    combinator::map,
+    sequence::{ preceded, pair, terminated, tuple, delimited },
+    multi::{ fold_many0, fold_many1, many0, many1 },
+    combinator::{ map, opt },
 };
 use crate::filter::{Filter, FilterType, AttributeDescription, GlobPart};
+use crate::filter::substring::SubstringMatcher;
 
+// Pseudo-attributes such as @line start with @.
 fn attribute_type(input: &str) -> IResult<&str, String> {
+    let (input, pseudo) = opt(char('@'))(input)?;
     let (input, start_char) = satisfy(|c| c.is_ascii_alphabetic())(input)?;
     let start_char = start_char.to_ascii_lowercase();
     fold_many0(
         satisfy(|c| c.is_ascii_alphanumeric() || c == '-'),
-        move || start_char.to_string(),
+        move || pseudo.into_iter().chain([start_char]).collect::<String>(),
         |mut s, c| { s.push(c.to_ascii_lowercase()); s},
     )(input)
 }
 
+// Options are case-insensitive like attribute types, so they are lowercased as well.
+fn attribute_option(input: &str) -> IResult<&str, String> {
+    preceded(
+        char(';'),
+        fold_many1(
+            satisfy(|c| c.is_ascii_alphanumeric() || c == '-'),
+            String::new,
+            |mut s, c| { s.push(c.to_ascii_lowercase()); s },
+        )
+    )(input)
+}
+
+fn attribute_description(input: &str) -> IResult<&str, AttributeDescription> {
+    map(pair(attribute_type, many0(attribute_option)),
+        |(attribute_type, options)| AttributeDescription{
+            attribute_type,
+            options,
+        })(input)
+}
+
 fn filter_type(input: &str) -> IResult<&str, FilterType> {
     alt((
         map(tag("="), |_| FilterType::Equal),
@@ -49,6 +72,7 @@ fn attribute_value_byte(input: &str) -> IResult<&str, u8> {
             |(fst, snd)| hex_digit_value(fst) * 16u8 + hex_digit_value(snd)
         ),
         map(
+            // a backslash that doesn't start an escape is taken as it is
             satisfy(|c| !"\0()*\x1b".chars().any(|b| b == c)),
             |c| {
                 let c = c.to_ascii_lowercase();
@@ -65,79 +89,137 @@ fn attribute_value(input: &str) -> IResult<&str, Vec<u8>> {
     fold_many0(attribute_value_byte, Vec::new, |mut v, byte| { v.push(byte); v })(input)
 }
 
-fn simple_filter(input: &str) -> IResult<&str, Filter> {
-    map(tuple((char('('), attribute_type, filter_type, attribute_value, char(')'))),
-        |(_,atype, ftype, avalue, _)| {
-            Filter::Simple(
-                AttributeDescription{
-                    attribute_type: atype,
-                },
-                ftype,
-                avalue
-            )
+// Unless strict, whitespace is allowed between the parentheses and operators of filters, though
+// not within the values of simple filters, where it is significant.
+fn space(strict: bool) -> impl Fn(&str) -> IResult<&str, &str> {
+    move |input| if strict { Ok((input, "")) } else { multispace0(input) }
+}
+
+fn open(strict: bool) -> impl Fn(&str) -> IResult<&str, char> {
+    move |input| terminated(char('('), space(strict))(input)
+}
+
+fn simple_filter(input: &str, strict: bool) -> IResult<&str, Filter> {
+    map(tuple((open(strict), attribute_description, filter_type, attribute_value, char(')'))),
+        |(_, attrdesc, ftype, avalue, _)| {
+            Filter::Simple(attrdesc, ftype, avalue)
         })(input)
 }
 
-fn present_filter(input: &str) -> IResult<&str, Filter> {
-    map(tuple((char('('), attribute_type, tag("=*)"))),
-        |(_,atype, _)| {
-            Filter::Present(
-                AttributeDescription{
-                    attribute_type: atype,
-                },
-            )
+fn present_filter(input: &str, strict: bool) -> IResult<&str, Filter> {
+    map(tuple((open(strict), attribute_description, tag("=*)"))),
+        |(_, attrdesc, _)| {
+            Filter::Present(attrdesc)
         })(input)
 }
 
-fn substring_filter(input: &str) -> IResult<&str, Filter> {
-    map(tuple((char('('), attribute_type, char('='), glob, char(')'))),
-        |(_,atype, _, glob, _)| {
-            Filter::Substring(
-                AttributeDescription{
-                    attribute_type: atype,
-                },
-                glob
-            )
+fn substring_filter(input: &str, strict: bool) -> IResult<&str, Filter> {
+    map(tuple((open(strict), attribute_description, char('='), glob, char(')'))),
+        |(_, attrdesc, _, glob, _)| {
+            Filter::Substring(attrdesc, SubstringMatcher::new(&glob))
         })(input)
 }
 
-fn glob(input: &str) -> IResult<&str, Vec<GlobPart>> {
+/// Parses the value of a substring filter. Consecutive wildcards are collapsed into one, since
+/// the empty substrings between them match anything.
+pub(crate) fn glob(input: &str) -> IResult<&str, Vec<GlobPart>> {
     let part = alt((
             map(char('*'), |_| GlobPart::Wildcard),
             map(attribute_value_byte, GlobPart::Literal),
     ));
-    many1(part)(input)
+    map(many1(part), |mut parts| {
+        parts.dedup_by(|a, b| *a == GlobPart::Wildcard && *b == GlobPart::Wildcard);
+        parts
+    })(input)
 }
 
-fn not_filter(input: &str) -> IResult<&str, Filter> {
-    map(delimited(tag("(!"), filter, char(')')),
+fn not_filter(input: &str, strict: bool) -> IResult<&str, Filter> {
+    map(delimited(tuple((open(strict), char('!'), space(strict))), |input| filter_with(input, strict), pair(space(strict), char(')'))),
         |inner_filter| Filter::Not(Box::new(inner_filter)),
     )(input)
 }
 
-fn and_filter(input: &str) -> IResult<&str, Filter> {
-    map(delimited(tag("(&"), many1(filter), char(')')),
+fn and_filter(input: &str, strict: bool) -> IResult<&str, Filter> {
+    map(delimited(tuple((open(strict), char('&'), space(strict))), many1(terminated(|input| filter_with(input, strict), space(strict))), char(')')),
         Filter::And
     )(input)
 }
 
-fn or_filter(input: &str) -> IResult<&str, Filter> {
-    map(delimited(tag("(|"), many1(filter), char(')')),
+fn or_filter(input: &str, strict: bool) -> IResult<&str, Filter> {
+    map(delimited(tuple((open(strict), char('|'), space(strict))), many1(terminated(|input| filter_with(input, strict), space(strict))), char(')')),
         Filter::Or
     )(input)
 }
 
+/// Parses a filter as RFC 4515 has it, with no whitespace between its parts.
 pub fn filter(input: &str) -> IResult<&str, Filter> {
+    filter_with(input, true)
+}
+
+/// Parses a filter, allowing whitespace between its parentheses and operators unless strict,
+/// as in `( & (a=1) (b=2) )`.
+pub fn filter_with(input: &str, strict: bool) -> IResult<&str, Filter> {
     alt((
-        simple_filter,
-        present_filter,
-        substring_filter,
-        not_filter,
-        and_filter,
-        or_filter,
+        |input| simple_filter(input, strict),
+        |input| present_filter(input, strict),
+        |input| substring_filter(input, strict),
+        |input| not_filter(input, strict),
+        |input| and_filter(input, strict),
+        |input| or_filter(input, strict),
     ))(input)
 }
 
+type Check<'a> = Result<&'a str, (&'a str, &'static str)>;
+
+fn expect_char<'a>(input: &'a str, c: char, expected: &'static str) -> Check<'a> {
+    input.strip_prefix(c).ok_or((input, expected))
+}
+
+fn skip_space(input: &str, strict: bool) -> &str {
+    if strict { input } else { input.trim_start_matches([' ', '\t', '\r', '\n']) }
+}
+
+// Follows the grammar of filter_with by hand, stopping where the input goes wrong.
+fn check_filter(input: &str, strict: bool) -> Check<'_> {
+    let input = skip_space(expect_char(input, '(', "'('")?, strict);
+    if let Some(mut input) = input.strip_prefix(['&', '|']) {
+        input = skip_space(input, strict);
+        loop {
+            input = skip_space(check_filter(input, strict)?, strict);
+            if !input.starts_with('(') {
+                return expect_char(input, ')', "'(' or ')'");
+            }
+        }
+    }
+    if let Some(input) = input.strip_prefix('!') {
+        let input = skip_space(check_filter(skip_space(input, strict), strict)?, strict);
+        return expect_char(input, ')', "')'");
+    }
+    let (mut input, _) = attribute_type(input).map_err(|_| (input, "an attribute type"))?;
+    while let Some(option) = input.strip_prefix(';') {
+        (input, _) = attribute_option(input).map_err(|_| (option, "an attribute option"))?;
+    }
+    let (mut input, ftype) = filter_type(input).map_err(|_| (input, "'=', '~=', '>=' or '<='"))?;
+    loop {
+        if ftype == FilterType::Equal && input.starts_with('*') {
+            input = &input[1..];
+        } else if let Ok((rest, _)) = attribute_value_byte(input) {
+            input = rest;
+        } else {
+            break;
+        }
+    }
+    expect_char(input, ')', "')'")
+}
+
+/// Tells where a filter that `filter_with` failed to parse, or parsed with input left over,
+/// goes wrong, as the input from there and what was expected there.
+pub(crate) fn diagnose(input: &str, strict: bool) -> (&str, &'static str) {
+    match check_filter(input, strict) {
+        Ok(remainder) => (remainder, "end of filter"),
+        Err(err) => err,
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -147,6 +229,7 @@ mod test {
         fn new(attribute_type: String) -> AttributeDescription {
             AttributeDescription{
                 attribute_type,
+                options: Vec::new(),
             }
         }
     }
@@ -195,5 +278,58 @@ mod test {
         assert_eq!(
             glob("f*"),
             Ok(("", vec![GlobPart::Literal(b'f'), GlobPart::Wildcard])));
+        assert_eq!(
+            glob("*f"),
+            Ok(("", vec![GlobPart::Wildcard, GlobPart::Literal(b'f')])));
+        assert_eq!(
+            glob("f**o***"),
+            Ok(("", vec![GlobPart::Literal(b'f'), GlobPart::Wildcard, GlobPart::Literal(b'o'), GlobPart::Wildcard])));
+        assert_eq!(glob("**"), Ok(("", vec![GlobPart::Wildcard])));
+        assert_eq!(filter("(cn=f\\oo*)"), filter("(cn=f\\5coo*)"));
+        assert_eq!(
+            filter("(path=C:\\dir)"),
+            Ok(("", Filter::Simple(AttributeDescription::new(String::from("path")), FilterType::Equal, b"c:\\dir".to_vec()))));
+    }
+
+    #[test]
+    fn test_attribute_options() {
+        let attrdesc = AttributeDescription{
+            attribute_type: String::from("cn"),
+            options: vec![String::from("lang-en"), String::from("binary")],
+        };
+        assert_eq!(
+            filter("(CN;Lang-EN;binary=*foo*)"),
+            Ok(("", Filter::Substring(attrdesc, SubstringMatcher::new(&[GlobPart::Wildcard, GlobPart::Literal(b'f'), GlobPart::Literal(b'o'), GlobPart::Literal(b'o'), GlobPart::Wildcard])))));
+        assert!(filter("(cn;=foo)").is_err());
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let expected = Filter::And(vec![
+            Filter::Simple(AttributeDescription::new(String::from("a")), FilterType::Equal, vec![b'1']),
+            Filter::Not(Box::new(Filter::Present(AttributeDescription::new(String::from("b"))))),
+        ]);
+        assert_eq!(filter_with("( & (a=1)\n\t( ! ( b=*) ) )", false), Ok(("", expected)));
+        assert!(filter("( & (a=1) (b=2) )").is_err());
+        assert_eq!(
+            filter_with("( cn=a b )", false),
+            Ok(("", Filter::Simple(AttributeDescription::new(String::from("cn")), FilterType::Equal, b"a b ".to_vec()))));
+        assert!(filter_with("(cn =a)", false).is_err());
+        assert_eq!(diagnose("( & (a=1) (b=2) ", false), ("", "'(' or ')'"));
+        assert_eq!(diagnose("( & (a=1) (b=2) )", true), (" & (a=1) (b=2) )", "an attribute type"));
+    }
+
+    #[test]
+    fn test_diagnose() {
+        assert_eq!(diagnose("cn=foo", true), ("cn=foo", "'('"));
+        assert_eq!(diagnose("(cn=foo", true), ("", "')'"));
+        assert_eq!(diagnose("(=foo)", true), ("=foo)", "an attribute type"));
+        assert_eq!(diagnose("(cn;=foo)", true), ("=foo)", "an attribute option"));
+        assert_eq!(diagnose("(cn:=foo)", true), (":=foo)", "'=', '~=', '>=' or '<='"));
+        assert_eq!(diagnose("(cn>=f*)", true), ("*)", "')'"));
+        assert_eq!(diagnose("(&)", true), (")", "'('"));
+        assert_eq!(diagnose("(&(cn=a)(sn=b)", true), ("", "'(' or ')'"));
+        assert_eq!(diagnose("(!(cn=a)(sn=b))", true), ("(sn=b))", "')'"));
+        assert_eq!(diagnose("(cn=a))", true), (")", "end of filter"));
     }
 }
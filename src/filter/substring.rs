@@ -0,0 +1,174 @@
+use crate::filter::GlobPart;
+use crate::filter::parser::glob;
+use nom::combinator::eof;
+use nom::sequence::terminated;
+
+// Filter values are lowercased by the parser, except for escaped bytes, which are matched as-is.
+fn byte_matches(value_byte: u8, glob_byte: u8) -> bool {
+    value_byte == glob_byte || value_byte.to_ascii_lowercase() == glob_byte
+}
+
+/// A literal part of a substring filter, prepared for Boyer-Moore-Horspool search.
+#[derive(Debug, PartialEq)]
+struct Literal {
+    bytes: Vec<u8>,
+    shift: Box<[usize; 256]>,
+}
+
+impl Literal {
+    fn new(bytes: Vec<u8>) -> Literal {
+        let len = bytes.len();
+        let mut shift = Box::new([len.max(1); 256]);
+        for (i, glob_byte) in bytes.iter().copied().enumerate().take(len.saturating_sub(1)) {
+            shift[glob_byte as usize] = len - 1 - i;
+            if glob_byte.is_ascii_lowercase() {
+                shift[glob_byte.to_ascii_uppercase() as usize] = len - 1 - i;
+            }
+        }
+        Literal{
+            bytes,
+            shift,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn is_match_at(&self, value: &[u8], offset: usize) -> bool {
+        value[offset..offset + self.len()].iter()
+            .zip(self.bytes.iter())
+            .rev()
+            .all(|(value_byte, glob_byte)| byte_matches(*value_byte, *glob_byte))
+    }
+
+    // Returns the offset of the first occurrence of the literal in value.
+    fn find(&self, value: &[u8]) -> Option<usize> {
+        let len = self.len();
+        let mut offset = 0;
+        while offset + len <= value.len() {
+            if self.is_match_at(value, offset) {
+                return Some(offset);
+            }
+            if len == 0 {
+                break;
+            }
+            offset += self.shift[value[offset + len - 1] as usize];
+        }
+        None
+    }
+}
+
+/// A substring filter compiled from a glob such as `foo*bar*baz`.
+#[derive(Debug, PartialEq)]
+pub struct SubstringMatcher {
+    initial: Literal,
+    any: Vec<Literal>,
+    final_: Option<Literal>, // None if the glob has no wildcard
+}
+
+impl SubstringMatcher {
+    pub fn new(glob: &[GlobPart]) -> SubstringMatcher {
+        let mut segments: Vec<Vec<u8>> = vec![Vec::new()];
+        for part in glob.iter() {
+            match part {
+                GlobPart::Wildcard => segments.push(Vec::new()),
+                GlobPart::Literal(c) => if let Some(segment) = segments.last_mut() {
+                    segment.push(*c);
+                },
+            }
+        }
+        let final_ = if segments.len() > 1 {
+            segments.pop().map(Literal::new)
+        } else {
+            None
+        };
+        let mut segments = segments.into_iter();
+        let initial = Literal::new(segments.next().unwrap_or_default());
+        let any = segments
+            .filter(|segment| !segment.is_empty())
+            .map(Literal::new)
+            .collect();
+        SubstringMatcher{
+            initial,
+            any,
+            final_,
+        }
+    }
+
+    /// Compiles a pattern with the syntax of the value of a substring filter, such as `smtp:*`.
+    /// As in filters, matching ignores ASCII case and special characters are escaped as `\2a`.
+    pub fn parse(pattern: &str) -> Result<SubstringMatcher, &'static str> {
+        match terminated(glob, eof)(pattern) {
+            Ok((_, glob)) => Ok(SubstringMatcher::new(&glob)),
+            Err(_) => Err("failed to parse substring pattern"),
+        }
+    }
+
+    pub fn is_match(&self, value: &[u8]) -> bool {
+        let final_ = match self.final_ {
+            Some(ref final_) => final_,
+            None => return value.len() == self.initial.len() && self.initial.is_match_at(value, 0),
+        };
+        if value.len() < self.initial.len() + final_.len() {
+            return false;
+        }
+        if !self.initial.is_match_at(value, 0) || !final_.is_match_at(value, value.len() - final_.len()) {
+            return false;
+        }
+        let mut start = self.initial.len();
+        let end = value.len() - final_.len();
+        for any in self.any.iter() {
+            match any.find(&value[start..end]) {
+                Some(offset) => start += offset + any.len(),
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matcher(s: &str) -> SubstringMatcher {
+        let (_, glob) = glob(s).unwrap();
+        SubstringMatcher::new(&glob)
+    }
+
+    #[test]
+    fn test_a() {
+        assert!(matcher("f*").is_match(b"foo"));
+        assert!(matcher("f*").is_match(b"FOO"));
+        assert!(!matcher("f*").is_match(b"bar"));
+        assert!(matcher("*oo").is_match(b"foo"));
+        assert!(matcher("*").is_match(b""));
+        assert!(matcher("**").is_match(b"x"));
+    }
+
+    #[test]
+    fn test_b() {
+        assert!(matcher("a*b*c").is_match(b"abc"));
+        assert!(matcher("a*b*c").is_match(b"axxbxxc"));
+        assert!(!matcher("a*b*c").is_match(b"acb"));
+        assert!(!matcher("ab*bc").is_match(b"abc"));
+        assert!(matcher("*src*root*").is_match(b"xsrcRootx"));
+        assert!(!matcher("*root*src*").is_match(b"xsrcRootx"));
+        assert!(matcher("*aab*").is_match(b"aaaab"));
+    }
+
+    #[test]
+    fn test_escaped() {
+        // escaped bytes are not lowercased by the parser
+        assert!(matcher("\\41*").is_match(b"Abc"));
+        assert!(!matcher("\\41*").is_match(b"abc"));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(SubstringMatcher::parse("smtp:*").is_ok_and(|matcher| matcher.is_match(b"SMTP:foo@example.com")));
+        assert!(SubstringMatcher::parse("foo").is_ok_and(|matcher| !matcher.is_match(b"foobar")));
+        assert!(SubstringMatcher::parse("a(b").is_err());
+    }
+}
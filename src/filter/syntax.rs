@@ -0,0 +1,73 @@
+use std::cmp::Ordering;
+
+/// How values of an attribute are compared in equality and ordering (`>=`, `<=`) filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// Octet-wise, ignoring ASCII case.
+    #[default]
+    String,
+    /// As integers, such as for uidNumber.
+    Integer,
+    /// Ignoring spaces and hyphens, such as for telephoneNumber.
+    TelephoneNumber,
+}
+
+impl Syntax {
+    /// Parses `string`, `integer` or `telephone`.
+    pub fn parse(s: &str) -> Result<Syntax, &'static str> {
+        match s.to_ascii_lowercase().as_str() {
+            "string" => Ok(Syntax::String),
+            "integer" => Ok(Syntax::Integer),
+            "telephone" => Ok(Syntax::TelephoneNumber),
+            _ => Err("unknown syntax; expected string, integer or telephone"),
+        }
+    }
+
+    /// Returns the syntax for an LDAP syntax OID (RFC 4517), if it is compared differently
+    /// from strings.
+    pub fn from_oid(oid: &str) -> Option<Syntax> {
+        match oid {
+            "1.3.6.1.4.1.1466.115.121.1.27" => Some(Syntax::Integer),
+            "1.3.6.1.4.1.1466.115.121.1.50" => Some(Syntax::TelephoneNumber),
+            _ => None,
+        }
+    }
+
+    /// Compares an attribute value with a filter value, which the filter parser has lowercased.
+    /// Returns None if the values cannot be compared, such as when one of them is not an
+    /// integer.
+    pub fn compare(&self, value: &[u8], filtervalue: &[u8]) -> Option<Ordering> {
+        match self {
+            Syntax::String => Some(value.to_ascii_lowercase().as_slice().cmp(filtervalue)),
+            Syntax::Integer => {
+                let value = parse_integer(value)?;
+                let filtervalue = parse_integer(filtervalue)?;
+                Some(value.cmp(&filtervalue))
+            },
+            Syntax::TelephoneNumber => {
+                let significant = |c: &&u8| **c != b' ' && **c != b'-';
+                let value = value.iter().filter(significant).map(u8::to_ascii_lowercase);
+                let filtervalue = filtervalue.iter().filter(significant).copied();
+                Some(value.cmp(filtervalue))
+            },
+        }
+    }
+}
+
+fn parse_integer(value: &[u8]) -> Option<i128> {
+    std::str::from_utf8(value).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(Syntax::String.compare(b"9", b"10000"), Some(Ordering::Greater));
+        assert_eq!(Syntax::Integer.compare(b"9", b"10000"), Some(Ordering::Less));
+        assert_eq!(Syntax::Integer.compare(b"-5", b"-10"), Some(Ordering::Greater));
+        assert_eq!(Syntax::Integer.compare(b"x", b"1"), None);
+        assert_eq!(Syntax::TelephoneNumber.compare(b"+47 123-45 678", b"+4712345678"), Some(Ordering::Equal));
+    }
+}
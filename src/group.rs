@@ -0,0 +1,178 @@
+use crate::dn::normalize;
+use crate::entry::{Entry, OwnedEntry};
+use crate::store::EntryStore;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// The attributes that hold the DNs of the members of a group, in lowercase.
+pub const MEMBER_ATTRIBUTES: [&str; 2] = ["member", "uniquemember"];
+
+/// Tells whether the entry has any member values.
+pub fn is_group(entry: &Entry) -> bool {
+    MEMBER_ATTRIBUTES.iter().any(|attr| entry.get_one(attr).is_some())
+}
+
+/// Returns the DNs of the direct members of a group entry, from its member and uniqueMember
+/// values. The optional unique identifier that may follow a uniqueMember DN, as in
+/// `cn=foo,dc=example#'0101'B`, is left out.
+pub fn members<'a>(entry: &'a Entry) -> impl Iterator<Item = Cow<'a, str>> {
+    MEMBER_ATTRIBUTES.iter()
+        .flat_map(|attr| entry.get_str(attr))
+        .map(|value| match value {
+            Cow::Borrowed(value) => Cow::Borrowed(strip_unique_id(value)),
+            Cow::Owned(value) => Cow::Owned(String::from(strip_unique_id(&value))),
+        })
+}
+
+/// Maps the normalized DN of each member to the DNs of the groups in the store that it is a
+/// direct member of, in the order the groups appear.
+pub fn member_of(store: &EntryStore) -> HashMap<String, Vec<String>> {
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for group in store.iter().filter(|entry| is_group(entry)) {
+        let Some(group_dn) = group.get_one_str("dn") else {
+            continue;
+        };
+        for member in members(group) {
+            let member_groups = groups.entry(normalize(&member)).or_default();
+            if !member_groups.iter().any(|dn| *dn == group_dn) {
+                member_groups.push(group_dn.to_string());
+            }
+        }
+    }
+    groups
+}
+
+/// The effective members of a group, found by `expand_members`.
+#[derive(Debug, Default)]
+pub struct Expansion {
+    /// The DNs of the direct and indirect members, each listed once.
+    pub members: Vec<String>,
+    /// The membership cycles found, each given as the DNs of the groups along it, starting and
+    /// ending with the same group.
+    pub cycles: Vec<Vec<String>>,
+}
+
+/// Resolves the members of a group transitively, so that the members of member groups in the
+/// store are members too. Member groups are expanded down to max_depth levels, where 1 means
+/// only the direct members. A group that is reached again through its own members is recorded
+/// as a cycle rather than expanded again.
+pub fn expand_members(store: &EntryStore, group: &Entry, max_depth: Option<usize>) -> Expansion {
+    let mut expansion = Expansion::default();
+    let Some(group_dn) = group.get_one_str("dn") else {
+        return expansion;
+    };
+    let mut path: Vec<(String, String)> = vec![(normalize(&group_dn), group_dn.into_owned())];
+    let mut seen: HashSet<String> = HashSet::new();
+    expand(store, group, max_depth, &mut path, &mut seen, &mut expansion);
+    expansion
+}
+
+// path holds the normalized and original DNs of the groups being expanded.
+fn expand(
+    store: &EntryStore,
+    group: &Entry,
+    max_depth: Option<usize>,
+    path: &mut Vec<(String, String)>,
+    seen: &mut HashSet<String>,
+    expansion: &mut Expansion,
+) {
+    for member in members(group) {
+        let key = normalize(&member);
+        if let Some(start) = path.iter().position(|(dn, _)| *dn == key) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|(_, dn)| dn.clone()).collect();
+            cycle.push(member.into_owned());
+            expansion.cycles.push(cycle);
+            continue;
+        }
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        expansion.members.push(member.to_string());
+        if max_depth.is_some_and(|max_depth| path.len() >= max_depth) {
+            continue;
+        }
+        if let Some(member_group) = store.get(&member).filter(|entry| is_group(entry)) {
+            path.push((key, member.into_owned()));
+            expand(store, member_group, max_depth, path, seen, expansion);
+            path.pop();
+        }
+    }
+}
+
+/// Returns a copy of the entry with its memberOf values replaced by the given group DNs.
+pub fn with_member_of(entry: &Entry, groups: &[String]) -> OwnedEntry {
+    let mut copy = OwnedEntry::default();
+    for attr in entry.attributes().filter(|attr| attr.lowercase != "memberof") {
+        for value in entry.get(attr.lowercase) {
+            copy.add_value(attr.name, value);
+        }
+    }
+    for group in groups.iter() {
+        copy.add_value("memberOf", group.as_bytes());
+    }
+    copy
+}
+
+// Removes a trailing #'BITS'B from a uniqueMember value.
+fn strip_unique_id(value: &str) -> &str {
+    let Some(without_b) = value.strip_suffix("'B") else {
+        return value;
+    };
+    match without_b.rfind("#'") {
+        Some(index) if without_b[index + 2..].bytes().all(|c| c == b'0' || c == b'1') => &value[..index],
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bulk::parse_entries;
+
+    #[test]
+    fn test_members() {
+        let group = OwnedEntry::from([
+            ("dn", b"cn=admins,dc=example".as_slice()),
+            ("member", b"cn=foo,dc=example".as_slice()),
+            ("uniqueMember", b"cn=bar,dc=example#'0101'B".as_slice()),
+            ("uniqueMember", b"cn=#'01'B\\, x,dc=example".as_slice()),
+        ]);
+        assert!(is_group(&group));
+        let members: Vec<Cow<str>> = members(&group).collect();
+        assert_eq!(members, ["cn=foo,dc=example", "cn=bar,dc=example", "cn=#'01'B\\, x,dc=example"]);
+    }
+
+    #[test]
+    fn test_member_of() -> std::io::Result<()> {
+        let mut store = EntryStore::new();
+        parse_entries(concat!(
+            "dn: cn=a,dc=example\nmember: cn=foo,dc=example\nmember: cn=bar,dc=example\n\n",
+            "dn: cn=b,dc=example\nuniqueMember: CN=Foo, DC=example\n\n",
+            "dn: cn=foo,dc=example\nmemberOf: cn=old,dc=example\n",
+        ).as_bytes(), &mut store)?;
+        let groups = member_of(&store);
+        let foo_groups = &groups["cn=foo,dc=example"];
+        assert_eq!(foo_groups, &["cn=a,dc=example", "cn=b,dc=example"]);
+        let foo = with_member_of(store.get("cn=foo,dc=example").unwrap(), foo_groups);
+        assert_eq!(foo.get_str("memberOf").collect::<Vec<_>>(), ["cn=a,dc=example", "cn=b,dc=example"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_members() -> std::io::Result<()> {
+        let mut store = EntryStore::new();
+        parse_entries(concat!(
+            "dn: cn=a\nmember: cn=b\nmember: cn=foo\n\n",
+            "dn: cn=b\nmember: cn=c\nmember: cn=foo\n\n",
+            "dn: cn=c\nmember: cn=bar\nmember: CN=A\n\n",
+        ).as_bytes(), &mut store)?;
+        let a = store.get("cn=a").unwrap();
+        let expansion = expand_members(&store, a, None);
+        assert_eq!(expansion.members, ["cn=b", "cn=c", "cn=bar", "cn=foo"]);
+        assert_eq!(expansion.cycles, [["cn=a", "cn=b", "cn=c", "CN=A"]]);
+        let expansion = expand_members(&store, a, Some(2));
+        assert_eq!(expansion.members, ["cn=b", "cn=c", "cn=foo"]);
+        assert!(expansion.cycles.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+/// Output that replaces a file once it has been completely written, for editing files in place
+/// without truncating the input as it is read. The output is written to a temporary file in the
+/// same directory, which is renamed over the original file by `commit`. The temporary file is
+/// removed if the output is dropped without being committed.
+pub struct InPlaceFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: Option<BufWriter<File>>, // None once committed
+}
+
+impl InPlaceFile {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<InPlaceFile> {
+        let path = path.as_ref().to_path_buf();
+        let permissions = fs::metadata(&path)?.permissions();
+        let file_name = path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+        let file = File::create(&temp_path)?;
+        let in_place = InPlaceFile{
+            path,
+            temp_path,
+            file: Some(BufWriter::new(file)),
+        };
+        fs::set_permissions(&in_place.temp_path, permissions)?;
+        Ok(in_place)
+    }
+
+    /// Flushes the output to disk and replaces the original file with it.
+    pub fn commit(mut self) -> Result<()> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let result = file.into_inner()
+            .map_err(|err| err.into_error())
+            .and_then(|file| file.sync_all())
+            .and_then(|_| fs::rename(&self.temp_path, &self.path));
+        if result.is_err() {
+            _ = fs::remove_file(&self.temp_path);
+            return result;
+        }
+        // the rename itself is only durable once the directory is synced
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for InPlaceFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self.file {
+            Some(ref mut file) => file.write(buf),
+            None => Err(std::io::Error::other("write after commit")),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.file {
+            Some(ref mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for InPlaceFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("ltools-inplace-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("test.ldif");
+        fs::write(&path, "dn: cn=foo\n")?;
+
+        let mut file = InPlaceFile::create(&path)?;
+        file.write_all(b"dn: cn=bar\n")?;
+        drop(file);
+        assert_eq!(fs::read_to_string(&path)?, "dn: cn=foo\n");
+
+        let mut file = InPlaceFile::create(&path)?;
+        file.write_all(b"dn: cn=bar\n")?;
+        file.commit()?;
+        assert_eq!(fs::read_to_string(&path)?, "dn: cn=bar\n");
+        assert_eq!(fs::read_dir(&dir)?.count(), 1);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+//! Processing input files on worker threads, for the `--jobs` option of `lget`, `lfilter` and
+//! `lcount`. The inputs are handed out to the workers one at a time, and their results are merged
+//! in the order the inputs were given, so that the output is the same as when the inputs are
+//! processed one after another.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+
+/// Fails if standard input, given as `-`, is among the inputs more than once, since it can only
+/// be read once, and with more than one job, it would be read by several workers at the same time.
+pub fn check_inputs(inputs: &[String]) -> Result<()> {
+    if inputs.iter().filter(|input| *input == "-").count() > 1 {
+        return Err(Error::new(ErrorKind::InvalidInput, "standard input (-) can only be given once with --jobs"));
+    }
+    Ok(())
+}
+
+/// Calls `process` on each input on up to `jobs` worker threads, and `merge` on the result of each
+/// input in the order of the inputs, as soon as the results of the inputs before it have been
+/// merged. Results that are ready before then are kept in memory. Stops at the first error, after
+/// the workers have finished the inputs they are processing.
+pub fn process_inputs<T, P, M>(inputs: &[String], jobs: usize, process: P, mut merge: M) -> Result<()>
+    where T: Send, P: Fn(&str) -> Result<T> + Sync, M: FnMut(T) -> Result<()>
+{
+    check_inputs(inputs)?;
+    let next_input = AtomicUsize::new(0);
+    let (sender, receiver) = channel::<(usize, Result<T>)>();
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, inputs.len().max(1)) {
+            let sender = sender.clone();
+            let next_input = &next_input;
+            let process = &process;
+            scope.spawn(move || loop {
+                let index = next_input.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else {
+                    break;
+                };
+                if sender.send((index, process(input))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending: BTreeMap<usize, T> = BTreeMap::new();
+        let mut next_output: usize = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result?);
+            while let Some(result) = pending.remove(&next_output) {
+                merge(result)?;
+                next_output += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_process_inputs() -> Result<()> {
+        let inputs: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let mut merged: Vec<usize> = Vec::new();
+        process_inputs(&inputs, 4, |input| {
+            // later inputs finish first
+            let i: usize = input.parse().unwrap();
+            thread::sleep(std::time::Duration::from_millis(20 - i as u64));
+            Ok(i)
+        }, |i| {
+            merged.push(i);
+            Ok(())
+        })?;
+        assert_eq!(merged, (0..20).collect::<Vec<usize>>());
+
+        let result = process_inputs(&inputs, 4, |input| match input {
+            "3" => Err(Error::other("failed")),
+            _ => Ok(()),
+        }, |_| Ok(()));
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_inputs() {
+        assert!(check_inputs(&[String::from("-"), String::from("a.ldif")]).is_ok());
+        assert!(check_inputs(&[String::from("-"), String::from("a.ldif"), String::from("-")]).is_err());
+    }
+}
@@ -1,14 +1,19 @@
-use crate::attrspec::AttrSpec;
+use crate::attrspec::{expand_wildcards, AttrSpec};
+use crate::dn;
 use crate::entry::{ Entry, WriteEntry };
+use crate::output::write_all_vectored;
+use crate::separator::RecordSeparator;
 use std::io::{
+    IoSlice,
     Write,
     Result,
 };
+use std::collections::BTreeMap;
 use std::write;
 
 pub struct JsonEntryWriter<W: Write> {
     dest: W,
-    record_separator: u8,
+    record_separator: RecordSeparator,
     attrspecs: Vec<AttrSpec>,
 }
 
@@ -16,18 +21,18 @@ impl<W: Write> JsonEntryWriter<W> {
     pub fn new(attrspecs: Vec<AttrSpec>, dest: W) -> JsonEntryWriter<W> {
         JsonEntryWriter{
             dest,
-            record_separator: b'\n',
+            record_separator: RecordSeparator::default(),
             attrspecs,
         }
     }
 
-    pub fn set_record_separator(&mut self, c: u8) -> &mut Self {
-        self.record_separator = c;
+    pub fn set_record_separator<S: Into<RecordSeparator>>(&mut self, record_separator: S) -> &mut Self {
+        self.record_separator = record_separator.into();
         self
     }
 }
 
-fn write_json_string<W: Write>(w: &mut W, s: &str) -> Result<()> {
+pub(crate) fn write_json_string<W: Write>(w: &mut W, s: &str) -> Result<()> {
     let mut written: usize = 0;
     w.write_all(b"\"")?;
     let mut utf16buf: [u16;2] = [0;2];
@@ -44,11 +49,11 @@ fn write_json_string<W: Write>(w: &mut W, s: &str) -> Result<()> {
                 '\t' => w.write_all(b"\\t")?,
                 c => {
                     for unit in c.encode_utf16(&mut utf16buf).iter() {
-                        write!(w, "\\u{unit:04}")?;
+                        write!(w, "\\u{unit:04x}")?;
                     }
                 }
             }
-            written += 1;
+            written += c.len_utf8();
         }
     }
     if written < s.len() {
@@ -58,28 +63,121 @@ fn write_json_string<W: Write>(w: &mut W, s: &str) -> Result<()> {
     Ok(())
 }
 
+// Writes the members of the JSON object of an entry, without the braces. Wildcards are expanded
+// for each entry, so that objects only have the attributes of their entry.
+fn write_members<W: Write>(dest: &mut W, attrspecs: &[AttrSpec], entry: &Entry) -> Result<()> {
+    let expanded: Vec<AttrSpec>;
+    let attrspecs = if attrspecs.iter().any(|attrspec| attrspec.is_wildcard() || attrspec.exclude) {
+        let attributes: Vec<&str> = entry.attributes().map(|attr| attr.name).collect();
+        expanded = expand_wildcards(attrspecs, &attributes);
+        &expanded
+    } else {
+        attrspecs
+    };
+    for (i, attrspec) in attrspecs.iter().enumerate() {
+        let values = attrspec.entry_values(entry)?;
+        if i != 0 {
+            dest.write_all(b",")?;
+        }
+        write_json_string(dest, &attrspec.attribute)?;
+        dest.write_all(b":[")?;
+        for (i, value) in values.iter().enumerate() {
+            if i != 0 {
+                dest.write_all(b",")?;
+            }
+            let value = String::from_utf8_lossy(value);
+            write_json_string(dest, &value)?;
+        }
+        dest.write_all(b"]")?;
+    }
+    Ok(())
+}
+
 impl<W: Write> WriteEntry for JsonEntryWriter<W> {
     fn write_entry(&mut self, entry: &Entry) -> Result<()> {
         self.dest.write_all(b"{")?;
-        for (i, attrspec) in self.attrspecs.iter().enumerate() {
-            let attrtype = &attrspec.attribute_lowercase;
-            let values = entry.get(attrtype);
-            let values = attrspec.filter_values(values);
+        write_members(&mut self.dest, &self.attrspecs, entry)?;
+        write_all_vectored(&mut self.dest, &mut [IoSlice::new(b"}"), IoSlice::new(self.record_separator.as_bytes())])
+    }
+}
+
+/// Gathers entries into one JSON object that mirrors the directory tree, which `finish` writes.
+/// Each entry is a member of its parent keyed by its RDN, as in
+/// `{"dc=com":{"attributes":{...},"children":{"dc=example":{...}}}}`. Entries that are only
+/// implied by the DNs of others have no `attributes` member. Children are in the order of their
+/// lowercase RDNs, and entries without a DN are left out. Of entries with the same DN, the last
+/// one is kept.
+pub struct JsonTreeWriter<W: Write> {
+    dest: W,
+    record_separator: RecordSeparator,
+    attrspecs: Vec<AttrSpec>,
+    nodes: Vec<TreeNode>, // the first is the root, above the top-level entries
+}
+
+struct TreeNode {
+    rdn: String,
+    attributes: Option<Vec<u8>>, // the members of the object of the entry
+    children: BTreeMap<String, usize>, // lowercase RDN to node index
+}
+
+impl<W: Write> JsonTreeWriter<W> {
+    pub fn new(attrspecs: Vec<AttrSpec>, dest: W) -> JsonTreeWriter<W> {
+        JsonTreeWriter{
+            dest,
+            record_separator: RecordSeparator::default(),
+            attrspecs,
+            nodes: vec![TreeNode{ rdn: String::new(), attributes: None, children: BTreeMap::new() }],
+        }
+    }
+
+    pub fn set_record_separator<S: Into<RecordSeparator>>(&mut self, record_separator: S) -> &mut Self {
+        self.record_separator = record_separator.into();
+        self
+    }
+
+    /// Writes the tree of the entries that have been written, followed by the record separator.
+    pub fn finish(&mut self) -> Result<()> {
+        self.write_children(0)?;
+        self.dest.write_all(self.record_separator.as_bytes())
+    }
+
+    fn write_children(&mut self, index: usize) -> Result<()> {
+        self.dest.write_all(b"{")?;
+        let children: Vec<usize> = self.nodes[index].children.values().copied().collect();
+        for (i, child) in children.into_iter().enumerate() {
             if i != 0 {
                 self.dest.write_all(b",")?;
             }
-            write_json_string(&mut self.dest, &attrspec.attribute)?;
-            self.dest.write_all(b":[")?;
-            for (i, value) in values.iter().enumerate() {
-                if i != 0 {
-                    self.dest.write_all(b",")?;
-                }
-                let value = String::from_utf8_lossy(value);
-                write_json_string(&mut self.dest, &value)?;
+            write_json_string(&mut self.dest, &self.nodes[child].rdn)?;
+            self.dest.write_all(b":{")?;
+            if let Some(ref attributes) = self.nodes[child].attributes {
+                write_all_vectored(&mut self.dest, &mut [IoSlice::new(b"\"attributes\":{"), IoSlice::new(attributes), IoSlice::new(b"},")])?;
+            }
+            self.dest.write_all(b"\"children\":")?;
+            self.write_children(child)?;
+            self.dest.write_all(b"}")?;
+        }
+        self.dest.write_all(b"}")
+    }
+}
+
+impl<W: Write> WriteEntry for JsonTreeWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let Some(dn) = entry.get_one_str("dn") else {
+            return Ok(());
+        };
+        let rdns: Vec<&str> = dn::rdns(&dn).collect();
+        let mut index = 0;
+        for rdn in rdns.into_iter().rev() {
+            let next = self.nodes.len();
+            index = *self.nodes[index].children.entry(rdn.to_ascii_lowercase()).or_insert(next);
+            if index == next {
+                self.nodes.push(TreeNode{ rdn: rdn.to_string(), attributes: None, children: BTreeMap::new() });
             }
-            self.dest.write_all(b"]")?;
         }
-        self.dest.write_all(b"}\n")?;
+        let mut attributes: Vec<u8> = Vec::new();
+        write_members(&mut attributes, &self.attrspecs, entry)?;
+        self.nodes[index].attributes = Some(attributes);
         Ok(())
     }
 }
@@ -111,4 +209,54 @@ mod test {
         assert_eq!(String::from_utf8_lossy(&buf), r#""foo\tbar\u0000baz\r\n""#);
         Ok(())
     }
+
+    #[test]
+    fn write_json_string_test_d() -> Result<()> {
+        let mut buf = Vec::new();
+        write_json_string(&mut buf, "Dzień dobry \u{1f600}")?;
+        assert_eq!(String::from_utf8_lossy(&buf), r#""Dzie\u0144 dobry \ud83d\ude00""#);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wildcard() -> Result<()> {
+        let attrspecs = vec![AttrSpec::parse("dn")?, AttrSpec::parse("*")?, AttrSpec::parse("!userPassword")?];
+        let mut buf = Vec::new();
+        let mut entry_writer = JsonEntryWriter::new(attrspecs, &mut buf);
+        entry_writer.write_entry(&Entry::from([("cn", b"foo".as_slice()), ("dn", b"cn=foo"), ("userPassword", b"secret")]))?;
+        entry_writer.write_entry(&Entry::from([("dn", b"cn=bar".as_slice()), ("mail", b"bar@example.com")]))?;
+        assert_eq!(String::from_utf8_lossy(&buf), concat!(
+            r#"{"dn":["cn=foo"],"cn":["foo"]}"#, "\n",
+            r#"{"dn":["cn=bar"],"mail":["bar@example.com"]}"#, "\n",
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_separator() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut entry_writer = JsonEntryWriter::new(vec![AttrSpec::parse("cn")?], &mut buf);
+        entry_writer.set_record_separator(RecordSeparator::NUL);
+        entry_writer.write_entry(&Entry::from([("cn", b"foo".as_slice())]))?;
+        entry_writer.write_entry(&Entry::from([("cn", b"bar".as_slice())]))?;
+        assert_eq!(String::from_utf8_lossy(&buf), "{\"cn\":[\"foo\"]}\0{\"cn\":[\"bar\"]}\0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut tree_writer = JsonTreeWriter::new(vec![AttrSpec::parse("cn")?], &mut buf);
+        tree_writer.write_entry(&Entry::from([("dn", b"cn=foo, ou=People,dc=example".as_slice()), ("cn", b"foo")]))?;
+        tree_writer.write_entry(&Entry::from([("dn", b"ou=people,dc=example".as_slice())]))?;
+        tree_writer.write_entry(&Entry::from([("dn", b"cn=bar,ou=people,dc=example".as_slice()), ("cn", b"bar")]))?;
+        tree_writer.write_entry(&Entry::from([("cn", b"no DN".as_slice())]))?;
+        tree_writer.finish()?;
+        assert_eq!(String::from_utf8_lossy(&buf), concat!(
+            r#"{"dc=example":{"children":{"ou=People":{"attributes":{"cn":[]},"children":{"#,
+            r#""cn=bar":{"attributes":{"cn":["bar"]},"children":{}},"#,
+            r#""cn=foo":{"attributes":{"cn":["foo"]},"children":{}}}}}}}"#, "\n",
+        ));
+        Ok(())
+    }
 }
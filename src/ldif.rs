@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+use std::io::{
+    Write,
+    Result,
+};
+use crate::base64::EncodeWriter;
+use crate::entry::{
+    AttributeType,
+    Entry,
+    WriteEntry,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// Decides which values are base64-encoded in LDIF output. By default, values are encoded when RFC
+/// 2849 requires it, which includes every value with bytes outside ASCII.
+#[derive(Debug, Clone, Default)]
+pub struct Base64Policy {
+    allow_utf8: bool,
+    always: HashSet<String>, // lowercase attribute names
+    values: HashSet<(String, Vec<u8>)>, // by lowercase attribute name
+}
+
+impl Base64Policy {
+    /// Writes values that are valid UTF-8 as they are, rather than base64-encoding them for
+    /// having bytes outside ASCII. This is not strictly LDIF, but most LDAP tools accept it, and
+    /// it keeps names with accented letters readable.
+    pub fn set_allow_utf8(&mut self, allow_utf8: bool) -> &mut Self {
+        self.allow_utf8 = allow_utf8;
+        self
+    }
+
+    /// Base64-encodes every value of the attribute, such as those of attributes that are binary
+    /// even when they happen to look like text.
+    pub fn add_always(&mut self, attr: &str) -> &mut Self {
+        self.always.insert(attr.to_ascii_lowercase());
+        self
+    }
+
+    /// Base64-encodes the value whenever it is a value of the attribute, such as one that was
+    /// base64-encoded in the input, to keep trailing spaces and the like from being lost on
+    /// their way through tools that don't expect them.
+    pub fn add_value(&mut self, attr: &str, value: &[u8]) -> &mut Self {
+        self.values.insert((attr.to_ascii_lowercase(), value.to_vec()));
+        self
+    }
+
+    /// Base64-encodes the values of the entry that were base64-encoded in the input.
+    pub fn add_encoded_values(&mut self, entry: &Entry) -> &mut Self {
+        for attr in entry.attributes() {
+            for (value, _) in entry.get_with_encoding(attr.lowercase).filter(|(_, base64)| *base64) {
+                self.values.insert((attr.lowercase.to_string(), value.to_vec()));
+            }
+        }
+        self
+    }
+
+    pub fn needs_base64(&self, attr: &str, value: &[u8]) -> bool {
+        if !self.always.is_empty() || !self.values.is_empty() {
+            let attr = attr.to_ascii_lowercase();
+            if self.always.contains(&attr) {
+                return true;
+            }
+            if !self.values.is_empty() && self.values.contains(&(attr, value.to_vec())) {
+                return true;
+            }
+        }
+        if self.allow_utf8 && std::str::from_utf8(value).is_ok() {
+            return needs_base64_ascii(value);
+        }
+        needs_base64(value)
+    }
+}
+
+/// Writes entries as LDIF in a canonical form: the DN first, followed by the other attributes
+/// sorted by name, with lines folded at 76 columns and a single blank line between entries.
+pub struct LdifEntryWriter<W: Write> {
+    dest: W,
+    fold_width: Option<usize>,
+    line_ending: LineEnding,
+    sort_attributes: bool,
+    base64_policy: Base64Policy,
+    line: Vec<u8>,
+    entry_count: usize,
+}
+
+impl<W: Write> LdifEntryWriter<W> {
+    pub fn new(dest: W) -> LdifEntryWriter<W> {
+        LdifEntryWriter{
+            dest,
+            fold_width: Some(76),
+            line_ending: LineEnding::Lf,
+            sort_attributes: true,
+            base64_policy: Base64Policy::default(),
+            line: Vec::new(),
+            entry_count: 0,
+        }
+    }
+
+    /// Sets the maximum line length, or disables folding with None.
+    pub fn set_fold_width(&mut self, fold_width: Option<usize>) -> &mut Self {
+        // continuation lines need room for the leading space and at least one character
+        self.fold_width = fold_width.map(|width| width.max(2));
+        self
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) -> &mut Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    pub fn set_sort_attributes(&mut self, sort_attributes: bool) -> &mut Self {
+        self.sort_attributes = sort_attributes;
+        self
+    }
+
+    pub fn set_base64_policy(&mut self, base64_policy: Base64Policy) -> &mut Self {
+        self.base64_policy = base64_policy;
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+
+    fn write_attrval(&mut self, attr: &str, value: &[u8]) -> Result<()> {
+        self.line.clear();
+        self.line.extend_from_slice(attr.as_bytes());
+        self.line.push(b':');
+        if self.base64_policy.needs_base64(attr, value) {
+            self.line.extend_from_slice(b": ");
+            let mut base64 = EncodeWriter::new(&mut self.line);
+            base64.write_all(value)?;
+            base64.flush()?;
+        } else {
+            self.line.push(b' ');
+            self.line.extend_from_slice(value);
+        }
+        self.write_line()
+    }
+
+    fn write_attrurl(&mut self, attr: &str, url: &str) -> Result<()> {
+        self.line.clear();
+        self.line.extend_from_slice(attr.as_bytes());
+        self.line.extend_from_slice(b":< ");
+        self.line.extend_from_slice(url.as_bytes());
+        self.write_line()
+    }
+
+    /// Writes an entry like write_entry, except that the values for which url_for returns a URL
+    /// are written as `attr:< URL` references instead. url_for is given the attribute, the index
+    /// of the value among the values of the attribute and the value.
+    pub fn write_entry_with_urls<F>(&mut self, entry: &Entry, mut url_for: F) -> Result<()>
+    where
+        F: FnMut(&AttributeType, usize, &[u8]) -> Result<Option<String>>,
+    {
+        if self.entry_count != 0 {
+            self.dest.write_all(self.line_ending.as_bytes())?;
+        }
+        self.entry_count += 1;
+        let mut attributes: Vec<AttributeType> = entry.attributes().collect();
+        if self.sort_attributes {
+            attributes.sort_by(|a, b| (a.lowercase != "dn", a.lowercase).cmp(&(b.lowercase != "dn", b.lowercase)));
+        }
+        for attr in attributes {
+            for (index, value) in entry.get(attr.lowercase).enumerate() {
+                match url_for(&attr, index, value)? {
+                    Some(url) => self.write_attrurl(attr.name, &url)?,
+                    None => self.write_attrval(attr.name, value)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self) -> Result<()> {
+        let width = match self.fold_width {
+            Some(width) if self.line.len() > width => width,
+            _ => {
+                self.dest.write_all(&self.line)?;
+                return self.dest.write_all(self.line_ending.as_bytes());
+            },
+        };
+        let (first, mut rest) = self.line.split_at(width);
+        self.dest.write_all(first)?;
+        while !rest.is_empty() {
+            let (segment, remainder) = rest.split_at(rest.len().min(width - 1));
+            self.dest.write_all(self.line_ending.as_bytes())?;
+            self.dest.write_all(b" ")?;
+            self.dest.write_all(segment)?;
+            rest = remainder;
+        }
+        self.dest.write_all(self.line_ending.as_bytes())
+    }
+}
+
+impl<W: Write> WriteEntry for LdifEntryWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        self.write_entry_with_urls(entry, |_, _, _| Ok(None))
+    }
+}
+
+/// Tells whether an LDIF value must be base64-encoded according to RFC 2849: values that are not
+/// SAFE-STRINGs or that end with a space.
+pub fn needs_base64(value: &[u8]) -> bool {
+    needs_base64_ascii(value) || !value.is_ascii()
+}
+
+// Like needs_base64, except that bytes outside ASCII are taken as safe.
+fn needs_base64_ascii(value: &[u8]) -> bool {
+    if let Some(c) = value.first() {
+        if matches!(c, b' ' | b':' | b'<') {
+            return true;
+        }
+    }
+    if value.last() == Some(&b' ') {
+        return true;
+    }
+    value.iter().any(|c| matches!(c, b'\0' | b'\n' | b'\r'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format(entries: &[Entry], configure: fn(&mut LdifEntryWriter<&mut Vec<u8>>)) -> Result<String> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut writer = LdifEntryWriter::new(&mut output);
+        configure(&mut writer);
+        for entry in entries {
+            writer.write_entry(entry)?;
+        }
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let entries = [
+            Entry::from([
+                ("sn", b"Foo".as_slice()),
+                ("dn", b"cn=foo,dc=example,dc=com".as_slice()),
+                ("CN", b"foo".as_slice()),
+                ("description", b"trailing space ".as_slice()),
+                ("cn", b"bar".as_slice()),
+            ]),
+            Entry::from([
+                ("dn", b"cn=b\xc3\xa5r".as_slice()),
+            ]),
+        ];
+        assert_eq!(format(&entries, |_| ())?, "\
+            dn: cn=foo,dc=example,dc=com\n\
+            CN: foo\n\
+            CN: bar\n\
+            description:: dHJhaWxpbmcgc3BhY2Ug\n\
+            sn: Foo\n\
+            \n\
+            dn:: Y249YsOlcg==\n\
+        ");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fold() -> Result<()> {
+        let entries = [Entry::from([
+            ("dn", b"cn=foo".as_slice()),
+            ("description", b"abcdefghij".as_slice()),
+        ])];
+        assert_eq!(format(&entries, |writer| { writer.set_fold_width(Some(8)).set_line_ending(LineEnding::CrLf); })?, "\
+            dn: cn=f\r\n oo\r\n\
+            descript\r\n ion: ab\r\n cdefghi\r\n j\r\n\
+        ");
+        assert_eq!(format(&entries, |writer| { writer.set_fold_width(None).set_sort_attributes(false); })?, "\
+            dn: cn=foo\n\
+            description: abcdefghij\n\
+        ");
+        Ok(())
+    }
+
+    #[test]
+    fn test_urls() -> Result<()> {
+        let entry = Entry::from([
+            ("dn", b"cn=foo".as_slice()),
+            ("jpegPhoto", b"\xff\xd8".as_slice()),
+            ("jpegPhoto", b"\xff\xd9".as_slice()),
+        ]);
+        let mut output: Vec<u8> = Vec::new();
+        LdifEntryWriter::new(&mut output).write_entry_with_urls(&entry, |attr, index, _| {
+            Ok((attr.lowercase == "jpegphoto" && index == 1).then(|| String::from("file:///tmp/photo")))
+        })?;
+        assert_eq!(String::from_utf8_lossy(&output), "\
+            dn: cn=foo\n\
+            jpegPhoto:: /9g=\n\
+            jpegPhoto:< file:///tmp/photo\n\
+        ");
+        Ok(())
+    }
+
+    #[test]
+    fn test_needs_base64() {
+        assert!(!needs_base64(b"foo bar"));
+        assert!(!needs_base64(b""));
+        assert!(needs_base64(b" foo"));
+        assert!(needs_base64(b"foo "));
+        assert!(needs_base64(b":foo"));
+        assert!(needs_base64(b"<foo"));
+        assert!(needs_base64(b"foo\nbar"));
+        assert!(needs_base64(b"b\xc3\xa5r"));
+    }
+
+    #[test]
+    fn test_base64_policy() {
+        let mut policy = Base64Policy::default();
+        assert!(policy.needs_base64("cn", b"b\xc3\xa5r"));
+        policy.set_allow_utf8(true).add_always("jpegPhoto");
+        assert!(!policy.needs_base64("cn", b"b\xc3\xa5r"));
+        assert!(policy.needs_base64("cn", b"b\xc3\xa5r "));
+        assert!(policy.needs_base64("cn", b"\xff"));
+        assert!(policy.needs_base64("JPEGPHOTO", b"foo"));
+    }
+
+    #[test]
+    fn test_base64_policy_values() -> Result<()> {
+        let mut entries: Vec<crate::entry::OwnedEntry> = Vec::new();
+        crate::bulk::parse_entries(&b"dn: cn=foo\ncn:: Zm9v\nsn: foo\n"[..], &mut entries)?;
+        let mut policy = Base64Policy::default();
+        policy.add_encoded_values(&entries[0]);
+        assert!(policy.needs_base64("CN", b"foo"));
+        assert!(!policy.needs_base64("sn", b"foo"));
+        assert!(!policy.needs_base64("dn", b"cn=foo"));
+        Ok(())
+    }
+}
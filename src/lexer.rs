@@ -1,5 +1,8 @@
-use std::io::{ Result, Error, ErrorKind };
-use crate::loc::{ Loc, LocWrite };
+use alloc::format;
+use alloc::vec::Vec;
+use crate::sink::Result;
+use crate::loc::{ Loc, LocError, LocWrite };
+use memchr::memchr;
 
 const MAX_TYPE_LENGTH: usize = 1024;
 
@@ -7,13 +10,15 @@ enum State {
     LineStart(bool), // bool indicates whether we are inside an entry
     CommentLine(bool), // bool indicates whether we are inside an entry
     AttributeType,
+    AttributeOption(bool), // bool indicates whether the option has any characters yet
     ValueColon,
     SafeStringValue,
     Base64Value,
     WhitespaceBefore(&'static State),
+    Separator, // the "-" line that ends an operation in a modify change record
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum TokenKind {
     AttributeType,
     ValueText,
@@ -25,7 +30,10 @@ pub enum TokenKind {
 #[derive(Debug, Eq, PartialEq)]
 pub struct Token<'a> {
     pub kind: TokenKind,
+    /// Where the token starts in the original input.
     pub loc: Loc,
+    /// Where the token starts in the input after CR stripping and unfolding.
+    pub logical_loc: Loc,
     pub segment: &'a str,
 }
 
@@ -38,6 +46,8 @@ pub struct Lexer<R> {
     token_receiver: R,
     buf: Vec<u8>,
     token_start: Loc,
+    logical_token_start: Loc,
+    logical_loc: Loc,
 }
 
 impl<R: WriteToken> Lexer<R> {
@@ -47,13 +57,16 @@ impl<R: WriteToken> Lexer<R> {
             token_receiver,
             buf: Vec::with_capacity(1028),
             token_start: Loc::default(),
+            logical_token_start: Loc::default(),
+            logical_loc: Loc::default(),
         }
     }
 
     fn emit(&mut self, token_kind: TokenKind) -> Result<()> {
-        let segment = unsafe { std::str::from_utf8_unchecked(&self.buf[..]) };
+        let segment = unsafe { core::str::from_utf8_unchecked(&self.buf[..]) };
         let token = Token{
             loc: self.token_start,
+            logical_loc: self.logical_token_start,
             kind: token_kind,
             segment,
         };
@@ -62,6 +75,15 @@ impl<R: WriteToken> Lexer<R> {
         Ok(())
     }
 
+    // Separators are passed on as an attribute type of "-" with an empty value, so that token
+    // writers that know nothing of change records see them as any other line.
+    fn emit_separator(&mut self) -> Result<()> {
+        self.buf.push(b'-');
+        self.emit(TokenKind::AttributeType)?;
+        self.emit(TokenKind::ValueText)?;
+        self.emit(TokenKind::ValueFinish)
+    }
+
     pub fn get_ref(&self) -> &R {
         &self.token_receiver
     }
@@ -91,12 +113,58 @@ macro_rules! BASE64_CHAR {
     () => { b'+' | b'/' | b'=' | DIGIT!() | ALPHA!() };
 }
 
+// The length of the prefix of line whose bytes are all allowed. The bytes are checked 32 at a
+// time without branching, which the compiler turns into SIMD instructions, before the position
+// of the first disallowed byte is found.
+#[inline]
+fn allowed_prefix(line: &[u8], is_allowed: impl Fn(u8) -> bool) -> usize {
+    let mut n: usize = 0;
+    for chunk in line.chunks_exact(32) {
+        if !chunk.iter().fold(true, |allowed, c| allowed & is_allowed(*c)) {
+            break;
+        }
+        n += chunk.len();
+    }
+    n + line[n..].iter().position(|c| !is_allowed(*c)).unwrap_or(line.len() - n)
+}
+
+// The number of bytes at the start of buf that the state takes as they are. Values and comments
+// make up most of the input, so rather than going through them a byte at a time, their states
+// find the end of the line with memchr and take the bytes up to it, or up to the first byte that
+// isn't allowed, which is then handled, and reported, like any other.
+fn run_length(state: &State, buf: &[u8]) -> usize {
+    let line = match state {
+        State::SafeStringValue | State::Base64Value | State::CommentLine(_) => &buf[..memchr(b'\n', buf).unwrap_or(buf.len())],
+        _ => return 0,
+    };
+    match state {
+        State::SafeStringValue => allowed_prefix(line, |c| matches!(c, SAFE_CHAR!())),
+        State::Base64Value => allowed_prefix(line, |c| matches!(c, BASE64_CHAR!())),
+        _ => allowed_prefix(line, |c| c.is_ascii()),
+    }
+}
+
 impl<R: WriteToken> LocWrite for Lexer<R> {
     fn loc_write(&mut self, loc: Loc, buf: &[u8]) -> Result<usize> {
         let mut loc = loc;
-        for c in buf.iter().copied() {
+        let mut i: usize = 0;
+        while i < buf.len() {
+            let run = run_length(&self.state, &buf[i..]);
+            if run > 0 {
+                if !matches!(self.state, State::CommentLine(_)) {
+                    self.buf.extend_from_slice(&buf[i..i + run]);
+                }
+                loc = loc.after_run(run);
+                self.logical_loc = self.logical_loc.after_run(run);
+                i += run;
+                if i == buf.len() {
+                    break;
+                }
+            }
+            let c = buf[i];
+            i += 1;
             if !c.is_ascii() {
-                return Err(Error::new(ErrorKind::Other, format!("non-ASCII character at line {}, column {}", loc.line, loc.column)));
+                return Err(LocError::new(loc, format!("non-ASCII character at line {}, column {}", loc.line, loc.column)).into());
             }
             self.state = match self.state {
                 State::LineStart(in_entry) => match c {
@@ -107,25 +175,41 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                         State::LineStart(false)
                     },
                     b'#' => State::CommentLine(in_entry),
+                    b'-' if in_entry => {
+                        self.token_start = loc;
+                        self.logical_token_start = self.logical_loc;
+                        State::Separator
+                    },
                     ALPHA!() => {
                         self.token_start = loc;
+                        self.logical_token_start = self.logical_loc;
                         self.buf.push(c);
                         State::AttributeType
                     },
                     DIGIT!() => {
-                        return Err(Error::new(ErrorKind::Other, format!("unexpected digit on line {}, column {} (OID attribute types are not yet supported)", loc.line, loc.column)));
+                        return Err(LocError::new(loc, format!("unexpected digit on line {}, column {} (OID attribute types are not yet supported)", loc.line, loc.column)).into());
                     }, 
                     _ => {
-                        return Err(Error::new(ErrorKind::Other, format!("unexpected character on line {}, column {}", loc.line, loc.column)));
+                        return Err(LocError::new(loc, format!("unexpected character on line {}, column {}", loc.line, loc.column)).into());
                     },
                 },
                 State::CommentLine(in_entry) => match c {
                     b'\n' => State::LineStart(in_entry),
                     _ => State::CommentLine(in_entry),
                 },
+                State::Separator => match c {
+                    b'\n' => {
+                        self.emit_separator()?;
+                        State::LineStart(true)
+                    },
+                    _ => return Err(LocError::new(loc, format!("unexpected character on line {}, column {} after '-'", loc.line, loc.column)).into()),
+                },
                 State::AttributeType => match c {
+                    // Options such as lang-en in cn;lang-en are kept as part of the attribute
+                    // type, which is how entries and filters look them up.
                     b';' => {
-                        return Err(Error::new(ErrorKind::Other, format!("unexpected semicolon on line {}, column {} (attribute options are not yet supported)", loc.line, loc.column)));
+                        self.buf.push(c);
+                        State::AttributeOption(false)
                     },
                     ALPHA!() | DIGIT!() | b'-' | b'_' => {
                         // Underscores are not legal in LDAP attribute type names, but we allow
@@ -133,7 +217,7 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                         // under NetIQ IDM's cn=jvm_stats,cn=monitor subtree.
                         if self.buf.len() >= MAX_TYPE_LENGTH {
                             let msg = format!("maximum attribute type name length exceeded on line {}, column {}", loc.line, loc.column);
-                            return Err(Error::new(ErrorKind::Other, msg));
+                            return Err(LocError::new(loc, msg).into());
                         }
                         self.buf.push(c);
                         State::AttributeType
@@ -142,11 +226,31 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                         self.emit(TokenKind::AttributeType)?;
                         State::ValueColon
                     },
-                    _ => return Err(Error::new(ErrorKind::Other, format!("unexpected character in attribute type name on line {}, column {}", loc.line, loc.column))),
+                    _ => return Err(LocError::new(loc, format!("unexpected character in attribute type name on line {}, column {}", loc.line, loc.column)).into()),
+                },
+                State::AttributeOption(nonempty) => match c {
+                    ALPHA!() | DIGIT!() | b'-' => {
+                        if self.buf.len() >= MAX_TYPE_LENGTH {
+                            let msg = format!("maximum attribute type name length exceeded on line {}, column {}", loc.line, loc.column);
+                            return Err(LocError::new(loc, msg).into());
+                        }
+                        self.buf.push(c);
+                        State::AttributeOption(true)
+                    },
+                    b';' if nonempty => {
+                        self.buf.push(c);
+                        State::AttributeOption(false)
+                    },
+                    b':' if nonempty => {
+                        self.emit(TokenKind::AttributeType)?;
+                        State::ValueColon
+                    },
+                    _ => return Err(LocError::new(loc, format!("unexpected character in attribute option on line {}, column {}", loc.line, loc.column)).into()),
                 },
                 State::ValueColon => match c {
                     SAFE_INIT_CHAR!() => {
                         self.token_start = loc;
+                        self.logical_token_start = self.logical_loc;
                         self.buf.push(c);
                         State::SafeStringValue
                     },
@@ -157,8 +261,8 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                         self.emit(TokenKind::ValueFinish)?;
                         State::LineStart(true)
                     },
-                    b'<' => return Err(Error::new(ErrorKind::Other, format!("unexpected '<' on line {}, column {} (URL values not implemented at this time)", loc.line, loc.column))),
-                    _ => return Err(Error::new(ErrorKind::Other, format!("unexpected character on line {}, column {} (expecting attribute value)", loc.line, loc.column))),
+                    b'<' => return Err(LocError::new(loc, format!("unexpected '<' on line {}, column {} (URL values not implemented at this time)", loc.line, loc.column)).into()),
+                    _ => return Err(LocError::new(loc, format!("unexpected character on line {}, column {} (expecting attribute value)", loc.line, loc.column)).into()),
                 },
                 State::SafeStringValue => match c {
                     SAFE_CHAR!() => {
@@ -170,7 +274,7 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                         self.emit(TokenKind::ValueFinish)?;
                         State::LineStart(true)
                     },
-                    _ => return Err(Error::new(ErrorKind::Other, format!("illegal LDIF safe-string character on line {}, column {} (a work-around is to base64-encode the value)", loc.line, loc.column))),
+                    _ => return Err(LocError::new(loc, format!("illegal LDIF safe-string character on line {}, column {} (a work-around is to base64-encode the value)", loc.line, loc.column)).into()),
                 },
                 State::Base64Value => match c {
                     BASE64_CHAR!() => {
@@ -182,12 +286,13 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                         self.emit(TokenKind::ValueFinish)?;
                         State::LineStart(true)
                     },
-                    _ => return Err(Error::new(ErrorKind::Other, format!("unexpected character on line {}, column {} while expecting base64 code", loc.line, loc.column))),
+                    _ => return Err(LocError::new(loc, format!("unexpected character on line {}, column {} while expecting base64 code", loc.line, loc.column)).into()),
                 },
                 State::WhitespaceBefore(next_state) => match (next_state, c) {
                     (_, b' ') => State::WhitespaceBefore(next_state),
                     (State::SafeStringValue, SAFE_INIT_CHAR!()) => {
                         self.token_start = loc;
+                        self.logical_token_start = self.logical_loc;
                         self.buf.push(c);
                         State::SafeStringValue
                     },
@@ -198,6 +303,7 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                     },
                     (State::Base64Value, BASE64_CHAR!()) => {
                         self.token_start = loc;
+                        self.logical_token_start = self.logical_loc;
                         self.buf.push(c);
                         State::Base64Value
                     },
@@ -206,10 +312,11 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                         self.emit(TokenKind::ValueFinish)?;
                         State::LineStart(true)
                     },
-                    (_, _) => return Err(Error::new(ErrorKind::Other, format!("unexpected character on line {}, column {} while expecting value after attribute type", loc.line, loc.column))),
+                    (_, _) => return Err(LocError::new(loc, format!("unexpected character on line {}, column {} while expecting value after attribute type", loc.line, loc.column)).into()),
                 },
             };
             loc = loc.after(c);
+            self.logical_loc = self.logical_loc.after(c);
         }
 
         match self.state {
@@ -225,7 +332,7 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
         match self.state {
             State::LineStart(in_entry) => if in_entry { self.emit(TokenKind::EntryFinish)? },
             State::CommentLine(in_entry) => if in_entry { self.emit(TokenKind::EntryFinish)? },
-            State::AttributeType => return Err(Error::new(ErrorKind::Other, format!("unexpected end of file on on line {}, column {} inside attribute type", loc.line, loc.column))),
+            State::AttributeType | State::AttributeOption(_) => return Err(LocError::new(loc, format!("unexpected end of file on on line {}, column {} inside attribute type", loc.line, loc.column)).into()),
             State::ValueColon | State::SafeStringValue | State::WhitespaceBefore(_) => {
                 self.emit(TokenKind::ValueText)?;
                 self.emit(TokenKind::ValueFinish)?;
@@ -236,6 +343,10 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
                 self.emit(TokenKind::ValueFinish)?;
                 self.emit(TokenKind::EntryFinish)?;
             },
+            State::Separator => {
+                self.emit_separator()?;
+                self.emit(TokenKind::EntryFinish)?;
+            },
         }
         Ok(())
     }
@@ -244,11 +355,16 @@ impl<R: WriteToken> LocWrite for Lexer<R> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+    use crate::crstrip::CrStripper;
+    use crate::loc::WriteLocWrapper;
+    use crate::unfold::Unfolder;
 
     #[derive(Debug, PartialEq, Eq)]
     struct TokenCopy {
         kind: TokenKind,
         loc: Loc,
+        logical_loc: Loc,
         segment: String,
     }
 
@@ -263,6 +379,7 @@ mod tests {
             self.push(TokenCopy{
                 kind: token.kind,
                 loc: token.loc,
+                logical_loc: token.logical_loc,
                 segment: token.segment.to_owned(),
             });
             Ok(())
@@ -324,5 +441,97 @@ mod tests {
         assert_eq!(tuples[22], (TokenKind::EntryFinish, String::from("")));
         assert_eq!(tuples.len(), 23);
     }
-}
 
+    #[test]
+    fn test_loc() -> Result<()> {
+        let mut vec = Vec::new();
+        let mut wrapper = WriteLocWrapper::new(CrStripper::new(Unfolder::new(Lexer::new(&mut vec))));
+        wrapper.write_all(b"dn: cn=foo\r\ndescription: aaa\r\n bbb\r\ncn: foo\r\n")?;
+        wrapper.flush()?;
+        let cn = vec.iter()
+            .find(|token| token.kind == TokenKind::AttributeType && token.segment == "cn")
+            .unwrap();
+        assert_eq!(cn.loc, Loc{ line: 4, column: 1, offset: 36 });
+        assert_eq!(cn.logical_loc, Loc{ line: 3, column: 1, offset: 31 });
+        Ok(())
+    }
+
+    #[test]
+    fn test_separator() -> Result<()> {
+        let mut vec = Vec::new();
+        let mut lexer = Lexer::new(&mut vec);
+        lexer.loc_write(Loc::default(), b"dn: cn=foo\nchangetype: modify\nadd: mail\nmail: a\n-\ndelete: sn\n-")?;
+        lexer.loc_flush(Loc::default())?;
+        let tuples: Vec<(TokenKind, String)> = vec.into_iter().map(TokenCopy::type_and_segment).collect();
+        assert_eq!(tuples[12], (TokenKind::AttributeType, String::from("-")));
+        assert_eq!(tuples[13], (TokenKind::ValueText, String::from("")));
+        assert_eq!(tuples[18], (TokenKind::AttributeType, String::from("-")));
+        assert_eq!(tuples[21], (TokenKind::EntryFinish, String::from("")));
+        assert!(Lexer::new(&mut Vec::new()).loc_write(Loc::default(), b"-\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_attribute_options() -> Result<()> {
+        let mut vec = Vec::new();
+        let mut lexer = Lexer::new(&mut vec);
+        lexer.loc_write(Loc::default(), b"dn: cn=foo\ncn;lang-en: foo\nuserCertificate;binary:: AA==\n")?;
+        lexer.loc_flush(Loc::default())?;
+        let types: Vec<String> = vec.into_iter()
+            .filter(|token| token.kind == TokenKind::AttributeType)
+            .map(|token| token.segment)
+            .collect();
+        assert_eq!(types, ["dn", "cn;lang-en", "userCertificate;binary"]);
+        for ldif in [b"cn;: foo\n".as_slice(), b"cn;lang-en;: foo\n", b"cn;lang_en: foo\n", b"cn;lang-en"] {
+            let mut tokens = Vec::new();
+            let mut lexer = Lexer::new(&mut tokens);
+            assert!(lexer.loc_write(Loc::default(), ldif).and_then(|_| lexer.loc_flush(Loc::default())).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_loc() {
+        let mut vec = Vec::new();
+        let mut wrapper = WriteLocWrapper::new(CrStripper::new(Unfolder::new(Lexer::new(&mut vec))));
+        let err = wrapper.write_all(b"dn: cn=foo\r\ndescription: aaa\r\n bbb\r\n!cn: foo\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "unexpected character on line 4, column 1\n4 | !cn: foo\n  | ^");
+    }
+
+    #[test]
+    fn test_runs() -> Result<()> {
+        let ldif = b"dn: cn=foo\n# a comment\ndescription: a long value\njpegPhoto:: QUJDREVG\n";
+        let mut whole = Vec::new();
+        let mut lexer = Lexer::new(&mut whole);
+        lexer.loc_write(Loc::default(), ldif)?;
+        lexer.loc_flush(Loc::default())?;
+        // the runs that values are taken in may end in the middle of a value
+        let mut pieces = Vec::new();
+        let mut lexer = Lexer::new(&mut pieces);
+        let mut loc = Loc::default();
+        for chunk in ldif.chunks(5) {
+            lexer.loc_write(loc, chunk)?;
+            loc = loc.after_all(chunk);
+        }
+        lexer.loc_flush(loc)?;
+        let values = |tokens: Vec<TokenCopy>| tokens.into_iter()
+            .fold(vec![String::new()], |mut values, token| {
+                match token.kind {
+                    TokenKind::ValueText | TokenKind::ValueBase64 => values.last_mut().unwrap().push_str(&token.segment),
+                    TokenKind::ValueFinish => values.push(String::new()),
+                    _ => {},
+                }
+                values
+            });
+        assert_eq!(whole[4].segment, "a long value");
+        assert_eq!(whole[4].logical_loc, Loc{ line: 3, column: 14, offset: 36 });
+        assert_eq!(whole[7].segment, "QUJDREVG");
+        assert_eq!(values(pieces), values(whole));
+
+        let err = Lexer::new(&mut Vec::new()).loc_write(Loc::default(), b"dn: cn=foo\ncn: a\0b\n").unwrap_err();
+        assert!(err.to_string().starts_with("illegal LDIF safe-string character on line 2, column 6"));
+        let err = Lexer::new(&mut Vec::new()).loc_write(Loc::default(), b"dn: cn=foo\n# \xc3\xa6\n").unwrap_err();
+        assert!(err.to_string().starts_with("non-ASCII character at line 2, column 3"));
+        Ok(())
+    }
+}
@@ -1,13 +1,93 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod sink;
 pub mod lexer;
 pub mod unfold;
 pub mod crstrip;
 pub mod base64;
-pub mod filter;
 pub mod loc;
 pub mod skip;
+pub mod diff;
+
+#[cfg(feature = "std")]
+pub mod filter;
+#[cfg(feature = "std")]
 pub mod cartesian;
+#[cfg(feature = "std")]
+pub mod separator;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
 pub mod tsv;
+#[cfg(feature = "std")]
 pub mod csv;
+#[cfg(feature = "std")]
 pub mod json;
+#[cfg(feature = "std")]
+pub mod ldif;
+#[cfg(feature = "std")]
+pub mod template;
+#[cfg(feature = "std")]
 pub mod attrspec;
+#[cfg(feature = "std")]
 pub mod entry;
+#[cfg(feature = "std")]
+pub mod dn;
+#[cfg(feature = "std")]
+pub mod bulk;
+#[cfg(feature = "std")]
+pub mod operational;
+#[cfg(feature = "std")]
+pub mod schema;
+#[cfg(feature = "std")]
+pub mod rename;
+#[cfg(feature = "std")]
+pub mod inplace;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "std")]
+pub mod channel;
+#[cfg(feature = "std")]
+pub mod jobs;
+#[cfg(feature = "std")]
+pub mod changerecord;
+#[cfg(feature = "std")]
+pub mod extsort;
+#[cfg(feature = "std")]
+pub mod diagnostic;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod refcheck;
+#[cfg(feature = "std")]
+pub mod normalize;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod store;
+#[cfg(feature = "std")]
+pub mod group;
+#[cfg(feature = "std")]
+pub mod xml;
+#[cfg(feature = "std")]
+pub mod auditlog;
+#[cfg(feature = "std")]
+pub mod rows;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod completions;
+#[cfg(feature = "passwd")]
+pub mod passwd;
+#[cfg(feature = "encrypt")]
+pub mod crypt;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "python")]
+pub mod python;
@@ -0,0 +1,242 @@
+use crate::base64::DecodeState;
+use crate::bulk::pipeline;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::lexer::{Token, TokenKind, WriteToken};
+use crate::loc::{Loc, LocError};
+use std::io::{ErrorKind, Read, Result, Write};
+
+const DEFAULT_MAX_LINE_LENGTH: usize = 76;
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// Checks the structure of LDIF beyond what is needed to parse it, such as whether entries start
+/// with a DN and whether values that RFC 2849 says should be base64-encoded are. The first syntax
+/// error is reported as a diagnostic, after which the rest of the input is not checked.
+pub struct Linter {
+    max_line_length: Option<usize>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter{
+            max_line_length: Some(DEFAULT_MAX_LINE_LENGTH),
+        }
+    }
+}
+
+impl Linter {
+    pub fn new() -> Linter {
+        Linter::default()
+    }
+
+    /// Sets the length in bytes above which lines are reported, or None to not check line
+    /// lengths. RFC 2849 recommends folding lines longer than 76 bytes.
+    pub fn set_max_line_length(&mut self, max_line_length: Option<usize>) -> &mut Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Returns the problems found in the input, ordered by location.
+    pub fn lint<R: Read + ?Sized>(&self, input: &mut R) -> Result<Vec<Diagnostic>> {
+        let mut tokens = LintTokenWriter::default();
+        let mut lines = LineChecker{
+            max_line_length: self.max_line_length,
+            loc: Loc::default(),
+            line_length: 0,
+            last: None,
+            diagnostics: Vec::new(),
+        };
+        let mut wrapper = pipeline(&mut tokens, b" ");
+        let mut buf = vec![0u8; BUFFER_SIZE];
+        let mut result: Result<()> = Ok(());
+        loop {
+            let len = match input.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            lines.check(&buf[..len]);
+            if result.is_ok() {
+                result = wrapper.write_all(&buf[..len]);
+            }
+        }
+        if result.is_ok() {
+            result = wrapper.flush();
+        }
+        drop(wrapper);
+        lines.finish();
+        let mut diagnostics = tokens.diagnostics;
+        diagnostics.append(&mut lines.diagnostics);
+        if let Err(err) = result {
+            let locerr = err.get_ref().and_then(|inner| inner.downcast_ref::<LocError>());
+            match locerr {
+                Some(locerr) => diagnostics.push(
+                    Diagnostic::new(Severity::Error, "syntax", locerr.message.clone())
+                        .at(locerr.loc)
+                        .in_entry(tokens.dn.as_deref())
+                ),
+                None => return Err(err),
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| diagnostic.loc.map(|loc| loc.offset));
+        Ok(diagnostics)
+    }
+}
+
+// Checks the lines of the original input, before unfolding.
+struct LineChecker {
+    max_line_length: Option<usize>,
+    loc: Loc,
+    line_length: usize, // not counting a CR before the newline
+    last: Option<u8>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl LineChecker {
+    fn check(&mut self, buf: &[u8]) {
+        for c in buf.iter().copied() {
+            if c == b'\n' {
+                if self.last == Some(b'\r') {
+                    self.line_length -= 1;
+                }
+                if let Some(max_line_length) = self.max_line_length.filter(|max| self.line_length > *max) {
+                    let message = format!("line is {} bytes long, which is more than {}", self.line_length, max_line_length);
+                    let loc = Loc{
+                        line: self.loc.line,
+                        column: max_line_length + 1,
+                        offset: self.loc.offset + max_line_length + 1 - self.loc.column,
+                    };
+                    self.diagnostics.push(Diagnostic::new(Severity::Warning, "long-line", message).at(loc));
+                }
+                self.line_length = 0;
+            } else {
+                self.line_length += 1;
+            }
+            self.loc = self.loc.after(c);
+            self.last = Some(c);
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.last.is_some_and(|c| c != b'\n') {
+            let loc = self.loc;
+            self.check(b"\n");
+            let message = String::from("the input does not end with a newline");
+            self.diagnostics.push(Diagnostic::new(Severity::Warning, "missing-newline", message).at(loc));
+        }
+    }
+}
+
+#[derive(Default)]
+struct LintTokenWriter {
+    diagnostics: Vec<Diagnostic>,
+    records: usize, // records finished so far
+    in_entry: bool,
+    dn: Option<String>,
+    attribute: String, // in lowercase
+    attribute_loc: Loc,
+    value: Vec<u8>,
+    base64: bool,
+}
+
+impl LintTokenWriter {
+    fn report(&mut self, severity: Severity, code: &'static str, message: String) {
+        let diagnostic = Diagnostic::new(severity, code, message)
+            .at(self.attribute_loc)
+            .in_entry(self.dn.as_deref());
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn finish_value(&mut self) {
+        let mut value: Vec<u8> = Vec::new();
+        if self.base64 {
+            let mut state = DecodeState::default();
+            if let Err(err) = state.decode(&self.value, &mut value).and_then(|_| state.finish()) {
+                self.report(Severity::Error, "invalid-base64", format!("invalid base64 value of {}: {}", self.attribute, err));
+                return;
+            }
+        } else {
+            value = std::mem::take(&mut self.value);
+        }
+        if self.attribute == "dn" {
+            if self.dn.is_some() {
+                self.report(Severity::Error, "duplicate-dn", String::from("entry has more than one DN"));
+            } else {
+                self.dn = Some(String::from_utf8_lossy(&value).into_owned());
+            }
+        }
+        if !self.base64 && value.ends_with(b" ") {
+            let message = format!("value of {} ends with a space and should be base64-encoded", self.attribute);
+            self.report(Severity::Warning, "should-be-base64", message);
+        } else if value.last().is_some_and(|c| matches!(c, b' ' | b'\t' | b'\r' | b'\n')) {
+            let message = format!("value of {} ends with whitespace", self.attribute);
+            self.report(Severity::Warning, "trailing-whitespace", message);
+        }
+    }
+}
+
+impl WriteToken for &mut LintTokenWriter {
+    fn write_token(&mut self, token: Token) -> Result<()> {
+        match token.kind {
+            TokenKind::AttributeType => {
+                self.attribute = token.segment.to_ascii_lowercase();
+                self.attribute_loc = token.loc;
+                let is_first = !self.in_entry;
+                self.in_entry = true;
+                if is_first && self.records == 0 && self.attribute == "version" {
+                    // the version line may be directly followed by the first entry
+                    self.in_entry = false;
+                } else if is_first && self.attribute != "dn" {
+                    self.report(Severity::Error, "missing-dn", format!("entry starts with {} instead of a DN", self.attribute));
+                }
+            },
+            TokenKind::ValueText => {
+                self.value.extend_from_slice(token.segment.as_bytes());
+                self.base64 = false;
+            },
+            TokenKind::ValueBase64 => {
+                self.value.extend_from_slice(token.segment.as_bytes());
+                self.base64 = true;
+            },
+            TokenKind::ValueFinish => {
+                self.finish_value();
+                self.value.clear();
+            },
+            TokenKind::EntryFinish => {
+                self.records += 1;
+                self.in_entry = false;
+                self.dn = None;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn codes(input: &str) -> Vec<&'static str> {
+        let diagnostics = Linter::new().lint(&mut input.as_bytes()).unwrap();
+        diagnostics.iter().map(|diagnostic| diagnostic.code).collect()
+    }
+
+    #[test]
+    fn test_a() {
+        assert!(codes("version: 1\ndn: cn=foo\ncn: foo\n\ndn: cn=bar\ncn: bar\n").is_empty());
+        assert_eq!(codes("dn: cn=foo\ncn: foo"), ["missing-newline"]);
+        assert_eq!(codes("cn: foo\n\ndn: cn=bar\ndn: cn=baz\n"), ["missing-dn", "duplicate-dn"]);
+        assert_eq!(codes("dn: cn=foo\ncn: foo \ndescription:: Zm9vCQ==\n"), ["should-be-base64", "trailing-whitespace"]);
+        assert_eq!(codes(&format!("dn: cn={}\n", "x".repeat(80))), ["long-line"]);
+        assert_eq!(codes(&format!("dn: cn={}\n {}\n", "x".repeat(60), "x".repeat(60))), Vec::<&str>::new());
+        assert_eq!(codes("dn: cn=foo\ncn:: Zm9v!\nsn: foo \n"), ["syntax"]);
+    }
+
+    #[test]
+    fn test_loc() {
+        let diagnostics = Linter::new().lint(&mut "dn: cn=foo\r\ncn: foo\r\ncn: bar \r\n".as_bytes()).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].loc.map(|loc| (loc.line, loc.column)), Some((3, 1)));
+        assert_eq!(diagnostics[0].dn.as_deref(), Some("cn=foo"));
+    }
+}
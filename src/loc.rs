@@ -1,5 +1,12 @@
-use std::io::{ Result, Write };
+use alloc::string::String;
+use core::fmt;
+use crate::sink::Result;
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::io::{ Write, Error, ErrorKind };
 
+/// A position in the input, counting lines and columns from 1 and offsets from 0.
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub struct Loc {
     pub line: usize,
@@ -33,17 +40,47 @@ impl Loc {
             },
         }
     }
+
+    /// The location after the bytes of `buf`.
+    pub fn after_all(self, buf: &[u8]) -> Self {
+        match memchr::memrchr(b'\n', buf) {
+            None => self.after_run(buf.len()),
+            Some(last) => Loc {
+                line: self.line + memchr::memchr_iter(b'\n', buf).count(),
+                column: buf.len() - last,
+                offset: self.offset + buf.len(),
+            },
+        }
+    }
+
+    /// The location after `n` bytes that aren't newlines.
+    pub fn after_run(self, n: usize) -> Self {
+        Loc {
+            line: self.line,
+            column: self.column + n,
+            offset: self.offset + n,
+        }
+    }
 }
 
+/// A writer that is told where in the input the data it receives comes from.
+///
+/// The location passed to `loc_write` is the location of the first byte of `buf` in the original
+/// input, before any CR stripping or unfolding. Writers that transform their input, such as
+/// `CrStripper` and `Unfolder`, only pass on spans that are contiguous in the original input, each
+/// with its original location, so that errors further down reference positions in the user's
+/// file. Positions within the transformed data are tracked separately by the `Lexer`.
 pub trait LocWrite {
     fn loc_write(&mut self, loc: Loc, buf: &[u8]) -> Result<usize>;
     fn loc_flush(&mut self, loc: Loc) -> Result<()>;
 }
 
+#[cfg(feature = "std")]
 pub struct LocWriteWrapper<W: Write> {
     inner: W,
 }
 
+#[cfg(feature = "std")]
 impl<W: Write> LocWriteWrapper<W> {
     pub fn new(inner: W) -> LocWriteWrapper<W> {
         LocWriteWrapper{
@@ -52,6 +89,7 @@ impl<W: Write> LocWriteWrapper<W> {
     }
 }
 
+#[cfg(feature = "std")]
 impl<W: Write> LocWrite for LocWriteWrapper<W> {
     fn loc_write(&mut self, _: Loc, buf: &[u8]) -> Result<usize> {
         self.inner.write(buf)
@@ -62,29 +100,157 @@ impl<W: Write> LocWrite for LocWriteWrapper<W> {
     }
 }
 
+/// An error at a location in the input.
+#[derive(Debug)]
+pub struct LocError {
+    pub loc: Loc,
+    pub message: String,
+    /// The input around the location, if it was available.
+    pub snippet: Option<String>,
+}
+
+impl LocError {
+    pub fn new(loc: Loc, message: String) -> LocError {
+        LocError{
+            loc,
+            message,
+            snippet: None,
+        }
+    }
+}
+
+impl fmt::Display for LocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(ref snippet) = self.snippet {
+            write!(f, "\n{}", snippet)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LocError {}
+
+#[cfg(feature = "std")]
+impl From<LocError> for Error {
+    fn from(err: LocError) -> Error {
+        Error::other(err)
+    }
+}
+
+/// Turns an error from decoding a base64 value that starts at loc, which is InvalidData, into a
+/// LocError that tells where the value is. Other errors, such as from writing the decoded value,
+/// are passed through.
+#[cfg(feature = "std")]
+pub fn base64_error(loc: Loc, err: Error) -> Error {
+    match err.kind() {
+        ErrorKind::InvalidData => LocError::new(
+            loc,
+            format!("invalid base64 value on line {}, column {}: {}", loc.line, loc.column, err),
+        ).into(),
+        _ => err,
+    }
+}
+
+// How much input before the current write is kept for error snippets
+#[cfg(feature = "std")]
+const CONTEXT_SIZE: usize = 1024;
+// How many bytes on each side of the error location are shown
+#[cfg(feature = "std")]
+const SNIPPET_WIDTH: usize = 60;
+
+#[cfg(feature = "std")]
 pub struct WriteLocWrapper<LW: LocWrite> {
     inner: LW,
     loc: Loc,
+    recent: VecDeque<u8>,
 }
 
+#[cfg(feature = "std")]
 impl<LW: LocWrite> WriteLocWrapper<LW> {
     pub fn new(inner: LW) -> WriteLocWrapper<LW> {
         WriteLocWrapper{
             inner,
             loc: Loc::default(),
+            recent: VecDeque::with_capacity(CONTEXT_SIZE),
         }
     }
+
+    pub fn get_mut(&mut self) -> &mut LW {
+        &mut self.inner
+    }
+
+    fn remember(&mut self, buf: &[u8]) {
+        let buf = &buf[buf.len().saturating_sub(CONTEXT_SIZE)..];
+        let excess = (self.recent.len() + buf.len()).saturating_sub(CONTEXT_SIZE);
+        self.recent.drain(..excess);
+        self.recent.extend(buf);
+    }
+
+    // Adds a snippet of the input to errors that carry a location.
+    fn add_snippet(&self, err: Error, buf: &[u8]) -> Error {
+        let is_loc_error = err.get_ref()
+            .map(|inner| inner.is::<LocError>())
+            .unwrap_or(false);
+        if !is_loc_error {
+            return err;
+        }
+        let kind = err.kind();
+        let mut locerr = match err.into_inner().map(|inner| inner.downcast::<LocError>()) {
+            Some(Ok(locerr)) => locerr,
+            Some(Err(inner)) => return Error::new(kind, inner),
+            None => return Error::from(kind),
+        };
+        let start_offset = self.loc.offset - self.recent.len();
+        if locerr.loc.offset >= start_offset {
+            // one more byte than is shown, to tell whether the line is truncated
+            let needed = (locerr.loc.offset + SNIPPET_WIDTH + 1).saturating_sub(self.loc.offset);
+            let mut context: Vec<u8> = self.recent.iter().copied().collect();
+            context.extend_from_slice(&buf[..buf.len().min(needed)]);
+            locerr.snippet = snippet(&context, locerr.loc.offset - start_offset, locerr.loc.line);
+        }
+        Error::new(kind, *locerr)
+    }
+}
+
+// Formats the line around position in context, with a marker under the position.
+#[cfg(feature = "std")]
+fn snippet(context: &[u8], position: usize, line: usize) -> Option<String> {
+    if position > context.len() {
+        return None;
+    }
+    let is_line_end = |c: &u8| *c == b'\n' || *c == b'\r';
+    let line_start = context[..position].iter().rposition(is_line_end).map(|i| i + 1).unwrap_or(0);
+    let line_end = context[position..].iter().position(is_line_end).map(|i| position + i).unwrap_or(context.len());
+    let start = line_start.max(position.saturating_sub(SNIPPET_WIDTH));
+    let end = line_end.min(position + SNIPPET_WIDTH);
+    let mut text = String::new();
+    if start > line_start {
+        text.push_str("...");
+    }
+    let marker_column = text.len() + position - start;
+    text.extend(context[start..end].iter().map(|c| if c.is_ascii_graphic() || *c == b' ' { *c as char } else { '?' }));
+    if end < line_end {
+        text.push_str("...");
+    }
+    let number = line.to_string();
+    Some(format!("{:width$} | {}\n{:width$} | {:>column$}", number, text, "", "^", width = number.len(), column = marker_column + 1))
 }
 
+#[cfg(feature = "std")]
 impl<LW: LocWrite> Write for WriteLocWrapper<LW> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.inner.loc_write(self.loc, buf)?;
-        self.loc = buf.iter().copied().fold(self.loc, |loc, c| loc.after(c));
+        if let Err(err) = self.inner.loc_write(self.loc, buf) {
+            return Err(self.add_snippet(err, buf));
+        }
+        self.loc = self.loc.after_all(buf);
+        self.remember(buf);
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> Result<()> {
-        self.inner.loc_flush(self.loc)
+        self.inner.loc_flush(self.loc).map_err(|err| self.add_snippet(err, b""))
     }
 }
 
@@ -107,6 +273,7 @@ pub mod test {
     /* we define it this way instead of `type LocWrites = Vec<(Loc, String)>` because in the current
      * version of Rust, the compiler claims that the Write implementation for Vec<u8> causes a
      * conflict */
+    #[derive(Default)]
     pub struct LocWrites {
         vec: Vec<(Loc, String)>,
     }
@@ -135,5 +302,67 @@ pub mod test {
             Ok(())
         }
     }
-}
 
+    struct FailAt(usize);
+
+    impl LocWrite for FailAt {
+        fn loc_write(&mut self, loc: Loc, buf: &[u8]) -> Result<usize> {
+            let end = buf.iter().copied().fold(loc, |loc, c| loc.after(c));
+            if self.0 < end.offset {
+                let loc = buf.iter().copied().take(self.0 - loc.offset).fold(loc, |loc, c| loc.after(c));
+                return Err(LocError::new(loc, String::from("failed")).into());
+            }
+            Ok(buf.len())
+        }
+
+        fn loc_flush(&mut self, _: Loc) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_after_all() {
+        for buf in [&b""[..], b"abc", b"a\nbc\n", b"\n\nxyz"] {
+            let expected = buf.iter().copied().fold(Loc::default(), |loc, c| loc.after(c));
+            assert_eq!(Loc::default().after_all(buf), expected);
+        }
+    }
+
+    #[test]
+    fn test_snippet() {
+        let mut wrapper = WriteLocWrapper::new(FailAt(14));
+        wrapper.write_all(b"first line\nsec").unwrap();
+        let err = wrapper.write_all(b"ond line\nthird line\n").unwrap_err();
+        assert_eq!(err.to_string(), "failed\n2 | second line\n  |    ^");
+    }
+
+    #[test]
+    fn test_snippet_truncated() {
+        let line = [b'x'; 200];
+        let mut wrapper = WriteLocWrapper::new(FailAt(100));
+        let err = wrapper.write_all(&line).unwrap_err();
+        let snippet = err.to_string();
+        let lines: Vec<&str> = snippet.lines().collect();
+        assert_eq!(lines[1], format!("1 | ...{}...", "x".repeat(2 * SNIPPET_WIDTH)));
+        assert_eq!(lines[2].find('^'), lines[1].find("...x").map(|i| i + 3 + SNIPPET_WIDTH));
+    }
+
+    #[test]
+    fn test_other_errors() {
+        let mut writes = LocWrites::new();
+        let mut wrapper = WriteLocWrapper::new(&mut writes);
+        wrapper.write_all(b"foo").unwrap();
+        let err = wrapper.add_snippet(Error::other("plain"), b"");
+        assert_eq!(err.to_string(), "plain");
+    }
+
+    #[test]
+    fn test_base64_error() {
+        let loc = Loc{ line: 3, column: 5, offset: 20 };
+        let err = base64_error(loc, Error::new(ErrorKind::InvalidData, "invalid byte"));
+        assert_eq!(err.to_string(), "invalid base64 value on line 3, column 5: invalid byte");
+        assert_eq!(err.get_ref().and_then(|err| err.downcast_ref::<LocError>()).map(|err| err.loc), Some(loc));
+        let err = base64_error(loc, Error::new(ErrorKind::BrokenPipe, "closed"));
+        assert_eq!(err.to_string(), "closed");
+    }
+}
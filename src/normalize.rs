@@ -0,0 +1,153 @@
+use crate::schema::DN_SYNTAXES;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+// The Directory String and Printable String syntaxes, whose attributes mostly use caseIgnoreMatch
+const CASE_IGNORE_SYNTAXES: &[&str] = &[
+    "1.3.6.1.4.1.1466.115.121.1.15",
+    "1.3.6.1.4.1.1466.115.121.1.44",
+];
+
+const TELEPHONE_NUMBER_SYNTAX: &str = "1.3.6.1.4.1.1466.115.121.1.50";
+
+/// A rewriting of values that makes values that a matching rule takes to be equal the same, for
+/// comparing values without reporting differences that the directory would ignore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalizer {
+    /// Normalizes DNs as by `dn::normalize`.
+    Dn,
+    /// Folds the values to lowercase.
+    Case,
+    /// Trims whitespace and collapses runs of it into a single space.
+    Space,
+    /// Removes spaces and hyphens, as telephoneNumberMatch ignores them.
+    Telephone,
+}
+
+impl Normalizer {
+    /// Parses the name of a normalizer, which is `dn`, `case`, `space` or `telephone`.
+    pub fn parse(name: &str) -> Option<Normalizer> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "dn" => Some(Normalizer::Dn),
+            "case" => Some(Normalizer::Case),
+            "space" => Some(Normalizer::Space),
+            "telephone" => Some(Normalizer::Telephone),
+            _ => None,
+        }
+    }
+
+    /// Returns the normalizers for values of the syntax with the given OID.
+    pub fn for_syntax(syntax: &str) -> &'static [Normalizer] {
+        if DN_SYNTAXES.contains(&syntax) {
+            &[Normalizer::Dn]
+        } else if CASE_IGNORE_SYNTAXES.contains(&syntax) {
+            &[Normalizer::Case, Normalizer::Space]
+        } else if syntax == TELEPHONE_NUMBER_SYNTAX {
+            &[Normalizer::Telephone]
+        } else {
+            &[]
+        }
+    }
+
+    pub fn apply<'a>(&self, value: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            Normalizer::Dn => Cow::Owned(crate::dn::normalize(&String::from_utf8_lossy(value)).into_bytes()),
+            Normalizer::Case => match std::str::from_utf8(value) {
+                Ok(value) => Cow::Owned(value.to_lowercase().into_bytes()),
+                Err(_) => Cow::Owned(value.to_ascii_lowercase()),
+            },
+            Normalizer::Space => {
+                let words: Vec<&[u8]> = value.split(u8::is_ascii_whitespace)
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                Cow::Owned(words.join(&b' '))
+            },
+            Normalizer::Telephone => Cow::Owned(value.iter().copied().filter(|c| !matches!(c, b' ' | b'-')).collect()),
+        }
+    }
+}
+
+/// The normalizers to apply to the values of each attribute.
+#[derive(Debug, Clone, Default)]
+pub struct Normalizers {
+    by_attribute: HashMap<String, Vec<Normalizer>>, // by lowercase attribute
+}
+
+impl Normalizers {
+    /// Normalizes the attributes according to their syntaxes, as returned by
+    /// `attribute_syntaxes_from_schema`.
+    pub fn from_syntaxes(syntaxes: &HashMap<String, String>) -> Normalizers {
+        let mut normalizers = Normalizers::default();
+        for (attr, syntax) in syntaxes.iter() {
+            for normalizer in Normalizer::for_syntax(syntax) {
+                normalizers.add(attr, *normalizer);
+            }
+        }
+        normalizers
+    }
+
+    pub fn add(&mut self, attr: &str, normalizer: Normalizer) -> &mut Self {
+        let normalizers = self.by_attribute.entry(attr.to_ascii_lowercase()).or_default();
+        if !normalizers.contains(&normalizer) {
+            normalizers.push(normalizer);
+        }
+        self
+    }
+
+    /// Adds the normalizers given as `ATTRIBUTE=NORMALIZER,...`, such as `cn=case,space`.
+    pub fn parse_and_add(&mut self, spec: &str) -> Result<&mut Self, String> {
+        let (attr, names) = spec.split_once('=')
+            .ok_or_else(|| format!("normalizers must be given as ATTRIBUTE=NORMALIZER, not {}", spec))?;
+        for name in names.split(',') {
+            let normalizer = Normalizer::parse(name)
+                .ok_or_else(|| format!("unknown normalizer {}; expected dn, case, space or telephone", name.trim()))?;
+            self.add(attr.trim(), normalizer);
+        }
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_attribute.is_empty()
+    }
+
+    /// Normalizes a value of the attribute, which should be lowercase.
+    pub fn normalize<'a>(&self, attr: &str, value: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut value = Cow::Borrowed(value);
+        for normalizer in self.by_attribute.get(attr).into_iter().flatten() {
+            value = match value {
+                Cow::Borrowed(value) => normalizer.apply(value),
+                Cow::Owned(value) => Cow::Owned(normalizer.apply(&value).into_owned()),
+            };
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize() -> Result<(), String> {
+        let mut normalizers = Normalizers::default();
+        normalizers.parse_and_add("manager=dn")?
+            .parse_and_add("CN=case, space")?
+            .parse_and_add("telephoneNumber=telephone")?;
+        assert_eq!(normalizers.normalize("manager", b"CN=Foo, DC=Example"), b"cn=foo,dc=example".as_slice());
+        assert_eq!(normalizers.normalize("cn", "  ÅSE   Foo ".as_bytes()), "åse foo".as_bytes());
+        assert_eq!(normalizers.normalize("telephonenumber", b"+47 22-33 44 55"), b"+4722334455".as_slice());
+        assert_eq!(normalizers.normalize("sn", b" Foo "), b" Foo ".as_slice());
+        assert!(normalizers.parse_and_add("cn=soundex").is_err());
+        assert!(normalizers.parse_and_add("cn").is_err());
+        let syntaxes = HashMap::from([
+            (String::from("member"), String::from("1.3.6.1.4.1.1466.115.121.1.12")),
+            (String::from("description"), String::from("1.3.6.1.4.1.1466.115.121.1.15")),
+            (String::from("userpassword"), String::from("1.3.6.1.4.1.1466.115.121.1.40")),
+        ]);
+        let normalizers = Normalizers::from_syntaxes(&syntaxes);
+        assert_eq!(normalizers.normalize("member", b"CN=A, DC=B"), b"cn=a,dc=b".as_slice());
+        assert_eq!(normalizers.normalize("description", b"A  B"), b"a b".as_slice());
+        assert_eq!(normalizers.normalize("userpassword", b"A  B"), b"A  B".as_slice());
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use crate::entry::{Entry, OwnedEntry, WriteEntry};
+#[cfg(feature = "cli")]
+use clap::{arg, Arg, ArgAction, ArgMatches};
+use std::collections::HashSet;
+
+/// Lowercase names of attributes that directory servers maintain themselves and that are usually
+/// only returned when asked for, from RFC 4512, RFC 3045 and common server implementations.
+pub const OPERATIONAL_ATTRIBUTES: &[&str] = &[
+    // RFC 4512
+    "createtimestamp",
+    "modifytimestamp",
+    "creatorsname",
+    "modifiersname",
+    "structuralobjectclass",
+    "governingstructurerule",
+    "subschemasubentry",
+    // RFC 3045
+    "vendorname",
+    "vendorversion",
+    // OpenLDAP
+    "entryuuid",
+    "entrycsn",
+    "entrydn",
+    "contextcsn",
+    "hassubordinates",
+    "numsubordinates",
+    "pwdchangedtime",
+    "pwdaccountlockedtime",
+    "pwdfailuretime",
+    "pwdhistory",
+    "pwdgraceusetime",
+    // 389 Directory Server
+    "nsuniqueid",
+    "parentid",
+    "entryid",
+    // eDirectory
+    "guid",
+    "localentryid",
+    "revision",
+    "subordinatecount",
+    // Active Directory
+    "usnchanged",
+    "usncreated",
+    "whenchanged",
+    "whencreated",
+    "dscorepropagationdata",
+];
+
+/// A set of attributes that are treated as operational.
+#[derive(Clone)]
+pub struct OperationalAttributes {
+    attributes: HashSet<String>, // lowercase
+}
+
+impl Default for OperationalAttributes {
+    fn default() -> Self {
+        OperationalAttributes{
+            attributes: OPERATIONAL_ATTRIBUTES.iter().map(|attr| attr.to_string()).collect(),
+        }
+    }
+}
+
+impl OperationalAttributes {
+    /// Treats the given attribute as operational in addition to the known operational attributes.
+    pub fn add(&mut self, attr: &str) -> &mut Self {
+        self.attributes.insert(attr.to_ascii_lowercase());
+        self
+    }
+
+    /// Reads the options of `operational_args`, giving the attributes to strip if
+    /// --strip-operational was given.
+    #[cfg(feature = "cli")]
+    pub fn from_matches(matches: &ArgMatches) -> Option<OperationalAttributes> {
+        if !matches.get_flag("strip-operational") {
+            return None;
+        }
+        let mut operational = OperationalAttributes::default();
+        for attr in matches.get_many::<String>("operational-attribute").into_iter().flatten() {
+            operational.add(attr);
+        }
+        Some(operational)
+    }
+
+    pub fn contains(&self, attr: &str) -> bool {
+        self.attributes.contains(&attr.to_ascii_lowercase())
+    }
+
+    /// Returns a copy of the entry without operational attributes.
+    pub fn strip(&self, entry: &Entry) -> OwnedEntry {
+        let mut stripped = OwnedEntry::default();
+        for attr in entry.attributes().filter(|attr| !self.attributes.contains(attr.lowercase)) {
+            for (value, base64) in entry.get_with_encoding(attr.lowercase) {
+                stripped.add_value_with_encoding(attr.name, value, base64);
+            }
+        }
+        stripped.set_provenance(entry.provenance().cloned());
+        stripped
+    }
+}
+
+/// The --strip-operational, --include-operational and --operational-attribute options, with
+/// strip_help as the help of --strip-operational, for `Command::args`. They are read back by
+/// `OperationalAttributes::from_matches`.
+#[cfg(feature = "cli")]
+pub fn operational_args(strip_help: &str) -> [Arg<'_>; 3] {
+    [
+        arg!(--"strip-operational")
+            .help(strip_help)
+            .action(ArgAction::SetTrue)
+            .overrides_with("include-operational"),
+        arg!(--"include-operational" "Keep operational attributes. This is the default.")
+            .action(ArgAction::SetTrue)
+            .overrides_with("strip-operational"),
+        arg!(--"operational-attribute" <ATTRIBUTE> "Treat ATTRIBUTE as operational in addition to the known operational attributes. Can be given multiple times.")
+            .required(false)
+            .action(ArgAction::Append),
+    ]
+}
+
+/// Writes entries to the destination without their operational attributes.
+pub struct OperationalStripper<W: WriteEntry> {
+    operational: OperationalAttributes,
+    dest: W,
+}
+
+impl<W: WriteEntry> OperationalStripper<W> {
+    pub fn new(operational: OperationalAttributes, dest: W) -> OperationalStripper<W> {
+        OperationalStripper{
+            operational,
+            dest,
+        }
+    }
+}
+
+impl<W: WriteEntry> WriteEntry for OperationalStripper<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.dest.write_entry(&self.operational.strip(entry))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        let mut operational = OperationalAttributes::default();
+        operational.add("lastLogonTimestamp");
+        let entry = Entry::from([
+            ("dn", b"cn=foo".as_slice()),
+            ("cn", b"foo"),
+            ("modifyTimestamp", b"20230101000000Z"),
+            ("lastLogonTimestamp", b"133000000000000000"),
+        ]);
+        let stripped = operational.strip(&entry);
+        let names: Vec<&str> = stripped.attributes().map(|attr| attr.name).collect();
+        assert_eq!(names, ["dn", "cn"]);
+        assert!(operational.contains("entryUUID"));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_from_matches() {
+        let command = || clap::Command::new("test").args(operational_args("Remove operational attributes."));
+        assert!(OperationalAttributes::from_matches(&command().get_matches_from(["test", "--operational-attribute", "foo"])).is_none());
+        let matches = command().get_matches_from(["test", "--strip-operational", "--operational-attribute", "lastLogonTimestamp"]);
+        let operational = OperationalAttributes::from_matches(&matches).unwrap();
+        assert!(operational.contains("lastlogontimestamp") && operational.contains("modifyTimestamp"));
+        assert!(OperationalAttributes::from_matches(&command().get_matches_from(["test", "--strip-operational", "--include-operational"])).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+//! Buffered output for the writers of extracted values. The TSV, CSV, JSON and LDIF writers
+//! build a record out of many small pieces, such as values and single-byte separators, and
+//! `OutputBuffer` gathers them so that writing millions of values takes few system calls. Pieces
+//! that are at least as large as the buffer are written together with what is buffered in one
+//! vectored write, rather than being copied.
+
+use std::io::{IoSlice, Result, Write};
+
+const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// Writes all of the slices, like `Write::write_all`, with as few calls to `write_vectored` as
+/// the destination allows.
+pub fn write_all_vectored<W: Write + ?Sized>(dest: &mut W, mut slices: &mut [IoSlice<'_>]) -> Result<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+    while !slices.is_empty() {
+        match dest.write_vectored(slices) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut slices, n),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// A `BufWriter`-like layer that writes what it has buffered when it is full, when it is flushed
+/// and when it is dropped, in which case errors are ignored. Call `flush` to see them.
+pub struct OutputBuffer<W: Write> {
+    buf: Vec<u8>,
+    dest: W,
+}
+
+impl<W: Write> OutputBuffer<W> {
+    pub fn new(dest: W) -> OutputBuffer<W> {
+        OutputBuffer::with_capacity(DEFAULT_CAPACITY, dest)
+    }
+
+    pub fn with_capacity(capacity: usize, dest: W) -> OutputBuffer<W> {
+        OutputBuffer{ buf: Vec::with_capacity(capacity), dest }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.dest
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+
+    fn write_buffered(&mut self) -> Result<()> {
+        if !self.buf.is_empty() {
+            self.dest.write_all(&self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    // Writes the buffer followed by the slices, which are too large to be buffered.
+    fn write_through(&mut self, slices: &[IoSlice<'_>]) -> Result<()> {
+        let mut all: Vec<IoSlice> = Vec::with_capacity(slices.len() + 1);
+        all.push(IoSlice::new(&self.buf));
+        all.extend(slices.iter().map(|slice| IoSlice::new(slice)));
+        write_all_vectored(&mut self.dest, &mut all)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for OutputBuffer<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        if self.buf.len() + buf.len() <= self.buf.capacity() {
+            self.buf.extend_from_slice(buf);
+        } else if buf.len() >= self.buf.capacity() {
+            self.write_through(&[IoSlice::new(buf)])?;
+        } else {
+            self.write_buffered()?;
+            self.buf.extend_from_slice(buf);
+        }
+        Ok(())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if self.buf.len() + total <= self.buf.capacity() {
+            for buf in bufs {
+                self.buf.extend_from_slice(buf);
+            }
+        } else if total >= self.buf.capacity() {
+            self.write_through(bufs)?;
+        } else {
+            self.write_buffered()?;
+            for buf in bufs {
+                self.buf.extend_from_slice(buf);
+            }
+        }
+        Ok(total)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_buffered()?;
+        self.dest.flush()
+    }
+}
+
+impl<W: Write> Drop for OutputBuffer<W> {
+    fn drop(&mut self) {
+        let _ = self.write_buffered();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Counts the calls that reach the destination.
+    #[derive(Default)]
+    struct CallCounter {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for &mut CallCounter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.calls += 1;
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+            self.calls += 1;
+            let mut n = 0;
+            for buf in bufs {
+                self.written.extend_from_slice(buf);
+                n += buf.len();
+            }
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_output_buffer() -> Result<()> {
+        let mut counter = CallCounter::default();
+        let mut expected: Vec<u8> = Vec::new();
+        {
+            let mut buffer = OutputBuffer::with_capacity(16, &mut counter);
+            for i in 0..10 {
+                write!(buffer, "{i}\t")?;
+                write!(expected, "{i}\t")?;
+            }
+            buffer.write_all(b"a value that doesn't fit\n")?;
+            expected.extend_from_slice(b"a value that doesn't fit\n");
+            assert_eq!(buffer.write_vectored(&[IoSlice::new(b"x"), IoSlice::new(b"\n")])?, 2);
+            expected.extend_from_slice(b"x\n");
+        }
+        assert_eq!(String::from_utf8_lossy(&counter.written), String::from_utf8_lossy(&expected));
+        assert_eq!(counter.calls, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_all_vectored() -> Result<()> {
+        // a destination that only takes a few bytes at a time
+        struct Trickle(Vec<u8>);
+        impl Write for Trickle {
+            fn write(&mut self, buf: &[u8]) -> Result<usize> {
+                let n = buf.len().min(3);
+                self.0.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+            fn flush(&mut self) -> Result<()> {
+                Ok(())
+            }
+        }
+        let mut dest = Trickle(Vec::new());
+        write_all_vectored(&mut dest, &mut [IoSlice::new(b"uid=1"), IoSlice::new(b""), IoSlice::new(b"\tmail\n")])?;
+        assert_eq!(dest.0, b"uid=1\tmail\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::base64::{DecodeState, EncodeState};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+use std::io::{Error, Result};
+
+const SSHA_SALT_LENGTH: usize = 8;
+const ARGON2_SALT_LENGTH: usize = 16;
+
+/// A scheme for hashing userPassword values, written as a prefix such as `{SSHA}` before the
+/// hash, as directory servers expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordScheme {
+    /// Salted SHA-1, as supported by most directory servers.
+    Ssha,
+    /// Argon2id in the PHC string format, as by the argon2 module of OpenLDAP.
+    Argon2,
+}
+
+impl PasswordScheme {
+    pub fn parse(name: &str) -> std::result::Result<PasswordScheme, &'static str> {
+        match name.to_ascii_lowercase().as_str() {
+            "ssha" => Ok(PasswordScheme::Ssha),
+            "argon2" => Ok(PasswordScheme::Argon2),
+            _ => Err("password scheme must be ssha or argon2"),
+        }
+    }
+}
+
+/// Tells whether a userPassword value starts with a scheme prefix such as `{SSHA}`, and so is
+/// presumably hashed already.
+pub fn is_hashed(value: &[u8]) -> bool {
+    value.starts_with(b"{") && value.iter().skip(1).position(|c| *c == b'}').is_some_and(|end| end > 0)
+}
+
+/// Hashes a password with a random salt, returning a value such as `{SSHA}...`.
+pub fn hash_password(password: &[u8], scheme: PasswordScheme) -> Result<Vec<u8>> {
+    match scheme {
+        PasswordScheme::Ssha => {
+            let mut salt = [0u8; SSHA_SALT_LENGTH];
+            OsRng.fill_bytes(&mut salt);
+            let mut digest_salt = ssha_digest(password, &salt);
+            digest_salt.extend_from_slice(&salt);
+            let mut hashed = b"{SSHA}".to_vec();
+            let mut state = EncodeState::default();
+            state.encode(&digest_salt, &mut hashed)?;
+            state.finish(&mut hashed)?;
+            Ok(hashed)
+        },
+        PasswordScheme::Argon2 => {
+            let mut salt = [0u8; ARGON2_SALT_LENGTH];
+            OsRng.fill_bytes(&mut salt);
+            let salt = SaltString::encode_b64(&salt).map_err(|err| Error::other(err.to_string()))?;
+            let hash = Argon2::default().hash_password(password, &salt).map_err(|err| Error::other(err.to_string()))?;
+            Ok(format!("{{ARGON2}}{}", hash).into_bytes())
+        },
+    }
+}
+
+/// Tells whether a userPassword value matches the password. Values without a scheme prefix are
+/// compared as plain text. Returns None if the value is hashed with a scheme other than SSHA, SHA
+/// or ARGON2.
+pub fn verify_password(value: &[u8], password: &[u8]) -> Option<bool> {
+    if !is_hashed(value) {
+        return Some(value == password);
+    }
+    let end = value.iter().position(|c| *c == b'}')?;
+    let scheme = String::from_utf8_lossy(&value[1..end]).to_ascii_uppercase();
+    let hash = &value[end + 1..];
+    match scheme.as_str() {
+        "SSHA" | "SHA" => {
+            let mut decoded: Vec<u8> = Vec::new();
+            let mut state = DecodeState::default();
+            state.decode(hash, &mut decoded).and_then(|_| state.finish()).ok()?;
+            if decoded.len() < 20 {
+                return Some(false);
+            }
+            let (digest, salt) = decoded.split_at(20);
+            Some(ssha_digest(password, salt) == digest)
+        },
+        "ARGON2" => {
+            let hash = std::str::from_utf8(hash).ok()?;
+            let hash = PasswordHash::new(hash).ok()?;
+            Some(Argon2::default().verify_password(password, &hash).is_ok())
+        },
+        _ => None,
+    }
+}
+
+fn ssha_digest(password: &[u8], salt: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(password);
+    hasher.update(salt);
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        for scheme in [PasswordScheme::Ssha, PasswordScheme::Argon2] {
+            let hashed = hash_password(b"secret", scheme)?;
+            assert!(is_hashed(&hashed));
+            assert_eq!(verify_password(&hashed, b"secret"), Some(true));
+            assert_eq!(verify_password(&hashed, b"Secret"), Some(false));
+        }
+        // the salt is 0123456789abcdef
+        assert_eq!(verify_password(b"{SSHA}A2cie4ShwUr6PZrFaCP1aj17nrQBI0VniavN7w==", b"secret"), Some(true));
+        assert_eq!(verify_password(b"{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=", b"secret"), Some(true));
+        assert_eq!(verify_password(b"secret", b"secret"), Some(true));
+        assert_eq!(verify_password(b"{CRYPT}aaqPiZY5xR5l.", b"secret"), None);
+        Ok(())
+    }
+}
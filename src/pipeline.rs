@@ -0,0 +1,87 @@
+use crate::entry::{Entry, OwnedEntry, WriteEntry};
+use crate::filter::{Filter, MatchOptions};
+use std::io::Result;
+
+/// Writes each entry to both destinations.
+pub struct Tee<A: WriteEntry, B: WriteEntry>(pub A, pub B);
+
+impl<A: WriteEntry, B: WriteEntry> WriteEntry for Tee<A, B> {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        self.0.write_entry(entry)?;
+        self.1.write_entry(entry)
+    }
+}
+
+/// Writes the entries that match the filter to the destination and drops the others.
+pub struct Filtered<W: WriteEntry> {
+    filter: Filter,
+    match_options: MatchOptions,
+    dest: W,
+}
+
+impl<W: WriteEntry> Filtered<W> {
+    pub fn new(filter: Filter, dest: W) -> Filtered<W> {
+        Filtered{
+            filter,
+            match_options: MatchOptions::default(),
+            dest,
+        }
+    }
+
+    pub fn set_match_options(&mut self, match_options: MatchOptions) -> &mut Self {
+        self.match_options = match_options;
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+}
+
+impl<W: WriteEntry> WriteEntry for Filtered<W> {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        if self.filter.is_match_with(entry, &self.match_options) {
+            self.dest.write_entry(entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the result of the function for each entry to the destination.
+pub struct Map<F: FnMut(&Entry) -> OwnedEntry, W: WriteEntry>(pub F, pub W);
+
+impl<F: FnMut(&Entry) -> OwnedEntry, W: WriteEntry> WriteEntry for Map<F, W> {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let mapped = (self.0)(entry);
+        self.1.write_entry(&mapped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let mut all: Vec<OwnedEntry> = Vec::new();
+        let mut people: Vec<OwnedEntry> = Vec::new();
+        let filter = Filter::parse("(objectClass=person)").unwrap();
+        let rename_cn = |entry: &Entry| {
+            let mut mapped = OwnedEntry::default();
+            for attr in entry.attributes() {
+                let name = if attr.lowercase == "cn" { "commonName" } else { attr.name };
+                for value in entry.get(attr.lowercase) {
+                    mapped.add_value(name, value);
+                }
+            }
+            mapped
+        };
+        let mut pipeline = Tee(&mut all, Filtered::new(filter, Map(rename_cn, &mut people)));
+        pipeline.write_entry(&Entry::from([("dn", b"cn=foo".as_slice()), ("objectClass", b"person"), ("cn", b"foo")]))?;
+        pipeline.write_entry(&Entry::from([("dn", b"cn=bar".as_slice()), ("objectClass", b"group"), ("cn", b"bar")]))?;
+        assert_eq!(all.len(), 2);
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].get_one_str("commonname").as_deref(), Some("foo"));
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use crate::lexer::{Token, TokenKind, WriteToken};
+use std::io::{stderr, IsTerminal, Read, Result, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const TERMINAL_INTERVAL: Duration = Duration::from_millis(200);
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+const BAR_WIDTH: usize = 30;
+
+/// Reports how much of the input has been processed to standard error, for operations on large
+/// inputs that would otherwise seem to hang. When standard error is a terminal, the report is a
+/// single line that is updated in place, with a progress bar if the total size of the input is
+/// known. Otherwise a line is written every few seconds.
+///
+/// The counts are updated through `ProgressReader` and `ProgressTokenWriter`, which may be used
+/// from several threads at once.
+pub struct Progress {
+    program: String,
+    total_bytes: Option<u64>,
+    bytes: AtomicU64,
+    entries: AtomicU64,
+    start: Instant,
+    last_report: Mutex<Instant>,
+    terminal: bool,
+}
+
+impl Progress {
+    pub fn new(program: &str, total_bytes: Option<u64>) -> Progress {
+        let start = Instant::now();
+        Progress{
+            program: String::from(program),
+            total_bytes,
+            bytes: AtomicU64::new(0),
+            entries: AtomicU64::new(0),
+            start,
+            last_report: Mutex::new(start),
+            terminal: stderr().is_terminal(),
+        }
+    }
+
+    /// Sets the total size of the input, such as the sum of the sizes of the input files, for
+    /// showing how much of it has been read.
+    pub fn set_total_bytes(&mut self, total_bytes: Option<u64>) -> &mut Self {
+        self.total_bytes = total_bytes;
+        self
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.tick();
+    }
+
+    pub fn add_entries(&self, entries: u64) {
+        self.entries.fetch_add(entries, Ordering::Relaxed);
+    }
+
+    fn tick(&self) {
+        let interval = if self.terminal { TERMINAL_INTERVAL } else { LOG_INTERVAL };
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return; // another thread is reporting
+        };
+        let now = Instant::now();
+        if now.duration_since(*last_report) < interval {
+            return;
+        }
+        *last_report = now;
+        self.report(now, false);
+    }
+
+    /// Writes the final counts. When standard error is a terminal, the line is ended, so that
+    /// further output starts on a line of its own.
+    pub fn finish(&self) {
+        self.report(Instant::now(), true);
+    }
+
+    fn report(&self, now: Instant, is_final: bool) {
+        let line = self.format(now.duration_since(self.start));
+        let mut dest = stderr().lock();
+        _ = if self.terminal {
+            write!(dest, "\r{}\x1b[K{}", line, if is_final { "\n" } else { "" })
+        } else {
+            writeln!(dest, "{}: {}", self.program, line)
+        };
+    }
+
+    fn format(&self, elapsed: Duration) -> String {
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let entries = self.entries.load(Ordering::Relaxed);
+        let seconds = elapsed.as_secs_f64().max(0.001);
+        let mut line = String::new();
+        if let Some(total_bytes) = self.total_bytes.filter(|total| *total > 0) {
+            let fraction = (bytes as f64 / total_bytes as f64).min(1.0);
+            if self.terminal {
+                let filled = (fraction * BAR_WIDTH as f64) as usize;
+                line.push_str(&format!("[{}{}] ", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled)));
+            }
+            line.push_str(&format!("{:.0}%, ", fraction * 100.0));
+        }
+        line.push_str(&format!(
+            "{} read, {} entries, {:.0} entries/s, {}/s",
+            format_bytes(bytes as f64),
+            entries,
+            entries as f64 / seconds,
+            format_bytes(bytes as f64 / seconds),
+        ));
+        line
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < units.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, units[unit])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+/// Returns the total size of the given files, or None if one of them is standard input (`-`) or
+/// isn't a regular file.
+pub fn total_file_size<S: AsRef<str>>(paths: &[S]) -> Option<u64> {
+    let mut total: u64 = 0;
+    for path in paths.iter().map(AsRef::as_ref) {
+        if path == "-" {
+            return None;
+        }
+        let metadata = std::fs::metadata(path).ok().filter(|metadata| metadata.is_file())?;
+        total += metadata.len();
+    }
+    Some(total)
+}
+
+/// Counts the bytes read from the inner reader.
+pub struct ProgressReader<'a, R: Read> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(inner: R, progress: &'a Progress) -> ProgressReader<'a, R> {
+        ProgressReader{
+            inner,
+            progress,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.progress.add_bytes(len as u64);
+        Ok(len)
+    }
+}
+
+/// Counts the entries passed on to the inner token writer.
+pub struct ProgressTokenWriter<'a, TR: WriteToken> {
+    inner: TR,
+    progress: Option<&'a Progress>,
+}
+
+impl<'a, TR: WriteToken> ProgressTokenWriter<'a, TR> {
+    /// Passes tokens on without counting them if progress is None.
+    pub fn new(inner: TR, progress: Option<&'a Progress>) -> ProgressTokenWriter<'a, TR> {
+        ProgressTokenWriter{
+            inner,
+            progress,
+        }
+    }
+}
+
+impl<TR: WriteToken> WriteToken for ProgressTokenWriter<'_, TR> {
+    fn write_token(&mut self, token: Token) -> Result<()> {
+        if let (TokenKind::EntryFinish, Some(progress)) = (&token.kind, self.progress) {
+            progress.add_entries(1);
+        }
+        self.inner.write_token(token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bulk::BulkParser;
+    use crate::entry::{EntryTokenWriter, OwnedEntry};
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let progress = Progress::new("test", Some(64));
+        let input = b"dn: cn=foo\ncn: foo\n\ndn: cn=bar\ncn: bar\n";
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let mut reader = ProgressReader::new(input.as_slice(), &progress);
+        let token_writer = ProgressTokenWriter::new(EntryTokenWriter::new(&mut entries), Some(&progress));
+        BulkParser::new().write_tokens(&mut reader, token_writer)?;
+        assert_eq!(entries.len(), 2);
+        let line = progress.format(Duration::from_secs(2));
+        assert!(line.ends_with("61%, 39 B read, 2 entries, 1 entries/s, 20 B/s"), "{}", line);
+        Ok(())
+    }
+}
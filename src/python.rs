@@ -0,0 +1,253 @@
+//! Python bindings, enabled by the `python` feature and built as the `ltools` module by the crate
+//! in `python/`:
+//!
+//! ```python
+//! import ltools
+//!
+//! people = ltools.Filter.parse("(&(objectClass=person)(mail=*))")
+//! for entry in ltools.read_ldif("export.ldif"):
+//!     if people.is_match(entry):
+//!         print(entry.dn, entry.get_str("mail"))
+//! ```
+//!
+//! Entries are dicts from attribute names, as written in the input, to lists of values as bytes,
+//! so that they can be handed to anything that takes a dict, such as `pandas.DataFrame`.
+
+use crate::bulk::EntryReader;
+use crate::entry::{OwnedEntry, ReadEntry};
+use crate::filter::Filter;
+use crate::loc::LocError;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString, PyTuple};
+use std::fs::File;
+use std::io::ErrorKind;
+
+create_exception!(ltools, LdifError, PyException, "Raised for input that isn't valid LDIF.");
+
+// Syntax errors come as LocErrors, which tell where in the input they are, and other problems
+// with the content as InvalidData.
+fn to_pyerr(err: std::io::Error) -> PyErr {
+    let is_ldif_error = err.kind() == ErrorKind::InvalidData
+        || err.get_ref().is_some_and(|inner| inner.is::<LocError>());
+    match is_ldif_error {
+        true => LdifError::new_err(err.to_string()),
+        false => PyErr::from(err),
+    }
+}
+
+/// An entry as a dict from attribute names, as written in the input, to lists of values as
+/// bytes. The get_* methods look attributes up regardless of case, as LDAP does.
+#[pyclass(extends = PyDict, subclass, module = "ltools")]
+pub struct Entry;
+
+#[pymethods]
+impl Entry {
+    // the items are set by dict.__init__, which takes the same arguments
+    #[new]
+    #[pyo3(signature = (*_args, **_kwargs))]
+    fn new(_args: &Bound<'_, PyTuple>, _kwargs: Option<&Bound<'_, PyDict>>) -> Entry {
+        Entry
+    }
+
+    fn get_values<'py>(slf: &Bound<'py, Self>, attr: &str) -> PyResult<Bound<'py, PyAny>> {
+        for (name, values) in slf.downcast::<PyDict>()?.iter() {
+            if name.extract::<&str>()?.eq_ignore_ascii_case(attr) {
+                return Ok(values);
+            }
+        }
+        Ok(PyList::empty(slf.py()).into_any())
+    }
+
+    fn get_one<'py>(slf: &Bound<'py, Self>, attr: &str) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let values = Entry::get_values(slf, attr)?;
+        if values.len()? == 0 {
+            return Ok(None);
+        }
+        values.get_item(0).map(Some)
+    }
+
+    fn get_str(slf: &Bound<'_, Self>, attr: &str) -> PyResult<Vec<String>> {
+        Entry::get_values(slf, attr)?.try_iter()?
+            .map(|value| value.and_then(|value| to_str(&value)))
+            .collect()
+    }
+
+    fn get_one_str(slf: &Bound<'_, Self>, attr: &str) -> PyResult<Option<String>> {
+        Entry::get_one(slf, attr)?.map(|value| to_str(&value)).transpose()
+    }
+
+    #[getter]
+    fn dn(slf: &Bound<'_, Self>) -> PyResult<Option<String>> {
+        Entry::get_one_str(slf, "dn")
+    }
+}
+
+// Values are bytes as read, but str is taken as well for entries built in Python.
+fn to_bytes(value: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    if let Ok(value) = value.downcast::<PyBytes>() {
+        return Ok(value.as_bytes().to_vec());
+    }
+    if let Ok(value) = value.downcast::<PyString>() {
+        return Ok(value.to_str()?.as_bytes().to_vec());
+    }
+    Err(PyTypeError::new_err("values must be bytes or str"))
+}
+
+fn to_str(value: &Bound<'_, PyAny>) -> PyResult<String> {
+    Ok(String::from_utf8_lossy(&to_bytes(value)?).into_owned())
+}
+
+fn from_dict(dict: &Bound<'_, PyDict>) -> PyResult<OwnedEntry> {
+    let mut entry = OwnedEntry::default();
+    for (name, values) in dict.iter() {
+        let name: &str = name.extract()?;
+        for value in values.try_iter()? {
+            entry.add_value(name, &to_bytes(&value?)?);
+        }
+    }
+    Ok(entry)
+}
+
+fn to_dict<'py>(py: Python<'py>, entry: &OwnedEntry) -> PyResult<Bound<'py, PyAny>> {
+    let dict = py.get_type::<Entry>().call0()?;
+    for attr in entry.attributes() {
+        let values = PyList::new(py, entry.get(attr.lowercase).map(|value| PyBytes::new(py, value)).collect::<Vec<_>>())?;
+        dict.set_item(attr.name, values)?;
+    }
+    Ok(dict)
+}
+
+/// An iterator over the entries of an LDIF file, as returned by read_ldif.
+#[pyclass(module = "ltools")]
+pub struct LdifReader {
+    reader: EntryReader<File>,
+}
+
+#[pymethods]
+impl LdifReader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        match self.reader.read_entry().map_err(to_pyerr)? {
+            Some(entry) => to_dict(py, &entry).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns an iterator over the entries of the LDIF file at path, which are parsed as they are
+/// asked for.
+#[pyfunction]
+fn read_ldif(path: &str) -> PyResult<LdifReader> {
+    let file = File::open(path)?;
+    Ok(LdifReader{ reader: EntryReader::new(file) })
+}
+
+/// An LDAP search filter such as (&(objectClass=person)(mail=*)).
+#[pyclass(name = "Filter", module = "ltools")]
+pub struct PyFilter {
+    filter: Filter,
+}
+
+#[pymethods]
+impl PyFilter {
+    #[staticmethod]
+    fn parse(text: &str) -> PyResult<PyFilter> {
+        let filter = Filter::parse(text).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(PyFilter{ filter })
+    }
+
+    /// Tells whether the entry, a dict from attribute names to lists of bytes or str values,
+    /// matches the filter.
+    fn is_match(&self, entry: &Bound<'_, PyDict>) -> PyResult<bool> {
+        Ok(self.filter.is_match(&from_dict(entry)?))
+    }
+}
+
+#[pymodule]
+fn ltools(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Entry>()?;
+    m.add_class::<LdifReader>()?;
+    m.add_class::<PyFilter>()?;
+    m.add("LdifError", m.py().get_type::<LdifError>())?;
+    m.add_function(wrap_pyfunction!(read_ldif, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pyo3::types::IntoPyDict;
+    use std::ffi::CStr;
+
+    // Runs the Python code with the module imported as ltools and the given path as path.
+    fn run(code: &CStr, path: &str) -> PyResult<()> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "ltools")?;
+            ltools(&module)?;
+            let locals = [("ltools", module.into_any()), ("path", PyString::new(py, path).into_any())].into_py_dict(py)?;
+            py.run(code, None, Some(&locals))
+        })
+    }
+
+    #[test]
+    fn test_read_ldif() -> PyResult<()> {
+        let path = std::env::temp_dir().join(format!("ltools-python-{}.ldif", std::process::id()));
+        std::fs::write(&path, b"dn: cn=foo,dc=example\ncn: foo\nMail: a@example.com\nMail: b@example.com\n\ndn: cn=bar,dc=example\ncn:: YmFy\n\n")?;
+        let result = run(cr#"
+entries = list(ltools.read_ldif(path))
+assert len(entries) == 2
+assert isinstance(entries[0], dict) and isinstance(entries[0], ltools.Entry)
+assert entries[0]["Mail"] == [b"a@example.com", b"b@example.com"]
+assert entries[0].get_values("mail") == [b"a@example.com", b"b@example.com"]
+assert entries[0].get_one_str("MAIL") == "a@example.com"
+assert entries[1].dn == "cn=bar,dc=example"
+assert entries[1].get_str("cn") == ["bar"]
+assert entries[1].get_one("sn") is None
+"#, path.to_str().unwrap());
+        std::fs::remove_file(&path)?;
+        result
+    }
+
+    #[test]
+    fn test_ldif_error() -> PyResult<()> {
+        let path = std::env::temp_dir().join(format!("ltools-python-error-{}.ldif", std::process::id()));
+        let mut result = Ok(());
+        for ldif in [&b"dn: cn=foo\ncn:: !!!!\n\n"[..], b"dn: cn=foo\ncn\n\n"] {
+            std::fs::write(&path, ldif)?;
+            result = result.and(run(cr#"
+try:
+    list(ltools.read_ldif(path))
+    assert False
+except ltools.LdifError as err:
+    assert "line" in str(err)
+"#, path.to_str().unwrap()));
+        }
+        std::fs::remove_file(&path)?;
+        result
+    }
+
+    #[test]
+    fn test_filter() -> PyResult<()> {
+        run(cr#"
+people = ltools.Filter.parse("(&(objectClass=person)(mail=*))")
+assert people.is_match({"objectClass": ["top", "person"], "mail": [b"a@example.com"]})
+assert not people.is_match(ltools.Entry({"objectClass": [b"person"]}))
+try:
+    ltools.Filter.parse("(cn=foo")
+    assert False
+except ValueError as err:
+    assert "offset" in str(err)
+try:
+    ltools.read_ldif(path + ".missing")
+    assert False
+except FileNotFoundError:
+    pass
+"#, "/nonexistent")
+    }
+}
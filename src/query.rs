@@ -0,0 +1,214 @@
+//! The query language of `lquery`, which puts attribute specifications, filters, sorting and
+//! limits together in one SQL-like statement:
+//!
+//! ```text
+//! SELECT dn, mail FROM - WHERE (objectClass=person) ORDER BY sn DESC, cn LIMIT 100
+//! ```
+//!
+//! Keywords are case-insensitive. The attributes are attribute specifications as for `lget`,
+//! separated by commas, and FROM takes files separated by commas, `-` being standard input. The
+//! WHERE clause is an LDAP filter, which has to be in parentheses.
+
+use crate::attrspec::AttrSpec;
+use crate::entry::Entry;
+use crate::filter::{Filter, MatchOptions};
+use nom::{
+    IResult,
+    branch::alt,
+    bytes::complete::{tag_no_case, take_while1},
+    character::complete::{char, digit1, multispace0, multispace1},
+    combinator::{eof, map, map_res, opt, value},
+    multi::separated_list1,
+    sequence::{pair, preceded, terminated, tuple},
+};
+use std::cmp::Ordering;
+use std::io::{Error, Result};
+
+pub struct Query {
+    pub select: Vec<AttrSpec>,
+    pub from: Vec<String>,
+    pub filter: Option<Filter>,
+    pub order_by: Vec<SortKey>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub attribute: String, // lowercase
+    pub descending: bool,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query> {
+        match parser::query(input.trim()) {
+            Ok((_, query)) => query,
+            Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+                Err(Error::other(format!("failed to parse query '{}' starting at '{}'", input, err.input)))
+            },
+            Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers"),
+        }
+    }
+
+    pub fn is_match(&self, entry: &Entry, options: &MatchOptions) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter.is_match_with(entry, options))
+    }
+
+    /// Compares entries by the ORDER BY attributes, using the first value of each, according to
+    /// the syntax of the attribute. Entries without the attribute, or whose values can't be
+    /// compared, such as non-integers in an integer attribute, come last whatever the direction.
+    pub fn compare(&self, a: &Entry, b: &Entry, options: &MatchOptions) -> Ordering {
+        for key in self.order_by.iter() {
+            let syntax = options.syntax(&key.attribute);
+            let ordering = match (a.get_one(&key.attribute), b.get_one(&key.attribute)) {
+                (Some(a), Some(b)) => match syntax.compare(a, &b.to_ascii_lowercase()) {
+                    Some(ordering) if key.descending => ordering.reverse(),
+                    Some(ordering) => ordering,
+                    None => syntax.compare(a, a).is_none().cmp(&syntax.compare(b, b).is_none()),
+                },
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+mod parser {
+    use super::*;
+
+    // The clauses are parsed first and their contents then, so that errors in attribute
+    // specifications and filters are reported as such.
+    type Clauses<'a> = (Vec<&'a str>, Vec<&'a str>, Option<&'a str>, Option<Vec<SortKey>>, Option<usize>);
+
+    pub(super) fn query(input: &str) -> IResult<&str, Result<Query>> {
+        map(
+            terminated(
+                tuple((
+                    preceded(keyword("SELECT"), list),
+                    preceded(pair(multispace1, keyword("FROM")), list),
+                    opt(preceded(pair(multispace1, keyword("WHERE")), parenthesized)),
+                    opt(preceded(tuple((multispace1, keyword("ORDER"), keyword("BY"))), separated_list1(comma, sort_key))),
+                    opt(preceded(pair(multispace1, keyword("LIMIT")), map_res(digit1, str::parse::<usize>))),
+                )),
+                pair(multispace0, eof),
+            ),
+            clauses,
+        )(input)
+    }
+
+    fn clauses((select, from, filter, order_by, limit): Clauses) -> Result<Query> {
+        Ok(Query{
+            select: select.into_iter().map(AttrSpec::parse).collect::<Result<Vec<AttrSpec>>>()?,
+            from: from.into_iter().map(String::from).collect(),
+            filter: match filter {
+                Some(filter) => Some(Filter::parse(filter).map_err(Error::other)?),
+                None => None,
+            },
+            order_by: order_by.unwrap_or_default(),
+            limit,
+        })
+    }
+
+    // A keyword followed by whitespace.
+    fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+        terminated(tag_no_case(word), multispace1)
+    }
+
+    fn comma(input: &str) -> IResult<&str, char> {
+        preceded(multispace0, terminated(char(','), multispace0))(input)
+    }
+
+    // Items such as attribute specifications and file names, which end at commas and whitespace.
+    fn list(input: &str) -> IResult<&str, Vec<&str>> {
+        separated_list1(comma, take_while1(|c: char| c != ',' && !c.is_whitespace()))(input)
+    }
+
+    // A filter, up to its closing parenthesis. Parentheses in values are escaped as \28 and \29,
+    // so counting them is enough.
+    fn parenthesized(input: &str) -> IResult<&str, &str> {
+        let mut depth: usize = 0;
+        for (i, c) in input.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 1 => depth -= 1,
+                ')' if depth == 1 => return Ok((&input[i + 1..], &input[..i + 1])),
+                _ if depth == 0 => break,
+                _ => {},
+            }
+        }
+        Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char)))
+    }
+
+    fn descending(input: &str) -> IResult<&str, bool> {
+        alt((value(false, tag_no_case("ASC")), value(true, tag_no_case("DESC"))))(input)
+    }
+
+    fn sort_key(input: &str) -> IResult<&str, SortKey> {
+        map(
+            pair(
+                take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'),
+                opt(preceded(multispace1, descending)),
+            ),
+            |(attribute, descending)| SortKey{
+                attribute: attribute.to_ascii_lowercase(),
+                descending: descending.unwrap_or(false),
+            },
+        )(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::OwnedEntry;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let query = Query::parse("select dn, mail:-none FROM a.ldif,- WHERE (&(objectClass=person)(cn=a b)) order by sn DESC, cn limit 100")?;
+        let attributes: Vec<&str> = query.select.iter().map(|attrspec| attrspec.attribute.as_str()).collect();
+        assert_eq!(attributes, ["dn", "mail"]);
+        assert_eq!(query.from, ["a.ldif", "-"]);
+        assert!(query.filter.is_some());
+        assert_eq!(query.order_by, [
+            SortKey{ attribute: String::from("sn"), descending: true },
+            SortKey{ attribute: String::from("cn"), descending: false },
+        ]);
+        assert_eq!(query.limit, Some(100));
+
+        let query = Query::parse("SELECT * FROM -")?;
+        assert!(query.select[0].is_wildcard() && query.filter.is_none() && query.limit.is_none());
+        assert!(Query::parse("SELECT dn FROM - WHERE objectClass=person").is_err());
+        assert!(Query::parse("SELECT dn FROM - LIMIT ten").is_err());
+        assert!(Query::parse("SELECT dn FROM - WHERE (cn=foo").is_err());
+        let err = Query::parse("SELECT dn FROM - WHERE (cn=(foo))").err().unwrap();
+        assert!(err.to_string().contains("(cn=(foo))"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare() -> Result<()> {
+        let query = Query::parse("SELECT dn FROM - ORDER BY uidNumber DESC, cn")?;
+        let entry = |cn: &str, uid_number: Option<&str>| {
+            let mut entry = OwnedEntry::default();
+            entry.add_value("cn", cn.as_bytes());
+            if let Some(uid_number) = uid_number {
+                entry.add_value("uidNumber", uid_number.as_bytes());
+            }
+            entry
+        };
+        let mut entries = [entry("b", Some("9")), entry("c", None), entry("A", Some("10")), entry("a", Some("10"))];
+        let mut options = MatchOptions::default();
+        entries.sort_by(|a, b| query.compare(a, b, &options));
+        let order: Vec<String> = entries.iter().map(|entry| entry.get_one_str("cn").unwrap().into_owned()).collect();
+        assert_eq!(order, ["b", "A", "a", "c"]);
+        options.set_syntax("uidNumber", crate::filter::syntax::Syntax::Integer);
+        entries.sort_by(|a, b| query.compare(a, b, &options));
+        let order: Vec<String> = entries.iter().map(|entry| entry.get_one_str("cn").unwrap().into_owned()).collect();
+        assert_eq!(order, ["A", "a", "b", "c"]);
+        Ok(())
+    }
+}
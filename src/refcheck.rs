@@ -0,0 +1,249 @@
+use crate::base64::DecodeState;
+use crate::bulk::BulkParser;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::dn::{is_ancestor_or_self, normalize, parent, rdns};
+use crate::lexer::{Token, TokenKind, WriteToken};
+use crate::loc::Loc;
+use std::collections::HashSet;
+use std::io::{Read, Result};
+
+/// The attributes that are checked unless others are given.
+pub const DEFAULT_REFERENCES: &[&str] = &["member", "uniqueMember", "manager", "secretary", "seeAlso"];
+
+struct Reference {
+    file: Option<usize>,
+    loc: Loc, // of the attribute type
+    attribute: String, // as spelled
+    value: String,
+    source: Option<String>, // the DN of the entry the reference is in
+}
+
+struct EntryDn {
+    file: Option<usize>,
+    loc: Loc,
+    dn: String,
+}
+
+/// Checks that the values of attributes that hold DNs, such as `member` and `manager`, and the
+/// parents of entries are DNs of entries in the same input, which may be read from several files.
+/// References are reported at the location of the attribute type and entries at that of their DN.
+#[derive(Default)]
+pub struct ReferenceChecker {
+    attributes: HashSet<String>, // lowercase
+    files: Vec<String>,
+    dns: HashSet<String>, // normalized
+    entries: Vec<EntryDn>,
+    references: Vec<Reference>,
+}
+
+impl ReferenceChecker {
+    /// Creates a checker of the values of the given attributes.
+    pub fn new<S: AsRef<str>>(attributes: &[S]) -> ReferenceChecker {
+        ReferenceChecker{
+            attributes: attributes.iter().map(|attr| attr.as_ref().to_ascii_lowercase()).collect(),
+            ..ReferenceChecker::default()
+        }
+    }
+
+    /// Also checks the values of the given attributes, such as those returned by
+    /// `dn_attributes_from_schema`.
+    pub fn add_attributes<I: IntoIterator<Item = String>>(&mut self, attributes: I) -> &mut Self {
+        self.attributes.extend(attributes.into_iter().map(|attr| attr.to_ascii_lowercase()));
+        self
+    }
+
+    /// Reads the entries of an input, which is named in the diagnostics if file is given.
+    pub fn read<R: Read + ?Sized>(&mut self, input: &mut R, file: Option<&str>) -> Result<()> {
+        let file = file.map(|file| {
+            self.files.push(String::from(file));
+            self.files.len() - 1
+        });
+        let mut collector = Collector{
+            checker: self,
+            file,
+            in_entry: false,
+            dn: None,
+            attribute: String::new(),
+            attribute_loc: Loc::default(),
+            value: Vec::new(),
+            base64: false,
+        };
+        BulkParser::new().write_tokens(input, &mut collector)
+    }
+
+    fn diagnostic(&self, code: &'static str, message: String, file: Option<usize>, loc: Loc, dn: Option<&str>) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(Severity::Error, code, message).at(loc).in_entry(dn);
+        diagnostic.file = file.map(|file| self.files[file].clone());
+        diagnostic
+    }
+
+    /// Returns a `dangling-reference` error for each value that is not the DN of an entry in the
+    /// input, in the order the values were read.
+    pub fn dangling_references(&self) -> Vec<Diagnostic> {
+        self.references.iter()
+            .filter(|reference| !self.dns.contains(&normalize(&reference.value)))
+            .map(|reference| {
+                let message = format!("{} refers to {}, which is not in the input", reference.attribute, reference.value);
+                self.diagnostic("dangling-reference", message, reference.file, reference.loc, reference.source.as_deref())
+            })
+            .collect()
+    }
+
+    // Whether the entry is in the input or is one of the existing entries or their ancestors.
+    fn exists(&self, dn: &str, existing: &[String]) -> bool {
+        let dn = normalize(dn);
+        self.dns.contains(&dn) || existing.iter().any(|base| is_ancestor_or_self(&dn, base))
+    }
+
+    /// Returns an `orphan` error for each entry whose parent is neither in the input nor one of
+    /// the existing entries, which are taken to have their ancestors too, such as the base DN of
+    /// the directory that the input is imported into.
+    pub fn orphans<S: AsRef<str>>(&self, existing: &[S]) -> Vec<Diagnostic> {
+        let existing: Vec<String> = existing.iter().map(|dn| normalize(dn.as_ref())).collect();
+        self.entries.iter()
+            .filter_map(|entry| Some((entry, parent(&entry.dn)?)))
+            .filter(|(_, parent)| !self.exists(parent, &existing))
+            .map(|(entry, parent)| {
+                let message = format!("the parent {} of the entry is neither in the input nor known to exist", parent);
+                self.diagnostic("orphan", message, entry.file, entry.loc, Some(&entry.dn))
+            })
+            .collect()
+    }
+
+    /// Returns the DNs of the entries that would have to be added for every entry to have a
+    /// parent, as in `orphans`, spelled as in the first entry below them. Parents come before
+    /// their children.
+    pub fn missing_parents<S: AsRef<str>>(&self, existing: &[S]) -> Vec<String> {
+        let existing: Vec<String> = existing.iter().map(|dn| normalize(dn.as_ref())).collect();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut missing: Vec<(usize, String)> = Vec::new(); // the depth and the DN
+        for entry in self.entries.iter() {
+            let mut dn = parent(&entry.dn);
+            while let Some(ancestor) = dn.filter(|ancestor| !self.exists(ancestor, &existing)) {
+                if !seen.insert(normalize(ancestor)) {
+                    break;
+                }
+                missing.push((rdns(ancestor).count(), ancestor.to_string()));
+                dn = parent(ancestor);
+            }
+        }
+        missing.sort_by_key(|(depth, _)| *depth);
+        missing.into_iter().map(|(_, dn)| dn).collect()
+    }
+}
+
+// Strips the optional UID of a value of the Name and Optional UID syntax, as in
+// uniqueMember: cn=foo,dc=example#'0101'B
+fn strip_uid(value: &str) -> &str {
+    match value.rfind("#'") {
+        Some(i) if value.ends_with("'B") && !value[..i].ends_with('\\') => &value[..i],
+        _ => value,
+    }
+}
+
+struct Collector<'a> {
+    checker: &'a mut ReferenceChecker,
+    file: Option<usize>,
+    in_entry: bool,
+    dn: Option<String>,
+    attribute: String, // as spelled
+    attribute_loc: Loc,
+    value: Vec<u8>,
+    base64: bool,
+}
+
+impl Collector<'_> {
+    fn finish_value(&mut self) -> Result<()> {
+        let value = if self.base64 {
+            let mut decoded: Vec<u8> = Vec::new();
+            let mut state = DecodeState::default();
+            state.decode(&self.value, &mut decoded)?;
+            state.finish()?;
+            decoded
+        } else {
+            std::mem::take(&mut self.value)
+        };
+        let value = String::from_utf8_lossy(&value);
+        let attribute = self.attribute.to_ascii_lowercase();
+        if attribute == "dn" {
+            if self.dn.is_none() {
+                self.checker.dns.insert(normalize(&value));
+                self.checker.entries.push(EntryDn{ file: self.file, loc: self.attribute_loc, dn: value.to_string() });
+                self.dn = Some(value.into_owned());
+            }
+        } else if self.checker.attributes.contains(&attribute) {
+            self.checker.references.push(Reference{
+                file: self.file,
+                loc: self.attribute_loc,
+                attribute: self.attribute.clone(),
+                value: strip_uid(&value).to_string(),
+                source: self.dn.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl WriteToken for &mut Collector<'_> {
+    fn write_token(&mut self, token: Token) -> Result<()> {
+        match token.kind {
+            TokenKind::AttributeType => {
+                self.attribute = token.segment.to_string();
+                self.attribute_loc = token.loc;
+                if !self.in_entry {
+                    self.in_entry = true;
+                    self.dn = None;
+                }
+            },
+            TokenKind::ValueText => {
+                self.value.extend_from_slice(token.segment.as_bytes());
+                self.base64 = false;
+            },
+            TokenKind::ValueBase64 => {
+                self.value.extend_from_slice(token.segment.as_bytes());
+                self.base64 = true;
+            },
+            TokenKind::ValueFinish => {
+                self.finish_value()?;
+                self.value.clear();
+            },
+            TokenKind::EntryFinish => self.in_entry = false,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dangling_references() -> Result<()> {
+        let mut checker = ReferenceChecker::new(DEFAULT_REFERENCES);
+        checker.read(&mut &b"dn: cn=staff,dc=example\nmember: cn=a,dc=example\nmember: CN=B, dc=example\nuniqueMember: cn=gone,dc=example#'0101'B\n\n"[..], Some("groups.ldif"))?;
+        checker.read(&mut &b"dn: cn=a,dc=example\nmanager:: Y249YixkYz1leGFtcGxl\n\ndn: cn=b,dc=example\nsecretary: cn=c,dc=example\n"[..], None)?;
+        let diagnostics = checker.dangling_references();
+        let mut text: Vec<u8> = Vec::new();
+        for diagnostic in diagnostics.iter() {
+            diagnostic.write_text(&mut text)?;
+        }
+        assert_eq!(String::from_utf8_lossy(&text), concat!(
+            "groups.ldif:4:1: error: uniqueMember refers to cn=gone,dc=example, which is not in the input [dangling-reference]\n",
+            "<stdin>:5:1: error: secretary refers to cn=c,dc=example, which is not in the input [dangling-reference]\n",
+        ));
+        assert_eq!(diagnostics[1].dn.as_deref(), Some("cn=b,dc=example"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_orphans() -> Result<()> {
+        let mut checker = ReferenceChecker::default();
+        checker.read(&mut &b"dn: dc=example,dc=com\n\ndn: uid=a,ou=People,ou=Staff,dc=example,dc=com\n\ndn: uid=b,ou=people,ou=staff,dc=example,dc=com\n\ndn: cn=c,o=other\n"[..], Some("a.ldif"))?;
+        let orphans = checker.orphans(&["dc=example, dc=com"]);
+        let orphans: Vec<(&str, usize)> = orphans.iter().map(|orphan| (orphan.dn.as_deref().unwrap(), orphan.loc.unwrap().line)).collect();
+        assert_eq!(orphans, [("uid=a,ou=People,ou=Staff,dc=example,dc=com", 3), ("uid=b,ou=people,ou=staff,dc=example,dc=com", 5), ("cn=c,o=other", 7)]);
+        assert_eq!(checker.missing_parents(&["dc=example,dc=com"]), ["o=other", "ou=Staff,dc=example,dc=com", "ou=People,ou=Staff,dc=example,dc=com"]);
+        assert_eq!(checker.missing_parents::<&str>(&[]), ["dc=com", "o=other", "ou=Staff,dc=example,dc=com", "ou=People,ou=Staff,dc=example,dc=com"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use crate::dn::{normalize, rdns};
+use crate::entry::{Entry, OwnedEntry, WriteEntry};
+use crate::lexer::{Token, TokenKind, WriteToken};
+use crate::schema::DN_ATTRIBUTES;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+
+enum NewName {
+    Rdn(String), // below the parent, which may itself be renamed
+    Dn(String),
+}
+
+/// Maps old DNs to new DNs. Entries below a renamed entry are renamed along with it, and the
+/// renames all apply to DNs as they were before any of them.
+#[derive(Default)]
+pub struct DnMap {
+    renames: HashMap<String, NewName>, // by normalized old DN
+}
+
+impl DnMap {
+    pub fn new() -> DnMap {
+        DnMap::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty()
+    }
+
+    /// Gives the entry a new RDN, keeping it below its parent.
+    pub fn rename(&mut self, dn: &str, new_rdn: &str) -> &mut Self {
+        self.renames.insert(normalize(dn), NewName::Rdn(new_rdn.to_string()));
+        self
+    }
+
+    /// Moves the entry to a new DN, such as for replacing the suffix of a tree.
+    pub fn move_to(&mut self, dn: &str, new_dn: &str) -> &mut Self {
+        self.renames.insert(normalize(dn), NewName::Dn(new_dn.to_string()));
+        self
+    }
+
+    /// Adds the rename of a modrdn or moddn change record. Returns false if the record is not
+    /// such a change record.
+    pub fn add_change_record(&mut self, record: &Entry) -> bool {
+        let is_moddn = record.get_one_str("changetype")
+            .map(|changetype| changetype.eq_ignore_ascii_case("modrdn") || changetype.eq_ignore_ascii_case("moddn"))
+            .unwrap_or(false);
+        let (dn, new_rdn) = match (record.get_one_str("dn"), record.get_one_str("newrdn")) {
+            (Some(dn), Some(new_rdn)) if is_moddn => (dn, new_rdn),
+            _ => return false,
+        };
+        match record.get_one_str("newsuperior") {
+            Some(new_superior) => self.move_to(&dn, &format!("{},{}", new_rdn, new_superior)),
+            None => self.rename(&dn, &new_rdn),
+        };
+        true
+    }
+
+    /// Returns the new DN of the given DN, which is borrowed if neither it nor any of its
+    /// ancestors are renamed.
+    pub fn rewrite<'a>(&self, dn: &'a str) -> Cow<'a, str> {
+        if self.renames.is_empty() {
+            return Cow::Borrowed(dn);
+        }
+        let rdns: Vec<&str> = rdns(dn).collect();
+        match self.rewrite_rdns(&rdns) {
+            Some(new_dn) => Cow::Owned(new_dn),
+            None => Cow::Borrowed(dn),
+        }
+    }
+
+    fn rewrite_rdns(&self, rdns: &[&str]) -> Option<String> {
+        let (rdn, parent) = rdns.split_first()?;
+        let new_rdn = match self.renames.get(&rdns.join(",").to_ascii_lowercase()) {
+            Some(NewName::Dn(new_dn)) => return Some(new_dn.clone()),
+            Some(NewName::Rdn(new_rdn)) => Some(new_rdn.as_str()),
+            None => None,
+        };
+        let new_parent = self.rewrite_rdns(parent);
+        if new_rdn.is_none() && new_parent.is_none() {
+            return None;
+        }
+        let rdn = new_rdn.unwrap_or(rdn);
+        let parent = new_parent.unwrap_or_else(|| parent.join(","));
+        if parent.is_empty() {
+            Some(rdn.to_string())
+        } else {
+            Some(format!("{},{}", rdn, parent))
+        }
+    }
+}
+
+/// Writing change records to the map adds the renames of those that are modrdn or moddn
+/// records.
+impl WriteEntry for DnMap {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.add_change_record(entry);
+        Ok(())
+    }
+}
+
+/// Rewrites the DNs of entries and the values of their attributes that hold DNs according to a
+/// DnMap before writing them to the destination.
+pub struct DnRewriter<W: WriteEntry> {
+    map: DnMap,
+    dn_attributes: HashSet<String>, // lowercase
+    dest: W,
+}
+
+impl<W: WriteEntry> DnRewriter<W> {
+    /// Creates a rewriter for the attributes in DN_ATTRIBUTES.
+    pub fn new(map: DnMap, dest: W) -> DnRewriter<W> {
+        DnRewriter{
+            map,
+            dn_attributes: DN_ATTRIBUTES.iter().map(|attr| attr.to_string()).collect(),
+            dest,
+        }
+    }
+
+    /// Sets the attributes whose values are rewritten, such as those found with
+    /// `schema::dn_attributes_from_schema`. The DN of the entry is always rewritten.
+    pub fn set_dn_attributes(&mut self, dn_attributes: HashSet<String>) -> &mut Self {
+        self.dn_attributes = dn_attributes.into_iter().map(|attr| attr.to_ascii_lowercase()).collect();
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+
+    pub fn rewrite_entry(&self, entry: &Entry) -> OwnedEntry {
+        let mut rewritten = OwnedEntry::default();
+        for attr in entry.attributes() {
+            let is_dn = attr.lowercase == "dn" || self.dn_attributes.contains(attr.lowercase);
+            for value in entry.get(attr.lowercase) {
+                match std::str::from_utf8(value) {
+                    Ok(dn) if is_dn => rewritten.add_value(attr.name, self.map.rewrite(dn).as_bytes()),
+                    _ => rewritten.add_value(attr.name, value),
+                }
+            }
+        }
+        rewritten
+    }
+}
+
+impl<W: WriteEntry> WriteEntry for DnRewriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let rewritten = self.rewrite_entry(entry);
+        self.dest.write_entry(&rewritten)
+    }
+}
+
+/// Maps attribute type names to other names, such as for moving entries between directory
+/// servers that name the same attributes differently. The mapping is read from lines such as
+/// `mail=rfc822Mailbox`, and blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct AttributeMap {
+    names: HashMap<String, String>, // new names by lowercase old name
+}
+
+impl AttributeMap {
+    pub fn new() -> AttributeMap {
+        AttributeMap::default()
+    }
+
+    pub fn parse(text: &str) -> std::io::Result<AttributeMap> {
+        let mut map = AttributeMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((old, new)) if !old.trim().is_empty() && !new.trim().is_empty() => {
+                    map.insert(old.trim(), new.trim());
+                },
+                _ => return Err(Error::new(ErrorKind::InvalidData, format!("line {}: expected OLD=NEW, such as mail=rfc822Mailbox", index + 1))),
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn load(path: &str) -> std::io::Result<AttributeMap> {
+        AttributeMap::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn insert(&mut self, old: &str, new: &str) -> &mut Self {
+        self.names.insert(old.to_ascii_lowercase(), new.to_string());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns the new name of the attribute, if it is mapped.
+    pub fn get(&self, attr: &str) -> Option<&str> {
+        if self.names.is_empty() {
+            return None;
+        }
+        self.names.get(&attr.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// Returns the map from the new names to the old names, for writing back out the names that
+    /// the input was read with.
+    pub fn reverse(&self) -> AttributeMap {
+        let mut reversed = AttributeMap::new();
+        for (old, new) in self.names.iter() {
+            reversed.insert(new, old);
+        }
+        reversed
+    }
+
+    /// Returns a copy of the entry with the mapped attributes renamed. The values of attributes
+    /// that end up with the same name are merged.
+    pub fn rename_entry(&self, entry: &Entry) -> OwnedEntry {
+        let mut renamed = OwnedEntry::default();
+        for attr in entry.attributes() {
+            let name = self.get(attr.name).unwrap_or(attr.name);
+            for (value, base64) in entry.get_with_encoding(attr.lowercase) {
+                renamed.add_value_with_encoding(name, value, base64);
+            }
+        }
+        renamed
+    }
+}
+
+/// Renames the attributes of entries according to an AttributeMap before writing them to the
+/// destination.
+pub struct AttributeRenamer<W: WriteEntry> {
+    map: AttributeMap,
+    dest: W,
+}
+
+impl<W: WriteEntry> AttributeRenamer<W> {
+    pub fn new(map: AttributeMap, dest: W) -> AttributeRenamer<W> {
+        AttributeRenamer{ map, dest }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+}
+
+impl<W: WriteEntry> WriteEntry for AttributeRenamer<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        if self.map.is_empty() {
+            return self.dest.write_entry(entry);
+        }
+        self.dest.write_entry(&self.map.rename_entry(entry))
+    }
+}
+
+/// Renames attribute type tokens according to an AttributeMap, for renaming attributes as they
+/// are parsed, before anything further down the pipeline sees them.
+pub struct AttributeRenameTokenWriter<'a, W: WriteToken> {
+    map: Option<&'a AttributeMap>,
+    inner: W,
+}
+
+impl<'a, W: WriteToken> AttributeRenameTokenWriter<'a, W> {
+    /// Creates a token writer that renames attributes according to the map, or passes tokens on
+    /// as they are if there is none.
+    pub fn new(map: Option<&'a AttributeMap>, inner: W) -> AttributeRenameTokenWriter<'a, W> {
+        AttributeRenameTokenWriter{ map, inner }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+impl<W: WriteToken> WriteToken for AttributeRenameTokenWriter<'_, W> {
+    fn write_token(&mut self, token: Token) -> std::io::Result<()> {
+        if token.kind == TokenKind::AttributeType {
+            if let Some(name) = self.map.and_then(|map| map.get(token.segment)) {
+                return self.inner.write_token(Token{ segment: name, ..token });
+            }
+        }
+        self.inner.write_token(token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let mut map = DnMap::new();
+        map.move_to("dc=example, dc=com", "dc=example,dc=org")
+            .rename("cn=John Smith,ou=people,dc=example,dc=com", "uid=jsmith");
+        assert_eq!(map.rewrite("CN=john smith,ou=people,dc=example,dc=com"), "uid=jsmith,ou=people,dc=example,dc=org");
+        assert_eq!(map.rewrite("ou=people, dc=example,dc=com"), "ou=people,dc=example,dc=org");
+        assert!(matches!(map.rewrite("ou=people, dc=example,dc=net"), Cow::Borrowed("ou=people, dc=example,dc=net")));
+    }
+
+    #[test]
+    fn test_change_records() -> std::io::Result<()> {
+        let mut map = DnMap::new();
+        map.write_entry(&Entry::from([
+            ("dn", b"ou=people,dc=example".as_slice()),
+            ("changetype", b"moddn"),
+            ("newrdn", b"ou=staff"),
+            ("deleteoldrdn", b"1"),
+        ]))?;
+        map.write_entry(&Entry::from([
+            ("dn", b"cn=foo,ou=groups,dc=example".as_slice()),
+            ("changetype", b"modrdn"),
+            ("newrdn", b"cn=bar"),
+            ("newsuperior", b"ou=roles,dc=example"),
+        ]))?;
+        map.write_entry(&Entry::from([("dn", b"cn=baz,dc=example".as_slice()), ("changetype", b"add")]))?;
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let mut rewriter = DnRewriter::new(map, &mut entries);
+        rewriter.write_entry(&Entry::from([
+            ("dn", b"uid=jsmith,ou=people,dc=example".as_slice()),
+            ("manager", b"uid=boss,ou=people,dc=example"),
+            ("description", b"ou=people,dc=example"),
+        ]))?;
+        rewriter.write_entry(&Entry::from([("dn", b"cn=foo,ou=groups,dc=example".as_slice())]))?;
+        assert_eq!(entries[0].get_one_str("dn").as_deref(), Some("uid=jsmith,ou=staff,dc=example"));
+        assert_eq!(entries[0].get_one_str("manager").as_deref(), Some("uid=boss,ou=staff,dc=example"));
+        assert_eq!(entries[0].get_one_str("description").as_deref(), Some("ou=people,dc=example"));
+        assert_eq!(entries[1].get_one_str("dn").as_deref(), Some("cn=bar,ou=roles,dc=example"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_attribute_map() -> std::io::Result<()> {
+        let map = AttributeMap::parse("# AD to OpenLDAP\nmail = rfc822Mailbox\n\nsAMAccountName=uid\n")?;
+        assert_eq!(map.get("MAIL"), Some("rfc822Mailbox"));
+        assert_eq!(map.reverse().get("rfc822mailbox"), Some("mail"));
+        assert!(AttributeMap::parse("mail").is_err());
+        let entry = Entry::from([
+            ("dn", b"uid=jsmith,dc=example".as_slice()),
+            ("sAMAccountName", b"jsmith"),
+            ("uid", b"john"),
+            ("cn", b"John Smith"),
+        ]);
+        let renamed = map.rename_entry(&entry);
+        let values: Vec<_> = renamed.get_str("uid").collect();
+        assert_eq!(values, ["jsmith", "john"]);
+        assert_eq!(renamed.get_one_str("cn").as_deref(), Some("John Smith"));
+
+        let mut entries: Vec<OwnedEntry> = Vec::new();
+        let token_writer = AttributeRenameTokenWriter::new(Some(&map), crate::entry::EntryTokenWriter::new(&mut entries));
+        crate::bulk::BulkParser::new().write_tokens(&mut &b"dn: cn=a\nmail: a@example.com\n\n"[..], token_writer)?;
+        assert_eq!(entries[0].get_one_str("rfc822mailbox").as_deref(), Some("a@example.com"));
+        assert_eq!(entries[0].get_one("mail"), None);
+        Ok(())
+    }
+}
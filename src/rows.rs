@@ -0,0 +1,261 @@
+use crate::entry::OwnedEntry;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// The formats of tabular data that can be read as entries, one entry for each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowFormat {
+    /// CSV with a header row naming the attribute of each column.
+    Csv,
+    /// Tab-separated values with a header row, as written by lget.
+    Tsv,
+    /// A JSON object on each line, with strings or arrays of strings as values, as written by
+    /// lget -j.
+    Json,
+}
+
+impl RowFormat {
+    pub fn parse(name: &str) -> std::result::Result<RowFormat, &'static str> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Ok(RowFormat::Csv),
+            "tsv" => Ok(RowFormat::Tsv),
+            "json" | "jsonl" => Ok(RowFormat::Json),
+            _ => Err("data format must be csv, tsv or json"),
+        }
+    }
+
+    /// Guesses the format from the file extension.
+    pub fn from_path(path: &str) -> Option<RowFormat> {
+        let extension = path.rsplit_once('.')?.1;
+        RowFormat::parse(extension).ok()
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Reads each row of the input as an entry. In CSV and TSV, the attributes are named by the
+/// header row and empty cells give no value.
+pub fn read_rows<R: Read>(mut input: R, format: RowFormat) -> Result<Vec<OwnedEntry>> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    match format {
+        RowFormat::Csv => Ok(table_entries(parse_csv(&text)?)),
+        RowFormat::Tsv => Ok(table_entries(text.lines()
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .filter(|line| !line.is_empty())
+            .map(|line| line.split('\t').map(String::from).collect())
+            .collect())),
+        RowFormat::Json => text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| parse_json_object(line)
+                .map_err(|err| invalid(format!("line {}: {}", index + 1, err))))
+            .collect(),
+    }
+}
+
+fn table_entries(mut rows: Vec<Vec<String>>) -> Vec<OwnedEntry> {
+    if rows.is_empty() {
+        return Vec::new();
+    }
+    let header = rows.remove(0);
+    rows.into_iter()
+        .map(|row| {
+            let mut entry = OwnedEntry::default();
+            for (attr, value) in header.iter().zip(row.iter()).filter(|(_, value)| !value.is_empty()) {
+                entry.add_value(attr, value.as_bytes());
+            }
+            entry
+        })
+        .collect()
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {},
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            },
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(invalid(String::from("unterminated quoted CSV field")));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+// Parses a JSON object whose values are strings, numbers, booleans, null or arrays of those.
+fn parse_json_object(line: &str) -> std::result::Result<OwnedEntry, String> {
+    let mut entry = OwnedEntry::default();
+    let mut chars = line.chars().peekable();
+    expect(&mut chars, '{')?;
+    if skip_whitespace(&mut chars) == Some('}') {
+        chars.next();
+    } else {
+        loop {
+            skip_whitespace(&mut chars);
+            let attr = parse_json_string(&mut chars)?;
+            expect(&mut chars, ':')?;
+            if skip_whitespace(&mut chars) == Some('[') {
+                chars.next();
+                if skip_whitespace(&mut chars) == Some(']') {
+                    chars.next();
+                } else {
+                    loop {
+                        if let Some(value) = parse_json_scalar(&mut chars)? {
+                            entry.add_value(&attr, value.as_bytes());
+                        }
+                        match next_token(&mut chars) {
+                            Some(',') => continue,
+                            Some(']') => break,
+                            _ => return Err(String::from("expected ',' or ']' in array")),
+                        }
+                    }
+                }
+            } else if let Some(value) = parse_json_scalar(&mut chars)? {
+                entry.add_value(&attr, value.as_bytes());
+            }
+            match next_token(&mut chars) {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err(String::from("expected ',' or '}' in object")),
+            }
+        }
+    }
+    match next_token(&mut chars) {
+        None => Ok(entry),
+        Some(_) => Err(String::from("unexpected text after object")),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    chars.peek().copied()
+}
+
+fn next_token(chars: &mut Peekable<Chars>) -> Option<char> {
+    skip_whitespace(chars);
+    chars.next()
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> std::result::Result<(), String> {
+    match next_token(chars) {
+        Some(c) if c == expected => Ok(()),
+        _ => Err(format!("expected '{}'", expected)),
+    }
+}
+
+// Returns None for null, and numbers and booleans as they are written.
+fn parse_json_scalar(chars: &mut Peekable<Chars>) -> std::result::Result<Option<String>, String> {
+    match skip_whitespace(chars) {
+        Some('"') => parse_json_string(chars).map(Some),
+        Some(c) if c == '-' || c.is_ascii_alphanumeric() => {
+            let mut literal = String::new();
+            while let Some(c) = chars.next_if(|c| matches!(c, '-' | '+' | '.') || c.is_ascii_alphanumeric()) {
+                literal.push(c);
+            }
+            match literal.as_str() {
+                "null" => Ok(None),
+                "true" | "false" => Ok(Some(literal)),
+                _ if literal.parse::<f64>().is_ok() => Ok(Some(literal)),
+                _ => Err(format!("unexpected '{}'", literal)),
+            }
+        },
+        _ => Err(String::from("expected a value")),
+    }
+}
+
+fn parse_json_string(chars: &mut Peekable<Chars>) -> std::result::Result<String, String> {
+    expect(chars, '"')?;
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let unit = parse_hex4(chars)?;
+                    let c = if (0xd800..0xdc00).contains(&unit) {
+                        // a high surrogate, which must be followed by an escaped low surrogate
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return Err(String::from("unpaired surrogate in string"));
+                        }
+                        let low = parse_hex4(chars)?;
+                        char::decode_utf16([unit, low]).next().and_then(|c| c.ok())
+                    } else {
+                        char::from_u32(unit as u32)
+                    };
+                    s.push(c.ok_or("invalid escape in string")?);
+                },
+                _ => return Err(String::from("invalid escape in string")),
+            },
+            Some(c) => s.push(c),
+            None => return Err(String::from("unterminated string")),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> std::result::Result<u16, String> {
+    let hex: String = chars.take(4).collect();
+    u16::from_str_radix(&hex, 16).map_err(|_| String::from("invalid \\u escape in string"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn values(entry: &OwnedEntry, attr: &str) -> Vec<String> {
+        entry.get_str(attr).map(String::from).collect()
+    }
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let rows = read_rows("uid,cn,mail\nalice,\"Smith, Alice\",\nbob,\"Bob \"\"B\"\"\",bob@example.com\r\n".as_bytes(), RowFormat::Csv)?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(values(&rows[0], "cn"), ["Smith, Alice"]);
+        assert!(rows[0].get_one("mail").is_none());
+        assert_eq!(values(&rows[1], "cn"), ["Bob \"B\""]);
+        assert_eq!(values(&rows[1], "mail"), ["bob@example.com"]);
+
+        let rows = read_rows("uid\tcn\nalice\tAlice\n".as_bytes(), RowFormat::Tsv)?;
+        assert_eq!(values(&rows[0], "cn"), ["Alice"]);
+
+        let rows = read_rows("{\"uid\":[\"alice\"],\"mail\":[\"a@example.com\",\"b@example.com\"],\"n\":3,\"x\":null}\n{\"cn\":\"\\u00c5se \\ud83d\\ude00\"}\n".as_bytes(), RowFormat::Json)?;
+        assert_eq!(values(&rows[0], "mail"), ["a@example.com", "b@example.com"]);
+        assert_eq!(values(&rows[0], "n"), ["3"]);
+        assert!(rows[0].get_one("x").is_none());
+        assert_eq!(values(&rows[1], "cn"), ["Åse 😀"]);
+        assert!(read_rows("{\"uid\": }".as_bytes(), RowFormat::Json).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+use crate::entry::{Entry, OwnedEntry, WriteEntry};
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+
+/// Lowercase names of commonly used attributes that hold DNs, for when no schema is available.
+pub const DN_ATTRIBUTES: &[&str] = &[
+    "member",
+    "uniquemember",
+    "memberof",
+    "manager",
+    "owner",
+    "secretary",
+    "seealso",
+    "roleoccupant",
+];
+
+// The DN syntax and the Name and Optional UID syntax of uniqueMember
+pub(crate) const DN_SYNTAXES: &[&str] = &[
+    "1.3.6.1.4.1.1466.115.121.1.12",
+    "1.3.6.1.4.1.1466.115.121.1.34",
+];
+
+// Keywords of attribute type and object class descriptions that are not followed by a value
+const FLAGS: &[&str] = &[
+    "OBSOLETE",
+    "SINGLE-VALUE",
+    "COLLECTIVE",
+    "NO-USER-MODIFICATION",
+    "ABSTRACT",
+    "STRUCTURAL",
+    "AUXILIARY",
+];
+
+/// The parts of an attribute type description (RFC 4512, section 4.1.2) that tell what syntax
+/// its values have.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AttributeTypeDescription {
+    pub oid: String,
+    pub names: Vec<String>,
+    pub sup: Option<String>,
+    pub syntax: Option<String>, // without length bound
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DescriptionToken<'a> {
+    Open,
+    Close,
+    Quoted(&'a str),
+    Word(&'a str),
+}
+
+fn tokenize(description: &str) -> Option<Vec<DescriptionToken<'_>>> {
+    let mut tokens: Vec<DescriptionToken> = Vec::new();
+    let mut rest = description.trim_start();
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '(' => {
+                tokens.push(DescriptionToken::Open);
+                rest = &rest[1..];
+            },
+            ')' => {
+                tokens.push(DescriptionToken::Close);
+                rest = &rest[1..];
+            },
+            '\'' => {
+                let end = rest[1..].find('\'')? + 1;
+                tokens.push(DescriptionToken::Quoted(&rest[1..end]));
+                rest = &rest[end + 1..];
+            },
+            _ => {
+                let end = rest.find(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '\'')
+                    .unwrap_or(rest.len());
+                tokens.push(DescriptionToken::Word(&rest[..end]));
+                rest = &rest[end..];
+            },
+        }
+        rest = rest.trim_start();
+    }
+    Some(tokens)
+}
+
+// The keywords of a description with their values, in order
+type DescriptionFields<'a> = Vec<(&'a str, Vec<&'a str>)>;
+
+// Parses a description of the form `( OID KEYWORD VALUE ... )` into its OID and the values of
+// each keyword, where a value is a single word or quoted string or a parenthesized list of them.
+fn parse_description(description: &str) -> Option<(String, DescriptionFields<'_>)> {
+    let tokens = tokenize(description)?;
+    let mut tokens = tokens.into_iter();
+    if tokens.next()? != DescriptionToken::Open {
+        return None;
+    }
+    let oid = match tokens.next()? {
+        DescriptionToken::Word(oid) => oid.to_string(),
+        _ => return None,
+    };
+    let mut fields: DescriptionFields = Vec::new();
+    loop {
+        let keyword = match tokens.next()? {
+            DescriptionToken::Close => return Some((oid, fields)),
+            DescriptionToken::Word(keyword) => keyword,
+            _ => return None,
+        };
+        if FLAGS.contains(&keyword) {
+            continue;
+        }
+        let mut values: Vec<&str> = Vec::new();
+        match tokens.next()? {
+            DescriptionToken::Open => loop {
+                match tokens.next()? {
+                    DescriptionToken::Close => break,
+                    DescriptionToken::Word("$") => (),
+                    DescriptionToken::Word(value) | DescriptionToken::Quoted(value) => values.push(value),
+                    DescriptionToken::Open => return None,
+                }
+            },
+            DescriptionToken::Word(value) | DescriptionToken::Quoted(value) => values.push(value),
+            DescriptionToken::Close => return None,
+        }
+        fields.push((keyword, values));
+    }
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+impl AttributeTypeDescription {
+    /// Parses a value of the attributeTypes attribute of a subschema entry, such as
+    /// `( 2.5.4.31 NAME 'member' SUP distinguishedName )`.
+    pub fn parse(description: &str) -> Option<AttributeTypeDescription> {
+        let (oid, fields) = parse_description(description)?;
+        let mut parsed = AttributeTypeDescription{
+            oid,
+            names: Vec::new(),
+            sup: None,
+            syntax: None,
+        };
+        for (keyword, values) in fields {
+            match keyword {
+                "NAME" => parsed.names = to_strings(&values),
+                "SUP" => parsed.sup = values.first().map(|sup| sup.to_string()),
+                "SYNTAX" => parsed.syntax = values.first()
+                    .map(|syntax| syntax.split('{').next().unwrap_or(syntax).to_string()),
+                _ => (),
+            }
+        }
+        Some(parsed)
+    }
+}
+
+/// The parts of an object class description (RFC 4512, section 4.1.1) that tell which
+/// attributes entries of the class may have.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ObjectClassDescription {
+    pub oid: String,
+    pub names: Vec<String>,
+    pub sup: Vec<String>,
+    pub must: Vec<String>,
+    pub may: Vec<String>,
+}
+
+impl ObjectClassDescription {
+    /// Parses a value of the objectClasses attribute of a subschema entry, such as
+    /// `( 2.5.6.6 NAME 'person' SUP top STRUCTURAL MUST ( sn $ cn ) MAY userPassword )`.
+    pub fn parse(description: &str) -> Option<ObjectClassDescription> {
+        let (oid, fields) = parse_description(description)?;
+        let mut parsed = ObjectClassDescription{
+            oid,
+            names: Vec::new(),
+            sup: Vec::new(),
+            must: Vec::new(),
+            may: Vec::new(),
+        };
+        for (keyword, values) in fields {
+            match keyword {
+                "NAME" => parsed.names = to_strings(&values),
+                "SUP" => parsed.sup = to_strings(&values),
+                "MUST" => parsed.must = to_strings(&values),
+                "MAY" => parsed.may = to_strings(&values),
+                _ => (),
+            }
+        }
+        Some(parsed)
+    }
+}
+
+/// Returns the syntax OID of each attribute type by its lowercase names and OID, either given
+/// in its description or inherited from its supertypes.
+pub fn attribute_syntaxes(descriptions: &[AttributeTypeDescription]) -> HashMap<String, String> {
+    let mut by_name: HashMap<String, &AttributeTypeDescription> = HashMap::new();
+    for description in descriptions.iter() {
+        by_name.insert(description.oid.to_ascii_lowercase(), description);
+        for name in description.names.iter() {
+            by_name.insert(name.to_ascii_lowercase(), description);
+        }
+    }
+    let mut syntaxes: HashMap<String, String> = HashMap::new();
+    for description in descriptions.iter() {
+        let mut current = description;
+        // the depth limit guards against cycles in broken schemas
+        for _ in 0..16 {
+            if let Some(ref syntax) = current.syntax {
+                syntaxes.insert(description.oid.to_ascii_lowercase(), syntax.clone());
+                for name in description.names.iter() {
+                    syntaxes.insert(name.to_ascii_lowercase(), syntax.clone());
+                }
+                break;
+            }
+            match current.sup.as_ref().and_then(|sup| by_name.get(&sup.to_ascii_lowercase())) {
+                Some(sup) => current = sup,
+                None => break,
+            }
+        }
+    }
+    syntaxes
+}
+
+/// Returns the lowercase names and OIDs of the attribute types that hold DNs, either by having
+/// a DN syntax or by inheriting it from their supertypes.
+pub fn dn_attributes(descriptions: &[AttributeTypeDescription]) -> HashSet<String> {
+    attribute_syntaxes(descriptions).into_iter()
+        .filter(|(_, syntax)| DN_SYNTAXES.contains(&syntax.as_str()))
+        .map(|(attribute, _)| attribute)
+        .collect()
+}
+
+/// Returns the syntaxes of the attribute types according to the attributeTypes values of a
+/// subschema entry, as by `attribute_syntaxes`.
+pub fn attribute_syntaxes_from_schema(schema: &Entry) -> HashMap<String, String> {
+    let descriptions: Vec<AttributeTypeDescription> = schema.get_str("attributetypes")
+        .filter_map(|description| AttributeTypeDescription::parse(&description))
+        .collect();
+    attribute_syntaxes(&descriptions)
+}
+
+/// Returns the attribute types that hold DNs according to the attributeTypes values of a
+/// subschema entry, such as one read from cn=schema or cn=subschema.
+pub fn dn_attributes_from_schema(schema: &Entry) -> HashSet<String> {
+    let descriptions: Vec<AttributeTypeDescription> = schema.get_str("attributetypes")
+        .filter_map(|description| AttributeTypeDescription::parse(&description))
+        .collect();
+    dn_attributes(&descriptions)
+}
+
+/// Trims entries to the attributes that a set of object classes allow, such as for importing
+/// entries from a rich source into a stricter directory. The DN is always kept, and of the
+/// objectClass values, only the given classes and their superclasses are kept.
+#[derive(Debug, Clone)]
+pub struct Projection {
+    classes: HashSet<String>, // lowercase names and OIDs, including superclasses
+    attributes: HashSet<String>, // lowercase names and OIDs
+}
+
+impl Projection {
+    /// Creates a projection onto the given object classes according to the objectClasses and
+    /// attributeTypes values of a subschema entry. Attributes are also allowed by their other
+    /// names and OIDs in the attributeTypes values.
+    pub fn from_schema<S: AsRef<str>>(schema: &Entry, classes: &[S]) -> std::io::Result<Projection> {
+        let descriptions: Vec<ObjectClassDescription> = schema.get_str("objectclasses")
+            .filter_map(|description| ObjectClassDescription::parse(&description))
+            .collect();
+        let mut by_name: HashMap<String, &ObjectClassDescription> = HashMap::new();
+        for description in descriptions.iter() {
+            by_name.insert(description.oid.to_ascii_lowercase(), description);
+            for name in description.names.iter() {
+                by_name.insert(name.to_ascii_lowercase(), description);
+            }
+        }
+        let mut projection = Projection{ classes: HashSet::new(), attributes: HashSet::new() };
+        let mut pending: Vec<String> = Vec::new();
+        for class in classes.iter() {
+            let class = class.as_ref().to_ascii_lowercase();
+            if !by_name.contains_key(&class) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("object class {} is not in the schema", class)));
+            }
+            pending.push(class);
+        }
+        while let Some(class) = pending.pop() {
+            let Some(description) = by_name.get(&class) else {
+                continue;
+            };
+            if !projection.classes.insert(description.oid.to_ascii_lowercase()) {
+                continue;
+            }
+            projection.classes.extend(description.names.iter().map(|name| name.to_ascii_lowercase()));
+            let attributes = description.must.iter().chain(description.may.iter());
+            projection.attributes.extend(attributes.map(|attr| attr.to_ascii_lowercase()));
+            pending.extend(description.sup.iter().map(|sup| sup.to_ascii_lowercase()));
+        }
+        // an attribute allowed by one of its names is allowed by all of them
+        for description in schema.get_str("attributetypes").filter_map(|description| AttributeTypeDescription::parse(&description)) {
+            let mut aliases = description.names.iter().map(|name| name.to_ascii_lowercase())
+                .chain(std::iter::once(description.oid.to_ascii_lowercase()));
+            if aliases.clone().any(|alias| projection.attributes.contains(&alias)) {
+                projection.attributes.extend(&mut aliases);
+            }
+        }
+        Ok(projection)
+    }
+
+    pub fn allows(&self, attr: &str) -> bool {
+        let attr = attr.to_ascii_lowercase();
+        attr == "dn" || self.attributes.contains(&attr)
+    }
+
+    pub fn project(&self, entry: &Entry) -> OwnedEntry {
+        let mut projected = OwnedEntry::default();
+        for attr in entry.attributes().filter(|attr| self.allows(attr.lowercase)) {
+            for (value, base64) in entry.get_with_encoding(attr.lowercase) {
+                if attr.lowercase == "objectclass" && !self.classes.contains(&String::from_utf8_lossy(value).to_ascii_lowercase()) {
+                    continue;
+                }
+                projected.add_value_with_encoding(attr.name, value, base64);
+            }
+        }
+        projected
+    }
+}
+
+/// Writes entries to the destination trimmed according to a Projection.
+pub struct Projector<W: WriteEntry> {
+    projection: Projection,
+    dest: W,
+}
+
+impl<W: WriteEntry> Projector<W> {
+    pub fn new(projection: Projection, dest: W) -> Projector<W> {
+        Projector{ projection, dest }
+    }
+}
+
+impl<W: WriteEntry> WriteEntry for Projector<W> {
+    fn write_entry(&mut self, entry: &Entry) -> std::io::Result<()> {
+        self.dest.write_entry(&self.projection.project(entry))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let description = AttributeTypeDescription::parse(
+            "( 2.5.4.3 NAME ( 'cn' 'commonName' ) DESC 'RFC4519: common name(s) for which the entity is known by' SUP name )"
+        );
+        assert_eq!(description, Some(AttributeTypeDescription{
+            oid: String::from("2.5.4.3"),
+            names: vec![String::from("cn"), String::from("commonName")],
+            sup: Some(String::from("name")),
+            syntax: None,
+        }));
+        let description = AttributeTypeDescription::parse(
+            "( 1.3.6.1.4.1.4203.1.3.5 NAME 'supportedFeatures' EQUALITY objectIdentifierMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.38{128} USAGE dSAOperation )"
+        );
+        assert_eq!(description.and_then(|description| description.syntax).as_deref(), Some("1.3.6.1.4.1.1466.115.121.1.38"));
+        assert_eq!(AttributeTypeDescription::parse("( 2.5.4.3 NAME 'cn"), None);
+    }
+
+    #[test]
+    fn test_dn_attributes() {
+        let schema = Entry::from([
+            ("dn", b"cn=schema".as_slice()),
+            ("attributeTypes", b"( 2.5.4.49 NAME 'distinguishedName' EQUALITY distinguishedNameMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.12 )"),
+            ("attributeTypes", b"( 2.5.4.31 NAME 'member' SUP distinguishedName )"),
+            ("attributeTypes", b"( 2.5.4.41 NAME 'name' SYNTAX 1.3.6.1.4.1.1466.115.121.1.15{32768} )"),
+            ("attributeTypes", b"( 2.5.4.3 NAME ( 'cn' 'commonName' ) SUP name )"),
+        ]);
+        let attributes = dn_attributes_from_schema(&schema);
+        let mut attributes: Vec<&str> = attributes.iter().map(String::as_str).collect();
+        attributes.sort();
+        assert_eq!(attributes, ["2.5.4.31", "2.5.4.49", "distinguishedname", "member"]);
+    }
+
+    #[test]
+    fn test_projection() -> std::io::Result<()> {
+        let schema = Entry::from([
+            ("dn", b"cn=schema".as_slice()),
+            ("attributeTypes", b"( 2.5.4.3 NAME ( 'cn' 'commonName' ) SUP name )"),
+            ("objectClasses", b"( 2.5.6.0 NAME 'top' ABSTRACT MUST objectClass )"),
+            ("objectClasses", b"( 2.5.6.6 NAME 'person' SUP top STRUCTURAL MUST ( sn $ cn ) MAY ( userPassword $ telephoneNumber ) )"),
+            ("objectClasses", b"( 1.2.840.113556.1.5.9 NAME 'user' SUP person STRUCTURAL MAY sAMAccountName )"),
+        ]);
+        let projection = Projection::from_schema(&schema, &["person"])?;
+        let entry = Entry::from([
+            ("dn", b"cn=jsmith,dc=example".as_slice()),
+            ("objectClass", b"top"),
+            ("objectClass", b"person"),
+            ("objectClass", b"user"),
+            ("commonName", b"jsmith"),
+            ("sn", b"Smith"),
+            ("sAMAccountName", b"jsmith"),
+        ]);
+        let projected = projection.project(&entry);
+        let attributes: Vec<&str> = projected.attributes().map(|attr| attr.name).collect();
+        assert_eq!(attributes, ["dn", "objectClass", "commonName", "sn"]);
+        let classes: Vec<_> = projected.get_str("objectclass").collect();
+        assert_eq!(classes, ["top", "person"]);
+        assert!(Projection::from_schema(&schema, &["inetOrgPerson"]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+/// The bytes that terminate each record written by the TSV, CSV, JSON and template writers, such as
+/// a newline, or a null byte for `xargs -0`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordSeparator {
+    Byte(u8),
+    CrLf,
+}
+
+impl RecordSeparator {
+    pub const LF: RecordSeparator = RecordSeparator::Byte(b'\n');
+    pub const NUL: RecordSeparator = RecordSeparator::Byte(0x00);
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            RecordSeparator::Byte(byte) => std::slice::from_ref(byte),
+            RecordSeparator::CrLf => b"\r\n",
+        }
+    }
+
+    /// The last byte of the separator, which ends a record when reading the output back.
+    pub fn last_byte(&self) -> u8 {
+        match self {
+            RecordSeparator::Byte(byte) => *byte,
+            RecordSeparator::CrLf => b'\n',
+        }
+    }
+}
+
+impl Default for RecordSeparator {
+    fn default() -> RecordSeparator {
+        RecordSeparator::LF
+    }
+}
+
+impl From<u8> for RecordSeparator {
+    fn from(byte: u8) -> RecordSeparator {
+        RecordSeparator::Byte(byte)
+    }
+}
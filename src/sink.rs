@@ -0,0 +1,93 @@
+//! The error type and output trait of the parsing layers (`CrStripper`, `Unfolder`, `Lexer` and
+//! the base64 codecs), which only depend on `core` and `alloc` so that they can be used without
+//! the standard library, such as in WASM or embedded targets. With the `std` feature, which is
+//! enabled by default, the error type is `std::io::Error` and any `std::io::Write` is a `Sink`,
+//! so that the layers plug into readers and writers as before.
+
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use crate::loc::LocError;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, Result};
+
+/// An error of the parsing layers when used without the standard library.
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub enum Error {
+    Loc(LocError),
+    InvalidData(String),
+    Other(String),
+}
+
+#[cfg(not(feature = "std"))]
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Loc(err) => err.fmt(f),
+            Error::InvalidData(message) | Error::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl From<LocError> for Error {
+    fn from(err: LocError) -> Error {
+        Error::Loc(err)
+    }
+}
+
+/// An error about malformed input, such as an invalid base64 character.
+#[cfg(feature = "std")]
+pub fn invalid_data(message: String) -> Error {
+    Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn invalid_data(message: String) -> Error {
+    Error::InvalidData(message)
+}
+
+#[cfg(feature = "std")]
+pub fn other<M: Into<String>>(message: M) -> Error {
+    Error::other(message.into())
+}
+
+#[cfg(not(feature = "std"))]
+pub fn other<M: Into<String>>(message: M) -> Error {
+    Error::Other(message.into())
+}
+
+/// Where the parsing layers write their output bytes.
+pub trait Sink {
+    fn put(&mut self, buf: &[u8]) -> Result<()>;
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Sink for W {
+    fn put(&mut self, buf: &[u8]) -> Result<()> {
+        self.write_all(buf)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Sink for Vec<u8> {
+    fn put(&mut self, buf: &[u8]) -> Result<()> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<S: Sink> Sink for &mut S {
+    fn put(&mut self, buf: &[u8]) -> Result<()> {
+        (**self).put(buf)
+    }
+}
@@ -1,12 +1,8 @@
-use std::io::{
-    Result,
-    Error,
-    ErrorKind,
-};
+use alloc::format;
+use alloc::vec::Vec;
+use crate::sink::{ Result, other };
 use crate::loc::{ Loc, LocWrite };
 
-const MAX_PREFIX: usize = 4;
-
 #[derive(Debug, Eq, PartialEq)]
 pub enum SkipToken {
     Byte(u8),
@@ -23,18 +19,19 @@ pub struct Skipper<'a, LW: LocWrite>  {
     state: SkipState,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[derive(Clone, Debug, Eq, PartialEq, Default)]
 pub enum SkipState {
     #[default]
     Writing,
     SkippingFrom(Loc, usize),
-    SkippingWithPrefix(Loc, [u8;MAX_PREFIX], usize),
+    // Data that may be skipped, carried over from previous buffers
+    SkippingWithPrefix(Loc, Vec<u8>),
 }
 
 impl SkipState {
     pub fn write_remainder<LW: LocWrite>(&self, dest: &mut LW) -> Result<()> {
-        if let SkipState::SkippingWithPrefix(loc, prefixbuf, prefixlen) = *self {
-            dest.loc_write(loc, &prefixbuf[..prefixlen])?;
+        if let SkipState::SkippingWithPrefix(loc, prefix) = self {
+            dest.loc_write(*loc, prefix)?;
         }
         Ok(())
     }
@@ -76,28 +73,19 @@ impl<'a, LW: LocWrite> Skipper<'a, LW> {
     pub fn shift(&mut self) -> Result<Option<u8>> {
         let lookahead = match self.lookahead() {
             None => {
-                return Err(Error::new(ErrorKind::Other, "call to .shift() after reaching end of buffer"));
+                return Err(other("call to .shift() after reaching end of buffer"));
             },
             Some(c) => c,
         };
-        match self.state {
-            SkipState::SkippingFrom(_, offset) => if self.pos + 1 - offset > MAX_PREFIX {
-                return Err(Error::new(ErrorKind::Other, "skipped data exceeds maximum"));
-            },
-            SkipState::SkippingWithPrefix(_, _, prefix_length) => if prefix_length + self.pos + 1 > MAX_PREFIX {
-                return Err(Error::new(ErrorKind::Other, "skipped data exceeds maximum"));
-            },
-            SkipState::Writing => (),
-        }
         self.loc = self.loc.after(lookahead);
         self.pos += 1;
         if self.lookahead().is_none() {
-            match self.state {
+            match &self.state {
                 SkipState::Writing => {
                     self.inner.loc_write(self.write_from_loc, &self.buf[self.write_from..])?;
                 },
                 SkipState::SkippingFrom(_, write_until) => {
-                    self.inner.loc_write(self.write_from_loc, &self.buf[self.write_from..write_until])?;
+                    self.inner.loc_write(self.write_from_loc, &self.buf[self.write_from..*write_until])?;
                 },
                 SkipState::SkippingWithPrefix(..) => {},
             }
@@ -107,21 +95,21 @@ impl<'a, LW: LocWrite> Skipper<'a, LW> {
 
     pub fn begin_skip(&mut self) -> Result<()> {
         if self.state != SkipState::Writing {
-            return Err(Error::new(ErrorKind::Other, format!("call to .begin_skip() in state {:?}", self.state)));
+            return Err(other(format!("call to .begin_skip() in state {:?}", self.state)));
         }
         self.state = SkipState::SkippingFrom(self.loc, self.pos);
         Ok(())
     }
 
     pub fn end_skip(&mut self) -> Result<()> {
-        match self.state {
+        match &self.state {
             SkipState::SkippingFrom(_, write_until) => {
                 if self.lookahead().is_some() {
-                    self.inner.loc_write(self.write_from_loc, &self.buf[self.write_from..write_until])?;
+                    self.inner.loc_write(self.write_from_loc, &self.buf[self.write_from..*write_until])?;
                 }
             },
             SkipState::SkippingWithPrefix(..) => {},
-            SkipState::Writing => return Err(Error::new(ErrorKind::Other, "call to .end_skip() while not skipping")),
+            SkipState::Writing => return Err(other("call to .end_skip() while not skipping")),
         }
         self.write_from_loc = self.loc;
         self.write_from = self.pos;
@@ -130,8 +118,8 @@ impl<'a, LW: LocWrite> Skipper<'a, LW> {
     }
 
     pub fn cancel_skip(&mut self) -> Result<()> {
-        if let SkipState::SkippingWithPrefix(loc, prefix_array, prefix_len) = self.state {
-            self.inner.loc_write(loc, &prefix_array[..prefix_len])?;
+        if let SkipState::SkippingWithPrefix(loc, prefix) = &self.state {
+            self.inner.loc_write(*loc, prefix)?;
         }
         self.state = SkipState::Writing;
         Ok(())
@@ -140,21 +128,13 @@ impl<'a, LW: LocWrite> Skipper<'a, LW> {
     pub fn save_state(self) -> SkipState {
         match self.state {
             SkipState::SkippingFrom(loc, offset) => {
-                let mut prefix_array = [0u8; 4];
-                let prefix_slice = &self.buf[offset..];
-                for (i, c) in prefix_slice.iter().copied().enumerate() {
-                    prefix_array[i] = c;
-                }
-                SkipState::SkippingWithPrefix(loc, prefix_array, prefix_slice.len())
+                SkipState::SkippingWithPrefix(loc, self.buf[offset..].to_vec())
             },
-            SkipState::SkippingWithPrefix(loc, prefix_array, prefix_len) => {
-                let mut new_prefix_array = [0u8; 4];
-                for (i,c) in prefix_array[..prefix_len].iter().chain(self.buf.iter()).copied().enumerate() {
-                    new_prefix_array[i] = c;
-                }
-                SkipState::SkippingWithPrefix(loc, new_prefix_array, prefix_len + self.buf.len())
+            SkipState::SkippingWithPrefix(loc, mut prefix) => {
+                prefix.extend_from_slice(self.buf);
+                SkipState::SkippingWithPrefix(loc, prefix)
             },
-            SkipState::Writing => self.state,
+            SkipState::Writing => SkipState::Writing,
         }
     }
 
@@ -249,6 +229,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_long_skip() -> Result<()> {
+        let mut writes = LocWrites::new();
+        let mut skipper = Skipper::new(&mut writes, Loc::default(), b"ab");
+        skipper.shift()?;
+        skipper.begin_skip()?;
+        skipper.shift()?;
+        let mut saved_state = skipper.save_state();
+        let mut loc = b"ab".iter().copied().fold(Loc::default(), |l, c| l.after(c));
+        for _ in 0..3 {
+            skipper = Skipper::new_with_state(&mut writes, loc, b"cde", saved_state);
+            while skipper.lookahead().is_some() {
+                skipper.shift()?;
+            }
+            saved_state = skipper.save_state();
+            loc = b"cde".iter().copied().fold(loc, |l, c| l.after(c));
+        }
+        skipper = Skipper::new_with_state(&mut writes, loc, b"f", saved_state);
+        skipper.cancel_skip()?;
+        skipper.shift()?;
+        assert_eq!(writes.len(), 3);
+        assert_eq!(writes[0].1, "a");
+        assert_eq!(writes[1].1, "bcdecdecde");
+        assert_eq!(writes[1].0, Loc{ offset: 1, line: 1, column: 2});
+        assert_eq!(writes[2].1, "f");
+        Ok(())
+    }
+
     #[test]
     fn test_e() -> Result<()> {
         let mut writes = LocWrites::new();
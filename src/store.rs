@@ -0,0 +1,67 @@
+use crate::dn::normalize;
+use crate::entry::{Entry, OwnedEntry, WriteEntry};
+use std::collections::HashMap;
+use std::io::Result;
+
+/// Entries kept in memory in the order they were written, which can be looked up by DN
+/// regardless of case and spacing, for tools that need to follow references between entries,
+/// such as from groups to their members.
+#[derive(Default)]
+pub struct EntryStore {
+    entries: Vec<OwnedEntry>,
+    index: HashMap<String, usize>, // by normalized DN
+}
+
+impl EntryStore {
+    pub fn new() -> EntryStore {
+        EntryStore::default()
+    }
+
+    /// Returns the entry with the DN. If there are several, the last one written is returned.
+    pub fn get(&self, dn: &str) -> Option<&OwnedEntry> {
+        self.index.get(&normalize(dn)).map(|index| &self.entries[*index])
+    }
+
+    pub fn get_mut(&mut self, dn: &str) -> Option<&mut OwnedEntry> {
+        self.index.get(&normalize(dn)).map(|index| &mut self.entries[*index])
+    }
+
+    /// Iterates over the entries in the order they were written, including entries without a DN.
+    pub fn iter(&self) -> impl Iterator<Item = &OwnedEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl WriteEntry for EntryStore {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        if let Some(dn) = entry.get_one_str("dn") {
+            self.index.insert(normalize(&dn), self.entries.len());
+        }
+        self.entries.push(entry.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bulk::parse_entries;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let mut store = EntryStore::new();
+        parse_entries(b"dn: cn=foo, dc=example\ncn: foo\n\ndn: cn=bar,dc=example\ncn: bar\n", &mut store)?;
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("CN=Foo,DC=example").and_then(|entry| entry.get_one_str("cn")).as_deref(), Some("foo"));
+        assert!(store.get("cn=baz,dc=example").is_none());
+        Ok(())
+    }
+}
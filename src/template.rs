@@ -0,0 +1,216 @@
+use std::io::{
+    Write,
+    Result,
+};
+use crate::cartesian::cartesian_product;
+use crate::attrspec::AttrSpec;
+use crate::separator::RecordSeparator;
+use crate::entry::{
+    Entry,
+    EntryValue,
+    WriteEntry,
+};
+
+enum TemplatePart {
+    Literal(String),
+    Placeholder(usize), // index into attrspecs
+}
+
+pub struct Template {
+    parts: Vec<TemplatePart>,
+    attrspecs: Vec<AttrSpec>,
+}
+
+// Finds the brace that ends the placeholder starting at the brace at start. Braces in the
+// placeholder must be balanced, so that `{mail:-{none}}` has the default `{none}`, except in
+// double-quoted attribute names. An unbalanced brace in a default is written `\7b` or `\7d`.
+fn placeholder_end(input: &str, start: usize) -> Option<usize> {
+    let mut depth: usize = 0;
+    let mut quoted = false;
+    let mut chars = input[start + 1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quoted => {
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            '{' if !quoted => depth += 1,
+            '}' if !quoted && depth == 0 => return Some(start + 1 + i),
+            '}' if !quoted => depth -= 1,
+            _ => {},
+        }
+    }
+    None
+}
+
+impl Template {
+    /// Parses templates such as `uid={uid} mail={mail:-<none>}`, where each placeholder is an
+    /// attribute specification. Literal braces are written as `{{` and `}}`. Braces in a
+    /// placeholder must be balanced, as in `{mail:-{none}}`; an unbalanced brace in a default is
+    /// written as `\7b` or `\7d`, as other bytes are.
+    pub fn parse(input: &str) -> std::io::Result<Template> {
+        let mut parts: Vec<TemplatePart> = Vec::new();
+        let mut attrspecs: Vec<AttrSpec> = Vec::new();
+        let mut literal = String::new();
+        let mut chars = input.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|(_, c)| *c) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek().map(|(_, c)| *c) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let end = match placeholder_end(input, i) {
+                        Some(end) => end,
+                        None => return Err(std::io::Error::other(
+                            format!("Failed to parse template '{}': unterminated placeholder starting at '{}'", input, &input[i..]),
+                        )),
+                    };
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    attrspecs.push(AttrSpec::parse(&input[i + 1..end])?);
+                    parts.push(TemplatePart::Placeholder(attrspecs.len() - 1));
+                    while chars.peek().map(|(j, _)| *j <= end).unwrap_or(false) {
+                        chars.next();
+                    }
+                },
+                '}' => return Err(std::io::Error::other(
+                    format!("Failed to parse template '{}': unmatched '}}' at '{}'", input, &input[i..]),
+                )),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(Template{
+            parts,
+            attrspecs,
+        })
+    }
+
+    pub fn attrspecs(&self) -> &[AttrSpec] {
+        &self.attrspecs
+    }
+
+    /// Fills in the placeholders with the values of the entry. A multi-valued attribute gives a
+    /// result for each of its values, and several of them a result for each combination of their
+    /// values, in the order of the values. There are no results if an attribute lacks values and
+    /// has no default, and a single one if there are no placeholders.
+    pub fn render(&self, entry: &Entry) -> Result<Vec<Vec<u8>>> {
+        if self.attrspecs.is_empty() {
+            return Ok(vec![self.parts.iter()
+                .flat_map(|part| match part {
+                    TemplatePart::Literal(literal) => literal.as_bytes(),
+                    TemplatePart::Placeholder(_) => &[],
+                })
+                .copied()
+                .collect()]);
+        }
+        let attrvalues: Vec<Vec<EntryValue>> = self.attrspecs.iter()
+            .map(|attrspec| attrspec.entry_values(entry).map(|values| values.into_owned()))
+            .collect::<Result<_>>()?;
+        Ok(cartesian_product(&attrvalues)
+            .map(|record| {
+                let mut rendered: Vec<u8> = Vec::new();
+                for part in self.parts.iter() {
+                    match part {
+                        TemplatePart::Literal(literal) => rendered.extend_from_slice(literal.as_bytes()),
+                        TemplatePart::Placeholder(index) => rendered.extend_from_slice(record[*index]),
+                    }
+                }
+                rendered
+            })
+            .collect())
+    }
+}
+
+pub struct TemplateEntryWriter<W: Write> {
+    template: Template,
+    dest: W,
+    record_separator: RecordSeparator,
+}
+
+impl<W: Write> TemplateEntryWriter<W> {
+    pub fn new(template: Template, dest: W) -> TemplateEntryWriter<W> {
+        TemplateEntryWriter {
+            template,
+            dest,
+            record_separator: RecordSeparator::default(),
+        }
+    }
+
+    pub fn set_record_separator<S: Into<RecordSeparator>>(&mut self, record_separator: S) -> &mut Self {
+        self.record_separator = record_separator.into();
+        self
+    }
+}
+
+impl<W: Write> WriteEntry for TemplateEntryWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        for rendered in self.template.render(entry)? {
+            self.dest.write_all(&rendered)?;
+            self.dest.write_all(self.record_separator.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() -> Result<()> {
+        let template = Template::parse("uid={uid} mail={mail:-<none>} {{literal}}")?;
+        let mut output: Vec<u8> = Vec::new();
+        let mut template_entry_writer = TemplateEntryWriter::new(template, &mut output);
+        template_entry_writer.write_entry(&Entry::from([
+            ("uid", b"foo".as_slice()),
+        ]))?;
+        assert_eq!(String::from_utf8_lossy(&output), "uid=foo mail=<none> {literal}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let entry = Entry::from([("mail", b"a@example.com".as_slice()), ("mail", b"b@example.com".as_slice())]);
+        assert_eq!(Template::parse("<{mail}>")?.render(&entry)?, [b"<a@example.com>".to_vec(), b"<b@example.com>".to_vec()]);
+        assert_eq!(Template::parse("{{top}}")?.render(&entry)?, [b"{top}".to_vec()]);
+        assert!(Template::parse("{sn}")?.render(&entry)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_multi_valued() -> Result<()> {
+        let entry = Entry::from([("cn", b"a".as_slice()), ("cn", b"b"), ("mail", b"1"), ("mail", b"2")]);
+        let rendered = Template::parse("{cn}:{mail}")?.render(&entry)?;
+        assert_eq!(rendered, [b"a:1".to_vec(), b"a:2".to_vec(), b"b:1".to_vec(), b"b:2".to_vec()]);
+        let rendered = Template::parse("{cn}:{cn}")?.render(&entry)?;
+        assert_eq!(rendered.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_braces_in_default() -> Result<()> {
+        let entry = Entry::from([("uid", b"foo".as_slice())]);
+        assert_eq!(Template::parse("{mail:-{none}}!")?.render(&entry)?, [b"{none}!".to_vec()]);
+        assert_eq!(Template::parse("{mail:-{{}{}}}")?.render(&entry)?, [b"{{}{}}".to_vec()]);
+        assert_eq!(Template::parse("{mail:-\\7d}{uid}")?.render(&entry)?, [b"}foo".to_vec()]);
+        assert_eq!(Template::parse("{mail:-\\7b}")?.render(&entry)?, [b"{".to_vec()]);
+        assert!(Template::parse("{mail:-{none}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(Template::parse("{uid").is_err());
+        assert!(Template::parse("uid}").is_err());
+        assert!(Template::parse("{#}").is_err());
+    }
+}
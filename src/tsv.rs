@@ -1,9 +1,12 @@
 use std::io::{
+    IoSlice,
     Write,
     Result,
 };
 use crate::cartesian::cartesian_product;
+use crate::output::write_all_vectored;
 use crate::attrspec::AttrSpec;
+use crate::separator::RecordSeparator;
 use crate::entry::{
     Entry,
     EntryValue,
@@ -13,7 +16,7 @@ use crate::entry::{
 pub struct TsvEntryWriter<W: Write> {
     attrspecs: Vec<AttrSpec>,
     dest: W,
-    record_separator: u8,
+    record_separator: RecordSeparator,
 }
 
 impl<W: Write> TsvEntryWriter<W> {
@@ -22,12 +25,12 @@ impl<W: Write> TsvEntryWriter<W> {
         TsvEntryWriter {
             attrspecs,
             dest,
-            record_separator: b'\n',
+            record_separator: RecordSeparator::default(),
         }
     }
 
-    pub fn set_record_separator(&mut self, record_separator: u8) -> &mut Self {
-        self.record_separator = record_separator;
+    pub fn set_record_separator<S: Into<RecordSeparator>>(&mut self, record_separator: S) -> &mut Self {
+        self.record_separator = record_separator.into();
         self
     }
 }
@@ -35,16 +38,21 @@ impl<W: Write> TsvEntryWriter<W> {
 impl<W: Write> WriteEntry for TsvEntryWriter<W> {
     fn write_entry(&mut self, entry: &Entry) -> Result<()> {
         let attrvalues: Vec<Vec<EntryValue>> = self.attrspecs.iter()
-            .map(|attrspec| attrspec.filter_values(entry.get(&attrspec.attribute_lowercase)).into_owned())
-            .collect();
+            .map(|attrspec| attrspec.entry_values(entry).map(|values| values.into_owned()))
+            .collect::<Result<_>>()?;
+        // each record is written in one vectored write, rather than a write for each value and
+        // separator
+        let mut slices: Vec<IoSlice> = Vec::with_capacity(2 * self.attrspecs.len());
         for record in cartesian_product(&attrvalues) {
             for (i, value) in record.iter().enumerate() {
                 if i != 0 {
-                    self.dest.write_all(b"\t")?;
+                    slices.push(IoSlice::new(b"\t"));
                 }
-                self.dest.write_all(value)?;
+                slices.push(IoSlice::new(value));
             }
-            self.dest.write_all(&[self.record_separator])?;
+            slices.push(IoSlice::new(self.record_separator.as_bytes()));
+            write_all_vectored(&mut self.dest, &mut slices)?;
+            slices.clear();
         }
         Ok(())
     }
@@ -1,4 +1,6 @@
-use std::io::Result;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::sink::Result;
 use crate::loc::{ Loc, LocWrite };
 use crate::skip::{ Skipper, SkipState };
 
@@ -12,6 +14,7 @@ pub struct Unfolder<LW: LocWrite> {
     inner: LW,
     state: State,
     skipstate: SkipState,
+    continuation_chars: Vec<u8>,
 }
 
 impl<LW: LocWrite> Unfolder<LW> {
@@ -20,14 +23,25 @@ impl<LW: LocWrite> Unfolder<LW> {
             inner,
             state: State::Text,
             skipstate: SkipState::default(),
+            continuation_chars: vec![b' '],
         }
     }
 
+    /// Sets the characters that mark a line as the continuation of the previous line. RFC 2849
+    /// only allows a space, but some producers fold lines with a tab.
+    pub fn set_continuation_chars(&mut self, continuation_chars: &[u8]) -> &mut Self {
+        self.continuation_chars = continuation_chars.to_vec();
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut LW {
+        &mut self.inner
+    }
 }
 
 impl<LW: LocWrite> LocWrite for Unfolder<LW> {
     fn loc_write(&mut self, loc: Loc, buf: &[u8]) -> Result<usize> {
-        let mut skipper = Skipper::new_with_state(&mut self.inner, loc, buf, self.skipstate);
+        let mut skipper = Skipper::new_with_state(&mut self.inner, loc, buf, core::mem::take(&mut self.skipstate));
         while let Some(c) = skipper.lookahead() {
             self.state = match (self.state, c) {
                 (State::Text, b'\n') => {
@@ -39,13 +53,14 @@ impl<LW: LocWrite> LocWrite for Unfolder<LW> {
                     skipper.shift()?;
                     State::Text
                 },
-                (State::Newline, b' ') => {
+                (State::Newline, c) if self.continuation_chars.contains(&c) => {
                     skipper.shift()?;
                     skipper.end_skip()?;
                     State::Text
                 },
                 (State::Newline, b'\n') => {
                     skipper.cancel_skip()?;
+                    skipper.begin_skip()?;
                     skipper.shift()?;
                     State::Newline
                 }
@@ -164,6 +179,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    pub fn test_j() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut unfolder = Unfolder::new(LocWriteWrapper::new(&mut buf));
+        unfolder.loc_write(Loc::default(), b"a\n\n b")?;
+        assert_eq!(String::from_utf8_lossy(&buf[..]), "a\nb");
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_tab() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut unfolder = Unfolder::new(LocWriteWrapper::new(&mut buf));
+        unfolder.set_continuation_chars(b" \t");
+        unfolder.loc_write(Loc::default(), b"a\n\tb\n c\n\td")?;
+        assert_eq!(String::from_utf8_lossy(&buf[..]), "abcd");
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_no_tab() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut unfolder = Unfolder::new(LocWriteWrapper::new(&mut buf));
+        unfolder.loc_write(Loc::default(), b"a\n\tb")?;
+        assert_eq!(String::from_utf8_lossy(&buf[..]), "a\n\tb");
+        Ok(())
+    }
+
     #[test]
     pub fn test_loc() -> Result<()> {
         let mut writes = LocWrites::new();
@@ -180,7 +223,7 @@ mod test {
         let mut bufwriter = BufWriter::with_capacity(256, &mut buf);
         let mut unfolder = Unfolder::new(LocWriteWrapper::new(&mut bufwriter));
         let mut writer = WriteLocWrapper::new(&mut unfolder);
-        writer.write(b"foo\n")?;
+        writer.write_all(b"foo\n")?;
         writer.flush()?;
         (_, _) = bufwriter.into_parts(); // drop but dont flush
         assert_eq!(String::from_utf8_lossy(&buf[..]), "foo\n");
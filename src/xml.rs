@@ -0,0 +1,141 @@
+//! Lightweight reformatting of XML values, such as the DirXML-ConfigValues of eDirectory, for
+//! reviewing them. The XML is not validated beyond what is needed to find the markup, and the
+//! functions return None for values they can't make sense of, which are then best left as they
+//! are.
+
+#[derive(Debug, PartialEq)]
+enum Node<'a> {
+    Open(&'a str),  // a start tag
+    Close(&'a str), // an end tag
+    Other(&'a str), // an empty-element tag, comment, processing instruction or declaration
+    Text(&'a str),  // character data, including CDATA sections
+}
+
+fn tokenize(xml: &str) -> Option<Vec<Node<'_>>> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut rest = xml;
+    let mut depth: usize = 0;
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            nodes.push(Node::Text(&rest[..end]));
+            rest = &rest[end..];
+            continue;
+        }
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->")? + 3
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>")? + 3
+        } else if rest.starts_with("<?") {
+            rest.find("?>")? + 2
+        } else {
+            tag_end(rest)?
+        };
+        let markup = &rest[..end];
+        rest = &rest[end..];
+        if markup.starts_with("<![CDATA[") {
+            nodes.push(Node::Text(markup));
+        } else if markup.starts_with("<!") || markup.starts_with("<?") || markup.ends_with("/>") {
+            nodes.push(Node::Other(markup));
+        } else if markup.starts_with("</") {
+            depth = depth.checked_sub(1)?;
+            nodes.push(Node::Close(markup));
+        } else {
+            depth += 1;
+            nodes.push(Node::Open(markup));
+        }
+    }
+    (depth == 0).then_some(nodes)
+}
+
+// Finds the end of a tag, skipping over quoted attribute values that may contain '>'.
+fn tag_end(input: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (index, c) in input.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(index + 1),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn is_insignificant(node: &Node) -> bool {
+    matches!(node, Node::Text(text) if text.trim().is_empty())
+}
+
+/// Removes the whitespace-only text between tags.
+pub fn strip(xml: &str) -> Option<String> {
+    let nodes = tokenize(xml)?;
+    let mut stripped = String::with_capacity(xml.len());
+    for node in nodes.iter().filter(|node| !is_insignificant(node)) {
+        let (Node::Open(s) | Node::Close(s) | Node::Other(s) | Node::Text(s)) = node;
+        stripped.push_str(s);
+    }
+    Some(stripped)
+}
+
+/// Puts each tag on a line of its own, indented by two spaces for each level of nesting.
+/// Elements that hold only text are kept on a single line, with the text trimmed.
+pub fn pretty(xml: &str) -> Option<String> {
+    let nodes: Vec<Node> = tokenize(xml)?.into_iter().filter(|node| !is_insignificant(node)).collect();
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth: usize = 0;
+    let mut index = 0;
+    while index < nodes.len() {
+        let indent = "  ".repeat(depth);
+        match (&nodes[index], nodes.get(index + 1), nodes.get(index + 2)) {
+            (Node::Open(open), Some(Node::Text(text)), Some(Node::Close(close))) => {
+                lines.push(format!("{}{}{}{}", indent, open, text.trim(), close));
+                index += 3;
+            },
+            (Node::Open(open), Some(Node::Close(close)), _) => {
+                lines.push(format!("{}{}{}", indent, open, close));
+                index += 2;
+            },
+            (Node::Open(open), _, _) => {
+                lines.push(format!("{}{}", indent, open));
+                depth += 1;
+                index += 1;
+            },
+            (Node::Close(close), _, _) => {
+                depth -= 1;
+                lines.push(format!("{}{}", "  ".repeat(depth), close));
+                index += 1;
+            },
+            (Node::Other(s) | Node::Text(s), _, _) => {
+                lines.push(format!("{}{}", indent, s.trim()));
+                index += 1;
+            },
+        }
+    }
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_a() {
+        let xml = "<?xml version=\"1.0\"?>\n<config a='>'>\n  <item/><name>  foo </name>\n<!-- x --><empty></empty><v><![CDATA[<raw>]]></v></config>";
+        assert_eq!(strip(xml).as_deref(), Some(
+            "<?xml version=\"1.0\"?><config a='>'><item/><name>  foo </name><!-- x --><empty></empty><v><![CDATA[<raw>]]></v></config>"
+        ));
+        assert_eq!(pretty(xml).as_deref(), Some("\
+            <?xml version=\"1.0\"?>\n\
+            <config a='>'>\n  \
+              <item/>\n  \
+              <name>foo</name>\n  \
+              <!-- x -->\n  \
+              <empty></empty>\n  \
+              <v><![CDATA[<raw>]]></v>\n\
+            </config>"
+        ));
+        assert_eq!(pretty("<a><b></a>"), None);
+        assert_eq!(pretty("</a>"), None);
+        assert_eq!(pretty("not xml").as_deref(), Some("not xml"));
+    }
+}
@@ -0,0 +1,140 @@
+// Property tests for the parsing layers: entries survive being written and parsed again, and the
+// streaming state machines give the same result however their input is split between writes.
+
+use ltools::base64::{DecodeWriter, EncodeWriter};
+use ltools::bulk::{parse_entries, BulkParser};
+use ltools::entry::{Entry, OwnedEntry, WriteEntry};
+use ltools::filter::Filter;
+use ltools::ldif::{LdifEntryWriter, LineEnding};
+use ltools::loc::{LocWriteWrapper, WriteLocWrapper};
+use ltools::unfold::Unfolder;
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+use std::io::Write;
+
+// The attributes and values of an entry, with lowercase attribute names.
+type Attributes = Vec<(String, Vec<Vec<u8>>)>;
+
+// The parser keeps attributes in the order they were first seen in the stream rather than in each
+// entry, so they are compared in sorted order, with the DN first.
+fn attributes_of(entry: &Entry) -> Attributes {
+    let mut attributes: Attributes = entry.attributes()
+        .map(|attr| (attr.lowercase.to_string(), entry.get(attr.lowercase).map(<[u8]>::to_vec).collect()))
+        .collect();
+    attributes.sort_by_key(|(attr, _)| (attr != "dn", attr.clone()));
+    attributes
+}
+
+fn arb_entry() -> impl Strategy<Value = Attributes> {
+    let dn = "[a-z]{1,8}=[ -~]{0,20}".prop_filter("DN values can't end with a space", |dn| !dn.ends_with(' '));
+    let attributes = btree_map("[a-z][a-z0-9-]{0,15}", vec(vec(any::<u8>(), 0..48), 1..4), 0..8);
+    (dn, attributes).prop_map(|(dn, attributes)| {
+        std::iter::once((String::from("dn"), vec![dn.into_bytes()]))
+            .chain(attributes.into_iter().filter(|(attr, _)| attr != "dn"))
+            .collect()
+    })
+}
+
+fn to_entry(attributes: &Attributes) -> OwnedEntry {
+    let mut entry = OwnedEntry::default();
+    for (attr, values) in attributes.iter() {
+        for value in values.iter() {
+            entry.add_value(attr, value);
+        }
+    }
+    entry
+}
+
+fn write_ldif(entries: &[Attributes], fold_width: Option<usize>, line_ending: LineEnding) -> Vec<u8> {
+    let mut ldif: Vec<u8> = Vec::new();
+    let mut writer = LdifEntryWriter::new(&mut ldif);
+    writer.set_fold_width(fold_width)
+        .set_line_ending(line_ending)
+        .set_sort_attributes(false);
+    for entry in entries.iter() {
+        writer.write_entry(&to_entry(entry)).unwrap();
+    }
+    ldif
+}
+
+fn parse_ldif(ldif: &[u8], buffer_size: usize) -> std::io::Result<Vec<Attributes>> {
+    let mut entries: Vec<OwnedEntry> = Vec::new();
+    BulkParser::with_capacity(buffer_size).write_entries(&mut &ldif[..], &mut entries)?;
+    Ok(entries.iter().map(|entry| attributes_of(entry)).collect())
+}
+
+fn unfold_in_chunks(input: &[u8], chunk_size: usize) -> Option<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut unfolder = WriteLocWrapper::new(Unfolder::new(LocWriteWrapper::new(&mut output)));
+    for chunk in input.chunks(chunk_size) {
+        unfolder.write_all(chunk).ok()?;
+    }
+    unfolder.flush().ok()?;
+    drop(unfolder);
+    Some(output)
+}
+
+proptest! {
+    #[test]
+    fn entries_round_trip(
+        entries in vec(arb_entry(), 0..6),
+        fold_width in proptest::option::of(2usize..100),
+        crlf in any::<bool>(),
+        buffer_size in 1usize..64,
+    ) {
+        let ldif = write_ldif(&entries, fold_width, if crlf { LineEnding::CrLf } else { LineEnding::Lf });
+        prop_assert_eq!(parse_ldif(&ldif, buffer_size)?, entries);
+    }
+
+    #[test]
+    fn parsing_does_not_depend_on_buffer_size(ldif in vec(prop_oneof![
+        Just(b'\n'), Just(b'\r'), Just(b' '), Just(b':'), Just(b'#'), Just(b'-'), Just(b'a'), Just(b'='), Just(b'<'),
+    ], 0..200), buffer_size in 1usize..32) {
+        // the excerpt of the line below an error message shows only as much as has been buffered
+        let message = |err: std::io::Error| err.to_string().lines().next().map(String::from);
+        let whole = parse_ldif(&ldif, ldif.len().max(1)).map_err(message);
+        let split = parse_ldif(&ldif, buffer_size).map_err(message);
+        prop_assert_eq!(whole, split);
+    }
+
+    #[test]
+    fn unfolding_does_not_depend_on_chunk_size(input in vec(prop_oneof![
+        Just(b'\n'), Just(b' '), Just(b'\t'), Just(b'a'),
+    ], 0..100), chunk_size in 1usize..16) {
+        prop_assert_eq!(unfold_in_chunks(&input, input.len().max(1)), unfold_in_chunks(&input, chunk_size));
+    }
+
+    #[test]
+    fn base64_round_trips(data in vec(any::<u8>(), 0..256), chunk_size in 1usize..16) {
+        let mut encoded: Vec<u8> = Vec::new();
+        let mut encoder = EncodeWriter::new(&mut encoded);
+        for chunk in data.chunks(chunk_size) {
+            encoder.write_all(chunk)?;
+        }
+        encoder.flush()?;
+        let mut decoded: Vec<u8> = Vec::new();
+        let mut decoder = DecodeWriter::new(&mut decoded);
+        for chunk in encoded.chunks(chunk_size) {
+            decoder.write_all(chunk)?;
+        }
+        decoder.flush()?;
+        prop_assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn filter_parser_does_not_panic(filter in "[()&|!=<>~*a-z0-9:.\\\\ -]{0,40}") {
+        if let Ok(filter) = Filter::parse(&filter) {
+            filter.is_match(&Entry::from([("cn", b"foo".as_slice())]));
+        }
+    }
+}
+
+#[test]
+fn parse_entries_matches_bulk_parser() -> std::io::Result<()> {
+    let ldif = b"dn: cn=foo\ncn: f\n oo\ndescription:: Zm9v\n\ndn: cn=bar\ncn: bar\n";
+    let mut entries: Vec<OwnedEntry> = Vec::new();
+    parse_entries(ldif, &mut entries)?;
+    let attributes: Vec<Attributes> = entries.iter().map(|entry| attributes_of(entry)).collect();
+    assert_eq!(attributes, parse_ldif(ldif, 3)?);
+    Ok(())
+}